@@ -0,0 +1,4 @@
+mod openapi;
+mod v1;
+
+pub use v1::scope as v1_scope;
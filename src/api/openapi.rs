@@ -0,0 +1,418 @@
+use serde_json::{json, Value};
+
+/// OpenAPI 3.0 description of the `/api/v1` routes in `v1.rs`. Keep the two
+/// in sync when adding endpoints or response fields.
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "CI/CD Dashboard API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "JSON API covering repositories, build status, deploy configs and deploy actions. Errors are returned as an `Error` body with the matching HTTP status."
+        },
+        "servers": [{ "url": "/api/v1" }],
+        "paths": {
+            "/repos": {
+                "get": {
+                    "operationId": "listRepos",
+                    "summary": "List tracked repositories",
+                    "responses": ok(array_of("Repo"))
+                }
+            },
+            "/repos/{owner}/{repo}": {
+                "get": {
+                    "operationId": "getRepo",
+                    "summary": "Get a repository",
+                    "parameters": [path_param("owner"), path_param("repo")],
+                    "responses": ok(schema_ref("Repo"))
+                }
+            },
+            "/repos/{owner}/{repo}/branches": {
+                "get": {
+                    "operationId": "listBranches",
+                    "summary": "List active branches with the build status of their head commit",
+                    "parameters": [path_param("owner"), path_param("repo")],
+                    "responses": ok(array_of("Branch"))
+                }
+            },
+            "/repos/{owner}/{repo}/commits": {
+                "get": {
+                    "operationId": "listCommits",
+                    "summary": "List recent commits on a branch, newest first",
+                    "parameters": [
+                        path_param("owner"),
+                        path_param("repo"),
+                        {
+                            "name": "branch",
+                            "in": "query",
+                            "description": "Defaults to the repository's default branch",
+                            "schema": { "type": "string" }
+                        },
+                        limit_param()
+                    ],
+                    "responses": ok(array_of("Commit"))
+                }
+            },
+            "/repos/{owner}/{repo}/commits/{sha}": {
+                "get": {
+                    "operationId": "getCommit",
+                    "summary": "Get a commit with its individual builds, parents and branches",
+                    "parameters": [path_param("owner"), path_param("repo"), path_param("sha")],
+                    "responses": ok(schema_ref("CommitDetail"))
+                }
+            },
+            "/deploy-configs": {
+                "get": {
+                    "operationId": "listDeployConfigs",
+                    "summary": "List deploy configs",
+                    "responses": ok(array_of("DeployConfig"))
+                }
+            },
+            "/deploy-configs/{name}": {
+                "get": {
+                    "operationId": "getDeployConfig",
+                    "summary": "Get a deploy config with the status of its resources",
+                    "parameters": [path_param("name")],
+                    "responses": ok(schema_ref("DeployConfig"))
+                }
+            },
+            "/deploy-configs/{name}/history": {
+                "get": {
+                    "operationId": "getDeployHistory",
+                    "summary": "List recent deploy events, newest first",
+                    "parameters": [path_param("name"), limit_param()],
+                    "responses": ok(array_of("DeployEvent"))
+                }
+            },
+            "/deploy-configs/{name}/actions": {
+                "post": {
+                    "operationId": "postDeployAction",
+                    "summary": "Deploy, undeploy, bounce, run jobs or toggle settings on a deploy config",
+                    "parameters": [path_param("name")],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": schema_ref("ActionRequest") } }
+                    },
+                    "responses": ok(schema_ref("ActionResponse"))
+                }
+            },
+            "/watchdog": {
+                "get": {
+                    "operationId": "getWatchdog",
+                    "summary": "Health of every repository's default branch and every deploy config",
+                    "responses": ok(schema_ref("Watchdog"))
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "required": ["error", "status"],
+                    "properties": {
+                        "error": { "type": "string" },
+                        "status": { "type": "integer" }
+                    }
+                },
+                "BuildStatus": {
+                    "type": "string",
+                    "enum": ["None", "Pending", "Success", "Failure"]
+                },
+                "HealthStatus": {
+                    "type": "string",
+                    "enum": ["healthy", "warning", "error", "unknown", "info"]
+                },
+                "Repo": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer" },
+                        "owner_name": { "type": "string" },
+                        "name": { "type": "string" },
+                        "default_branch": { "type": "string" },
+                        "private": { "type": "boolean" },
+                        "language": { "type": "string", "nullable": true }
+                    }
+                },
+                "Branch": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "head_sha": { "type": "string" },
+                        "build_status": schema_ref("BuildStatus")
+                    }
+                },
+                "Commit": {
+                    "type": "object",
+                    "properties": {
+                        "sha": { "type": "string" },
+                        "message": { "type": "string" },
+                        "author": { "type": "string" },
+                        "committer": { "type": "string" },
+                        "timestamp": { "type": "integer", "description": "Unix seconds" },
+                        "build_status": schema_ref("BuildStatus")
+                    }
+                },
+                "CommitDetail": {
+                    "allOf": [
+                        schema_ref("Commit"),
+                        {
+                            "type": "object",
+                            "properties": {
+                                "builds": array_of("Build"),
+                                "parents": { "type": "array", "items": { "type": "string" } },
+                                "branches": { "type": "array", "items": { "type": "string" } }
+                            }
+                        }
+                    ]
+                },
+                "Build": {
+                    "type": "object",
+                    "properties": {
+                        "repo_id": { "type": "integer" },
+                        "commit_id": { "type": "integer" },
+                        "check_name": { "type": "string" },
+                        "status": schema_ref("BuildStatus"),
+                        "url": { "type": "string" },
+                        "start_time": { "type": "integer", "nullable": true },
+                        "settle_time": { "type": "integer", "nullable": true },
+                        "app_id": { "type": "integer", "nullable": true }
+                    }
+                },
+                "Version": {
+                    "type": "object",
+                    "properties": {
+                        "sha": { "type": "string" },
+                        "branch": { "type": "string", "nullable": true }
+                    }
+                },
+                "DriftedResource": {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "type": "string" },
+                        "name": { "type": "string" },
+                        "fields": { "type": "array", "items": { "type": "string" } }
+                    }
+                },
+                "Resource": {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "type": "string" },
+                        "name": { "type": "string" },
+                        "status_summary": { "type": "string", "nullable": true },
+                        "status_level": {
+                            "type": "string",
+                            "nullable": true,
+                            "enum": ["error", "warn", "neutral", "muted"]
+                        },
+                        "children": array_of("Resource")
+                    }
+                },
+                "DeployConfig": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "namespace": { "type": "string" },
+                        "team": { "type": "string" },
+                        "kind": { "type": "string" },
+                        "state": {
+                            "type": "string",
+                            "enum": ["deployed", "deployed_config_only", "undeployed"]
+                        },
+                        "autodeploy": { "type": "boolean" },
+                        "orphaned": { "type": "boolean" },
+                        "orphan_reason": { "type": "string", "nullable": true },
+                        "orphan_kept": { "type": "boolean" },
+                        "supports_bounce": { "type": "boolean" },
+                        "supports_execute_job": { "type": "boolean" },
+                        "artifact_repo": { "type": "string", "nullable": true },
+                        "config_repo": { "type": "string" },
+                        "artifact": nullable_ref("Version"),
+                        "config": nullable_ref("Version"),
+                        "drifted": array_of("DriftedResource"),
+                        "resources": {
+                            "description": "Only included when fetching a single deploy config",
+                            "type": "array",
+                            "items": schema_ref("Resource")
+                        }
+                    }
+                },
+                "DeployEvent": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "timestamp": { "type": "integer", "description": "Unix milliseconds" },
+                        "initiator": { "type": "string" },
+                        "config_sha": { "type": "string", "nullable": true },
+                        "artifact_sha": { "type": "string", "nullable": true },
+                        "artifact_branch": { "type": "string", "nullable": true },
+                        "config_branch": { "type": "string", "nullable": true },
+                        "prev_artifact_sha": { "type": "string", "nullable": true },
+                        "prev_config_sha": { "type": "string", "nullable": true },
+                        "artifact_repo_id": { "type": "integer", "nullable": true },
+                        "config_repo_id": { "type": "integer", "nullable": true },
+                        "config_version_hash": { "type": "string", "nullable": true },
+                        "prev_config_version_hash": { "type": "string", "nullable": true }
+                    }
+                },
+                "ActionRequest": {
+                    "type": "object",
+                    "required": ["action"],
+                    "properties": {
+                        "action": {
+                            "type": "string",
+                            "enum": [
+                                "deploy",
+                                "undeploy",
+                                "bounce",
+                                "execute-job",
+                                "toggle-autodeploy",
+                                "toggle-orphan-keep"
+                            ]
+                        },
+                        "branch": {
+                            "type": "string",
+                            "description": "Deploy the head of this branch"
+                        },
+                        "sha": {
+                            "type": "string",
+                            "description": "Deploy this commit; takes precedence over branch"
+                        }
+                    }
+                },
+                "ActionResponse": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "action": { "type": "string" }
+                    }
+                },
+                "Watchdog": {
+                    "type": "object",
+                    "properties": {
+                        "repos": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "owner": { "type": "string" },
+                                    "name": { "type": "string" },
+                                    "status": schema_ref("HealthStatus"),
+                                    "sha": { "type": "string", "nullable": true },
+                                    "message": { "type": "string", "nullable": true }
+                                }
+                            }
+                        },
+                        "deploy_configs": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "name": { "type": "string" },
+                                    "namespace": { "type": "string" },
+                                    "team": { "type": "string" },
+                                    "status": schema_ref("HealthStatus"),
+                                    "message": { "type": "string", "nullable": true }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn nullable_ref(name: &str) -> Value {
+    json!({ "nullable": true, "allOf": [schema_ref(name)] })
+}
+
+fn array_of(name: &str) -> Value {
+    json!({ "type": "array", "items": schema_ref(name) })
+}
+
+fn path_param(name: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+fn limit_param() -> Value {
+    json!({
+        "name": "limit",
+        "in": "query",
+        "description": "Defaults to 20, at most 500",
+        "schema": { "type": "integer", "minimum": 1, "maximum": 500 }
+    })
+}
+
+/// A 200 response with `schema`, plus the shared error responses.
+fn ok(schema: Value) -> Value {
+    let error = json!({
+        "content": { "application/json": { "schema": schema_ref("Error") } }
+    });
+    json!({
+        "200": {
+            "description": "OK",
+            "content": { "application/json": { "schema": schema } }
+        },
+        "400": { "description": "Invalid input", "content": error["content"] },
+        "404": { "description": "Not found", "content": error["content"] },
+        "500": { "description": "Internal error", "content": error["content"] }
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn refs(value: &Value, out: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(r)) => out.push(r.clone()),
+                        _ => refs(value, out),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn every_ref_resolves() {
+        let doc = document();
+        let mut found = vec![];
+        refs(&doc, &mut found);
+        assert!(!found.is_empty());
+
+        for r in found {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(
+                doc["components"]["schemas"].get(name).is_some(),
+                "unresolved {}",
+                r
+            );
+        }
+    }
+
+    #[test]
+    fn operation_ids_are_unique() {
+        let doc = document();
+        let mut ids: Vec<&str> = doc["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .flat_map(|item| item.as_object().unwrap().values())
+            .map(|op| op["operationId"].as_str().unwrap())
+            .collect();
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+}
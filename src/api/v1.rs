@@ -0,0 +1,514 @@
+use actix_web::{get, post, web, HttpResponse, Scope};
+use kube::{Client, ResourceExt};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::audit::AuditSource;
+use crate::auth::CurrentUser;
+use crate::build_status::BuildStatus;
+use crate::crab_ext::Octocrabs;
+use crate::db::deploy_event::DeployEvent;
+use crate::db::git_branch::GitBranch;
+use crate::db::git_commit::GitCommit;
+use crate::db::git_commit_build::GitCommitBuild;
+use crate::db::git_repo::GitRepo;
+use crate::error::{AppError, AppResult};
+use crate::kubernetes::api::{
+    get_all_deploy_configs, get_deploy_config, list_namespace_objects, ListMode,
+};
+use crate::kubernetes::drift::DriftedResource;
+use crate::kubernetes::repo::DeploymentState;
+use crate::kubernetes::DeployConfig;
+use crate::web::{
+    check_deploy_config_health, check_repo_health, execute_action, Action, HealthStatus,
+    ResourceStatuses,
+};
+
+use super::openapi;
+
+const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 500;
+
+/// All `/api/v1` routes. Extractor failures and unknown routes produce the
+/// same JSON error body as `AppError`.
+pub fn scope() -> Scope {
+    web::scope("/api/v1")
+        .app_data(
+            web::JsonConfig::default()
+                .error_handler(|e, _| AppError::InvalidInput(e.to_string()).into()),
+        )
+        .app_data(
+            web::QueryConfig::default()
+                .error_handler(|e, _| AppError::InvalidInput(e.to_string()).into()),
+        )
+        .app_data(
+            web::PathConfig::default()
+                .error_handler(|e, _| AppError::InvalidInput(e.to_string()).into()),
+        )
+        .service(openapi_document)
+        .service(list_repos)
+        .service(get_repo)
+        .service(list_branches)
+        .service(list_commits)
+        .service(get_commit)
+        .service(list_deploy_configs)
+        .service(get_deploy_config_by_name)
+        .service(get_deploy_history)
+        .service(post_deploy_action)
+        .service(get_watchdog)
+        .default_service(web::to(|| async {
+            Err::<HttpResponse, _>(AppError::NotFound("No such API endpoint".to_string()))
+        }))
+}
+
+#[derive(Deserialize)]
+struct LimitQuery {
+    limit: Option<u32>,
+}
+
+impl LimitQuery {
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+}
+
+#[derive(Deserialize)]
+struct CommitsQuery {
+    branch: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct BranchResponse {
+    name: String,
+    head_sha: String,
+    build_status: BuildStatus,
+}
+
+#[derive(Serialize)]
+struct CommitResponse {
+    sha: String,
+    message: String,
+    author: String,
+    committer: String,
+    timestamp: i64,
+    build_status: BuildStatus,
+}
+
+#[derive(Serialize)]
+struct CommitDetailResponse {
+    #[serde(flatten)]
+    commit: CommitResponse,
+    builds: Vec<GitCommitBuild>,
+    parents: Vec<String>,
+    branches: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct VersionResponse {
+    sha: String,
+    branch: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeployConfigResponse {
+    name: String,
+    namespace: String,
+    team: String,
+    kind: String,
+    state: &'static str,
+    autodeploy: bool,
+    orphaned: bool,
+    orphan_reason: Option<String>,
+    orphan_kept: bool,
+    supports_bounce: bool,
+    supports_execute_job: bool,
+    artifact_repo: Option<String>,
+    config_repo: String,
+    artifact: Option<VersionResponse>,
+    config: Option<VersionResponse>,
+    drifted: Vec<DriftedResource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<Vec<Value>>,
+}
+
+#[derive(Deserialize)]
+struct ActionRequest {
+    action: String,
+    branch: Option<String>,
+    sha: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ActionResponse {
+    name: String,
+    action: String,
+}
+
+#[derive(Serialize)]
+struct RepoHealthResponse {
+    owner: String,
+    name: String,
+    status: HealthStatus,
+    sha: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeployConfigHealthResponse {
+    name: String,
+    namespace: String,
+    team: String,
+    status: HealthStatus,
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct WatchdogResponse {
+    repos: Vec<RepoHealthResponse>,
+    deploy_configs: Vec<DeployConfigHealthResponse>,
+}
+
+fn kube_client(client: Option<web::Data<Client>>) -> AppResult<web::Data<Client>> {
+    client
+        .ok_or_else(|| AppError::KubernetesConfig("Kubernetes client is not available".to_string()))
+}
+
+fn find_repo(
+    owner: &str,
+    name: &str,
+    conn: &PooledConnection<SqliteConnectionManager>,
+) -> AppResult<GitRepo> {
+    GitRepo::get_by_name(owner, name, conn)?
+        .ok_or_else(|| AppError::NotFound(format!("Repository {}/{}", owner, name)))
+}
+
+async fn find_deploy_config(client: &Client, name: &str) -> AppResult<DeployConfig> {
+    get_deploy_config(client, name)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Deploy config {}", name)))
+}
+
+fn build_status(
+    commit: &GitCommit,
+    conn: &PooledConnection<SqliteConnectionManager>,
+) -> AppResult<BuildStatus> {
+    Ok(commit.get_build_status(conn)?.into())
+}
+
+fn commit_response(
+    commit: GitCommit,
+    conn: &PooledConnection<SqliteConnectionManager>,
+) -> AppResult<CommitResponse> {
+    Ok(CommitResponse {
+        build_status: build_status(&commit, conn)?,
+        sha: commit.sha,
+        message: commit.message,
+        author: commit.author,
+        committer: commit.committer,
+        timestamp: commit.timestamp,
+    })
+}
+
+fn deploy_config_response(
+    config: &DeployConfig,
+    resources: Option<Vec<Value>>,
+) -> DeployConfigResponse {
+    let (state, artifact, deployed_config) = match config.deployment_state() {
+        DeploymentState::DeployedWithArtifact { artifact, config } => (
+            "deployed",
+            Some(VersionResponse {
+                sha: artifact.sha,
+                branch: artifact.branch,
+            }),
+            Some(VersionResponse {
+                sha: config.sha,
+                branch: config.branch,
+            }),
+        ),
+        DeploymentState::DeployedOnlyConfig { config } => (
+            "deployed_config_only",
+            None,
+            Some(VersionResponse {
+                sha: config.sha,
+                branch: config.branch,
+            }),
+        ),
+        DeploymentState::Undeployed => ("undeployed", None, None),
+    };
+    let config_repo = config.config_repository();
+
+    DeployConfigResponse {
+        name: config.name_any(),
+        namespace: config.namespace().unwrap_or_else(|| "default".to_string()),
+        team: config.team().to_string(),
+        kind: config.kind().to_string(),
+        state,
+        autodeploy: config.autodeploy(),
+        orphaned: config.is_orphaned(),
+        orphan_reason: config.orphan_reason().map(str::to_string),
+        orphan_kept: config.is_orphan_kept(),
+        supports_bounce: config.supports_bounce(),
+        supports_execute_job: config.supports_execute_job(),
+        artifact_repo: config
+            .artifact_repository()
+            .map(|r| format!("{}/{}", r.owner, r.repo)),
+        config_repo: format!("{}/{}", config_repo.owner, config_repo.repo),
+        artifact,
+        config: deployed_config,
+        drifted: config.drifted_resources().to_vec(),
+        resources,
+    }
+}
+
+#[get("/openapi.json")]
+async fn openapi_document() -> HttpResponse {
+    HttpResponse::Ok().json(openapi::document())
+}
+
+#[get("/repos")]
+async fn list_repos(pool: web::Data<Pool<SqliteConnectionManager>>) -> AppResult<HttpResponse> {
+    let conn = pool.get()?;
+    Ok(HttpResponse::Ok().json(GitRepo::get_all(&conn)?))
+}
+
+#[get("/repos/{owner}/{repo}")]
+async fn get_repo(
+    path: web::Path<(String, String)>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+) -> AppResult<HttpResponse> {
+    let (owner, name) = path.into_inner();
+    let conn = pool.get()?;
+    Ok(HttpResponse::Ok().json(find_repo(&owner, &name, &conn)?))
+}
+
+#[get("/repos/{owner}/{repo}/branches")]
+async fn list_branches(
+    path: web::Path<(String, String)>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+) -> AppResult<HttpResponse> {
+    let (owner, name) = path.into_inner();
+    let conn = pool.get()?;
+    let repo = find_repo(&owner, &name, &conn)?;
+
+    let branches = GitBranch::get_active_by_repo(repo.id, &conn)?
+        .into_iter()
+        .map(|branch| {
+            let build_status = match GitCommit::get_by_sha(&branch.head_commit_sha, repo.id, &conn)?
+            {
+                Some(commit) => build_status(&commit, &conn)?,
+                None => BuildStatus::None,
+            };
+            Ok(BranchResponse {
+                name: branch.name,
+                head_sha: branch.head_commit_sha,
+                build_status,
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    Ok(HttpResponse::Ok().json(branches))
+}
+
+#[get("/repos/{owner}/{repo}/commits")]
+async fn list_commits(
+    path: web::Path<(String, String)>,
+    query: web::Query<CommitsQuery>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+) -> AppResult<HttpResponse> {
+    let (owner, name) = path.into_inner();
+    let conn = pool.get()?;
+    let repo = find_repo(&owner, &name, &conn)?;
+
+    let branch_name = query.branch.as_deref().unwrap_or(&repo.default_branch);
+    let branch = GitBranch::get_by_name(branch_name, repo.id, &conn)?
+        .ok_or_else(|| AppError::NotFound(format!("Branch {}", branch_name)))?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let commits = branch
+        .recent_commits(limit, &conn)?
+        .into_iter()
+        .map(|commit| commit_response(commit, &conn))
+        .collect::<AppResult<Vec<_>>>()?;
+
+    Ok(HttpResponse::Ok().json(commits))
+}
+
+#[get("/repos/{owner}/{repo}/commits/{sha}")]
+async fn get_commit(
+    path: web::Path<(String, String, String)>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+) -> AppResult<HttpResponse> {
+    let (owner, name, sha) = path.into_inner();
+    let conn = pool.get()?;
+    let repo = find_repo(&owner, &name, &conn)?;
+
+    let commit = GitCommit::get_by_sha(&sha, repo.id, &conn)?
+        .ok_or_else(|| AppError::NotFound(format!("Commit {}", sha)))?;
+    let builds = GitCommitBuild::get_all_by_commit_id(&commit.id, &repo.id, &conn)?;
+    let parents = commit
+        .get_parents(&conn)?
+        .into_iter()
+        .map(|c| c.sha)
+        .collect();
+    let branches = commit
+        .get_branches(&conn)?
+        .into_iter()
+        .map(|b| b.name)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(CommitDetailResponse {
+        commit: commit_response(commit, &conn)?,
+        builds,
+        parents,
+        branches,
+    }))
+}
+
+#[get("/deploy-configs")]
+async fn list_deploy_configs(client: Option<web::Data<Client>>) -> AppResult<HttpResponse> {
+    let client = kube_client(client)?;
+    let configs: Vec<DeployConfigResponse> = get_all_deploy_configs(&client)
+        .await?
+        .iter()
+        .map(|config| deploy_config_response(config, None))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(configs))
+}
+
+#[get("/deploy-configs/{name}")]
+async fn get_deploy_config_by_name(
+    path: web::Path<String>,
+    client: Option<web::Data<Client>>,
+) -> AppResult<HttpResponse> {
+    let client = kube_client(client)?;
+    let config = find_deploy_config(&client, &path).await?;
+
+    let namespace = config.namespace().unwrap_or_else(|| "default".to_string());
+    let namespaced_objs = list_namespace_objects(&client, &namespace, ListMode::All).await?;
+    let resources = config.format_resources_json(&namespaced_objs);
+
+    Ok(HttpResponse::Ok().json(deploy_config_response(&config, Some(resources))))
+}
+
+#[get("/deploy-configs/{name}/history")]
+async fn get_deploy_history(
+    path: web::Path<String>,
+    query: web::Query<LimitQuery>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+) -> AppResult<HttpResponse> {
+    let conn = pool.get()?;
+    let events = DeployEvent::get_recent_by_name(&path, query.limit(), &conn)?;
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[post("/deploy-configs/{name}/actions")]
+async fn post_deploy_action(
+    path: web::Path<String>,
+    body: web::Json<ActionRequest>,
+    client: Option<web::Data<Client>>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    octocrabs: web::Data<Octocrabs>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    let client = kube_client(client)?;
+    let action = Action::parse(&body.action, body.branch.as_deref(), body.sha.as_deref())
+        .ok_or_else(|| AppError::InvalidInput(format!("Unknown action: {}", body.action)))?;
+    let config = find_deploy_config(&client, &path).await?;
+
+    execute_action(
+        &action,
+        &config,
+        &client,
+        &pool,
+        &octocrabs,
+        &user,
+        AuditSource::Api,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ActionResponse {
+        name: config.name_any(),
+        action: action.describe(),
+    }))
+}
+
+#[get("/watchdog")]
+async fn get_watchdog(
+    client: Option<web::Data<Client>>,
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+) -> AppResult<HttpResponse> {
+    let client = kube_client(client)?;
+    let conn = pool.get()?;
+
+    let repos = GitRepo::get_all(&conn)?
+        .into_iter()
+        .map(|repo| {
+            let (status, sha, message) = check_repo_health(&repo, &conn)?;
+            Ok(RepoHealthResponse {
+                owner: repo.owner_name,
+                name: repo.name,
+                status,
+                sha,
+                message,
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+    drop(conn);
+
+    let mut deploy_configs = vec![];
+    for config in get_all_deploy_configs(&client).await? {
+        let (status, message) = check_deploy_config_health(&config, &client).await?;
+        deploy_configs.push(DeployConfigHealthResponse {
+            name: config.name_any(),
+            namespace: config.namespace().unwrap_or_else(|| "default".to_string()),
+            team: config.team().to_string(),
+            status,
+            message,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(WatchdogResponse {
+        repos,
+        deploy_configs,
+    }))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    #[actix_web::test]
+    async fn unknown_routes_return_json_errors() {
+        let app = test::init_service(App::new().service(scope())).await;
+
+        let response = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/api/v1/nope").to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], 404);
+        assert!(body["error"].as_str().unwrap().starts_with("Not found"));
+    }
+
+    #[actix_web::test]
+    async fn serves_openapi_document() {
+        let app = test::init_service(App::new().service(scope())).await;
+
+        let body: Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get()
+                .uri("/api/v1/openapi.json")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(body["openapi"], "3.0.3");
+    }
+}
@@ -0,0 +1,90 @@
+//! The audit log: one row per mutating action, from the UI, the APIs,
+//! webhooks or background automation, whether it succeeded or not.
+
+use std::fmt::Display;
+
+use crate::db::audit_event::{AuditEvent, AuditEventEgg};
+use crate::prelude::*;
+
+/// Where an action came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditSource {
+    Ui,
+    Mcp,
+    /// The REST and GraphQL APIs.
+    Api,
+    /// GitHub webhooks.
+    Webhook,
+    /// Background tasks such as the orphan reaper and the config sweeper.
+    Automation,
+}
+
+impl AuditSource {
+    pub const ALL: [AuditSource; 5] = [
+        AuditSource::Ui,
+        AuditSource::Mcp,
+        AuditSource::Api,
+        AuditSource::Webhook,
+        AuditSource::Automation,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditSource::Ui => "ui",
+            AuditSource::Mcp => "mcp",
+            AuditSource::Api => "api",
+            AuditSource::Webhook => "webhook",
+            AuditSource::Automation => "automation",
+        }
+    }
+}
+
+/// An audit entry under construction.
+pub struct AuditRecord {
+    egg: AuditEventEgg,
+}
+
+impl AuditRecord {
+    pub fn new(actor: &str, source: AuditSource, action: &str, target: &str) -> Self {
+        AuditRecord {
+            egg: AuditEventEgg {
+                actor: actor.to_string(),
+                source: source.as_str().to_string(),
+                action: action.to_string(),
+                target: target.to_string(),
+                params: serde_json::Value::Object(Default::default()),
+                success: false,
+                error: None,
+            },
+        }
+    }
+
+    pub fn params(mut self, params: serde_json::Value) -> Self {
+        self.egg.params = params;
+        self
+    }
+
+    /// Store the entry with the outcome of the action. Failing to store it
+    /// never fails the action, so errors are only logged.
+    pub fn record<T, E: Display>(
+        mut self,
+        pool: &Pool<SqliteConnectionManager>,
+        result: &Result<T, E>,
+    ) {
+        self.egg.success = result.is_ok();
+        self.egg.error = result.as_ref().err().map(|e| e.to_string());
+        let inserted = pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|conn| AuditEvent::insert(&self.egg, &conn));
+        if let Err(e) = inserted {
+            log::error!(
+                "Failed to record audit event {} on {} by {}: {}",
+                self.egg.action,
+                self.egg.target,
+                self.egg.actor,
+                e
+            );
+        }
+    }
+}
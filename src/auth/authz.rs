@@ -0,0 +1,271 @@
+//! Team roles. A role on a team allows everything the lower roles allow:
+//!
+//! - `viewer`: read-only access
+//! - `deployer`: deploy, undeploy, bounce and run jobs
+//! - `admin`: toggle autodeploy and orphan cleanup
+//!
+//! An admin on [`ALL_TEAMS`] can also bootstrap the database and manage
+//! webhooks and role bindings.
+//!
+//! A personal API token acts as its owner, limited by its [`TokenScope`].
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::db::role_binding::RoleBinding;
+
+/// The team of a role binding that applies to every team.
+pub const ALL_TEAMS: &str = "*";
+
+/// Role names, lowest first.
+pub const ROLES: [&str; 3] = ["viewer", "deployer", "admin"];
+
+/// Subject kinds of a role binding.
+pub const BINDING_KINDS: [&str; 2] = ["user", "group"];
+
+/// API token scope names, narrowest first.
+pub const TOKEN_SCOPES: [&str; 3] = ["read", "deploy", "admin"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Viewer,
+    Deployer,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "deployer" => Some(Role::Deployer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Deployer => "deployer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a personal API token may do on behalf of its owner. A token never
+/// grants more than the owner's own roles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Viewer on every team.
+    Read,
+    /// Deployer on the listed teams, viewer elsewhere.
+    Deploy(Vec<String>),
+    /// Everything the owner may do.
+    Admin,
+}
+
+impl TokenScope {
+    pub fn parse(scope: &str, teams: &[String]) -> Option<Self> {
+        match scope {
+            "read" => Some(TokenScope::Read),
+            "deploy" => Some(TokenScope::Deploy(teams.to_vec())),
+            "admin" => Some(TokenScope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Deploy(_) => "deploy",
+            TokenScope::Admin => "admin",
+        }
+    }
+}
+
+/// The roles a user holds, per team and across all teams.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Roles {
+    global: Option<Role>,
+    teams: HashMap<String, Role>,
+}
+
+impl Roles {
+    /// Admin on every team.
+    pub fn admin() -> Self {
+        Roles {
+            global: Some(Role::Admin),
+            teams: HashMap::new(),
+        }
+    }
+
+    /// The roles of a user with `login` in `groups`: `default` on every team,
+    /// raised by the bindings that name the user or one of their groups.
+    pub fn resolve(
+        login: &str,
+        groups: &[String],
+        bindings: &[RoleBinding],
+        default: Option<Role>,
+    ) -> Self {
+        let mut roles = Roles {
+            global: default,
+            teams: HashMap::new(),
+        };
+        for binding in bindings {
+            let applies = match binding.kind.as_str() {
+                "user" => binding.subject.eq_ignore_ascii_case(login),
+                "group" => groups
+                    .iter()
+                    .any(|g| g.eq_ignore_ascii_case(&binding.subject)),
+                _ => false,
+            };
+            if !applies {
+                continue;
+            }
+            match Role::parse(&binding.role) {
+                Some(role) => roles.grant(&binding.team, role),
+                None => log::warn!("Ignoring role binding with unknown role {}", binding.role),
+            }
+        }
+        roles
+    }
+
+    pub fn grant(&mut self, team: &str, role: Role) {
+        if team == ALL_TEAMS {
+            self.global = self.global.max(Some(role));
+        } else {
+            let current = self.teams.entry(team.to_string()).or_insert(role);
+            *current = (*current).max(role);
+        }
+    }
+
+    /// The highest role on a team, if any.
+    pub fn role(&self, team: &str) -> Option<Role> {
+        self.global.max(self.teams.get(team).copied())
+    }
+
+    pub fn allows(&self, team: &str, required: Role) -> bool {
+        self.role(team).is_some_and(|role| role >= required)
+    }
+
+    /// Teams with a role beyond the global one, for display.
+    pub fn team_roles(&self) -> Vec<(&str, Role)> {
+        let mut teams: Vec<(&str, Role)> = self
+            .teams
+            .iter()
+            .filter(|(_, role)| Some(**role) > self.global)
+            .map(|(team, role)| (team.as_str(), *role))
+            .collect();
+        teams.sort_unstable();
+        teams
+    }
+
+    pub fn global_role(&self) -> Option<Role> {
+        self.global
+    }
+
+    /// These roles as seen through an API token with `scope`.
+    pub fn limit(&self, scope: &TokenScope) -> Roles {
+        let cap = |role: Option<Role>, max: Role| role.map(|r| r.min(max));
+        match scope {
+            TokenScope::Admin => self.clone(),
+            TokenScope::Read => Roles {
+                global: cap(self.global, Role::Viewer),
+                teams: self
+                    .teams
+                    .iter()
+                    .map(|(team, role)| (team.clone(), (*role).min(Role::Viewer)))
+                    .collect(),
+            },
+            TokenScope::Deploy(teams) => {
+                let mut limited = self.limit(&TokenScope::Read);
+                for team in teams {
+                    if let Some(role) = cap(self.role(team), Role::Deployer) {
+                        limited.teams.insert(team.clone(), role);
+                    }
+                }
+                limited
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(kind: &str, subject: &str, team: &str, role: &str) -> RoleBinding {
+        RoleBinding {
+            id: 0,
+            kind: kind.to_string(),
+            subject: subject.to_string(),
+            team: team.to_string(),
+            role: role.to_string(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn resolves_bindings() {
+        let bindings = vec![
+            binding("user", "Octocat", "payments", "deployer"),
+            binding("group", "acme/search-devs", "search", "admin"),
+            binding("group", "acme/sre", ALL_TEAMS, "admin"),
+            binding("user", "octocat", "search", "viewer"),
+        ];
+
+        let roles = Roles::resolve(
+            "octocat",
+            &["acme/search-devs".to_string()],
+            &bindings,
+            Some(Role::Viewer),
+        );
+        assert_eq!(roles.role("payments"), Some(Role::Deployer));
+        assert_eq!(roles.role("search"), Some(Role::Admin));
+        assert_eq!(roles.role("billing"), Some(Role::Viewer));
+        assert!(roles.allows("payments", Role::Deployer));
+        assert!(!roles.allows("payments", Role::Admin));
+        assert!(!roles.allows(ALL_TEAMS, Role::Admin));
+
+        let sre = Roles::resolve("hubot", &["ACME/sre".to_string()], &bindings, None);
+        assert!(sre.allows(ALL_TEAMS, Role::Admin));
+        assert!(sre.allows("anything", Role::Admin));
+
+        let nobody = Roles::resolve("nobody", &[], &bindings, None);
+        assert_eq!(nobody.role("payments"), None);
+        assert!(!nobody.allows("payments", Role::Viewer));
+    }
+
+    #[test]
+    fn limits_roles_to_token_scope() {
+        let bindings = vec![
+            binding("user", "octocat", "payments", "deployer"),
+            binding("user", "octocat", "search", "admin"),
+        ];
+        let roles = Roles::resolve("octocat", &[], &bindings, Some(Role::Viewer));
+
+        let read = roles.limit(&TokenScope::Read);
+        assert_eq!(read.role("search"), Some(Role::Viewer));
+        assert_eq!(read.role("billing"), Some(Role::Viewer));
+
+        let deploy = roles.limit(&TokenScope::Deploy(vec![
+            "search".to_string(),
+            "billing".to_string(),
+        ]));
+        assert_eq!(deploy.role("search"), Some(Role::Deployer));
+        assert_eq!(deploy.role("payments"), Some(Role::Viewer));
+        // The owner can't deploy to billing, so neither can the token.
+        assert_eq!(deploy.role("billing"), Some(Role::Viewer));
+
+        assert_eq!(roles.limit(&TokenScope::Admin), roles);
+        assert!(!Roles::admin()
+            .limit(&TokenScope::Deploy(vec!["search".to_string()]))
+            .allows(ALL_TEAMS, Role::Deployer));
+    }
+}
@@ -0,0 +1,171 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_session::SessionExt;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{HttpMessage, HttpResponse, ResponseError};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+
+use super::{AuthState, CurrentUser};
+use crate::error::AppError;
+use crate::webhooks::http::WEBHOOK_PATH;
+
+/// Paths anyone may reach: login itself, static assets, metrics and GitHub
+/// webhooks, which are verified by their signature instead.
+const PUBLIC_PREFIXES: [&str; 4] = ["/auth/", "/res/", "/api/metrics", WEBHOOK_PATH];
+
+/// Paths for programmatic clients, which must send a bearer token.
+const BEARER_PREFIXES: [&str; 2] = ["/mcp", "/api/v1"];
+
+fn has_prefix(path: &str, prefixes: &[&str]) -> bool {
+    prefixes.iter().any(|p| path.starts_with(p))
+}
+
+/// Resolves the [`CurrentUser`] of every request and turns away requests
+/// without one. Must be wrapped inside the session middleware.
+pub struct RequireAuth {
+    state: Arc<AuthState>,
+}
+
+impl RequireAuth {
+    pub fn new(state: Arc<AuthState>) -> Self {
+        Self { state }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAuthMiddleware {
+            service: Rc::new(service),
+            state: self.state.clone(),
+        }))
+    }
+}
+
+pub struct RequireAuthMiddleware<S> {
+    service: Rc<S>,
+    state: Arc<AuthState>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let state = self.state.clone();
+
+        Box::pin(async move {
+            match authenticate(&state, &req).await {
+                Ok(Some(user)) => {
+                    req.extensions_mut().insert(user);
+                }
+                Ok(None) => {}
+                Err(response) => return Ok(req.into_response(response).map_into_right_body()),
+            }
+            service.call(req).await.map(|res| res.map_into_left_body())
+        })
+    }
+}
+
+/// The user behind a request; `None` for public paths. An `Err` is the
+/// response to send instead.
+async fn authenticate(
+    state: &AuthState,
+    req: &ServiceRequest,
+) -> Result<Option<CurrentUser>, HttpResponse> {
+    let path = req.path();
+    if has_prefix(path, &PUBLIC_PREFIXES) {
+        return Ok(None);
+    }
+    if !state.enabled() {
+        return Ok(Some(CurrentUser::anonymous()));
+    }
+
+    let bearer = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim);
+    if let Some(token) = bearer {
+        return match state.bearer_user(token).await {
+            Ok(user) => Ok(Some(user)),
+            Err(e) => {
+                log::warn!("Rejected bearer token for {}: {}", path, e);
+                Err(AppError::Unauthorized("Invalid bearer token".to_string()).error_response())
+            }
+        };
+    }
+    if has_prefix(path, &BEARER_PREFIXES) {
+        return Err(
+            AppError::Unauthorized("A bearer token is required".to_string()).error_response(),
+        );
+    }
+
+    match state.session_user(&req.get_session()) {
+        Ok(Some(user)) => Ok(Some(user)),
+        Ok(None) => Err(login_required(req)),
+        Err(e) => {
+            log::error!("Failed to load session user: {}", e);
+            Err(e.error_response())
+        }
+    }
+}
+
+/// Send browsers to the login page, and tell htmx to do the same rather than
+/// swapping the login page into a fragment.
+fn login_required(req: &ServiceRequest) -> HttpResponse {
+    let next = match req.query_string() {
+        "" => req.path().to_string(),
+        query => format!("{}?{}", req.path(), query),
+    };
+    let login = url::form_urlencoded::Serializer::new("/auth/login?".to_string())
+        .append_pair("next", &next)
+        .finish();
+
+    if req.headers().contains_key("HX-Request") {
+        HttpResponse::Unauthorized()
+            .insert_header(("HX-Redirect", login))
+            .finish()
+    } else if req.method() == actix_web::http::Method::GET {
+        HttpResponse::Found()
+            .insert_header(("Location", login))
+            .finish()
+    } else {
+        AppError::Unauthorized("Not logged in".to_string()).error_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_paths() {
+        assert!(has_prefix("/auth/callback", &PUBLIC_PREFIXES));
+        assert!(has_prefix("/res/styles.css", &PUBLIC_PREFIXES));
+        assert!(!has_prefix("/api/deploy/ns/api", &PUBLIC_PREFIXES));
+        assert!(has_prefix("/api/v1/repos", &BEARER_PREFIXES));
+        assert!(has_prefix("/mcp", &BEARER_PREFIXES));
+        assert!(!has_prefix("/api/graphql", &BEARER_PREFIXES));
+    }
+}
@@ -0,0 +1,431 @@
+//! Login through GitHub OAuth or a generic OIDC provider, and the identity of
+//! the user behind every request.
+//!
+//! Browsers log in at `/auth/login` and carry a signed session cookie. `/mcp`
+//! and `/api/v1` take `Authorization: Bearer <token>` instead, where the token
+//! is either a personal API token issued on the settings page or an access
+//! token from the same provider (for GitHub, a personal access token works).
+//! Without a configured provider, authentication is disabled and every request
+//! acts as `anonymous`, an admin on every team. See [`authz`] for what each
+//! role may do.
+
+pub mod authz;
+mod middleware;
+mod provider;
+
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_session::Session;
+use actix_web::cookie::Key;
+use actix_web::{get, web, FromRequest, HttpMessage, HttpRequest, HttpResponse, Scope};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::db::api_token::ApiToken;
+use crate::db::role_binding::RoleBinding;
+use crate::db::user::{User, UserEgg};
+use crate::prelude::*;
+
+use authz::{Role, Roles, TokenScope, ALL_TEAMS};
+pub use middleware::RequireAuth;
+use provider::Provider;
+
+/// How long a validated bearer token is trusted before asking the provider again.
+const TOKEN_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Personal API tokens start with this, to tell them apart from provider tokens.
+pub const API_TOKEN_PREFIX: &str = "cicd_";
+
+const SESSION_USER_ID: &str = "user_id";
+const SESSION_LOGIN_STATE: &str = "login_state";
+const SESSION_LOGIN_NEXT: &str = "login_next";
+
+/// The user a request acts as. Recorded as the initiator of deploy events.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurrentUser {
+    /// `None` when authentication is disabled.
+    pub id: Option<i64>,
+    pub login: String,
+    pub roles: Roles,
+}
+
+impl CurrentUser {
+    pub fn anonymous() -> Self {
+        CurrentUser {
+            id: None,
+            login: "anonymous".to_string(),
+            roles: Roles::admin(),
+        }
+    }
+
+    /// Whether the user holds at least `role` on `team`.
+    pub fn can(&self, team: &str, role: Role) -> bool {
+        self.roles.allows(team, role)
+    }
+
+    /// Whether the user is an admin on every team.
+    pub fn is_admin(&self) -> bool {
+        self.can(ALL_TEAMS, Role::Admin)
+    }
+
+    /// Fail with [`AppError::Forbidden`], and log the attempt, unless the
+    /// user holds at least `role` on `team`. `what` names the attempted action.
+    pub fn authorize(&self, team: &str, role: Role, what: &str) -> AppResult<()> {
+        if self.can(team, role) {
+            return Ok(());
+        }
+        let scope = if team == ALL_TEAMS {
+            "every team".to_string()
+        } else {
+            format!("team {}", team)
+        };
+        log::warn!(
+            "Denied {} to {}: requires {} on {}",
+            what,
+            self.login,
+            role,
+            scope
+        );
+        Err(AppError::Forbidden(format!(
+            "{} requires the {} role on {}",
+            what, role, scope
+        )))
+    }
+
+    /// [`CurrentUser::authorize`] for actions reserved to admins of every team.
+    pub fn authorize_admin(&self, what: &str) -> AppResult<()> {
+        self.authorize(ALL_TEAMS, Role::Admin, what)
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<CurrentUser>()
+                .cloned()
+                .ok_or_else(|| AppError::Unauthorized("Not logged in".to_string())),
+        )
+    }
+}
+
+/// Authentication settings and caches, shared by the middleware and the login
+/// routes.
+pub struct AuthState {
+    provider: Option<Provider>,
+    /// External base URL, for OAuth redirect URIs.
+    public_url: String,
+    http: reqwest::Client,
+    pool: Pool<SqliteConnectionManager>,
+    /// Logins that are admins on every team regardless of role bindings.
+    admins: Vec<String>,
+    /// The role of logged-in users on teams without a binding for them.
+    default_role: Option<Role>,
+    /// SHA-256 of a bearer token to the user it resolved to.
+    token_cache: Mutex<HashMap<String, (CurrentUser, Instant)>>,
+}
+
+impl AuthState {
+    pub fn from_env(pool: Pool<SqliteConnectionManager>) -> AppResult<Arc<Self>> {
+        let provider = Provider::from_env()?;
+        match &provider {
+            Some(provider) => log::info!("Authentication enabled via {}", provider.name()),
+            None => log::warn!(
+                "No GITHUB_OAUTH_CLIENT_ID or OIDC_ISSUER_URL set, authentication is disabled"
+            ),
+        }
+        let default_role = match std::env::var("DEFAULT_ROLE").as_deref() {
+            Err(_) | Ok("") => Some(Role::Viewer),
+            Ok("none") => None,
+            Ok(role) => Some(Role::parse(role).ok_or_else(|| {
+                AppError::Config(format!(
+                    "DEFAULT_ROLE must be none, {}, not {}",
+                    authz::ROLES.join(", "),
+                    role
+                ))
+            })?),
+        };
+        Ok(Arc::new(AuthState {
+            provider,
+            public_url: std::env::var("PUBLIC_URL")
+                .unwrap_or_else(|_| "http://localhost:8080".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            http: reqwest::Client::new(),
+            pool,
+            admins: std::env::var("ADMIN_USERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|admin| !admin.is_empty())
+                .map(str::to_string)
+                .collect(),
+            default_role,
+            token_cache: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// Session cookies are only marked secure when served over HTTPS.
+    pub fn secure_cookies(&self) -> bool {
+        self.public_url.starts_with("https://")
+    }
+
+    fn redirect_uri(&self) -> String {
+        format!("{}/auth/callback", self.public_url)
+    }
+
+    /// The logged-in user of a session, if any.
+    fn session_user(&self, session: &Session) -> AppResult<Option<CurrentUser>> {
+        let Some(id) = session
+            .get::<i64>(SESSION_USER_ID)
+            .map_err(|e| AppError::Internal(e.to_string()))?
+        else {
+            return Ok(None);
+        };
+        User::get_by_id(id, &self.pool.get()?)?
+            .map(|user| self.current_user(user))
+            .transpose()
+    }
+
+    /// Resolve the roles of a user.
+    fn current_user(&self, user: User) -> AppResult<CurrentUser> {
+        let bindings = RoleBinding::get_all(&self.pool.get()?)?;
+        let mut roles = Roles::resolve(&user.login, &user.groups, &bindings, self.default_role);
+        if self
+            .admins
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(&user.login))
+        {
+            roles.grant(ALL_TEAMS, Role::Admin);
+        }
+        Ok(CurrentUser {
+            id: Some(user.id),
+            login: user.login,
+            roles,
+        })
+    }
+
+    /// The user a bearer token belongs to, asking the provider on a cache miss.
+    async fn bearer_user(&self, token: &str) -> AppResult<CurrentUser> {
+        let Some(provider) = &self.provider else {
+            return Ok(CurrentUser::anonymous());
+        };
+        if token.starts_with(API_TOKEN_PREFIX) {
+            return self.api_token_user(token);
+        }
+        let key = hash_token(token);
+        if let Ok(cache) = self.token_cache.lock() {
+            if let Some((user, at)) = cache.get(&key) {
+                if at.elapsed() < TOKEN_CACHE_TTL {
+                    return Ok(user.clone());
+                }
+            }
+        }
+
+        let identity = provider.identity(&self.http, token).await?;
+        let user = self.current_user(self.record_login(provider, identity)?)?;
+        if let Ok(mut cache) = self.token_cache.lock() {
+            cache.retain(|_, (_, at)| at.elapsed() < TOKEN_CACHE_TTL);
+            cache.insert(key, (user.clone(), Instant::now()));
+        }
+        Ok(user)
+    }
+
+    /// The owner of a personal API token, with roles limited to its scope.
+    fn api_token_user(&self, token: &str) -> AppResult<CurrentUser> {
+        let conn = self.pool.get()?;
+        let api_token = ApiToken::get_by_hash(&hash_token(token), &conn)?
+            .ok_or_else(|| AppError::Unauthorized("Unknown API token".to_string()))?;
+        let scope = TokenScope::parse(&api_token.scope, &api_token.teams).ok_or_else(|| {
+            AppError::Unauthorized(format!("Unknown API token scope {}", api_token.scope))
+        })?;
+        let owner = User::get_by_id(api_token.user_id, &conn)?
+            .ok_or_else(|| AppError::Unauthorized("API token owner not found".to_string()))?;
+        api_token.touch(&conn)?;
+        drop(conn);
+
+        let mut user = self.current_user(owner)?;
+        user.roles = user.roles.limit(&scope);
+        Ok(user)
+    }
+
+    fn record_login(&self, provider: &Provider, identity: provider::Identity) -> AppResult<User> {
+        User::upsert_login(
+            &UserEgg {
+                provider: provider.name().to_string(),
+                subject: identity.subject,
+                login: identity.login,
+                name: identity.name,
+                email: identity.email,
+                groups: identity.groups,
+            },
+            &self.pool.get()?,
+        )
+    }
+}
+
+/// A new personal API token. Only its [`hash_token`] is stored.
+pub fn generate_api_token() -> String {
+    format!(
+        "{}{}",
+        API_TOKEN_PREFIX,
+        Alphanumeric.sample_string(&mut rand::thread_rng(), 40)
+    )
+}
+
+/// SHA-256 of a bearer token, as lowercase hex.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Login, OAuth callback and logout routes.
+pub fn routes() -> Scope {
+    web::scope("/auth")
+        .service(login)
+        .service(callback)
+        .service(logout)
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    next: Option<String>,
+}
+
+/// Whether `path` stays on this site when redirected to. Browsers read `\`
+/// as `/`, so `/\evil.example` would leave it.
+pub fn is_local_path(path: &str) -> bool {
+    path.starts_with('/') && !path.starts_with("//") && !path.contains('\\')
+}
+
+/// Only same-site paths are followed after login.
+fn safe_next(next: Option<&str>) -> String {
+    next.filter(|n| is_local_path(n)).unwrap_or("/").to_string()
+}
+
+#[get("/login")]
+async fn login(
+    state: web::Data<Arc<AuthState>>,
+    session: Session,
+    query: web::Query<LoginQuery>,
+) -> AppResult<HttpResponse> {
+    let next = safe_next(query.next.as_deref());
+    let Some(provider) = &state.provider else {
+        return Ok(redirect(&next));
+    };
+
+    let login_state = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+    session
+        .insert(SESSION_LOGIN_STATE, &login_state)
+        .and_then(|_| session.insert(SESSION_LOGIN_NEXT, &next))
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let url = provider
+        .authorize_url(&state.http, &login_state, &state.redirect_uri())
+        .await?;
+    Ok(redirect(&url))
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: String,
+    state: String,
+}
+
+#[get("/callback")]
+async fn callback(
+    state: web::Data<Arc<AuthState>>,
+    session: Session,
+    query: web::Query<CallbackQuery>,
+) -> AppResult<HttpResponse> {
+    let provider = state
+        .provider
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Authentication is disabled".to_string()))?;
+    let expected = session
+        .remove_as::<String>(SESSION_LOGIN_STATE)
+        .and_then(Result::ok);
+    if expected.as_deref() != Some(query.state.as_str()) {
+        return Err(AppError::Unauthorized(
+            "Login state mismatch, please try again".to_string(),
+        ));
+    }
+    let next = session
+        .remove_as::<String>(SESSION_LOGIN_NEXT)
+        .and_then(Result::ok);
+
+    let token = provider
+        .exchange_code(&state.http, &query.code, &state.redirect_uri())
+        .await?;
+    let identity = provider.identity(&state.http, &token).await?;
+    let user = state.record_login(provider, identity)?;
+    log::info!("{} logged in", user.login);
+
+    session.renew();
+    session
+        .insert(SESSION_USER_ID, user.id)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(redirect(&safe_next(next.as_deref())))
+}
+
+#[get("/logout")]
+async fn logout(session: Session) -> HttpResponse {
+    session.purge();
+    redirect("/")
+}
+
+fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header(("Location", location))
+        .finish()
+}
+
+/// The signing key for session cookies, from `SESSION_KEY` (at least 32
+/// bytes). Without it a random key is used and sessions end on restart.
+pub fn session_key() -> AppResult<Key> {
+    match std::env::var("SESSION_KEY") {
+        Ok(secret) if secret.len() >= 64 => Key::try_from(secret.as_bytes())
+            .map_err(|e| AppError::Config(format!("Invalid SESSION_KEY: {}", e))),
+        Ok(secret) if secret.len() >= 32 => Ok(Key::derive_from(secret.as_bytes())),
+        Ok(_) => Err(AppError::Config(
+            "SESSION_KEY must be at least 32 bytes".to_string(),
+        )),
+        Err(_) => {
+            log::warn!("SESSION_KEY not set, using a random key; sessions end on restart");
+            Ok(Key::generate())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_follows_local_paths_after_login() {
+        assert_eq!(safe_next(Some("/deploy?name=api")), "/deploy?name=api");
+        assert_eq!(safe_next(Some("//evil.example")), "/");
+        assert_eq!(safe_next(Some("/\\evil.example")), "/");
+        assert_eq!(safe_next(Some("https://evil.example")), "/");
+        assert_eq!(safe_next(None), "/");
+    }
+
+    #[test]
+    fn generates_prefixed_api_tokens() {
+        let token = generate_api_token();
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(token.len(), API_TOKEN_PREFIX.len() + 40);
+        assert_ne!(token, generate_api_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_eq!(hash_token(&token).len(), 64);
+    }
+}
@@ -0,0 +1,315 @@
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+use crate::error::{AppError, AppResult};
+
+const GITHUB_AUTHORIZE_URL: &str = "https://github.com/login/oauth/authorize";
+const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// A user as described by the identity provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    /// Stable, provider-specific user id.
+    pub subject: String,
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// GitHub teams as `org/team-slug`, or the OIDC `groups` claim.
+    pub groups: Vec<String>,
+}
+
+/// The endpoints published in an OIDC discovery document.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OidcMetadata {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+pub enum Provider {
+    GitHub {
+        client_id: String,
+        client_secret: String,
+        /// Only members of this organization may log in.
+        org: Option<String>,
+    },
+    Oidc {
+        issuer: String,
+        client_id: String,
+        client_secret: String,
+        metadata: OnceCell<OidcMetadata>,
+    },
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    id: u64,
+    login: String,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubMembership {
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubTeam {
+    slug: String,
+    organization: GitHubOrg,
+}
+
+#[derive(Deserialize)]
+struct GitHubOrg {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct OidcUserInfo {
+    sub: String,
+    preferred_username: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+impl Provider {
+    /// GitHub OAuth if `GITHUB_OAUTH_CLIENT_ID` is set, otherwise OIDC if
+    /// `OIDC_ISSUER_URL` is set.
+    pub fn from_env() -> AppResult<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let required =
+            |name: &str| var(name).ok_or_else(|| AppError::Config(format!("{} must be set", name)));
+
+        if let Some(client_id) = var("GITHUB_OAUTH_CLIENT_ID") {
+            return Ok(Some(Provider::GitHub {
+                client_id,
+                client_secret: required("GITHUB_OAUTH_CLIENT_SECRET")?,
+                org: var("GITHUB_OAUTH_ORG"),
+            }));
+        }
+        if let Some(issuer) = var("OIDC_ISSUER_URL") {
+            return Ok(Some(Provider::Oidc {
+                issuer: issuer.trim_end_matches('/').to_string(),
+                client_id: required("OIDC_CLIENT_ID")?,
+                client_secret: required("OIDC_CLIENT_SECRET")?,
+                metadata: OnceCell::new(),
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Stored with each user, so the same subject from two providers is two users.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::GitHub { .. } => "github",
+            Provider::Oidc { .. } => "oidc",
+        }
+    }
+
+    async fn metadata(&self, http: &reqwest::Client) -> AppResult<Option<&OidcMetadata>> {
+        let Provider::Oidc {
+            issuer, metadata, ..
+        } = self
+        else {
+            return Ok(None);
+        };
+        let metadata = metadata
+            .get_or_try_init(|| async {
+                http.get(format!("{}/.well-known/openid-configuration", issuer))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<OidcMetadata>()
+                    .await
+            })
+            .await?;
+        Ok(Some(metadata))
+    }
+
+    /// Where to send the browser to log in.
+    pub async fn authorize_url(
+        &self,
+        http: &reqwest::Client,
+        state: &str,
+        redirect_uri: &str,
+    ) -> AppResult<String> {
+        let url = match self {
+            // read:org covers the organization check and team memberships.
+            Provider::GitHub { client_id, .. } => url::Url::parse_with_params(
+                GITHUB_AUTHORIZE_URL,
+                &[
+                    ("client_id", client_id.as_str()),
+                    ("redirect_uri", redirect_uri),
+                    ("scope", "read:user user:email read:org"),
+                    ("state", state),
+                ],
+            ),
+            Provider::Oidc { client_id, .. } => {
+                let metadata = self.metadata(http).await?.ok_or("OIDC metadata missing")?;
+                url::Url::parse_with_params(
+                    &metadata.authorization_endpoint,
+                    &[
+                        ("response_type", "code"),
+                        ("client_id", client_id.as_str()),
+                        ("redirect_uri", redirect_uri),
+                        ("scope", "openid profile email"),
+                        ("state", state),
+                    ],
+                )
+            }
+        };
+        url.map(String::from)
+            .map_err(|e| AppError::Config(format!("Invalid authorization URL: {}", e)))
+    }
+
+    /// Exchange an authorization code for an access token.
+    pub async fn exchange_code(
+        &self,
+        http: &reqwest::Client,
+        code: &str,
+        redirect_uri: &str,
+    ) -> AppResult<String> {
+        let request = match self {
+            Provider::GitHub {
+                client_id,
+                client_secret,
+                ..
+            } => http.post(GITHUB_TOKEN_URL).form(&[
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+            ]),
+            Provider::Oidc {
+                client_id,
+                client_secret,
+                ..
+            } => {
+                let metadata = self.metadata(http).await?.ok_or("OIDC metadata missing")?;
+                http.post(&metadata.token_endpoint).form(&[
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", redirect_uri),
+                    ("client_id", client_id.as_str()),
+                    ("client_secret", client_secret.as_str()),
+                ])
+            }
+        };
+
+        let response: TokenResponse = request
+            .header("Accept", "application/json")
+            .send()
+            .await?
+            .json()
+            .await?;
+        match response {
+            TokenResponse {
+                access_token: Some(token),
+                ..
+            } => Ok(token),
+            TokenResponse {
+                error,
+                error_description,
+                ..
+            } => Err(AppError::Unauthorized(format!(
+                "Token exchange failed: {}",
+                error_description
+                    .or(error)
+                    .unwrap_or_else(|| "no access token".to_string())
+            ))),
+        }
+    }
+
+    /// Resolve an access token to the user it belongs to. Fails if the token
+    /// is invalid or the user is not allowed in.
+    pub async fn identity(&self, http: &reqwest::Client, token: &str) -> AppResult<Identity> {
+        match self {
+            Provider::GitHub { org, .. } => {
+                let user: GitHubUser = github_get(http, token, "/user").await?;
+                if let Some(org) = org {
+                    let membership: Result<GitHubMembership, _> =
+                        github_get(http, token, &format!("/user/memberships/orgs/{}", org)).await;
+                    if !membership.is_ok_and(|m| m.state == "active") {
+                        return Err(AppError::Unauthorized(format!(
+                            "{} is not a member of {}",
+                            user.login, org
+                        )));
+                    }
+                }
+                // Tokens without read:org see no teams, which only costs
+                // the roles granted to teams.
+                let groups =
+                    match github_get::<Vec<GitHubTeam>>(http, token, "/user/teams?per_page=100")
+                        .await
+                    {
+                        Ok(teams) => teams
+                            .into_iter()
+                            .map(|t| format!("{}/{}", t.organization.login, t.slug))
+                            .collect(),
+                        Err(e) => {
+                            log::debug!("Failed to list teams of {}: {}", user.login, e);
+                            vec![]
+                        }
+                    };
+                Ok(Identity {
+                    subject: user.id.to_string(),
+                    login: user.login,
+                    name: user.name,
+                    email: user.email,
+                    groups,
+                })
+            }
+            Provider::Oidc { .. } => {
+                let metadata = self.metadata(http).await?.ok_or("OIDC metadata missing")?;
+                let info: OidcUserInfo = http
+                    .get(&metadata.userinfo_endpoint)
+                    .bearer_auth(token)
+                    .send()
+                    .await?
+                    .error_for_status()
+                    .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?
+                    .json()
+                    .await?;
+                Ok(Identity {
+                    login: info
+                        .preferred_username
+                        .or_else(|| info.email.clone())
+                        .unwrap_or_else(|| info.sub.clone()),
+                    subject: info.sub,
+                    name: info.name,
+                    email: info.email,
+                    groups: info.groups,
+                })
+            }
+        }
+    }
+}
+
+async fn github_get<T: serde::de::DeserializeOwned>(
+    http: &reqwest::Client,
+    token: &str,
+    path: &str,
+) -> AppResult<T> {
+    Ok(http
+        .get(format!("{}{}", GITHUB_API_URL, path))
+        .bearer_auth(token)
+        .header("User-Agent", "cicd")
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()
+        .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?
+        .json()
+        .await?)
+}
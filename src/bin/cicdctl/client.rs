@@ -0,0 +1,84 @@
+use anyhow::{anyhow, bail, Context};
+use serde_json::{json, Value};
+
+/// Calls the server's MCP tools over HTTP, the same surface agents use.
+pub struct Client {
+    http: reqwest::Client,
+    endpoint: String,
+    token: Option<String>,
+}
+
+impl Client {
+    pub fn new(server: &str, token: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            endpoint: format!("{}/mcp", server.trim_end_matches('/')),
+            token,
+        }
+    }
+
+    /// Call a tool and return its text output. Tool errors become `Err`.
+    pub async fn call(&self, tool: &str, arguments: Value) -> anyhow::Result<String> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": tool, "arguments": arguments },
+        });
+
+        let mut request = self.http.post(&self.endpoint).json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.endpoint))?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            bail!("Server returned {}: {}", status, text.trim());
+        }
+
+        let response: Value = response
+            .json()
+            .await
+            .context("Server returned invalid JSON")?;
+        if let Some(error) = response.get("error") {
+            bail!(
+                "{}",
+                error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Unknown error")
+            );
+        }
+
+        let result = response
+            .get("result")
+            .ok_or_else(|| anyhow!("Response has no result"))?;
+        let text = result
+            .get("content")
+            .and_then(Value::as_array)
+            .map(|content| {
+                content
+                    .iter()
+                    .filter_map(|c| c.get("text").and_then(Value::as_str))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+
+        if result.get("isError").and_then(Value::as_bool) == Some(true) {
+            bail!("{}", text);
+        }
+        Ok(text)
+    }
+
+    /// Call a tool whose text output is JSON.
+    pub async fn call_json(&self, tool: &str, arguments: Value) -> anyhow::Result<Value> {
+        let text = self.call(tool, arguments).await?;
+        serde_json::from_str(&text).with_context(|| format!("{} did not return JSON", tool))
+    }
+}
@@ -0,0 +1,353 @@
+//! `cicdctl`: a command-line client for the cicd server.
+//!
+//! Talks to the server's `/mcp` endpoint, so every subcommand maps onto one of
+//! the MCP tools and behaves exactly as it would for an agent.
+
+mod client;
+mod output;
+mod wait;
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+
+use client::Client;
+use wait::Health;
+
+#[derive(Parser)]
+#[command(name = "cicdctl", about = "Command-line client for the cicd server")]
+struct Cli {
+    /// Base URL of the cicd server.
+    #[arg(
+        long,
+        env = "CICD_SERVER",
+        default_value = "http://localhost:8080",
+        global = true
+    )]
+    server: String,
+
+    /// Bearer token sent with every request.
+    #[arg(long, env = "CICD_TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t = Format::Table, global = true)]
+    output: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List all deploy configs.
+    List,
+    /// Show a deploy config and the status of its resources.
+    #[command(alias = "status")]
+    Get { name: String },
+    /// Show the build status of a repository branch.
+    BuildStatus {
+        /// Repository in owner/name format.
+        repo: String,
+        /// Defaults to the repository's default branch.
+        #[arg(long)]
+        branch: Option<String>,
+    },
+    /// Deploy a config, optionally from a specific branch or SHA.
+    Deploy {
+        name: String,
+        #[arg(long, conflicts_with = "sha")]
+        branch: Option<String>,
+        #[arg(long)]
+        sha: Option<String>,
+        /// Wait for the deploy to become healthy.
+        #[arg(long)]
+        wait: bool,
+        /// Seconds to wait before giving up.
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
+    /// Undeploy a config, removing its resources.
+    Undeploy { name: String },
+    /// Restart the Deployments owned by a config.
+    Bounce { name: String },
+    /// Trigger the CronJobs owned by a config.
+    RunJob { name: String },
+    /// Toggle autodeploy on a config.
+    Autodeploy { name: String },
+    /// Pause or resume cleanup of an orphaned config.
+    Keep { name: String },
+    /// Print recent logs from a config's workloads.
+    Logs {
+        name: String,
+        /// Only this child resource.
+        #[arg(long)]
+        resource: Option<String>,
+        /// Lines per pod.
+        #[arg(long, default_value_t = 100)]
+        tail: u64,
+    },
+    /// Show recent deploys of a config.
+    History {
+        name: String,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Wait for a config's resources to become healthy.
+    Wait {
+        name: String,
+        /// Seconds to wait before giving up.
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
+}
+
+/// Exit code when `--wait`/`wait` gives up before the config is healthy.
+const EXIT_UNHEALTHY: u8 = 2;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let client = Client::new(&cli.server, cli.token.clone());
+
+    match run(&cli, &client).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: &Cli, client: &Client) -> anyhow::Result<ExitCode> {
+    match &cli.command {
+        Command::List => {
+            let configs = client.call_json("list_deploy_configs", json!({})).await?;
+            print_value(cli.output, &configs, |configs| {
+                let rows: Vec<Vec<String>> = configs
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|c| {
+                        vec![
+                            output::field(c, "name"),
+                            output::field(c, "team"),
+                            output::field(c, "kind"),
+                            output::field(c, "state"),
+                            output::field(c, "autodeploy"),
+                            output::version(c, "artifact_sha", "artifact_branch"),
+                        ]
+                    })
+                    .collect();
+                output::table(
+                    &["NAME", "TEAM", "KIND", "STATE", "AUTODEPLOY", "ARTIFACT"],
+                    &rows,
+                )
+            });
+        }
+        Command::Get { name } => {
+            let config = client
+                .call_json("get_deploy_config", json!({ "name": name }))
+                .await?;
+            print_value(cli.output, &config, format_config);
+        }
+        Command::BuildStatus { repo, branch } => {
+            let status = client
+                .call_json(
+                    "get_build_status",
+                    json!({ "repo": repo, "branch": branch }),
+                )
+                .await?;
+            print_value(cli.output, &status, |s| {
+                output::table(
+                    &["REPO", "BRANCH", "HEAD", "STATUS"],
+                    &[vec![
+                        output::field(s, "repo"),
+                        output::field(s, "branch"),
+                        output::field(s, "head_sha"),
+                        output::field(s, "build_status"),
+                    ]],
+                )
+            });
+        }
+        Command::Deploy {
+            name,
+            branch,
+            sha,
+            wait,
+            timeout,
+        } => {
+            let message = client
+                .call(
+                    "deploy",
+                    json!({ "name": name, "branch": branch, "sha": sha }),
+                )
+                .await?;
+            print_message(cli.output, &message);
+            if *wait {
+                return wait_healthy(cli.output, client, name, *timeout).await;
+            }
+        }
+        Command::Undeploy { name } => action(cli.output, client, "undeploy", name).await?,
+        Command::Bounce { name } => action(cli.output, client, "bounce", name).await?,
+        Command::RunJob { name } => action(cli.output, client, "execute_job", name).await?,
+        Command::Autodeploy { name } => {
+            action(cli.output, client, "toggle_autodeploy", name).await?
+        }
+        Command::Keep { name } => action(cli.output, client, "toggle_orphan_keep", name).await?,
+        Command::Logs {
+            name,
+            resource,
+            tail,
+        } => {
+            let logs = client
+                .call(
+                    "get_logs",
+                    json!({ "name": name, "resource": resource, "tail_lines": tail }),
+                )
+                .await?;
+            print_message(cli.output, &logs);
+        }
+        Command::History { name, limit } => {
+            let events = client
+                .call_json(
+                    "get_deploy_history",
+                    json!({ "name": name, "limit": limit }),
+                )
+                .await?;
+            print_value(cli.output, &events, |events| {
+                let rows: Vec<Vec<String>> = events
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|e| {
+                        vec![
+                            e.get("timestamp")
+                                .and_then(Value::as_i64)
+                                .map(output::timestamp)
+                                .unwrap_or_else(|| "-".to_string()),
+                            output::field(e, "initiator"),
+                            output::version(e, "artifact_sha", "artifact_branch"),
+                            output::version(e, "config_sha", "config_branch"),
+                        ]
+                    })
+                    .collect();
+                output::table(&["TIME", "INITIATOR", "ARTIFACT", "CONFIG"], &rows)
+            });
+        }
+        Command::Wait { name, timeout } => {
+            return wait_healthy(cli.output, client, name, *timeout).await;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn action(format: Format, client: &Client, tool: &str, name: &str) -> anyhow::Result<()> {
+    let message = client.call(tool, json!({ "name": name })).await?;
+    print_message(format, &message);
+    Ok(())
+}
+
+async fn wait_healthy(
+    format: Format,
+    client: &Client,
+    name: &str,
+    timeout: u64,
+) -> anyhow::Result<ExitCode> {
+    let health = wait::wait_for_healthy(client, name, Duration::from_secs(timeout)).await?;
+    let (healthy, state, problems) = match &health {
+        Health::Healthy => (true, "healthy", vec![]),
+        Health::Progressing(problems) => (false, "progressing", problems.clone()),
+        Health::Failing(problems) => (false, "failing", problems.clone()),
+    };
+
+    match format {
+        Format::Json => println!(
+            "{}",
+            json!({ "name": name, "healthy": healthy, "state": state, "problems": problems })
+        ),
+        Format::Table if healthy => println!("{} is healthy", name),
+        Format::Table => {
+            println!("{} is still {} after {}s", name, state, timeout);
+            for problem in problems {
+                println!("  {}", problem);
+            }
+        }
+    }
+
+    Ok(if healthy {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_UNHEALTHY)
+    })
+}
+
+fn print_value(format: Format, value: &Value, table: impl FnOnce(&Value) -> String) {
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        ),
+        Format::Table => println!("{}", table(value)),
+    }
+}
+
+fn print_message(format: Format, message: &str) {
+    match format {
+        Format::Json => println!("{}", json!({ "message": message })),
+        Format::Table => println!("{}", message),
+    }
+}
+
+fn format_config(config: &Value) -> String {
+    let mut lines = vec![
+        format!("Name:        {}", output::field(config, "name")),
+        format!("Namespace:   {}", output::field(config, "namespace")),
+        format!("Team:        {}", output::field(config, "team")),
+        format!("Kind:        {}", output::field(config, "kind")),
+        format!("State:       {}", output::field(config, "state")),
+        format!("Autodeploy:  {}", output::field(config, "autodeploy")),
+        format!(
+            "Artifact:    {} {}",
+            output::field(config, "artifact_repo"),
+            output::version(config, "artifact_sha", "artifact_branch")
+        ),
+        format!(
+            "Config:      {} {}",
+            output::field(config, "config_repo"),
+            output::version(config, "config_sha", "config_branch")
+        ),
+    ];
+    if config.get("orphaned").and_then(Value::as_bool) == Some(true) {
+        lines.push(format!(
+            "Orphaned:    {}{}",
+            output::field(config, "orphan_reason"),
+            if config.get("orphan_kept").and_then(Value::as_bool) == Some(true) {
+                " (kept)"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    let resources = config
+        .get("resources")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    if !resources.is_empty() {
+        lines.push(String::new());
+        lines.push("Resources:".to_string());
+        lines.push(output::resource_tree(&resources));
+    }
+    lines.join("\n")
+}
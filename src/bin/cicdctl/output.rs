@@ -0,0 +1,130 @@
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+
+/// Render rows as a left-aligned table with a header.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            rows.iter()
+                .filter_map(|r| r.get(i))
+                .map(|c| c.chars().count())
+                .chain(std::iter::once(h.len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:<w$}", c, w = w))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    std::iter::once(line(headers.to_vec()))
+        .chain(
+            rows.iter()
+                .map(|r| line(r.iter().map(String::as_str).collect())),
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A string field, or `-` when missing or null.
+pub fn field(value: &Value, key: &str) -> String {
+    match value.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Bool(b)) => b.to_string(),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+/// `branch@sha7`, or whichever half is present.
+pub fn version(value: &Value, sha_key: &str, branch_key: &str) -> String {
+    let sha = value
+        .get(sha_key)
+        .and_then(Value::as_str)
+        .map(|s| s.chars().take(7).collect::<String>());
+    let branch = value.get(branch_key).and_then(Value::as_str);
+    match (branch, sha) {
+        (Some(branch), Some(sha)) => format!("{}@{}", branch, sha),
+        (None, Some(sha)) => sha,
+        (Some(branch), None) => branch.to_string(),
+        (None, None) => "-".to_string(),
+    }
+}
+
+pub fn timestamp(millis: i64) -> String {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Indented resource tree from `get_deploy_config`'s `resources`.
+pub fn resource_tree(resources: &[Value]) -> String {
+    fn walk(resource: &Value, depth: usize, out: &mut Vec<String>) {
+        let status = resource
+            .get("status_summary")
+            .and_then(Value::as_str)
+            .map(|s| format!(" ({})", s))
+            .unwrap_or_default();
+        out.push(format!(
+            "{}{} {}{}",
+            "  ".repeat(depth),
+            field(resource, "kind"),
+            field(resource, "name"),
+            status
+        ));
+        for child in resource
+            .get("children")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            walk(child, depth + 1, out);
+        }
+    }
+
+    let mut out = vec![];
+    for resource in resources {
+        walk(resource, 0, &mut out);
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn table_pads_columns() {
+        let rendered = table(
+            &["NAME", "TEAM"],
+            &[
+                vec!["api".to_string(), "platform".to_string()],
+                vec!["worker-long".to_string(), "-".to_string()],
+            ],
+        );
+        assert_eq!(
+            rendered,
+            "NAME         TEAM\napi          platform\nworker-long  -"
+        );
+    }
+
+    #[test]
+    fn version_combines_branch_and_short_sha() {
+        let value = json!({ "sha": "0123456789abcdef", "branch": "main" });
+        assert_eq!(version(&value, "sha", "branch"), "main@0123456");
+        assert_eq!(version(&json!({}), "sha", "branch"), "-");
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::client::Client;
+
+/// How often the config is polled while waiting.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Consecutive healthy polls required, so a rollout the controller hasn't
+/// started yet isn't mistaken for a finished one.
+const HEALTHY_POLLS: u32 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum Health {
+    Healthy,
+    /// Resources still rolling out or otherwise not ready.
+    Progressing(Vec<String>),
+    /// Resources reporting errors (crash loops, failed jobs, ...).
+    Failing(Vec<String>),
+}
+
+/// Summarise the resource tree from `get_deploy_config`.
+pub fn health(resources: &[Value]) -> Health {
+    fn walk(resource: &Value, warn: &mut Vec<String>, error: &mut Vec<String>) {
+        let describe = || {
+            format!(
+                "{} {}: {}",
+                resource.get("kind").and_then(Value::as_str).unwrap_or("?"),
+                resource.get("name").and_then(Value::as_str).unwrap_or("?"),
+                resource
+                    .get("status_summary")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown")
+            )
+        };
+        match resource.get("status_level").and_then(Value::as_str) {
+            Some("error") => error.push(describe()),
+            Some("warn") => warn.push(describe()),
+            _ => {}
+        }
+        for child in resource
+            .get("children")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            walk(child, warn, error);
+        }
+    }
+
+    let mut warn = vec![];
+    let mut error = vec![];
+    for resource in resources {
+        walk(resource, &mut warn, &mut error);
+    }
+
+    if !error.is_empty() {
+        Health::Failing(error)
+    } else if !warn.is_empty() {
+        Health::Progressing(warn)
+    } else {
+        Health::Healthy
+    }
+}
+
+/// Poll `name` until it is healthy or `timeout` elapses.
+pub async fn wait_for_healthy(
+    client: &Client,
+    name: &str,
+    timeout: Duration,
+) -> anyhow::Result<Health> {
+    let deadline = Instant::now() + timeout;
+    let mut healthy_polls = 0;
+
+    loop {
+        let config = client
+            .call_json("get_deploy_config", json!({ "name": name }))
+            .await?;
+        let resources = config
+            .get("resources")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+
+        let current = health(&resources);
+        match &current {
+            Health::Healthy => {
+                healthy_polls += 1;
+                if healthy_polls >= HEALTHY_POLLS {
+                    return Ok(current);
+                }
+            }
+            Health::Progressing(waiting) | Health::Failing(waiting) => {
+                healthy_polls = 0;
+                eprintln!("Waiting for {}: {}", name, waiting.join("; "));
+            }
+        }
+
+        if Instant::now() + POLL_INTERVAL > deadline {
+            return Ok(current);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn resource(level: &str, children: Vec<Value>) -> Value {
+        json!({
+            "kind": "Deployment",
+            "name": "web",
+            "status_summary": "summary",
+            "status_level": level,
+            "children": children,
+        })
+    }
+
+    #[test]
+    fn neutral_tree_is_healthy() {
+        let tree = vec![resource("neutral", vec![resource("muted", vec![])])];
+        assert_eq!(health(&tree), Health::Healthy);
+    }
+
+    #[test]
+    fn errors_anywhere_in_the_tree_win_over_warnings() {
+        let tree = vec![
+            resource("warn", vec![]),
+            resource("neutral", vec![resource("error", vec![])]),
+        ];
+        assert_eq!(
+            health(&tree),
+            Health::Failing(vec!["Deployment web: summary".to_string()])
+        );
+        assert!(matches!(
+            health(&[resource("warn", vec![])]),
+            Health::Progressing(_)
+        ));
+    }
+}
@@ -55,6 +55,17 @@ impl BuildStatus {
             Some(_) => BuildStatus::None,
         }
     }
+
+    /// Map a commit status `state` from the older Statuses API, whose
+    /// statuses have no separate conclusion.
+    pub fn from_commit_state(state: &str) -> Self {
+        match state {
+            "pending" => BuildStatus::Pending,
+            "success" => BuildStatus::Success,
+            "failure" | "error" => BuildStatus::Failure,
+            _ => BuildStatus::None,
+        }
+    }
 }
 
 impl From<BuildStatus> for String {
@@ -1,48 +1,508 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use octocrab::models::{AppId, InstallationId};
 use octocrab::Octocrab;
+use opentelemetry::KeyValue;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::db::github_token_access::GitHubTokenAccess;
+use crate::error::{AppError, AppResult};
 
 pub trait IRepo {
     fn owner(&self) -> &str;
     fn repo(&self) -> &str;
 }
 
-pub type Octocrabs = Vec<Octocrab>;
+/// A GitHub client and what it authenticates as.
+#[derive(Clone)]
+pub struct GitHubClient {
+    /// Names the client in logs, metrics and the database: `pat-<hash>` for
+    /// personal access tokens, `app-<account>` for app installations.
+    pub label: String,
+    pub crab: Octocrab,
+    /// The app installation the client acts as, if not a personal token.
+    pub installation: Option<u64>,
+}
 
-pub trait OctocrabExt {
-    async fn crab_for<T: IRepo>(&self, repo: &T) -> Option<&Octocrab>;
+#[derive(Default)]
+struct Installations {
+    clients: BTreeMap<u64, GitHubClient>,
+    /// `owner/repo`, lowercased, to the installation that can access it.
+    repos: HashMap<String, u64>,
 }
 
-impl OctocrabExt for Octocrabs {
-    async fn crab_for<T: IRepo>(&self, repo: &T) -> Option<&Octocrab> {
-        if self.len() == 1 {
-            return Some(&self[0]);
+/// The GitHub clients: a GitHub App's installations when `GITHUB_APP_ID` and
+/// `GITHUB_APP_PRIVATE_KEY` are set, and the personal access tokens in
+/// `GITHUB_PATS`. Which of them can access a repo is remembered in the
+/// database, and the installation (or else token) with the most quota left
+/// is used.
+#[derive(Clone, Default)]
+pub struct Octocrabs {
+    /// Authenticated as the app itself, to look up and act as installations.
+    app: Option<Octocrab>,
+    installations: Arc<RwLock<Installations>>,
+    pats: Vec<GitHubClient>,
+    /// Where repo access is remembered; without it, it is looked up each time.
+    cache: Option<Pool<SqliteConnectionManager>>,
+    /// Remaining core rate limit by client label, as last polled.
+    quotas: Arc<RwLock<HashMap<String, u64>>>,
+}
+
+/// How long which clients can access a repo is remembered, so tokens granted
+/// access later are picked up.
+const ACCESS_TTL_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Deserialize)]
+struct InstallationRepos {
+    repositories: Vec<InstallationRepo>,
+}
+
+#[derive(Deserialize)]
+struct InstallationRepo {
+    full_name: String,
+}
+
+fn repo_key(owner: &str, repo: &str) -> String {
+    format!("{}/{}", owner, repo).to_lowercase()
+}
+
+/// A personal access token's label: a prefix of its SHA-256, which stays the
+/// same however `GITHUB_PATS` is reordered and doesn't reveal the token.
+fn pat_label(token: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(token.as_bytes()));
+    format!("pat-{}", &hash[..8])
+}
+
+impl Octocrabs {
+    /// `pats` are the personal token clients by label.
+    pub fn new(app: Option<Octocrab>, pats: Vec<(String, Octocrab)>) -> Self {
+        Self {
+            app,
+            installations: Default::default(),
+            pats: pats
+                .into_iter()
+                .map(|(label, crab)| GitHubClient {
+                    label,
+                    crab,
+                    installation: None,
+                })
+                .collect(),
+            cache: None,
+            quotas: Default::default(),
+        }
+    }
+
+    pub fn with_cache(mut self, pool: Pool<SqliteConnectionManager>) -> Self {
+        self.cache = Some(pool);
+        self
+    }
+
+    /// Record a client's remaining rate limit, for choosing between clients.
+    pub fn record_quota(&self, label: &str, remaining: u64) {
+        if let Ok(mut quotas) = self.quotas.write() {
+            quotas.insert(label.to_string(), remaining);
+        }
+    }
+
+    /// Of the clients able to access a repo, the installation with the most
+    /// quota left, or the personal token with the most without one; the
+    /// first one while none has been polled.
+    fn choose(&self, eligible: Vec<GitHubClient>) -> Option<GitHubClient> {
+        let quotas = self.quotas.read().ok()?;
+        let (installations, pats): (Vec<_>, Vec<_>) = eligible
+            .into_iter()
+            .partition(|client| client.installation.is_some());
+        let candidates = if installations.is_empty() {
+            pats
+        } else {
+            installations
+        };
+        candidates
+            .into_iter()
+            .min_by_key(|client| Reverse(quotas.get(&client.label).copied()))
+    }
+
+    /// Every client: the known installations, then the personal tokens.
+    pub fn clients(&self) -> Vec<GitHubClient> {
+        let mut clients: Vec<GitHubClient> = match self.installations.read() {
+            Ok(installations) => installations.clients.values().cloned().collect(),
+            Err(_) => vec![],
+        };
+        clients.extend(self.pats.iter().cloned());
+        clients
+    }
+
+    /// Add an installation's client, reusing the existing one so its cached
+    /// token is kept.
+    fn add_installation(&self, id: u64, account: &str) -> AppResult<GitHubClient> {
+        let app = self
+            .app
+            .as_ref()
+            .ok_or_else(|| AppError::Config("No GitHub App configured".to_string()))?;
+        let mut installations = self
+            .installations
+            .write()
+            .map_err(|_| AppError::Internal("Installations lock poisoned".to_string()))?;
+        if let Some(client) = installations.clients.get(&id) {
+            return Ok(client.clone());
+        }
+        let client = GitHubClient {
+            label: format!("app-{}", account.to_lowercase()),
+            crab: app.installation(InstallationId(id))?,
+            installation: Some(id),
+        };
+        installations.clients.insert(id, client.clone());
+        Ok(client)
+    }
+
+    fn map_repo(&self, full_name: &str, installation: u64) {
+        if let Ok(mut installations) = self.installations.write() {
+            installations
+                .repos
+                .insert(full_name.to_lowercase(), installation);
+        }
+    }
+
+    /// Fetch the app's installations and the repos each can access. Repos
+    /// added later are looked up when first used.
+    pub async fn load_installations(&self) -> AppResult<()> {
+        let Some(app) = &self.app else {
+            return Ok(());
+        };
+        let mut page = app.apps().installations().per_page(100).send().await?;
+        loop {
+            for installation in &page.items {
+                let client =
+                    self.add_installation(installation.id.0, &installation.account.login)?;
+                let mut page_num = 1u32;
+                loop {
+                    let route =
+                        format!("/installation/repositories?per_page=100&page={}", page_num);
+                    let repos = client
+                        .crab
+                        .get::<InstallationRepos, _, ()>(&route, None)
+                        .await?
+                        .repositories;
+                    for repo in &repos {
+                        self.map_repo(&repo.full_name, installation.id.0);
+                    }
+                    if repos.len() < 100 {
+                        break;
+                    }
+                    page_num += 1;
+                }
+                log::info!(
+                    "GitHub App installation {} ({}) loaded",
+                    installation.id,
+                    client.label
+                );
+            }
+            match app.get_page(&page.next).await? {
+                Some(next) => page = next,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// The installation client for a repo, asking GitHub which installation
+    /// covers it when not known yet.
+    async fn installation_for<T: IRepo>(&self, repo: &T) -> Option<GitHubClient> {
+        let key = repo_key(repo.owner(), repo.repo());
+        let known = self.installations.read().ok().and_then(|installations| {
+            let id = installations.repos.get(&key)?;
+            installations.clients.get(id).cloned()
+        });
+        if known.is_some() {
+            return known;
+        }
+
+        let app = self.app.as_ref()?;
+        let installation = app
+            .apps()
+            .get_repository_installation(repo.owner(), repo.repo())
+            .await
+            .ok()?;
+        match self.add_installation(installation.id.0, &installation.account.login) {
+            Ok(client) => {
+                self.map_repo(&key, installation.id.0);
+                Some(client)
+            }
+            Err(e) => {
+                log::warn!("Failed to use installation {}: {}", installation.id, e);
+                None
+            }
+        }
+    }
+
+    /// The clients recently remembered to access a repo, if any of them
+    /// still exist.
+    fn cached(&self, key: &str) -> Option<Vec<GitHubClient>> {
+        let conn = self.cache.as_ref()?.get().ok()?;
+        let since = chrono::Utc::now().timestamp_millis() - ACCESS_TTL_MS;
+        let labels = GitHubTokenAccess::get_tokens(key, since, &conn).ok()?;
+        let clients = self.clients();
+        let cached: Vec<GitHubClient> = labels
+            .iter()
+            .filter_map(|label| clients.iter().find(|c| &c.label == label).cloned())
+            .collect();
+        (!cached.is_empty()).then_some(cached)
+    }
+
+    /// Every client that can access a repo: its app installation, and the
+    /// personal tokens that can see it.
+    async fn probe<T: IRepo>(&self, repo: &T) -> Vec<GitHubClient> {
+        let mut eligible: Vec<GitHubClient> =
+            self.installation_for(repo).await.into_iter().collect();
+        if self.app.is_none() && self.pats.len() == 1 {
+            eligible.push(self.pats[0].clone());
+            return eligible;
         }
 
-        for crab in self {
-            let result = crab.repos(repo.owner(), repo.repo()).get().await;
+        for client in &self.pats {
+            let result = client.crab.repos(repo.owner(), repo.repo()).get().await;
 
             if result.is_ok() {
-                return Some(crab);
+                eligible.push(client.clone());
+            }
+        }
+
+        eligible
+    }
+
+    /// The client to use for a repo, chosen among those remembered or found
+    /// to access it.
+    pub async fn client_for<T: IRepo>(&self, repo: &T) -> Option<GitHubClient> {
+        let key = repo_key(repo.owner(), repo.repo());
+        let m = crate::metrics::get();
+        let eligible = match self.cached(&key) {
+            Some(cached) => {
+                m.github_token_cache
+                    .add(1, &[KeyValue::new("result", "hit")]);
+                cached
+            }
+            None => {
+                m.github_token_cache
+                    .add(1, &[KeyValue::new("result", "miss")]);
+                let eligible = self.probe(repo).await;
+                if let Some(pool) = &self.cache {
+                    let labels: Vec<String> = eligible.iter().map(|c| c.label.clone()).collect();
+                    let now = chrono::Utc::now().timestamp_millis();
+                    if let Err(e) = pool
+                        .get()
+                        .map_err(AppError::from)
+                        .and_then(|conn| GitHubTokenAccess::replace(&key, &labels, now, &conn))
+                    {
+                        log::warn!("Failed to remember GitHub access to {}: {}", key, e);
+                    }
+                }
+                eligible
+            }
+        };
+
+        let chosen = self.choose(eligible)?;
+        m.github_token_chosen
+            .add(1, &[KeyValue::new("token", chosen.label.clone())]);
+        Some(chosen)
+    }
+
+    /// Forget which clients can access a repo, so the next use looks again.
+    pub fn invalidate<T: IRepo>(&self, repo: &T) {
+        let key = repo_key(repo.owner(), repo.repo());
+        if let Ok(mut installations) = self.installations.write() {
+            installations.repos.remove(&key);
+        }
+        if let Some(conn) = self.cache.as_ref().and_then(|pool| pool.get().ok()) {
+            if let Err(e) = GitHubTokenAccess::delete_for_repo(&key, &conn) {
+                log::warn!("Failed to forget GitHub access to {}: {}", key, e);
             }
         }
+    }
+
+    /// Pass an error from a request about `repo` through, forgetting the
+    /// repo's clients when it says access was lost (401, 403 or 404).
+    pub fn invalidate_on_error<T: IRepo>(
+        &self,
+        repo: &T,
+        error: octocrab::Error,
+    ) -> octocrab::Error {
+        if let octocrab::Error::GitHub { source, .. } = &error {
+            if matches!(source.status_code.as_u16(), 401 | 403 | 404) {
+                log::info!(
+                    "GitHub access to {}/{} failed ({}), looking up its tokens again",
+                    repo.owner(),
+                    repo.repo(),
+                    source.status_code
+                );
+                self.invalidate(repo);
+            }
+        }
+        error
+    }
+}
 
-        None
+pub trait OctocrabExt {
+    async fn crab_for<T: IRepo>(&self, repo: &T) -> Option<Octocrab>;
+}
+
+impl OctocrabExt for Octocrabs {
+    async fn crab_for<T: IRepo>(&self, repo: &T) -> Option<Octocrab> {
+        self.client_for(repo).await.map(|client| client.crab)
     }
 }
 
+/// The app client from `GITHUB_APP_ID` and `GITHUB_APP_PRIVATE_KEY` (the PEM
+/// itself) or `GITHUB_APP_PRIVATE_KEY_PATH`.
+fn initialize_app() -> Option<Octocrab> {
+    let app_id: u64 = std::env::var("GITHUB_APP_ID").ok()?.parse().ok()?;
+    let key = match std::env::var("GITHUB_APP_PRIVATE_KEY") {
+        Ok(key) => key,
+        Err(_) => {
+            let path = std::env::var("GITHUB_APP_PRIVATE_KEY_PATH").ok()?;
+            #[allow(clippy::expect_used)]
+            std::fs::read_to_string(&path).expect("Failed to read GITHUB_APP_PRIVATE_KEY_PATH")
+        }
+    };
+    #[allow(clippy::expect_used)]
+    let key = jsonwebtoken::EncodingKey::from_rsa_pem(key.as_bytes())
+        .expect("Invalid GitHub App private key");
+    #[allow(clippy::expect_used)]
+    Some(
+        Octocrab::builder()
+            .app(AppId(app_id), key)
+            .build()
+            .expect("Failed to build Octocrab client for the GitHub App"),
+    )
+}
+
 pub fn initialize_octocrabs() -> Octocrabs {
-    let github_pats = std::env::var("GITHUB_PATS").ok();
-    let Some(github_pats) = github_pats else {
-        return vec![];
+    let pats = match std::env::var("GITHUB_PATS") {
+        Ok(github_pats) => github_pats
+            .split(',')
+            .map(|pat| {
+                #[allow(clippy::expect_used)]
+                let crab = Octocrab::builder()
+                    .personal_token(pat)
+                    .build()
+                    .expect("Failed to build Octocrab client - invalid GitHub PAT");
+                (pat_label(pat), crab)
+            })
+            .collect(),
+        Err(_) => vec![],
     };
 
-    github_pats
-        .split(',')
-        .map(|pat| {
-            #[allow(clippy::expect_used)]
-            Octocrab::builder()
-                .personal_token(pat)
-                .build()
-                .expect("Failed to build Octocrab client - invalid GitHub PAT")
-        })
-        .collect()
+    Octocrabs::new(initialize_app(), pats)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    struct Repo(&'static str, &'static str);
+
+    impl IRepo for Repo {
+        fn owner(&self) -> &str {
+            self.0
+        }
+        fn repo(&self) -> &str {
+            self.1
+        }
+    }
+
+    fn pats(labels: &[&str]) -> Vec<(String, Octocrab)> {
+        labels
+            .iter()
+            .map(|label| (label.to_string(), Octocrab::default()))
+            .collect()
+    }
+
+    #[test]
+    fn labels_tokens_by_hash() {
+        assert_eq!(pat_label("ghp_one"), pat_label("ghp_one"));
+        assert_ne!(pat_label("ghp_one"), pat_label("ghp_two"));
+        assert!(pat_label("ghp_one").starts_with("pat-"));
+        assert!(!pat_label("ghp_one").contains("ghp"));
+    }
+
+    #[tokio::test]
+    async fn prefers_installations_over_tokens() {
+        let _ = crate::metrics::init(&prometheus::Registry::new());
+        let octocrabs = Octocrabs::new(None, pats(&["pat-a"]));
+        {
+            let mut installations = octocrabs.installations.write().unwrap();
+            installations.clients.insert(
+                7,
+                GitHubClient {
+                    label: "app-acme".to_string(),
+                    crab: Octocrab::default(),
+                    installation: Some(7),
+                },
+            );
+            installations.repos.insert(repo_key("acme", "api"), 7);
+        }
+
+        let client = octocrabs.client_for(&Repo("Acme", "API")).await.unwrap();
+        assert_eq!(client.label, "app-acme");
+        assert_eq!(client.installation, Some(7));
+        // Whatever quota the personal token has left.
+        octocrabs.record_quota("app-acme", 10);
+        octocrabs.record_quota("pat-a", 5000);
+        let client = octocrabs.client_for(&Repo("acme", "api")).await.unwrap();
+        assert_eq!(client.label, "app-acme");
+        let client = octocrabs.client_for(&Repo("other", "web")).await.unwrap();
+        assert_eq!(client.label, "pat-a");
+        assert_eq!(
+            octocrabs
+                .clients()
+                .iter()
+                .map(|c| c.label.as_str())
+                .collect::<Vec<_>>(),
+            vec!["app-acme", "pat-a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn chooses_remembered_tokens_by_quota() {
+        let _ = crate::metrics::init(&prometheus::Registry::new());
+        let pool = crate::db::test_pool();
+        let octocrabs =
+            Octocrabs::new(None, pats(&["pat-a", "pat-b", "pat-c"])).with_cache(pool.clone());
+        let now = chrono::Utc::now().timestamp_millis();
+        GitHubTokenAccess::replace(
+            "acme/api",
+            &["pat-a".to_string(), "pat-c".to_string()],
+            now,
+            &pool.get().unwrap(),
+        )
+        .unwrap();
+        // Checked too long ago to be trusted.
+        GitHubTokenAccess::replace(
+            "acme/web",
+            &["pat-b".to_string()],
+            now - ACCESS_TTL_MS - 1,
+            &pool.get().unwrap(),
+        )
+        .unwrap();
+        assert!(octocrabs.cached("acme/web").is_none());
+
+        let repo = Repo("acme", "api");
+        assert_eq!(octocrabs.client_for(&repo).await.unwrap().label, "pat-a");
+        octocrabs.record_quota("pat-a", 10);
+        octocrabs.record_quota("pat-b", 5000);
+        octocrabs.record_quota("pat-c", 4000);
+        assert_eq!(octocrabs.client_for(&repo).await.unwrap().label, "pat-c");
+
+        octocrabs.invalidate(&repo);
+        assert!(
+            GitHubTokenAccess::get_tokens("acme/api", 0, &pool.get().unwrap())
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
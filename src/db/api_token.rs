@@ -0,0 +1,128 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+
+/// A personal API token. Only the SHA-256 of the token is stored; the token
+/// itself is shown once, when it is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub token_hash: String,
+    /// `read`, `deploy` or `admin`
+    pub scope: String,
+    /// The teams a `deploy` token may deploy to.
+    pub teams: Vec<String>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+pub struct ApiTokenEgg {
+    pub user_id: i64,
+    pub name: String,
+    pub token_hash: String,
+    pub scope: String,
+    pub teams: Vec<String>,
+}
+
+/// `last_used_at` is only written when it is older than this, so busy
+/// clients don't write on every request.
+const LAST_USED_RESOLUTION_MS: i64 = 60 * 1000;
+
+const COLUMNS: &str = "id, user_id, name, token_hash, scope, teams, created_at, last_used_at";
+
+impl ApiToken {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let teams: String = row.get(5)?;
+        Ok(ApiToken {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            name: row.get(2)?,
+            token_hash: row.get(3)?,
+            scope: row.get(4)?,
+            teams: teams
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+            created_at: row.get(6)?,
+            last_used_at: row.get(7)?,
+        })
+    }
+
+    pub fn get_by_hash(
+        token_hash: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let token = conn
+            .prepare(&format!(
+                "SELECT {} FROM api_token WHERE token_hash = ?1",
+                COLUMNS
+            ))?
+            .query_row(params![token_hash], ApiToken::from_row)
+            .optional()?;
+
+        Ok(token)
+    }
+
+    pub fn get_by_user(
+        user_id: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let tokens = conn
+            .prepare(&format!(
+                "SELECT {} FROM api_token WHERE user_id = ?1 ORDER BY id",
+                COLUMNS
+            ))?
+            .query_map(params![user_id], ApiToken::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(tokens)
+    }
+
+    pub fn insert(
+        egg: &ApiTokenEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        conn.prepare("INSERT INTO api_token (user_id, name, token_hash, scope, teams, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?
+            .execute(params![
+                egg.user_id,
+                egg.name,
+                egg.token_hash,
+                egg.scope,
+                egg.teams.join(","),
+                chrono::Utc::now().timestamp_millis()
+            ])?;
+
+        Ok(())
+    }
+
+    /// Revoke one of a user's tokens. Returns the revoked token, if it existed.
+    pub fn delete(
+        id: i64,
+        user_id: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let token = conn
+            .prepare(&format!(
+                "DELETE FROM api_token WHERE id = ?1 AND user_id = ?2 RETURNING {}",
+                COLUMNS
+            ))?
+            .query_row(params![id, user_id], ApiToken::from_row)
+            .optional()?;
+
+        Ok(token)
+    }
+
+    pub fn touch(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        let now = chrono::Utc::now().timestamp_millis();
+        conn.prepare(
+            "UPDATE api_token SET last_used_at = ?2 WHERE id = ?1 AND (last_used_at IS NULL OR last_used_at < ?3)",
+        )?
+        .execute(params![self.id, now, now - LAST_USED_RESOLUTION_MS])?;
+
+        Ok(())
+    }
+}
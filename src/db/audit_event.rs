@@ -0,0 +1,143 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+use serde::Serialize;
+
+/// A mutating action: who did what to which target, from where, and how it
+/// went.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub timestamp: i64,
+    /// A user login, or the name of an automated process.
+    pub actor: String,
+    /// `ui`, `mcp`, `api`, `webhook` or `automation`
+    pub source: String,
+    pub action: String,
+    /// A deploy config, repository or setting.
+    pub target: String,
+    pub params: serde_json::Value,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+pub struct AuditEventEgg {
+    pub actor: String,
+    pub source: String,
+    pub action: String,
+    pub target: String,
+    pub params: serde_json::Value,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Filters for [`AuditEvent::search`]; `None` matches everything.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub source: Option<String>,
+    pub action: Option<String>,
+    /// Matches targets containing this text.
+    pub target: Option<String>,
+    pub success: Option<bool>,
+    /// Only events at or after this timestamp (ms).
+    pub since: Option<i64>,
+}
+
+impl AuditEvent {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let params: String = row.get(6)?;
+        Ok(AuditEvent {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            actor: row.get(2)?,
+            source: row.get(3)?,
+            action: row.get(4)?,
+            target: row.get(5)?,
+            params: serde_json::from_str(&params).unwrap_or(serde_json::Value::Null),
+            success: row.get(7)?,
+            error: row.get(8)?,
+        })
+    }
+
+    pub fn insert(
+        egg: &AuditEventEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        conn.prepare("INSERT INTO audit_event (timestamp, actor, source, action, target, params, success, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?
+            .execute(params![
+                chrono::Utc::now().timestamp_millis(),
+                egg.actor,
+                egg.source,
+                egg.action,
+                egg.target,
+                egg.params.to_string(),
+                egg.success,
+                egg.error
+            ])?;
+
+        Ok(())
+    }
+
+    /// The most recent events matching `filter`, newest first.
+    pub fn search(
+        filter: &AuditFilter,
+        limit: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let events = conn
+            .prepare(
+                "SELECT id, timestamp, actor, source, action, target, params, success, error
+                 FROM audit_event
+                 WHERE (?1 IS NULL OR actor = ?1)
+                   AND (?2 IS NULL OR source = ?2)
+                   AND (?3 IS NULL OR action = ?3)
+                   AND (?4 IS NULL OR instr(target, ?4) > 0)
+                   AND (?5 IS NULL OR success = ?5)
+                   AND (?6 IS NULL OR timestamp >= ?6)
+                 ORDER BY id DESC
+                 LIMIT ?7",
+            )?
+            .query_map(
+                params![
+                    filter.actor,
+                    filter.source,
+                    filter.action,
+                    filter.target,
+                    filter.success,
+                    filter.since,
+                    limit
+                ],
+                AuditEvent::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    /// Distinct actors and actions, for the filter dropdowns.
+    pub fn get_distinct(
+        column: AuditColumn,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<String>> {
+        let column = match column {
+            AuditColumn::Actor => "actor",
+            AuditColumn::Action => "action",
+        };
+        let values = conn
+            .prepare(&format!(
+                "SELECT DISTINCT {0} FROM audit_event ORDER BY {0}",
+                column
+            ))?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(values)
+    }
+}
+
+pub enum AuditColumn {
+    Actor,
+    Action,
+}
@@ -0,0 +1,38 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+
+/// The settled build status last announced for a commit, so that each
+/// outcome is only notified once.
+pub struct BuildNotification;
+
+impl BuildNotification {
+    /// Record `status` for the commit, returning whether it differs from the
+    /// one recorded before (and should therefore be notified).
+    pub fn record(
+        repo_id: u64,
+        commit_sha: &str,
+        status: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<bool> {
+        let changed = conn.execute(
+            r#"
+            INSERT INTO build_notification (repo_id, commit_sha, status, notified_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(repo_id, commit_sha) DO UPDATE SET
+              status=excluded.status,
+              notified_at=excluded.notified_at
+            WHERE build_notification.status != excluded.status
+            "#,
+            params![
+                repo_id,
+                commit_sha,
+                status,
+                chrono::Utc::now().timestamp_millis()
+            ],
+        )?;
+
+        Ok(changed > 0)
+    }
+}
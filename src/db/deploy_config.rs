@@ -39,6 +39,35 @@ impl DeployConfig {
         Ok(deploy_configs)
     }
 
+    /// Active configs that build from or are defined in a repository.
+    pub fn get_active_by_repo_id(
+        repo_id: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let mut deploy_configs = Vec::new();
+        let mut stmt = conn.prepare("SELECT name, team, kind, config_repo_id, artifact_repo_id, active FROM deploy_config WHERE (artifact_repo_id = ?1 OR config_repo_id = ?1) AND active = TRUE")?;
+        let mut rows = stmt.query(params![repo_id])?;
+
+        while let Some(row) = rows.next()? {
+            deploy_configs.push(DeployConfig::from_row(row)?);
+        }
+
+        Ok(deploy_configs)
+    }
+
+    pub fn get_active_by_name(
+        name: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let mut stmt = conn.prepare("SELECT name, team, kind, config_repo_id, artifact_repo_id, active FROM deploy_config WHERE name = ?1 AND active = TRUE")?;
+        let mut rows = stmt.query(params![name])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(DeployConfig::from_row(row)?)),
+            None => Ok(None),
+        }
+    }
+
     pub fn upsert(
         deploy_config: &DeployConfig,
         conn: &PooledConnection<SqliteConnectionManager>,
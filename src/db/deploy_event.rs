@@ -1,5 +1,5 @@
 use crate::{
-    db::git_repo::GitRepo,
+    db::{git_commit::GitCommit, git_repo::GitRepo},
     error::AppResult,
     kubernetes::{deploy_handlers::DeployAction, DeployConfig},
 };
@@ -7,7 +7,9 @@ use chrono::Utc;
 use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
 
+#[derive(Clone, Debug, Serialize)]
 pub struct DeployEvent {
     pub name: String,
     pub timestamp: i64,
@@ -29,6 +31,7 @@ impl DeployEvent {
         action: &DeployAction,
         conn: &PooledConnection<SqliteConnectionManager>,
         config: &DeployConfig,
+        initiator: &str,
     ) -> AppResult<Option<Self>> {
         match action {
             DeployAction::Deploy {
@@ -39,7 +42,7 @@ impl DeployEvent {
                 let mut event = DeployEvent {
                     name: name.clone(),
                     timestamp: Utc::now().timestamp_millis(),
-                    initiator: "USER".to_string(),
+                    initiator: initiator.to_string(),
                     config_sha: Some(cfg_state.sha.clone()),
                     artifact_sha: artifact.as_ref().map(|a| a.sha.clone()),
                     artifact_branch: artifact.as_ref().and_then(|a| a.branch.clone()),
@@ -115,7 +118,7 @@ impl DeployEvent {
                 let mut event = DeployEvent {
                     name: name.clone(),
                     timestamp: Utc::now().timestamp_millis(),
-                    initiator: "USER".to_string(),
+                    initiator: initiator.to_string(),
                     config_sha: None,
                     artifact_sha: None,
                     artifact_branch: None,
@@ -143,6 +146,7 @@ impl DeployEvent {
                 Ok(Some(event))
             }
             DeployAction::ToggleAutodeploy { .. }
+            | DeployAction::ToggleOrphanKeep { .. }
             | DeployAction::Bounce { .. }
             | DeployAction::ExecuteJob { .. } => {
                 // No event
@@ -151,6 +155,69 @@ impl DeployEvent {
         }
     }
 
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(DeployEvent {
+            name: row.get(0)?,
+            timestamp: row.get(1)?,
+            initiator: row.get(2)?,
+            config_sha: row.get(3)?,
+            artifact_sha: row.get(4)?,
+            artifact_branch: row.get(5)?,
+            config_branch: row.get(6)?,
+            prev_artifact_sha: row.get(7)?,
+            prev_config_sha: row.get(8)?,
+            artifact_repo_id: row.get(9)?,
+            config_repo_id: row.get(10)?,
+            config_version_hash: row.get(11)?,
+            prev_config_version_hash: row.get(12)?,
+        })
+    }
+
+    /// The most recent `limit` events for a config, newest first.
+    pub fn get_recent_by_name(
+        name: &str,
+        limit: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let mut stmt = conn.prepare("SELECT name, timestamp, initiator, config_sha, artifact_sha, artifact_branch, config_branch, prev_artifact_sha, prev_config_sha, artifact_repo_id, config_repo_id, config_version_hash, prev_config_version_hash FROM deploy_event WHERE name = ?1 ORDER BY timestamp DESC LIMIT ?2")?;
+        let events = stmt
+            .query_map(params![name, limit], DeployEvent::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    /// The most recent `limit` events across all configs, newest first.
+    pub fn get_recent(
+        limit: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let mut stmt = conn.prepare("SELECT name, timestamp, initiator, config_sha, artifact_sha, artifact_branch, config_branch, prev_artifact_sha, prev_config_sha, artifact_repo_id, config_repo_id, config_version_hash, prev_config_version_hash FROM deploy_event ORDER BY timestamp DESC LIMIT ?1")?;
+        let events = stmt
+            .query_map(params![limit], DeployEvent::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    /// Whether this event deployed an artifact committed before the one it
+    /// replaced. Unknown commits are never treated as a rollback.
+    pub fn is_rollback(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<bool> {
+        let (Some(sha), Some(prev_sha)) = (&self.artifact_sha, &self.prev_artifact_sha) else {
+            return Ok(false);
+        };
+        if sha == prev_sha {
+            return Ok(false);
+        }
+        match (
+            GitCommit::find_by_sha(sha, conn)?,
+            GitCommit::find_by_sha(prev_sha, conn)?,
+        ) {
+            (Some(commit), Some(prev)) => Ok(commit.timestamp < prev.timestamp),
+            _ => Ok(false),
+        }
+    }
+
     pub fn insert(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<Self> {
         conn.prepare("INSERT INTO deploy_event (name, timestamp, initiator, config_sha, artifact_sha, artifact_branch, config_branch, prev_artifact_sha, prev_config_sha, artifact_repo_id, config_repo_id, config_version_hash, prev_config_version_hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)")?
           .execute(params![
@@ -169,6 +236,8 @@ impl DeployEvent {
             self.prev_config_version_hash
           ])?;
 
+        crate::events::publish(crate::events::Event::Deploy(self.clone()));
+
         Ok(Self {
             name: self.name.clone(),
             timestamp: self.timestamp,
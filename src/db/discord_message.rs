@@ -0,0 +1,51 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+
+/// A Discord message posted for `key` (e.g. `build:<repo>:<check run>`) in
+/// one channel.
+pub struct DiscordMessage {
+    pub key: String,
+    pub channel_id: u64,
+    pub message_id: u64,
+}
+
+impl DiscordMessage {
+    pub fn get(
+        key: &str,
+        channel_id: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let message = conn
+            .prepare("SELECT key, channel_id, message_id FROM discord_message WHERE key = ?1 AND channel_id = ?2")?
+            .query_row(params![key, channel_id], |row| {
+                Ok(DiscordMessage {
+                    key: row.get(0)?,
+                    channel_id: row.get(1)?,
+                    message_id: row.get(2)?,
+                })
+            })
+            .optional()?;
+
+        Ok(message)
+    }
+
+    pub fn upsert(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare("INSERT OR REPLACE INTO discord_message (key, channel_id, message_id) VALUES (?1, ?2, ?3)")?
+            .execute(params![self.key, self.channel_id, self.message_id])?;
+
+        Ok(())
+    }
+
+    pub fn delete(
+        key: &str,
+        channel_id: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        conn.prepare("DELETE FROM discord_message WHERE key = ?1 AND channel_id = ?2")?
+            .execute(params![key, channel_id])?;
+
+        Ok(())
+    }
+}
@@ -91,6 +91,42 @@ impl GitBranch {
         Ok(branch)
     }
 
+    /// Active branches of a repo, by name.
+    pub fn get_active_by_repo(
+        repo_id: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let branches = conn
+            .prepare("SELECT id, name, head_commit_sha, repo_id, active FROM git_branch WHERE repo_id = ?1 AND active = TRUE ORDER BY name")?
+            .query_and_then(params![repo_id], GitBranch::from_row)?
+            .collect::<AppResult<Vec<_>>>()?;
+
+        Ok(branches)
+    }
+
+    /// The most recent `limit` commits on this branch, newest first.
+    pub fn recent_commits(
+        &self,
+        limit: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<GitCommit>> {
+        let commits = conn
+            .prepare(
+                r#"
+                    SELECT c.id, c.sha, c.repo_id, c.message, c.author, c.committer, c.timestamp
+                    FROM git_commit c
+                    JOIN git_commit_branch cb ON c.id = cb.commit_id
+                    WHERE cb.branch_id = ?1
+                    ORDER BY c.timestamp DESC
+                    LIMIT ?2
+                "#,
+            )?
+            .query_and_then(params![self.id, limit], GitCommit::from_row)?
+            .collect::<AppResult<Vec<_>>>()?;
+
+        Ok(commits)
+    }
+
     pub fn mark_inactive(
         &mut self,
         conn: &PooledConnection<SqliteConnectionManager>,
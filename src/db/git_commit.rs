@@ -8,6 +8,7 @@ use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GitCommit {
@@ -56,6 +57,20 @@ impl GitCommit {
         Ok(commit)
     }
 
+    /// Look up a commit by SHA in any repo.
+    pub fn find_by_sha(
+        sha: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let commit = conn.prepare("SELECT id, sha, repo_id, message, author, committer, timestamp FROM git_commit WHERE sha = ?1 ORDER BY id LIMIT 1")?
+          .query_row(params![sha], |row| {
+            Ok(GitCommit::from_row(row))
+          })
+          .optional().map_err(AppError::from)?.transpose()?;
+
+        Ok(commit)
+    }
+
     pub fn get_parents(
         &self,
         conn: &PooledConnection<SqliteConnectionManager>,
@@ -77,6 +92,65 @@ impl GitCommit {
         Ok(parent_commits)
     }
 
+    /// Commits in the same repo that list this commit as a parent.
+    pub fn get_children(
+        &self,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<GitCommit>> {
+        let child_commits: Vec<GitCommit> = conn
+            .prepare(
+                r#"
+                    SELECT gc.id, gc.sha, gc.repo_id, gc.message, gc.author, gc.committer, gc.timestamp
+                    FROM git_commit_parent gcp
+                    JOIN git_commit gc ON gc.id = gcp.commit_id
+                    WHERE gcp.parent_sha = ?1
+                    AND gc.repo_id = ?2
+                    ORDER BY gc.timestamp
+                "#,
+            )?
+            .query_and_then(params![self.sha, self.repo_id], GitCommit::from_row)?
+            .collect::<AppResult<Vec<GitCommit>>>()?;
+
+        Ok(child_commits)
+    }
+
+    /// The commits between `base` (exclusive) and `head` (inclusive), newest
+    /// first, found by walking parents from `head` until `base` as far as they
+    /// are known locally. At most `limit` commits are returned; the flag is
+    /// false when the walk stopped early or never reached `base`.
+    pub fn get_range(
+        base: &GitCommit,
+        head: &GitCommit,
+        limit: usize,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<(Vec<GitCommit>, bool)> {
+        let mut seen = HashSet::from([base.sha.clone(), head.sha.clone()]);
+        let mut queue = VecDeque::new();
+        if base.sha != head.sha {
+            queue.push_back(head.clone());
+        }
+        let mut commits = vec![];
+        let mut complete = true;
+        let mut reached_base = base.sha == head.sha;
+        while let Some(commit) = queue.pop_front() {
+            if commits.len() >= limit {
+                complete = false;
+                break;
+            }
+            for parent in commit.get_parents(conn)? {
+                if parent.sha == base.sha {
+                    reached_base = true;
+                } else if seen.insert(parent.sha.clone()) {
+                    queue.push_back(parent);
+                }
+            }
+            commits.push(commit);
+        }
+        commits.sort_by_key(|c| std::cmp::Reverse(c.timestamp));
+
+        Ok((commits, complete && reached_base))
+    }
+
     pub fn get_branches(
         &self,
         conn: &PooledConnection<SqliteConnectionManager>,
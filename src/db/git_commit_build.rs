@@ -2,8 +2,9 @@ use crate::error::{AppError, AppResult};
 use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct GitCommitBuild {
     pub repo_id: u64,
     pub commit_id: i64,
@@ -13,9 +14,9 @@ pub struct GitCommitBuild {
     pub start_time: Option<u64>,
     pub settle_time: Option<u64>,
     /// GitHub App id that produced this check run (e.g. 15368 for GitHub
-    /// Actions). Nullable: legacy rows and the collapsed legacy-status entry
-    /// have no single app. Lets deploy configs eventually depend on specific
-    /// checks keyed by (app_id, check name).
+    /// Actions). Nullable: legacy rows and commit statuses have no app. Lets
+    /// deploy configs eventually depend on specific checks keyed by (app_id,
+    /// check name).
     pub app_id: Option<u64>,
 }
 
@@ -33,6 +34,12 @@ impl GitCommitBuild {
         })
     }
 
+    /// The check name under which a commit status of `context` is stored,
+    /// next to the `run-<id>` check runs.
+    pub fn status_check_name(context: &str) -> String {
+        format!("status-{}", context)
+    }
+
     pub fn get_all_by_commit_id(
         commit_id: &i64,
         repo_id: &u64,
@@ -132,6 +139,43 @@ impl GitCommitBuild {
         })
     }
 
+    /// Upsert a commit status reported at `reported_at` (ms), unless a newer
+    /// status for the same context is already stored. Returns whether the
+    /// build was written.
+    pub fn upsert_status(
+        build: &GitCommitBuild,
+        reported_at: Option<u64>,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<bool> {
+        let written = conn
+            .prepare(
+                "INSERT INTO git_commit_build (repo_id, commit_id, check_name, status, url, start_time, settle_time, app_id, reported_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT(repo_id, commit_id, check_name) DO UPDATE SET
+                     status = excluded.status,
+                     url = excluded.url,
+                     start_time = COALESCE(git_commit_build.start_time, excluded.start_time),
+                     settle_time = excluded.settle_time,
+                     app_id = excluded.app_id,
+                     reported_at = excluded.reported_at
+                 WHERE git_commit_build.reported_at IS NULL
+                    OR excluded.reported_at >= git_commit_build.reported_at",
+            )?
+            .execute(params![
+                build.repo_id,
+                build.commit_id,
+                build.check_name,
+                build.status,
+                build.url,
+                build.start_time,
+                build.settle_time,
+                build.app_id,
+                reported_at
+            ])?;
+
+        Ok(written > 0)
+    }
+
     /// Make the stored builds for a commit match `builds` exactly.
     ///
     /// Upserts every build in `builds`, then deletes any existing rows for the
@@ -0,0 +1,169 @@
+use crate::{db::git_branch::GitBranch, error::AppResult};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, params_from_iter, types::Value, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A pull request as last reported by GitHub.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GitPullRequest {
+    /// GitHub's id, not the number.
+    pub id: u64,
+    pub repo_id: u64,
+    pub number: u64,
+    pub title: String,
+    pub author: String,
+    /// `open` or `closed`
+    pub state: String,
+    pub draft: bool,
+    pub merged: bool,
+    pub head_branch: String,
+    pub head_sha: String,
+    /// The repo the head branch lives in; differs from `repo_id` for forks,
+    /// and is `None` when the fork was deleted.
+    pub head_repo_id: Option<u64>,
+    pub base_branch: String,
+    pub html_url: String,
+    pub updated_at: i64,
+}
+
+const COLUMNS: &str = "id, repo_id, number, title, author, state, draft, merged, head_branch, head_sha, head_repo_id, base_branch, html_url, updated_at";
+
+impl GitPullRequest {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(GitPullRequest {
+            id: row.get(0)?,
+            repo_id: row.get(1)?,
+            number: row.get(2)?,
+            title: row.get(3)?,
+            author: row.get(4)?,
+            state: row.get(5)?,
+            draft: row.get(6)?,
+            merged: row.get(7)?,
+            head_branch: row.get(8)?,
+            head_sha: row.get(9)?,
+            head_repo_id: row.get(10)?,
+            base_branch: row.get(11)?,
+            html_url: row.get(12)?,
+            updated_at: row.get(13)?,
+        })
+    }
+
+    /// Insert or update the pull request, unless what is stored is newer;
+    /// replayed events must not undo later ones.
+    pub fn upsert(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare(&format!(
+            "INSERT INTO git_pull_request ({})
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                 title = excluded.title,
+                 state = excluded.state,
+                 draft = excluded.draft,
+                 merged = excluded.merged,
+                 head_sha = excluded.head_sha,
+                 head_repo_id = excluded.head_repo_id,
+                 base_branch = excluded.base_branch,
+                 updated_at = excluded.updated_at
+             WHERE excluded.updated_at >= git_pull_request.updated_at",
+            COLUMNS
+        ))?
+        .execute(params![
+            self.id,
+            self.repo_id,
+            self.number,
+            self.title,
+            self.author,
+            self.state,
+            self.draft,
+            self.merged,
+            self.head_branch,
+            self.head_sha,
+            self.head_repo_id,
+            self.base_branch,
+            self.html_url,
+            self.updated_at
+        ])?;
+
+        Ok(())
+    }
+
+    /// The pull request opened from `branch`: an open one if there is one,
+    /// else the most recently updated. Pull requests from forks are never
+    /// linked, since a fork's branch names say nothing about ours.
+    pub fn get_for_branch(
+        branch: &GitBranch,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let pull_request = conn
+            .prepare(&format!(
+                "SELECT {} FROM git_pull_request
+                 WHERE repo_id = ?1 AND head_repo_id = ?1 AND head_branch = ?2
+                 ORDER BY state = 'open' DESC, updated_at DESC
+                 LIMIT 1",
+                COLUMNS
+            ))?
+            .query_row(
+                params![branch.repo_id, branch.name],
+                GitPullRequest::from_row,
+            )
+            .optional()?;
+
+        Ok(pull_request)
+    }
+
+    /// [`Self::get_for_branch`] for many branches with a single query, keyed
+    /// by `(repo_id, branch name)`. Branches without a pull request are absent.
+    pub fn get_for_branches<'a>(
+        branches: impl IntoIterator<Item = &'a GitBranch>,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<HashMap<(u64, String), Self>> {
+        let branches: Vec<&GitBranch> = branches.into_iter().collect();
+        if branches.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // Rows for other pairs of the listed repos and names are harmless:
+        // they are simply never looked up.
+        let repo_ids: Vec<Value> = branches
+            .iter()
+            .map(|b| Value::from(b.repo_id as i64))
+            .collect();
+        let names: Vec<Value> = branches
+            .iter()
+            .map(|b| Value::from(b.name.clone()))
+            .collect();
+        let sql = format!(
+            "SELECT {} FROM git_pull_request
+             WHERE repo_id = head_repo_id
+               AND repo_id IN ({})
+               AND head_branch IN ({})
+             ORDER BY state = 'open' DESC, updated_at DESC",
+            COLUMNS,
+            vec!["?"; repo_ids.len()].join(", "),
+            vec!["?"; names.len()].join(", ")
+        );
+        let mut pull_requests = HashMap::new();
+        for pull_request in conn.prepare(&sql)?.query_map(
+            params_from_iter(repo_ids.into_iter().chain(names)),
+            GitPullRequest::from_row,
+        )? {
+            let pull_request = pull_request?;
+            pull_requests
+                .entry((pull_request.repo_id, pull_request.head_branch.clone()))
+                .or_insert(pull_request);
+        }
+
+        Ok(pull_requests)
+    }
+
+    /// `draft`, `open`, `merged` or `closed`
+    pub fn display_state(&self) -> &'static str {
+        match (self.state.as_str(), self.draft, self.merged) {
+            (_, _, true) => "merged",
+            ("open", true, _) => "draft",
+            ("open", false, _) => "open",
+            _ => "closed",
+        }
+    }
+}
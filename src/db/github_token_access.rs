@@ -0,0 +1,56 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+
+/// Which GitHub clients were found able to access a repo, by client label.
+pub struct GitHubTokenAccess;
+
+impl GitHubTokenAccess {
+    /// The labels of the clients known to access `repo` (`owner/name`,
+    /// lowercased) as of `since` (ms) or later, in the order they were found.
+    pub fn get_tokens(
+        repo: &str,
+        since: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<String>> {
+        let tokens = conn
+            .prepare(
+                "SELECT token FROM github_token_access WHERE repo = ?1 AND checked_at >= ?2 ORDER BY position",
+            )?
+            .query_map(params![repo, since], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(tokens)
+    }
+
+    /// Replace what is known about `repo` with `tokens`.
+    pub fn replace(
+        repo: &str,
+        tokens: &[String],
+        checked_at: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        Self::delete_for_repo(repo, conn)?;
+        for (position, token) in tokens.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO github_token_access (repo, token, position, checked_at) VALUES (?1, ?2, ?3, ?4)",
+                params![repo, token, position, checked_at],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_for_repo(
+        repo: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        conn.execute(
+            "DELETE FROM github_token_access WHERE repo = ?1",
+            params![repo],
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// A GitHub event as received through the websocket proxy or the HTTP
+/// endpoint, or recovered after a gap, with what each handler made of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncomingEvent {
+    pub id: i64,
+    /// GitHub's `X-GitHub-Delivery`, when the transport passed it on.
+    pub delivery_id: Option<String>,
+    /// SHA-256 of the event type and payload, for dedupe across transports.
+    pub payload_hash: String,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    /// `websocket`, `http` or `catchup`
+    pub source: String,
+    pub received_at: i64,
+    /// `pending`, `success` or `failed`
+    pub status: String,
+    pub outcomes: Vec<HandlerOutcome>,
+    pub processed_at: Option<i64>,
+    /// How often the event was replayed from the admin page.
+    pub replays: i64,
+}
+
+pub struct IncomingEventEgg {
+    pub delivery_id: Option<String>,
+    pub payload_hash: String,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub source: String,
+}
+
+/// The result of one handler, or of parsing the payload.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HandlerOutcome {
+    pub handler: String,
+    pub error: Option<String>,
+    /// Calls made, including retries.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// Filters for [`IncomingEvent::search`]; `None` matches everything.
+#[derive(Clone, Debug, Default)]
+pub struct IncomingEventFilter {
+    pub event_type: Option<String>,
+    pub status: Option<String>,
+}
+
+const COLUMNS: &str = "id, delivery_id, payload_hash, event_type, payload, source, received_at, status, outcomes, processed_at, replays";
+
+impl IncomingEvent {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let payload: String = row.get(4)?;
+        let outcomes: String = row.get(8)?;
+        Ok(IncomingEvent {
+            id: row.get(0)?,
+            delivery_id: row.get(1)?,
+            payload_hash: row.get(2)?,
+            event_type: row.get(3)?,
+            payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::Null),
+            source: row.get(5)?,
+            received_at: row.get(6)?,
+            status: row.get(7)?,
+            outcomes: serde_json::from_str(&outcomes).unwrap_or_default(),
+            processed_at: row.get(9)?,
+            replays: row.get(10)?,
+        })
+    }
+
+    /// Store a newly received event as pending. Returns `None`, storing
+    /// nothing, when the same delivery or payload was already received.
+    pub fn insert(
+        egg: &IncomingEventEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<i64>> {
+        let inserted = conn
+            .prepare(
+                "INSERT INTO incoming_event (delivery_id, payload_hash, event_type, payload, source, received_at, status, outcomes, replays)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'pending', '[]', 0)
+                 ON CONFLICT DO NOTHING",
+            )?
+            .execute(params![
+                egg.delivery_id,
+                egg.payload_hash,
+                egg.event_type,
+                egg.payload.to_string(),
+                egg.source,
+                chrono::Utc::now().timestamp_millis()
+            ])?;
+
+        Ok((inserted > 0).then(|| conn.last_insert_rowid()))
+    }
+
+    /// The stored event with the same delivery id or payload as `egg`.
+    pub fn find_duplicate(
+        egg: &IncomingEventEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let event = conn
+            .prepare(&format!(
+                "SELECT {} FROM incoming_event WHERE delivery_id = ?1 OR payload_hash = ?2 LIMIT 1",
+                COLUMNS
+            ))?
+            .query_row(
+                params![egg.delivery_id, egg.payload_hash],
+                IncomingEvent::from_row,
+            )
+            .optional()?;
+
+        Ok(event)
+    }
+
+    /// Events received before `before` (ms) and not yet processed, oldest
+    /// first.
+    pub fn get_pending(
+        before: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let events = conn
+            .prepare(&format!(
+                "SELECT {} FROM incoming_event WHERE status = 'pending' AND received_at < ?1 ORDER BY id",
+                COLUMNS
+            ))?
+            .query_map(params![before], IncomingEvent::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    pub fn get_by_id(
+        id: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let event = conn
+            .prepare(&format!(
+                "SELECT {} FROM incoming_event WHERE id = ?1",
+                COLUMNS
+            ))?
+            .query_row(params![id], IncomingEvent::from_row)
+            .optional()?;
+
+        Ok(event)
+    }
+
+    /// Record the handlers' outcomes; `replayed` counts it as a replay.
+    pub fn record_outcomes(
+        id: i64,
+        outcomes: &[HandlerOutcome],
+        replayed: bool,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        let status = if outcomes.iter().all(|o| o.error.is_none()) {
+            "success"
+        } else {
+            "failed"
+        };
+        conn.prepare(
+            "UPDATE incoming_event SET status = ?2, outcomes = ?3, processed_at = ?4, replays = replays + ?5 WHERE id = ?1",
+        )?
+        .execute(params![
+            id,
+            status,
+            serde_json::to_string(outcomes).unwrap_or_else(|_| "[]".to_string()),
+            chrono::Utc::now().timestamp_millis(),
+            replayed as i64
+        ])?;
+
+        Ok(())
+    }
+
+    /// The most recent events matching `filter`, newest first.
+    pub fn search(
+        filter: &IncomingEventFilter,
+        limit: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let events = conn
+            .prepare(&format!(
+                "SELECT {} FROM incoming_event
+                 WHERE (?1 IS NULL OR event_type = ?1)
+                   AND (?2 IS NULL OR status = ?2)
+                 ORDER BY id DESC
+                 LIMIT ?3",
+                COLUMNS
+            ))?
+            .query_map(
+                params![filter.event_type, filter.status, limit],
+                IncomingEvent::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    /// When the newest stored event was received, if any.
+    pub fn latest_received_at(
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<i64>> {
+        let latest = conn
+            .prepare("SELECT MAX(received_at) FROM incoming_event")?
+            .query_row([], |row| row.get(0))?;
+
+        Ok(latest)
+    }
+
+    /// Distinct event types, for the filter dropdown.
+    pub fn get_event_types(
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<String>> {
+        let types = conn
+            .prepare("SELECT DISTINCT event_type FROM incoming_event ORDER BY event_type")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(types)
+    }
+
+    /// Delete events received before `before` (ms), then all but the newest
+    /// `keep`. Returns how many were deleted.
+    pub fn prune(
+        before: i64,
+        keep: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<usize> {
+        let expired = conn
+            .prepare("DELETE FROM incoming_event WHERE received_at < ?1")?
+            .execute(params![before])?;
+        let excess = conn
+            .prepare(
+                "DELETE FROM incoming_event WHERE id NOT IN (SELECT id FROM incoming_event ORDER BY id DESC LIMIT ?1)",
+            )?
+            .execute(params![keep])?;
+
+        Ok(expired + excess)
+    }
+}
@@ -108,6 +108,209 @@ pub fn migrate(mut conn: PooledConnection<SqliteConnectionManager>) -> AppResult
         M::up(indoc! { r#"
           ALTER TABLE git_commit_build ADD COLUMN app_id INTEGER;
         "#}),
+        // Discord messages posted per build, deploy config or alert, so that
+        // later updates edit the original message even after a restart.
+        M::up(indoc! { r#"
+          CREATE TABLE discord_message (
+              key TEXT NOT NULL,
+              channel_id INTEGER NOT NULL,
+              message_id INTEGER NOT NULL,
+              PRIMARY KEY(key, channel_id)
+          );
+        "#}),
+        // Outgoing webhooks: subscriptions and a log of delivery attempts.
+        M::up(indoc! { r#"
+          CREATE TABLE webhook_subscription (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              url TEXT NOT NULL,
+              format TEXT NOT NULL,
+              secret TEXT,
+              events TEXT NOT NULL,
+              team TEXT,
+              repo TEXT,
+              config TEXT,
+              created_at INTEGER NOT NULL
+          );
+
+          CREATE TABLE webhook_delivery (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              subscription_id INTEGER NOT NULL,
+              event TEXT NOT NULL,
+              timestamp INTEGER NOT NULL,
+              attempts INTEGER NOT NULL,
+              status_code INTEGER,
+              error TEXT,
+              success BOOLEAN NOT NULL,
+              FOREIGN KEY(subscription_id) REFERENCES webhook_subscription(id) ON DELETE CASCADE
+          );
+          CREATE INDEX IF NOT EXISTS idx_webhook_delivery_subscription ON webhook_delivery(subscription_id, id);
+        "#}),
+        // Users who have logged in through the configured identity provider.
+        M::up(indoc! { r#"
+          CREATE TABLE app_user (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              provider TEXT NOT NULL,
+              subject TEXT NOT NULL,
+              login TEXT NOT NULL,
+              name TEXT,
+              email TEXT,
+              created_at INTEGER NOT NULL,
+              last_login_at INTEGER NOT NULL,
+              UNIQUE(provider, subject)
+          );
+        "#}),
+        // Team roles granted to users or identity provider groups, and the
+        // groups each user belonged to at their last login.
+        M::up(indoc! { r#"
+          ALTER TABLE app_user ADD COLUMN groups TEXT NOT NULL DEFAULT '';
+
+          CREATE TABLE role_binding (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              kind TEXT NOT NULL,
+              subject TEXT NOT NULL,
+              team TEXT NOT NULL,
+              role TEXT NOT NULL,
+              created_at INTEGER NOT NULL,
+              UNIQUE(kind, subject, team)
+          );
+        "#}),
+        // Every mutating action, whoever or whatever performed it.
+        M::up(indoc! { r#"
+          CREATE TABLE audit_event (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              timestamp INTEGER NOT NULL,
+              actor TEXT NOT NULL,
+              source TEXT NOT NULL,
+              action TEXT NOT NULL,
+              target TEXT NOT NULL,
+              params TEXT NOT NULL,
+              success BOOLEAN NOT NULL,
+              error TEXT
+          );
+          CREATE INDEX IF NOT EXISTS idx_audit_event_timestamp ON audit_event(timestamp);
+        "#}),
+        // Personal API tokens, stored as SHA-256 hashes.
+        M::up(indoc! { r#"
+          CREATE TABLE api_token (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              user_id INTEGER NOT NULL,
+              name TEXT NOT NULL,
+              token_hash TEXT NOT NULL UNIQUE,
+              scope TEXT NOT NULL,
+              teams TEXT NOT NULL,
+              created_at INTEGER NOT NULL,
+              last_used_at INTEGER,
+              FOREIGN KEY(user_id) REFERENCES app_user(id)
+          );
+          CREATE INDEX IF NOT EXISTS idx_api_token_user ON api_token(user_id);
+        "#}),
+        // GitHub events as received, with each handler's outcome.
+        M::up(indoc! { r#"
+          CREATE TABLE incoming_event (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              delivery_id TEXT UNIQUE,
+              payload_hash TEXT NOT NULL UNIQUE,
+              event_type TEXT NOT NULL,
+              payload TEXT NOT NULL,
+              source TEXT NOT NULL,
+              received_at INTEGER NOT NULL,
+              status TEXT NOT NULL,
+              outcomes TEXT NOT NULL,
+              processed_at INTEGER,
+              replays INTEGER NOT NULL
+          );
+          CREATE INDEX IF NOT EXISTS idx_incoming_event_received_at ON incoming_event(received_at);
+        "#}),
+        // Pull requests, linked to their head branch by name.
+        M::up(indoc! { r#"
+          CREATE TABLE git_pull_request (
+              id INTEGER PRIMARY KEY,
+              repo_id INTEGER NOT NULL,
+              number INTEGER NOT NULL,
+              title TEXT NOT NULL,
+              author TEXT NOT NULL,
+              state TEXT NOT NULL,
+              draft BOOLEAN NOT NULL,
+              merged BOOLEAN NOT NULL,
+              head_branch TEXT NOT NULL,
+              head_sha TEXT NOT NULL,
+              head_repo_id INTEGER,
+              base_branch TEXT NOT NULL,
+              html_url TEXT NOT NULL,
+              updated_at INTEGER NOT NULL,
+              UNIQUE(repo_id, number),
+              FOREIGN KEY(repo_id) REFERENCES git_repo(id)
+          );
+          CREATE INDEX IF NOT EXISTS idx_git_pull_request_head ON git_pull_request(repo_id, head_branch);
+        "#}),
+        // GitHub Actions runs and their jobs, for build timelines.
+        M::up(indoc! { r#"
+          CREATE TABLE workflow_run (
+              id INTEGER PRIMARY KEY,
+              repo_id INTEGER NOT NULL,
+              head_sha TEXT NOT NULL,
+              head_branch TEXT,
+              name TEXT NOT NULL,
+              run_number INTEGER NOT NULL,
+              run_attempt INTEGER NOT NULL,
+              event TEXT NOT NULL,
+              status TEXT NOT NULL,
+              conclusion TEXT,
+              html_url TEXT NOT NULL,
+              created_at INTEGER NOT NULL,
+              run_started_at INTEGER,
+              updated_at INTEGER NOT NULL,
+              FOREIGN KEY(repo_id) REFERENCES git_repo(id)
+          );
+          CREATE INDEX IF NOT EXISTS idx_workflow_run_commit ON workflow_run(repo_id, head_sha);
+          CREATE TABLE workflow_job (
+              id INTEGER PRIMARY KEY,
+              run_id INTEGER NOT NULL,
+              run_attempt INTEGER NOT NULL,
+              repo_id INTEGER NOT NULL,
+              head_sha TEXT NOT NULL,
+              name TEXT NOT NULL,
+              status TEXT NOT NULL,
+              conclusion TEXT,
+              runner_name TEXT,
+              html_url TEXT,
+              created_at INTEGER NOT NULL,
+              started_at INTEGER,
+              completed_at INTEGER,
+              steps TEXT NOT NULL,
+              FOREIGN KEY(repo_id) REFERENCES git_repo(id)
+          );
+          CREATE INDEX IF NOT EXISTS idx_workflow_job_commit ON workflow_job(repo_id, head_sha);
+          CREATE INDEX IF NOT EXISTS idx_workflow_job_name ON workflow_job(repo_id, name, completed_at);
+        "#}),
+        // Which GitHub clients (by label) can access each repo, so they aren't
+        // probed on every request. Entries are trusted until they expire or
+        // a request fails with an access error.
+        M::up(indoc! { r#"
+          CREATE TABLE github_token_access (
+              repo TEXT NOT NULL,
+              token TEXT NOT NULL,
+              position INTEGER NOT NULL,
+              checked_at INTEGER NOT NULL,
+              PRIMARY KEY (repo, token)
+          );
+        "#}),
+        // The settled build status last sent to outgoing webhooks per commit,
+        // so a commit's outcome is announced once rather than per check run.
+        M::up(indoc! { r#"
+          CREATE TABLE build_notification (
+              repo_id INTEGER NOT NULL,
+              commit_sha TEXT NOT NULL,
+              status TEXT NOT NULL,
+              notified_at INTEGER NOT NULL,
+              PRIMARY KEY (repo_id, commit_sha)
+          );
+        "#}),
+        // When a commit status row was reported, so that a status delivered
+        // late cannot overwrite a newer one for the same context.
+        M::up(indoc! { r#"
+          ALTER TABLE git_commit_build ADD COLUMN reported_at INTEGER;
+        "#}),
     ]);
 
     conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
@@ -1,16 +1,29 @@
 use std::ops::Deref;
 
+pub mod api_token;
+pub mod audit_event;
+pub mod build_notification;
 pub mod deploy_config;
 pub mod deploy_config_version;
 pub mod deploy_event;
+pub mod discord_message;
 pub mod functions;
 pub mod git_branch;
 pub mod git_commit;
 pub mod git_commit_branch;
 pub mod git_commit_build;
 pub mod git_commit_parent;
+pub mod git_pull_request;
 pub mod git_repo;
+pub mod github_token_access;
+pub mod incoming_event;
 pub mod migrations;
+pub mod role_binding;
+pub mod user;
+pub mod webhook_delivery;
+pub mod webhook_subscription;
+pub mod workflow_job;
+pub mod workflow_run;
 
 pub struct ExistenceResult {
     id: u64,
@@ -23,3 +36,16 @@ impl Deref for ExistenceResult {
         &self.id
     }
 }
+
+/// A migrated in-memory database for tests. It has a single connection so
+/// every checkout sees the same database.
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+pub fn test_pool() -> r2d2::Pool<r2d2_sqlite::SqliteConnectionManager> {
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .build(r2d2_sqlite::SqliteConnectionManager::memory())
+        .unwrap();
+    migrations::migrate(pool.get().unwrap()).unwrap();
+    pool
+}
@@ -0,0 +1,86 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+
+/// A role on a team (or `*` for every team) granted to a user login or a
+/// provider group.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoleBinding {
+    pub id: i64,
+    /// `user` or `group`
+    pub kind: String,
+    /// A login for `user` bindings, a group name for `group` bindings.
+    pub subject: String,
+    pub team: String,
+    /// `viewer`, `deployer` or `admin`
+    pub role: String,
+    pub created_at: i64,
+}
+
+pub struct RoleBindingEgg {
+    pub kind: String,
+    pub subject: String,
+    pub team: String,
+    pub role: String,
+}
+
+impl RoleBinding {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(RoleBinding {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            subject: row.get(2)?,
+            team: row.get(3)?,
+            role: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    }
+
+    pub fn get_all(conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<Vec<Self>> {
+        let bindings = conn
+            .prepare(
+                "SELECT id, kind, subject, team, role, created_at FROM role_binding ORDER BY team, kind, subject",
+            )?
+            .query_map([], RoleBinding::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(bindings)
+    }
+
+    pub fn get_by_id(
+        id: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let binding = conn
+            .prepare(
+                "SELECT id, kind, subject, team, role, created_at FROM role_binding WHERE id = ?1",
+            )?
+            .query_row(params![id], RoleBinding::from_row)
+            .optional()?;
+
+        Ok(binding)
+    }
+
+    /// Grant a role, replacing any role the subject already has on the team.
+    pub fn upsert(
+        egg: &RoleBindingEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<()> {
+        let created_at = chrono::Utc::now().timestamp_millis();
+        conn.prepare(
+            "INSERT INTO role_binding (kind, subject, team, role, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(kind, subject, team) DO UPDATE SET role = excluded.role",
+        )?
+        .execute(params![egg.kind, egg.subject, egg.team, egg.role, created_at])?;
+
+        Ok(())
+    }
+
+    pub fn delete(id: i64, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare("DELETE FROM role_binding WHERE id = ?1")?
+            .execute(params![id])?;
+
+        Ok(())
+    }
+}
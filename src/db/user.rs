@@ -0,0 +1,95 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+
+/// A user known through the identity provider (`github` or `oidc`), keyed by
+/// the provider's stable subject id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub provider: String,
+    pub subject: String,
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// Provider groups (GitHub `org/team` slugs or OIDC `groups`) at the last login.
+    pub groups: Vec<String>,
+}
+
+pub struct UserEgg {
+    pub provider: String,
+    pub subject: String,
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    /// Provider groups (GitHub `org/team` slugs or OIDC `groups`) at the last login.
+    pub groups: Vec<String>,
+}
+
+impl User {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(User {
+            id: row.get(0)?,
+            provider: row.get(1)?,
+            subject: row.get(2)?,
+            login: row.get(3)?,
+            name: row.get(4)?,
+            email: row.get(5)?,
+            groups: row
+                .get::<_, String>(6)?
+                .split(',')
+                .filter(|g| !g.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    pub fn get_by_id(
+        id: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let user = conn
+            .prepare(
+                "SELECT id, provider, subject, login, name, email, groups FROM app_user WHERE id = ?1",
+            )?
+            .query_row(params![id], User::from_row)
+            .optional()?;
+
+        Ok(user)
+    }
+
+    /// Record a login, creating the user or refreshing their profile.
+    pub fn upsert_login(
+        egg: &UserEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Self> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let user = conn
+            .prepare(
+                "INSERT INTO app_user (provider, subject, login, name, email, groups, created_at, last_login_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)
+                 ON CONFLICT(provider, subject) DO UPDATE SET
+                     login = excluded.login,
+                     name = excluded.name,
+                     email = excluded.email,
+                     groups = excluded.groups,
+                     last_login_at = excluded.last_login_at
+                 RETURNING id, provider, subject, login, name, email, groups",
+            )?
+            .query_row(
+                params![
+                    egg.provider,
+                    egg.subject,
+                    egg.login,
+                    egg.name,
+                    egg.email,
+                    egg.groups.join(","),
+                    now
+                ],
+                User::from_row,
+            )?;
+
+        Ok(user)
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+
+/// How many deliveries are kept in the log.
+const RETAINED: i64 = 1000;
+
+/// The outcome of delivering one notification to a subscription, after retries.
+#[derive(Clone, Debug)]
+pub struct WebhookDelivery {
+    pub subscription_id: i64,
+    pub event: String,
+    pub timestamp: i64,
+    pub attempts: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub success: bool,
+}
+
+impl WebhookDelivery {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(WebhookDelivery {
+            subscription_id: row.get(0)?,
+            event: row.get(1)?,
+            timestamp: row.get(2)?,
+            attempts: row.get(3)?,
+            status_code: row.get(4)?,
+            error: row.get(5)?,
+            success: row.get(6)?,
+        })
+    }
+
+    /// Record a delivery, dropping the oldest entries beyond the retained count.
+    pub fn insert(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare("INSERT INTO webhook_delivery (subscription_id, event, timestamp, attempts, status_code, error, success) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?
+            .execute(params![
+                self.subscription_id,
+                self.event,
+                self.timestamp,
+                self.attempts,
+                self.status_code,
+                self.error,
+                self.success
+            ])?;
+        conn.prepare("DELETE FROM webhook_delivery WHERE id <= last_insert_rowid() - ?1")?
+            .execute(params![RETAINED])?;
+
+        Ok(())
+    }
+
+    /// The most recent `limit` deliveries, newest first.
+    pub fn get_recent(
+        limit: u32,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let deliveries = conn
+            .prepare("SELECT subscription_id, event, timestamp, attempts, status_code, error, success FROM webhook_delivery ORDER BY id DESC LIMIT ?1")?
+            .query_map(params![limit], WebhookDelivery::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(deliveries)
+    }
+}
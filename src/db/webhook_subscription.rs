@@ -0,0 +1,144 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+
+/// An outgoing webhook and the notifications it receives. Empty filters match
+/// everything.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebhookSubscription {
+    pub id: i64,
+    pub url: String,
+    /// `slack` or `json`
+    pub format: String,
+    /// HMAC key for signing `json` payloads.
+    pub secret: Option<String>,
+    /// Event kinds to deliver; empty for all.
+    pub events: Vec<String>,
+    pub team: Option<String>,
+    /// `owner/name`
+    pub repo: Option<String>,
+    pub config: Option<String>,
+    pub created_at: i64,
+}
+
+pub struct WebhookSubscriptionEgg {
+    pub url: String,
+    pub format: String,
+    pub secret: Option<String>,
+    pub events: Vec<String>,
+    pub team: Option<String>,
+    pub repo: Option<String>,
+    pub config: Option<String>,
+}
+
+const COLUMNS: &str = "id, url, format, secret, events, team, repo, config, created_at";
+
+impl WebhookSubscription {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let events: String = row.get(4)?;
+        Ok(WebhookSubscription {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            format: row.get(2)?,
+            secret: row.get(3)?,
+            events: events
+                .split(',')
+                .filter(|e| !e.is_empty())
+                .map(str::to_string)
+                .collect(),
+            team: row.get(5)?,
+            repo: row.get(6)?,
+            config: row.get(7)?,
+            created_at: row.get(8)?,
+        })
+    }
+
+    pub fn get_all(conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<Vec<Self>> {
+        let subscriptions = conn
+            .prepare(&format!(
+                "SELECT {} FROM webhook_subscription ORDER BY id",
+                COLUMNS
+            ))?
+            .query_map([], WebhookSubscription::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(subscriptions)
+    }
+
+    pub fn get_by_id(
+        id: i64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<Self>> {
+        let subscription = conn
+            .prepare(&format!(
+                "SELECT {} FROM webhook_subscription WHERE id = ?1",
+                COLUMNS
+            ))?
+            .query_row(params![id], WebhookSubscription::from_row)
+            .optional()?;
+
+        Ok(subscription)
+    }
+
+    pub fn insert(
+        egg: &WebhookSubscriptionEgg,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Self> {
+        let created_at = chrono::Utc::now().timestamp_millis();
+        conn.prepare("INSERT INTO webhook_subscription (url, format, secret, events, team, repo, config, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?
+            .execute(params![
+                egg.url,
+                egg.format,
+                egg.secret,
+                egg.events.join(","),
+                egg.team,
+                egg.repo,
+                egg.config,
+                created_at
+            ])?;
+
+        Ok(WebhookSubscription {
+            id: conn.last_insert_rowid(),
+            url: egg.url.clone(),
+            format: egg.format.clone(),
+            secret: egg.secret.clone(),
+            events: egg.events.clone(),
+            team: egg.team.clone(),
+            repo: egg.repo.clone(),
+            config: egg.config.clone(),
+            created_at,
+        })
+    }
+
+    /// Delete a subscription and its delivery log.
+    pub fn delete(id: i64, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare("DELETE FROM webhook_delivery WHERE subscription_id = ?1")?
+            .execute(params![id])?;
+        conn.prepare("DELETE FROM webhook_subscription WHERE id = ?1")?
+            .execute(params![id])?;
+
+        Ok(())
+    }
+
+    /// Whether a notification of `kind` about these teams, repositories and
+    /// config should be delivered here.
+    pub fn matches(
+        &self,
+        kind: &str,
+        teams: &[&str],
+        repos: &[String],
+        config: Option<&str>,
+    ) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|e| e == kind))
+            && self
+                .team
+                .as_deref()
+                .is_none_or(|team| teams.contains(&team))
+            && self
+                .repo
+                .as_deref()
+                .is_none_or(|repo| repos.iter().any(|r| r.eq_ignore_ascii_case(repo)))
+            && self.config.as_deref().is_none_or(|c| config == Some(c))
+    }
+}
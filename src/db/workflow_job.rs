@@ -0,0 +1,173 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// A job of a workflow run attempt, with its steps.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkflowJob {
+    pub id: u64,
+    pub run_id: u64,
+    pub run_attempt: u32,
+    pub repo_id: u64,
+    pub head_sha: String,
+    pub name: String,
+    /// `queued`, `waiting`, `in_progress` or `completed`
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub runner_name: Option<String>,
+    pub html_url: Option<String>,
+    /// When the job was queued.
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub completed_at: Option<u64>,
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkflowStep {
+    pub number: u32,
+    pub name: String,
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub started_at: Option<u64>,
+    pub completed_at: Option<u64>,
+}
+
+const COLUMNS: &str = "id, run_id, run_attempt, repo_id, head_sha, name, status, conclusion, runner_name, html_url, created_at, started_at, completed_at, steps";
+
+impl WorkflowJob {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let steps: String = row.get(13)?;
+        Ok(WorkflowJob {
+            id: row.get(0)?,
+            run_id: row.get(1)?,
+            run_attempt: row.get(2)?,
+            repo_id: row.get(3)?,
+            head_sha: row.get(4)?,
+            name: row.get(5)?,
+            status: row.get(6)?,
+            conclusion: row.get(7)?,
+            runner_name: row.get(8)?,
+            html_url: row.get(9)?,
+            created_at: row.get(10)?,
+            started_at: row.get(11)?,
+            completed_at: row.get(12)?,
+            steps: serde_json::from_str(&steps).unwrap_or_default(),
+        })
+    }
+
+    /// Insert or update the job. A completed job stays completed, whatever
+    /// order its events are processed in.
+    pub fn upsert(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare(&format!(
+            "INSERT INTO workflow_job ({})
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                 status = excluded.status,
+                 conclusion = excluded.conclusion,
+                 runner_name = excluded.runner_name,
+                 html_url = excluded.html_url,
+                 started_at = excluded.started_at,
+                 completed_at = excluded.completed_at,
+                 steps = excluded.steps
+             WHERE workflow_job.status != 'completed' OR excluded.status = 'completed'",
+            COLUMNS
+        ))?
+        .execute(params![
+            self.id,
+            self.run_id,
+            self.run_attempt,
+            self.repo_id,
+            self.head_sha,
+            self.name,
+            self.status,
+            self.conclusion,
+            self.runner_name,
+            self.html_url,
+            self.created_at,
+            self.started_at,
+            self.completed_at,
+            serde_json::to_string(&self.steps).unwrap_or_else(|_| "[]".to_string())
+        ])?;
+
+        Ok(())
+    }
+
+    /// The jobs of every run and attempt of a commit, in the order they were
+    /// queued.
+    pub fn get_by_commit(
+        repo_id: u64,
+        sha: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let jobs = conn
+            .prepare(&format!(
+                "SELECT {} FROM workflow_job WHERE repo_id = ?1 AND head_sha = ?2 ORDER BY created_at, id",
+                COLUMNS
+            ))?
+            .query_map(params![repo_id, sha], WorkflowJob::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(jobs)
+    }
+
+    /// Average run time (excluding time queued) of the last `limit`
+    /// successful jobs of this name in the repo.
+    pub fn avg_duration_ms(
+        repo_id: u64,
+        name: &str,
+        limit: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<u64>> {
+        let avg: Option<f64> = conn
+            .prepare(
+                "SELECT AVG(duration) FROM (
+                     SELECT completed_at - started_at AS duration FROM workflow_job
+                     WHERE repo_id = ?1 AND name = ?2 AND conclusion = 'success'
+                       AND started_at IS NOT NULL AND completed_at > started_at
+                     ORDER BY completed_at DESC
+                     LIMIT ?3
+                 )",
+            )?
+            .query_row(params![repo_id, name, limit], |row| row.get(0))?;
+
+        Ok(avg.map(|ms| ms as u64))
+    }
+
+    /// How much longer the job will probably take, given how long it takes
+    /// on average: zero once completed, the whole average while queued.
+    pub fn remaining_ms(&self, avg_ms: Option<u64>, now_ms: u64) -> Option<u64> {
+        if self.status == "completed" {
+            return Some(0);
+        }
+        let avg_ms = avg_ms?;
+        match self.started_at {
+            Some(started) => Some(avg_ms.saturating_sub(now_ms.saturating_sub(started))),
+            None => Some(avg_ms),
+        }
+    }
+
+    /// How much longer the commit's unfinished jobs will probably take, by
+    /// each job's own history. Jobs run in parallel, so the slowest decides;
+    /// jobs that have not been queued yet are not accounted for. `None` when
+    /// nothing is unfinished or there is no history.
+    pub fn estimate_remaining_ms(
+        repo_id: u64,
+        sha: &str,
+        now_ms: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Option<u64>> {
+        let mut remaining = None;
+        for job in Self::get_by_commit(repo_id, sha, conn)? {
+            if job.status == "completed" {
+                continue;
+            }
+            let avg_ms = Self::avg_duration_ms(repo_id, &job.name, 10, conn)?;
+            remaining = remaining.max(job.remaining_ms(avg_ms, now_ms));
+        }
+
+        Ok(remaining)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::error::AppResult;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// A GitHub Actions workflow run, as of its latest attempt.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkflowRun {
+    pub id: u64,
+    pub repo_id: u64,
+    pub head_sha: String,
+    pub head_branch: Option<String>,
+    pub name: String,
+    pub run_number: u64,
+    pub run_attempt: u32,
+    /// What triggered the run, e.g. `push` or `pull_request`.
+    pub event: String,
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub html_url: String,
+    pub created_at: u64,
+    pub run_started_at: Option<u64>,
+    pub updated_at: u64,
+}
+
+const COLUMNS: &str = "id, repo_id, head_sha, head_branch, name, run_number, run_attempt, event, status, conclusion, html_url, created_at, run_started_at, updated_at";
+
+impl WorkflowRun {
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(WorkflowRun {
+            id: row.get(0)?,
+            repo_id: row.get(1)?,
+            head_sha: row.get(2)?,
+            head_branch: row.get(3)?,
+            name: row.get(4)?,
+            run_number: row.get(5)?,
+            run_attempt: row.get(6)?,
+            event: row.get(7)?,
+            status: row.get(8)?,
+            conclusion: row.get(9)?,
+            html_url: row.get(10)?,
+            created_at: row.get(11)?,
+            run_started_at: row.get(12)?,
+            updated_at: row.get(13)?,
+        })
+    }
+
+    /// Insert or update the run, unless what is stored is of a later attempt
+    /// or more recent.
+    pub fn upsert(&self, conn: &PooledConnection<SqliteConnectionManager>) -> AppResult<()> {
+        conn.prepare(&format!(
+            "INSERT INTO workflow_run ({})
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                 name = excluded.name,
+                 run_attempt = excluded.run_attempt,
+                 status = excluded.status,
+                 conclusion = excluded.conclusion,
+                 html_url = excluded.html_url,
+                 run_started_at = excluded.run_started_at,
+                 updated_at = excluded.updated_at
+             WHERE excluded.run_attempt > workflow_run.run_attempt
+                OR (excluded.run_attempt = workflow_run.run_attempt AND excluded.updated_at >= workflow_run.updated_at)",
+            COLUMNS
+        ))?
+        .execute(params![
+            self.id,
+            self.repo_id,
+            self.head_sha,
+            self.head_branch,
+            self.name,
+            self.run_number,
+            self.run_attempt,
+            self.event,
+            self.status,
+            self.conclusion,
+            self.html_url,
+            self.created_at,
+            self.run_started_at,
+            self.updated_at
+        ])?;
+
+        Ok(())
+    }
+
+    /// The runs of a commit, in the order they were created.
+    pub fn get_by_commit(
+        repo_id: u64,
+        sha: &str,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Vec<Self>> {
+        let runs = conn
+            .prepare(&format!(
+                "SELECT {} FROM workflow_run WHERE repo_id = ?1 AND head_sha = ?2 ORDER BY created_at, id",
+                COLUMNS
+            ))?
+            .query_map(params![repo_id, sha], WorkflowRun::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(runs)
+    }
+}
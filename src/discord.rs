@@ -0,0 +1,291 @@
+//! Discord notifications for builds, deploys and watchdog alerts.
+//!
+//! Messages go to the channel of the team that owns the repository or deploy
+//! config (`DISCORD_TEAM_CHANNELS`), falling back to `DISCORD_CHANNEL_ID`.
+//! Messages that get updated later (builds and watchdog alerts) are recorded
+//! in SQLite, so a redelivered webhook or a restart edits the original message
+//! instead of posting a new one.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use serenity::all::{ChannelId, CreateMessage, EditMessage, Http, MessageId};
+use serenity::async_trait;
+
+use crate::build_status::BuildStatus;
+use crate::db::deploy_config::DeployConfig;
+use crate::db::discord_message::DiscordMessage;
+use crate::notifications::{Notification, Notifier};
+use crate::prelude::*;
+use crate::webhooks::models::CheckRunEvent;
+use crate::webhooks::WebhookHandler;
+
+/// Where notifications are posted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelRouting {
+    default_channel: Option<u64>,
+    team_channels: HashMap<String, u64>,
+}
+
+impl ChannelRouting {
+    /// Parse `DISCORD_CHANNEL_ID` and `DISCORD_TEAM_CHANNELS`, a comma-separated
+    /// list of `team=channel_id` pairs. Malformed entries are logged and skipped.
+    pub fn new(default_channel: Option<&str>, team_channels: Option<&str>) -> Self {
+        let default_channel = default_channel.and_then(|id| match id.trim().parse() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                log::warn!("Ignoring invalid DISCORD_CHANNEL_ID {:?}", id);
+                None
+            }
+        });
+        let team_channels = team_channels
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let parsed = entry.split_once('=').and_then(|(team, id)| {
+                    Some((team.trim().to_string(), id.trim().parse().ok()?))
+                });
+                if parsed.is_none() {
+                    log::warn!("Ignoring invalid DISCORD_TEAM_CHANNELS entry {:?}", entry);
+                }
+                parsed
+            })
+            .collect();
+
+        Self {
+            default_channel,
+            team_channels,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("DISCORD_CHANNEL_ID").ok().as_deref(),
+            std::env::var("DISCORD_TEAM_CHANNELS").ok().as_deref(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default_channel.is_none() && self.team_channels.is_empty()
+    }
+
+    /// The channel for a team, or the default channel for unknown teams.
+    pub fn channel(&self, team: &str) -> Option<u64> {
+        self.team_channels
+            .get(team)
+            .copied()
+            .or(self.default_channel)
+    }
+
+    /// The distinct channels for a set of teams; the default channel when
+    /// there are none.
+    pub fn channels<'a>(&self, teams: impl IntoIterator<Item = &'a str>) -> BTreeSet<u64> {
+        let channels: BTreeSet<u64> = teams
+            .into_iter()
+            .filter_map(|team| self.channel(team))
+            .collect();
+        if channels.is_empty() {
+            self.default_channel.into_iter().collect()
+        } else {
+            channels
+        }
+    }
+}
+
+/// Posts to Discord as a bot. Handles check run webhooks for build messages and
+/// acts as a [`Notifier`] for everything else.
+#[derive(Clone)]
+pub struct DiscordNotifier {
+    http: Arc<Http>,
+    routing: ChannelRouting,
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl DiscordNotifier {
+    /// `None` unless `DISCORD_BOT_TOKEN` and at least one channel are set.
+    pub fn from_env(pool: Pool<SqliteConnectionManager>) -> Option<Self> {
+        let token = std::env::var("DISCORD_BOT_TOKEN").ok()?;
+        let routing = ChannelRouting::from_env();
+        if routing.is_empty() {
+            log::warn!("DISCORD_BOT_TOKEN is set but no channel is configured, Discord notifications are disabled");
+            return None;
+        }
+        log::info!("Discord notifications enabled");
+        Some(Self {
+            http: Arc::new(Http::new(&token)),
+            routing,
+            pool,
+        })
+    }
+
+    async fn send(&self, channel_id: u64, content: &str) -> AppResult<MessageId> {
+        let message = ChannelId::new(channel_id)
+            .send_message(&self.http, CreateMessage::new().content(content))
+            .await
+            .map_err(|e| AppError::Discord(e.to_string()))?;
+        Ok(message.id)
+    }
+
+    /// Edit the message recorded for `key` in a channel, or post and record a
+    /// new one. With `only_if_new`, an existing message is left alone.
+    async fn upsert(
+        &self,
+        key: &str,
+        channel_id: u64,
+        content: &str,
+        only_if_new: bool,
+    ) -> AppResult<()> {
+        let existing = DiscordMessage::get(key, channel_id, &self.pool.get()?)?;
+        if let Some(existing) = existing {
+            if only_if_new {
+                return Ok(());
+            }
+            let edited = ChannelId::new(channel_id)
+                .edit_message(
+                    &self.http,
+                    MessageId::new(existing.message_id),
+                    EditMessage::new().content(content),
+                )
+                .await;
+            match edited {
+                Ok(_) => return Ok(()),
+                // The message may have been deleted; post a fresh one.
+                Err(e) => log::warn!("Failed to edit Discord message for {}: {}", key, e),
+            }
+        }
+
+        let message_id = self.send(channel_id, content).await?;
+        DiscordMessage {
+            key: key.to_string(),
+            channel_id,
+            message_id: message_id.get(),
+        }
+        .upsert(&self.pool.get()?)
+    }
+
+    fn build_teams(&self, repo_id: u64) -> AppResult<Vec<String>> {
+        Ok(
+            DeployConfig::get_active_by_repo_id(repo_id, &self.pool.get()?)?
+                .into_iter()
+                .map(|dc| dc.team)
+                .collect(),
+        )
+    }
+}
+
+/// The message for a check run in its current state.
+fn build_message(event: &CheckRunEvent) -> String {
+    let run = &event.check_run;
+    let repo = format!("{}/{}", event.repository.owner.login, event.repository.name);
+    let sha = run
+        .check_suite
+        .head_sha
+        .get(..7)
+        .unwrap_or(&run.check_suite.head_sha);
+    let url = run.html_url.as_deref().unwrap_or(&run.details_url);
+
+    let summary = if run.status == "completed" {
+        match BuildStatus::from_conclusion(run.conclusion.as_deref()) {
+            BuildStatus::Success => "✅ Build succeeded",
+            BuildStatus::Failure => "❌ Build failed",
+            BuildStatus::Pending | BuildStatus::None => "⚪ Build finished",
+        }
+    } else {
+        "🔨 Build started"
+    };
+    format!(
+        "{}: {} `{}` at `{}` <{}>",
+        summary, repo, run.name, sha, url
+    )
+}
+
+#[async_trait]
+impl WebhookHandler for DiscordNotifier {
+    async fn handle_check_run(&self, event: CheckRunEvent) -> Result<(), anyhow::Error> {
+        let teams = self.build_teams(event.repository.id)?;
+        let key = format!("build:{}:{}", event.repository.id, event.check_run.id);
+        let content = build_message(&event);
+        // Progress updates before completion would only repeat the start message.
+        let only_if_new = event.check_run.status != "completed";
+
+        for channel_id in self.routing.channels(teams.iter().map(String::as_str)) {
+            self.upsert(&key, channel_id, &content, only_if_new).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    async fn notify(&self, notification: &Notification) -> AppResult<()> {
+        for channel_id in self.routing.channels(notification.teams()) {
+            self.notify_channel(notification, channel_id).await?;
+        }
+        Ok(())
+    }
+}
+
+impl DiscordNotifier {
+    async fn notify_channel(&self, notification: &Notification, channel_id: u64) -> AppResult<()> {
+        let emoji = match notification {
+            // Builds are posted and edited by the check run handler, and
+            // deploys are announced once they have finished.
+            Notification::BuildFinished { .. } | Notification::DeployStarted { .. } => {
+                return Ok(())
+            }
+            // One message per alert, edited when the config recovers.
+            Notification::WatchdogAlert { name, .. } => {
+                let content = format!("🚨 {}", notification.message());
+                return self
+                    .upsert(&watchdog_key(name), channel_id, &content, true)
+                    .await;
+            }
+            Notification::WatchdogRecovered { name, .. } => {
+                let key = watchdog_key(name);
+                if DiscordMessage::get(&key, channel_id, &self.pool.get()?)?.is_none() {
+                    return Ok(());
+                }
+                let content = format!("💚 {}", notification.message());
+                self.upsert(&key, channel_id, &content, false).await?;
+                return DiscordMessage::delete(&key, channel_id, &self.pool.get()?);
+            }
+            Notification::DeployFailed { .. } => "❌",
+            Notification::RolledBack { .. } => "⏪",
+            Notification::Deployed { .. } | Notification::Undeployed { .. } => "🚀",
+            Notification::ConfigOrphaned { .. }
+            | Notification::OrphanCleanupScheduled { .. }
+            | Notification::OrphanCleanedUp { .. } => "🧹",
+        };
+        self.send(channel_id, &format!("{} {}", emoji, notification.message()))
+            .await
+            .map(|_| ())
+    }
+}
+
+fn watchdog_key(name: &str) -> String {
+    format!("watchdog:{}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_teams_to_channels() {
+        let routing = ChannelRouting::new(Some("1"), Some("payments=2, search = 3,bogus,x=y"));
+        assert_eq!(routing.channel("payments"), Some(2));
+        assert_eq!(routing.channel("search"), Some(3));
+        assert_eq!(routing.channel("unknown"), Some(1));
+        assert_eq!(
+            routing.channels(["payments", "search", "other"]),
+            BTreeSet::from([1, 2, 3])
+        );
+        assert_eq!(routing.channels([]), BTreeSet::from([1]));
+
+        let teams_only = ChannelRouting::new(None, Some("payments=2"));
+        assert_eq!(teams_only.channel("unknown"), None);
+        assert!(ChannelRouting::new(None, None).is_empty());
+    }
+}
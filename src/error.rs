@@ -128,6 +128,14 @@ pub enum AppError {
     /// Invalid input errors
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    /// Missing or invalid credentials
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// Authenticated, but not allowed to do this
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
 }
 
 /// Convenience type alias for Results using AppError
@@ -175,6 +183,10 @@ impl ResponseError for AppError {
 
             AppError::InvalidInput(_) | AppError::Parse(_) => StatusCode::BAD_REQUEST,
 
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+
             AppError::Webhook(_) | AppError::Http(_) => StatusCode::BAD_GATEWAY,
         }
     }
@@ -0,0 +1,83 @@
+//! In-process broadcast of build and deploy events for live consumers such as
+//! GraphQL subscriptions.
+//!
+//! Publishing never blocks: events are dropped when nobody is listening, and
+//! slow receivers skip ahead rather than holding up the publisher.
+
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+use crate::db::deploy_event::DeployEvent;
+
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A check run on a commit changed status.
+    Build {
+        repo_id: u64,
+        commit_sha: String,
+        check_name: String,
+        status: String,
+        url: String,
+    },
+    /// A deploy or undeploy was requested.
+    DeployStarted {
+        name: String,
+        action: String,
+        initiator: String,
+    },
+    /// A deploy or undeploy failed.
+    DeployFailed {
+        name: String,
+        action: String,
+        initiator: String,
+        error: String,
+    },
+    /// A deploy event was recorded.
+    Deploy(DeployEvent),
+    /// A deployed config was removed from its repo and marked orphaned.
+    ConfigOrphaned {
+        name: String,
+        namespace: String,
+        team: String,
+        reason: Option<String>,
+    },
+}
+
+fn sender() -> &'static broadcast::Sender<Event> {
+    static SENDER: OnceLock<broadcast::Sender<Event>> = OnceLock::new();
+    SENDER.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+pub fn publish(event: Event) {
+    // An error only means there are no subscribers right now.
+    let _ = sender().send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    sender().subscribe()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_receive_published_events() {
+        let mut rx = subscribe();
+        publish(Event::Build {
+            repo_id: 1,
+            commit_sha: "abc".to_string(),
+            check_name: "run-1".to_string(),
+            status: "Success".to_string(),
+            url: String::new(),
+        });
+
+        match rx.recv().await.unwrap() {
+            Event::Build { commit_sha, .. } => assert_eq!(commit_sha, "abc"),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
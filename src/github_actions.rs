@@ -0,0 +1,365 @@
+//! Re-running checks and dispatching workflows on GitHub.
+//!
+//! Nothing here writes build state: the check runs and workflow runs GitHub
+//! starts in response come back through the webhooks like any other.
+
+use serde::Deserialize;
+
+use crate::auth::authz::Role;
+use crate::auth::CurrentUser;
+use crate::build_status::BuildStatus;
+use crate::crab_ext::{OctocrabExt, Octocrabs};
+use crate::db::git_repo::GitRepo;
+use crate::error::{AppError, AppResult};
+use crate::kubernetes::api::get_all_deploy_configs;
+
+/// GitHub Actions' app id; its check runs are jobs of workflow runs.
+const GITHUB_ACTIONS_APP_ID: u64 = 15368;
+
+#[derive(Deserialize)]
+struct ApiCheckRuns {
+    check_runs: Vec<ApiCheckRun>,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckRun {
+    id: u64,
+    status: String,
+    conclusion: Option<String>,
+    html_url: Option<String>,
+    app: Option<ApiApp>,
+    check_suite: Option<ApiCheckSuite>,
+}
+
+#[derive(Deserialize)]
+struct ApiApp {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckSuite {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiWorkflows {
+    workflows: Vec<ApiWorkflow>,
+}
+
+#[derive(Deserialize)]
+struct ApiWorkflow {
+    id: u64,
+    name: String,
+    path: String,
+    state: String,
+}
+
+/// What to re-run of a commit's failed checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RerunScope {
+    /// Only the checks that failed.
+    Failed,
+    /// Every check of the suites (or workflow runs) with a failure.
+    Suite,
+}
+
+impl RerunScope {
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "failed" => Some(RerunScope::Failed),
+            "suite" => Some(RerunScope::Suite),
+            _ => None,
+        }
+    }
+}
+
+/// One request that re-runs part of a commit's checks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Rerun {
+    FailedJobs { run_id: u64 },
+    WorkflowRun { run_id: u64 },
+    CheckRun { id: u64 },
+    CheckSuite { id: u64 },
+}
+
+impl Rerun {
+    fn route(&self, repo: &GitRepo) -> String {
+        let base = format!("/repos/{}/{}", repo.owner_name, repo.name);
+        match self {
+            Rerun::FailedJobs { run_id } => {
+                format!("{}/actions/runs/{}/rerun-failed-jobs", base, run_id)
+            }
+            Rerun::WorkflowRun { run_id } => format!("{}/actions/runs/{}/rerun", base, run_id),
+            Rerun::CheckRun { id } => format!("{}/check-runs/{}/rerequest", base, id),
+            Rerun::CheckSuite { id } => format!("{}/check-suites/{}/rerequest", base, id),
+        }
+    }
+}
+
+/// The workflow run of a GitHub Actions check run, from its
+/// `.../actions/runs/<run>/job/<job>` URL.
+fn workflow_run_id(run: &ApiCheckRun) -> Option<u64> {
+    if run.app.as_ref().map(|a| a.id) != Some(GITHUB_ACTIONS_APP_ID) {
+        return None;
+    }
+    let url = run.html_url.as_deref()?;
+    let (_, rest) = url.split_once("/actions/runs/")?;
+    rest.split('/').next()?.parse().ok()
+}
+
+/// The requests re-running the failed checks in `scope`. Actions jobs are
+/// re-run through their workflow run, since other apps' checks can only be
+/// rerequested.
+fn reruns(runs: &[ApiCheckRun], scope: RerunScope) -> Vec<Rerun> {
+    let mut reruns = Vec::new();
+    for run in runs {
+        if BuildStatus::of(&run.status, &run.conclusion.as_deref()) != BuildStatus::Failure {
+            continue;
+        }
+        let rerun = match (workflow_run_id(run), scope) {
+            (Some(run_id), RerunScope::Failed) => Rerun::FailedJobs { run_id },
+            (Some(run_id), RerunScope::Suite) => Rerun::WorkflowRun { run_id },
+            (None, RerunScope::Failed) => Rerun::CheckRun { id: run.id },
+            (None, RerunScope::Suite) => match &run.check_suite {
+                Some(suite) => Rerun::CheckSuite { id: suite.id },
+                None => Rerun::CheckRun { id: run.id },
+            },
+        };
+        if !reruns.contains(&rerun) {
+            reruns.push(rerun);
+        }
+    }
+    reruns
+}
+
+/// Re-run the failed checks of a commit. Returns how many requests were
+/// made; fails when nothing failed.
+pub async fn rerun_checks(
+    octocrabs: &Octocrabs,
+    repo: &GitRepo,
+    sha: &str,
+    scope: RerunScope,
+) -> AppResult<usize> {
+    let crab = octocrabs.crab_for(repo).await.ok_or_else(|| {
+        AppError::Forbidden(format!(
+            "No token can access {}/{}",
+            repo.owner_name, repo.name
+        ))
+    })?;
+    let route = format!(
+        "/repos/{}/{}/commits/{}/check-runs?per_page=100",
+        repo.owner_name, repo.name, sha
+    );
+    let runs = crab
+        .get::<ApiCheckRuns, _, ()>(&route, None)
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?
+        .check_runs;
+
+    let reruns = reruns(&runs, scope);
+    if reruns.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "No failed checks on {}",
+            sha
+        )));
+    }
+    for rerun in &reruns {
+        log::info!("Re-running {:?} of {}", rerun, sha);
+        let response = crab._post(rerun.route(repo), None::<&()>).await?;
+        octocrab::map_github_error(response)
+            .await
+            .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
+    }
+    Ok(reruns.len())
+}
+
+/// A workflow that can be started by hand.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DispatchableWorkflow {
+    pub id: u64,
+    pub name: String,
+    pub path: String,
+}
+
+/// Whether a workflow file's triggers include `workflow_dispatch`, in any of
+/// the forms `on:` takes.
+fn has_dispatch_trigger(workflow: &str) -> bool {
+    let Ok(workflow) = serde_yaml::from_str::<serde_yaml::Value>(workflow) else {
+        return false;
+    };
+    match &workflow["on"] {
+        serde_yaml::Value::String(event) => event == "workflow_dispatch",
+        serde_yaml::Value::Sequence(events) => events
+            .iter()
+            .any(|e| e.as_str() == Some("workflow_dispatch")),
+        serde_yaml::Value::Mapping(events) => events.contains_key("workflow_dispatch"),
+        _ => false,
+    }
+}
+
+/// The repo's active workflows with a `workflow_dispatch` trigger on the
+/// default branch.
+pub async fn dispatchable_workflows(
+    octocrabs: &Octocrabs,
+    repo: &GitRepo,
+) -> AppResult<Vec<DispatchableWorkflow>> {
+    let Some(crab) = octocrabs.crab_for(repo).await else {
+        return Ok(vec![]);
+    };
+    let route = format!(
+        "/repos/{}/{}/actions/workflows?per_page=100",
+        repo.owner_name, repo.name
+    );
+    let workflows = crab
+        .get::<ApiWorkflows, _, ()>(&route, None)
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?
+        .workflows;
+
+    let mut dispatchable = Vec::new();
+    for workflow in workflows.into_iter().filter(|w| w.state == "active") {
+        let content = crab
+            .repos(&repo.owner_name, &repo.name)
+            .get_content()
+            .path(&workflow.path)
+            .send()
+            .await
+            .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
+        let file = content
+            .items
+            .first()
+            .and_then(|c| c.decoded_content())
+            .unwrap_or_default();
+        if has_dispatch_trigger(&file) {
+            dispatchable.push(DispatchableWorkflow {
+                id: workflow.id,
+                name: workflow.name,
+                path: workflow.path,
+            });
+        }
+    }
+    Ok(dispatchable)
+}
+
+/// Start a workflow on `branch`. `workflow` is its id or file name.
+pub async fn dispatch_workflow(
+    octocrabs: &Octocrabs,
+    repo: &GitRepo,
+    workflow: &str,
+    branch: &str,
+) -> AppResult<()> {
+    let crab = octocrabs.crab_for(repo).await.ok_or_else(|| {
+        AppError::Forbidden(format!(
+            "No token can access {}/{}",
+            repo.owner_name, repo.name
+        ))
+    })?;
+    log::info!(
+        "Dispatching workflow {} of {}/{} on {}",
+        workflow,
+        repo.owner_name,
+        repo.name,
+        branch
+    );
+    crab.actions()
+        .create_workflow_dispatch(&repo.owner_name, &repo.name, workflow, branch)
+        .send()
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
+    Ok(())
+}
+
+/// Fail unless the user may run CI for `repo`: a deployer on a team with a
+/// deploy config built from it, or an admin. `what` names the action.
+pub async fn authorize(
+    user: &CurrentUser,
+    client: Option<&kube::Client>,
+    repo: &GitRepo,
+    what: &str,
+) -> AppResult<()> {
+    if user.is_admin() {
+        return Ok(());
+    }
+    let configs = match client {
+        Some(client) => get_all_deploy_configs(client).await?,
+        None => vec![],
+    };
+    let teams: Vec<&str> = configs
+        .iter()
+        .filter(|c| {
+            c.artifact_repository()
+                .is_some_and(|a| a.owner == repo.owner_name && a.repo == repo.name)
+        })
+        .map(|c| c.team())
+        .collect();
+    if teams.iter().any(|team| user.can(team, Role::Deployer)) {
+        return Ok(());
+    }
+    match teams.first() {
+        Some(team) => user.authorize(team, Role::Deployer, what),
+        None => user.authorize_admin(what),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn check_run(id: u64, conclusion: &str, app: u64, url: &str, suite: u64) -> ApiCheckRun {
+        ApiCheckRun {
+            id,
+            status: "completed".to_string(),
+            conclusion: Some(conclusion.to_string()),
+            html_url: Some(url.to_string()),
+            app: Some(ApiApp { id: app }),
+            check_suite: Some(ApiCheckSuite { id: suite }),
+        }
+    }
+
+    #[test]
+    fn reruns_failed_checks() {
+        let runs = vec![
+            check_run(
+                1,
+                "failure",
+                GITHUB_ACTIONS_APP_ID,
+                "https://github.com/acme/api/actions/runs/77/job/1",
+                10,
+            ),
+            check_run(
+                2,
+                "timed_out",
+                GITHUB_ACTIONS_APP_ID,
+                "https://github.com/acme/api/actions/runs/77/job/2",
+                10,
+            ),
+            check_run(3, "success", GITHUB_ACTIONS_APP_ID, "", 10),
+            check_run(4, "failure", 42, "https://ci.example.com/4", 20),
+        ];
+
+        assert_eq!(
+            reruns(&runs, RerunScope::Failed),
+            vec![Rerun::FailedJobs { run_id: 77 }, Rerun::CheckRun { id: 4 }]
+        );
+        assert_eq!(
+            reruns(&runs, RerunScope::Suite),
+            vec![
+                Rerun::WorkflowRun { run_id: 77 },
+                Rerun::CheckSuite { id: 20 }
+            ]
+        );
+        assert!(reruns(&runs[2..3], RerunScope::Failed).is_empty());
+    }
+
+    #[test]
+    fn finds_dispatch_triggers() {
+        assert!(has_dispatch_trigger("on: workflow_dispatch\n"));
+        assert!(has_dispatch_trigger("on: [push, workflow_dispatch]\n"));
+        assert!(has_dispatch_trigger(
+            "on:\n  push:\n    branches: [main]\n  workflow_dispatch:\n    inputs: {}\n"
+        ));
+        assert!(!has_dispatch_trigger("on:\n  push:\n"));
+        assert!(!has_dispatch_trigger("jobs: {}\n"));
+    }
+}
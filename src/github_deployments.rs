@@ -66,10 +66,11 @@ pub async fn report_deploy_action(
             )
             .await;
         }
-        // Bounce / ExecuteJob / ToggleAutodeploy don't change which SHA is live.
+        // Bounce / ExecuteJob / the toggles don't change which SHA is live.
         DeployAction::Bounce { .. }
         | DeployAction::ExecuteJob { .. }
-        | DeployAction::ToggleAutodeploy { .. } => {}
+        | DeployAction::ToggleAutodeploy { .. }
+        | DeployAction::ToggleOrphanKeep { .. } => {}
     }
 }
 
@@ -84,7 +85,7 @@ async fn report_success(octocrabs: &Octocrabs, repo: &impl IRepo, sha: &str, env
         return;
     };
 
-    if let Err(e) = create_success(crab, repo, sha, environment).await {
+    if let Err(e) = create_success(octocrabs, &crab, repo, sha, environment).await {
         log::warn!(
             "GitHub deployment report failed for {}/{} env={}: {}",
             repo.owner(),
@@ -96,6 +97,7 @@ async fn report_success(octocrabs: &Octocrabs, repo: &impl IRepo, sha: &str, env
 }
 
 async fn create_success(
+    octocrabs: &Octocrabs,
     crab: &octocrab::Octocrab,
     repo: &impl IRepo,
     sha: &str,
@@ -115,7 +117,8 @@ async fn create_success(
                 "production_environment": true,
             })),
         )
-        .await?;
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
 
     let id = deployment
         .get("id")
@@ -138,7 +141,8 @@ async fn create_success(
                 "description": DESCRIPTION,
             })),
         )
-        .await?;
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
 
     Ok(())
 }
@@ -154,7 +158,7 @@ async fn report_inactive(octocrabs: &Octocrabs, repo: &impl IRepo, environment:
         return;
     };
 
-    if let Err(e) = set_inactive(crab, repo, environment).await {
+    if let Err(e) = set_inactive(octocrabs, &crab, repo, environment).await {
         log::warn!(
             "GitHub deployment deactivate failed for {}/{} env={}: {}",
             repo.owner(),
@@ -166,6 +170,7 @@ async fn report_inactive(octocrabs: &Octocrabs, repo: &impl IRepo, environment:
 }
 
 async fn set_inactive(
+    octocrabs: &Octocrabs,
     crab: &octocrab::Octocrab,
     repo: &impl IRepo,
     environment: &str,
@@ -181,7 +186,8 @@ async fn set_inactive(
             ),
             None::<&()>,
         )
-        .await?;
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
 
     let Some(id) = deployments
         .as_array()
@@ -206,7 +212,8 @@ async fn set_inactive(
                 "environment": environment,
             })),
         )
-        .await?;
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?;
 
     Ok(())
 }
@@ -0,0 +1,95 @@
+//! GraphQL API served at `/api/graphql`, with GraphiQL on GET and
+//! subscriptions over WebSocket at `/api/graphql/ws`.
+
+mod schema;
+mod types;
+
+use actix_web::{guard, web, HttpRequest, HttpResponse, Resource};
+use async_graphql::{http::GraphiQLSource, Context, Schema};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+
+use crate::auth::CurrentUser;
+use crate::crab_ext::Octocrabs;
+use crate::error::{AppError, AppResult};
+
+pub use schema::{MutationRoot, QueryRoot, SubscriptionRoot};
+
+pub type CicdSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+const ENDPOINT: &str = "/api/graphql";
+const SUBSCRIPTION_ENDPOINT: &str = "/api/graphql/ws";
+
+/// Build the schema. Without a Kubernetes client, deploy config fields and
+/// mutations return errors; repository and build data still work.
+pub fn build_schema(
+    pool: Pool<SqliteConnectionManager>,
+    octocrabs: Octocrabs,
+    client: Option<kube::Client>,
+) -> CicdSchema {
+    let mut builder = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(pool)
+        .data(octocrabs);
+    if let Some(client) = client {
+        builder = builder.data(client);
+    }
+    builder.finish()
+}
+
+/// Routes for queries and mutations (POST), GraphiQL (GET) and subscriptions.
+pub fn resources(schema: CicdSchema) -> (Resource, Resource) {
+    (
+        web::resource(ENDPOINT)
+            .app_data(web::Data::new(schema.clone()))
+            .route(web::post().to(graphql))
+            .route(web::get().to(graphiql)),
+        web::resource(SUBSCRIPTION_ENDPOINT)
+            .app_data(web::Data::new(schema))
+            .route(
+                web::get()
+                    .guard(guard::Header("upgrade", "websocket"))
+                    .to(subscription),
+            ),
+    )
+}
+
+/// Execute a query or mutation as the current user.
+async fn graphql(
+    schema: web::Data<CicdSchema>,
+    user: CurrentUser,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(request.into_inner().data(user)).await.into()
+}
+
+async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(
+            GraphiQLSource::build()
+                .endpoint(ENDPOINT)
+                .subscription_endpoint(SUBSCRIPTION_ENDPOINT)
+                .finish(),
+        )
+}
+
+async fn subscription(
+    schema: web::Data<CicdSchema>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    GraphQLSubscription::new(CicdSchema::clone(&schema)).start(&req, payload)
+}
+
+fn conn(ctx: &Context<'_>) -> AppResult<PooledConnection<SqliteConnectionManager>> {
+    let pool = ctx
+        .data::<Pool<SqliteConnectionManager>>()
+        .map_err(|e| AppError::GraphQL(e.message))?;
+    Ok(pool.get()?)
+}
+
+fn kube_client<'a>(ctx: &Context<'a>) -> AppResult<&'a kube::Client> {
+    ctx.data_opt::<kube::Client>()
+        .ok_or_else(|| AppError::KubernetesConfig("Kubernetes client is not available".to_string()))
+}
@@ -0,0 +1,356 @@
+use std::collections::{HashSet, VecDeque};
+
+use async_graphql::{Context, Object, Result, Subscription, ID};
+use futures_util::stream::{self, Stream, StreamExt};
+use kube::ResourceExt;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::audit::AuditSource;
+use crate::auth::CurrentUser;
+use crate::crab_ext::Octocrabs;
+use crate::db::deploy_event::DeployEvent as DbDeployEvent;
+use crate::db::functions::get_commits_since;
+use crate::db::git_branch::GitBranch;
+use crate::db::git_commit::GitCommit;
+use crate::db::git_repo::GitRepo;
+use crate::error::AppError;
+use crate::events::{self, Event};
+use crate::kubernetes::api::{get_all_deploy_configs, get_deploy_config};
+use crate::web::{execute_action, Action};
+
+use super::types::{
+    clamp_limit, Build, BuildEvent, DeployActionResult, DeployConfig, DeployEvent, Repository,
+};
+use super::{conn, kube_client};
+
+/// Default and maximum depth for `parentBuilds`.
+const DEFAULT_PARENT_DEPTH: u32 = 10;
+const MAX_PARENT_DEPTH: u32 = 100;
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Builds of commits from the last hour, newest first.
+    async fn recent_builds(&self, ctx: &Context<'_>) -> Result<Vec<Build>> {
+        let conn = conn(ctx)?;
+        let since = chrono::Utc::now().timestamp() - 60 * 60;
+        Ok(get_commits_since(&conn, since)?
+            .into_iter()
+            .map(Build)
+            .collect())
+    }
+
+    /// The build of a commit, looked up by SHA across all repositories.
+    async fn build(&self, ctx: &Context<'_>, sha: String) -> Result<Option<Build>> {
+        let conn = conn(ctx)?;
+        Ok(GitCommit::find_by_sha(&sha, &conn)?.map(Build))
+    }
+
+    /// Ancestor builds of a commit, breadth first, up to `maxDepth` generations.
+    async fn parent_builds(
+        &self,
+        ctx: &Context<'_>,
+        sha: String,
+        max_depth: Option<u32>,
+    ) -> Result<Vec<Build>> {
+        let conn = conn(ctx)?;
+        let max_depth = max_depth
+            .unwrap_or(DEFAULT_PARENT_DEPTH)
+            .min(MAX_PARENT_DEPTH);
+        let Some(start) = GitCommit::find_by_sha(&sha, &conn)? else {
+            return Ok(vec![]);
+        };
+
+        let mut seen = HashSet::from([start.sha.clone()]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        let mut builds = vec![];
+        while let Some((commit, depth)) = queue.pop_front() {
+            if depth >= max_depth {
+                continue;
+            }
+            for parent in commit.get_parents(&conn)? {
+                if seen.insert(parent.sha.clone()) {
+                    builds.push(Build(parent.clone()));
+                    queue.push_back((parent, depth + 1));
+                }
+            }
+        }
+        Ok(builds)
+    }
+
+    /// Builds of the direct children of a commit.
+    async fn child_builds(&self, ctx: &Context<'_>, sha: String) -> Result<Vec<Build>> {
+        let conn = conn(ctx)?;
+        let Some(commit) = GitCommit::find_by_sha(&sha, &conn)? else {
+            return Ok(vec![]);
+        };
+        Ok(commit.get_children(&conn)?.into_iter().map(Build).collect())
+    }
+
+    async fn repositories(&self, ctx: &Context<'_>) -> Result<Vec<Repository>> {
+        let conn = conn(ctx)?;
+        Ok(GitRepo::get_all(&conn)?
+            .into_iter()
+            .map(Repository)
+            .collect())
+    }
+
+    async fn repository(
+        &self,
+        ctx: &Context<'_>,
+        owner: String,
+        name: String,
+    ) -> Result<Option<Repository>> {
+        let conn = conn(ctx)?;
+        Ok(GitRepo::get_by_name(&owner, &name, &conn)?.map(Repository))
+    }
+
+    /// Active branches of a repository.
+    async fn branches(&self, ctx: &Context<'_>, repo_id: ID) -> Result<Vec<super::types::Branch>> {
+        let conn = conn(ctx)?;
+        Ok(GitBranch::get_active_by_repo(repo_id.parse()?, &conn)?
+            .into_iter()
+            .map(super::types::Branch)
+            .collect())
+    }
+
+    async fn deploy_configs(&self, ctx: &Context<'_>) -> Result<Vec<DeployConfig>> {
+        let client = kube_client(ctx)?;
+        Ok(get_all_deploy_configs(client)
+            .await?
+            .into_iter()
+            .map(DeployConfig)
+            .collect())
+    }
+
+    async fn deploy_config(&self, ctx: &Context<'_>, name: String) -> Result<Option<DeployConfig>> {
+        let client = kube_client(ctx)?;
+        Ok(get_deploy_config(client, &name).await?.map(DeployConfig))
+    }
+
+    /// Recent deploy events for a config, newest first.
+    async fn deploy_history(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        limit: Option<u32>,
+    ) -> Result<Vec<DeployEvent>> {
+        let conn = conn(ctx)?;
+        Ok(
+            DbDeployEvent::get_recent_by_name(&name, clamp_limit(limit), &conn)?
+                .into_iter()
+                .map(DeployEvent)
+                .collect(),
+        )
+    }
+}
+
+pub struct MutationRoot;
+
+impl MutationRoot {
+    async fn run(
+        &self,
+        ctx: &Context<'_>,
+        name: &str,
+        action: Action,
+    ) -> Result<DeployActionResult> {
+        let client = kube_client(ctx)?;
+        let octocrabs = ctx.data::<Octocrabs>()?;
+        let config = get_deploy_config(client, name)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Deploy config {}", name)))?;
+
+        let pool = ctx.data::<Pool<SqliteConnectionManager>>()?;
+        let user = ctx.data::<CurrentUser>()?;
+        execute_action(
+            &action,
+            &config,
+            client,
+            pool,
+            octocrabs,
+            user,
+            AuditSource::Api,
+        )
+        .await?;
+
+        Ok(DeployActionResult {
+            name: config.name_any(),
+            action: action.describe(),
+        })
+    }
+}
+
+#[Object]
+impl MutationRoot {
+    /// Deploy a config: the given SHA, the head of the given branch, or the
+    /// latest build of its default branch.
+    async fn deploy(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        branch: Option<String>,
+        sha: Option<String>,
+    ) -> Result<DeployActionResult> {
+        let action = Action::parse("deploy", branch.as_deref(), sha.as_deref())
+            .ok_or_else(|| AppError::InvalidInput("Invalid deploy".to_string()))?;
+        self.run(ctx, &name, action).await
+    }
+
+    async fn undeploy(&self, ctx: &Context<'_>, name: String) -> Result<DeployActionResult> {
+        self.run(ctx, &name, Action::Undeploy).await
+    }
+
+    /// Restart the Deployments owned by a config.
+    async fn bounce(&self, ctx: &Context<'_>, name: String) -> Result<DeployActionResult> {
+        self.run(ctx, &name, Action::Bounce).await
+    }
+
+    /// Trigger the CronJobs owned by a config.
+    async fn execute_job(&self, ctx: &Context<'_>, name: String) -> Result<DeployActionResult> {
+        self.run(ctx, &name, Action::ExecuteJob).await
+    }
+
+    async fn toggle_autodeploy(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> Result<DeployActionResult> {
+        self.run(ctx, &name, Action::ToggleAutodeploy).await
+    }
+
+    /// Pause or resume cleanup of an orphaned config.
+    async fn toggle_orphan_keep(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> Result<DeployActionResult> {
+        self.run(ctx, &name, Action::ToggleOrphanKeep).await
+    }
+}
+
+/// Every event published after subscribing. A receiver that falls behind
+/// skips the events it missed.
+fn event_stream() -> impl Stream<Item = Event> {
+    stream::unfold(events::subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("GraphQL subscriber lagged, skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Check run status changes, optionally for one repository.
+    async fn build_events(&self, repo_id: Option<ID>) -> Result<impl Stream<Item = BuildEvent>> {
+        let repo_id: Option<u64> = repo_id.map(|id| id.parse()).transpose()?;
+        Ok(event_stream().filter_map(move |event| async move {
+            match event {
+                Event::Build {
+                    repo_id: id,
+                    commit_sha,
+                    check_name,
+                    status,
+                    url,
+                } if repo_id.is_none_or(|r| r == id) => Some(BuildEvent {
+                    repo_id: ID(id.to_string()),
+                    commit_sha,
+                    check_name,
+                    status,
+                    url,
+                }),
+                _ => None,
+            }
+        }))
+    }
+
+    /// Deploy events as they are recorded, optionally for one config.
+    async fn deploy_events(&self, name: Option<String>) -> impl Stream<Item = DeployEvent> {
+        event_stream().filter_map(move |event| {
+            let name = name.clone();
+            async move {
+                match event {
+                    Event::Deploy(event) if name.as_ref().is_none_or(|n| *n == event.name) => {
+                        Some(DeployEvent(event))
+                    }
+                    _ => None,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::super::build_schema;
+    use super::*;
+    use crate::db::git_commit::GitCommitEgg;
+
+    fn commit(sha: &str, parents: &[&str], conn: &r2d2::PooledConnection<SqliteConnectionManager>) {
+        let commit = GitCommit::upsert(
+            &GitCommitEgg {
+                sha: sha.to_string(),
+                repo_id: 1,
+                message: format!("commit {}", sha),
+                author: "a".to_string(),
+                committer: "a".to_string(),
+                timestamp: 0,
+            },
+            conn,
+        )
+        .unwrap();
+        commit
+            .add_parent_shas(parents.iter().map(|p| p.to_string()).collect(), conn)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn walks_commit_graph() {
+        let pool = crate::db::test_pool();
+        {
+            let conn = pool.get().unwrap();
+            GitRepo {
+                id: 1,
+                owner_name: "o".to_string(),
+                name: "r".to_string(),
+                default_branch: "main".to_string(),
+                private: false,
+                language: None,
+            }
+            .upsert(&conn)
+            .unwrap();
+            commit("a", &[], &conn);
+            commit("b", &["a"], &conn);
+            commit("c", &["b"], &conn);
+        }
+
+        let schema = build_schema(pool, Octocrabs::default(), None);
+        let response = schema
+            .execute(
+                r#"{
+                    build(sha: "b") { status commit { parentShas children { sha } } }
+                    parentBuilds(sha: "c", maxDepth: 1) { commit { sha } }
+                }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["build"]["status"], "None");
+        assert_eq!(data["build"]["commit"]["parentShas"][0], "a");
+        assert_eq!(data["build"]["commit"]["children"][0]["sha"], "c");
+        assert_eq!(data["parentBuilds"].as_array().unwrap().len(), 1);
+        assert_eq!(data["parentBuilds"][0]["commit"]["sha"], "b");
+    }
+}
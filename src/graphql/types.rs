@@ -0,0 +1,469 @@
+use async_graphql::{Context, Json, Object, Result, SimpleObject, ID};
+use kube::ResourceExt;
+use serde_json::Value;
+
+use crate::build_status::BuildStatus;
+use crate::db::deploy_event::DeployEvent as DbDeployEvent;
+use crate::db::git_branch::GitBranch;
+use crate::db::git_commit::GitCommit;
+use crate::db::git_commit_build::GitCommitBuild;
+use crate::db::git_repo::GitRepo;
+use crate::kubernetes::api::{list_namespace_objects, ListMode};
+use crate::kubernetes::repo::DeploymentState;
+use crate::kubernetes::DeployConfig as KubeDeployConfig;
+use crate::web::ResourceStatuses;
+
+use super::{conn, kube_client};
+
+const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 500;
+
+pub(super) fn clamp_limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+}
+
+pub struct Repository(pub GitRepo);
+
+#[Object]
+impl Repository {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn owner(&self) -> &str {
+        &self.0.owner_name
+    }
+
+    async fn default_branch(&self) -> &str {
+        &self.0.default_branch
+    }
+
+    async fn is_private(&self) -> bool {
+        self.0.private
+    }
+
+    async fn language(&self) -> Option<&str> {
+        self.0.language.as_deref()
+    }
+
+    /// Active branches, by name.
+    async fn branches(&self, ctx: &Context<'_>) -> Result<Vec<Branch>> {
+        let conn = conn(ctx)?;
+        Ok(GitBranch::get_active_by_repo(self.0.id, &conn)?
+            .into_iter()
+            .map(Branch)
+            .collect())
+    }
+
+    async fn commit(&self, ctx: &Context<'_>, sha: String) -> Result<Option<Commit>> {
+        let conn = conn(ctx)?;
+        Ok(GitCommit::get_by_sha(&sha, self.0.id, &conn)?.map(Commit))
+    }
+}
+
+pub struct Branch(pub GitBranch);
+
+#[Object]
+impl Branch {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn head_commit_sha(&self) -> &str {
+        &self.0.head_commit_sha
+    }
+
+    async fn head_commit(&self, ctx: &Context<'_>) -> Result<Option<Commit>> {
+        let conn = conn(ctx)?;
+        Ok(GitCommit::get_by_sha(&self.0.head_commit_sha, self.0.repo_id, &conn)?.map(Commit))
+    }
+
+    /// Recent commits on this branch, newest first.
+    async fn commits(&self, ctx: &Context<'_>, limit: Option<u32>) -> Result<Vec<Commit>> {
+        let conn = conn(ctx)?;
+        Ok(self
+            .0
+            .recent_commits(clamp_limit(limit), &conn)?
+            .into_iter()
+            .map(Commit)
+            .collect())
+    }
+
+    async fn repository(&self, ctx: &Context<'_>) -> Result<Option<Repository>> {
+        let conn = conn(ctx)?;
+        Ok(GitRepo::get_by_id(&self.0.repo_id, &conn)?.map(Repository))
+    }
+}
+
+pub struct Commit(pub GitCommit);
+
+#[Object]
+impl Commit {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    async fn sha(&self) -> &str {
+        &self.0.sha
+    }
+
+    async fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    async fn timestamp(&self) -> i64 {
+        self.0.timestamp
+    }
+
+    async fn author(&self) -> &str {
+        &self.0.author
+    }
+
+    async fn committer(&self) -> &str {
+        &self.0.committer
+    }
+
+    async fn parent_shas(&self, ctx: &Context<'_>) -> Result<Vec<String>> {
+        let conn = conn(ctx)?;
+        Ok(self
+            .0
+            .get_parents(&conn)?
+            .into_iter()
+            .map(|c| c.sha)
+            .collect())
+    }
+
+    async fn parents(&self, ctx: &Context<'_>) -> Result<Vec<Commit>> {
+        let conn = conn(ctx)?;
+        Ok(self.0.get_parents(&conn)?.into_iter().map(Commit).collect())
+    }
+
+    /// Commits that have this commit as a parent.
+    async fn children(&self, ctx: &Context<'_>) -> Result<Vec<Commit>> {
+        let conn = conn(ctx)?;
+        Ok(self
+            .0
+            .get_children(&conn)?
+            .into_iter()
+            .map(Commit)
+            .collect())
+    }
+
+    /// Aggregate status across all checks: `None`, `Pending`, `Success` or `Failure`.
+    async fn build_status(&self, ctx: &Context<'_>) -> Result<String> {
+        let conn = conn(ctx)?;
+        let status: BuildStatus = self.0.get_build_status(&conn)?.into();
+        Ok(status.into())
+    }
+
+    async fn checks(&self, ctx: &Context<'_>) -> Result<Vec<BuildCheck>> {
+        let conn = conn(ctx)?;
+        Ok(
+            GitCommitBuild::get_all_by_commit_id(&self.0.id, &self.0.repo_id, &conn)?
+                .into_iter()
+                .map(BuildCheck)
+                .collect(),
+        )
+    }
+
+    async fn branches(&self, ctx: &Context<'_>) -> Result<Vec<Branch>> {
+        let conn = conn(ctx)?;
+        Ok(self
+            .0
+            .get_branches(&conn)?
+            .into_iter()
+            .map(Branch)
+            .collect())
+    }
+
+    async fn repository(&self, ctx: &Context<'_>) -> Result<Option<Repository>> {
+        let conn = conn(ctx)?;
+        Ok(GitRepo::get_by_id(&self.0.repo_id, &conn)?.map(Repository))
+    }
+}
+
+/// A single check run on a commit.
+pub struct BuildCheck(pub GitCommitBuild);
+
+#[Object]
+impl BuildCheck {
+    async fn name(&self) -> &str {
+        &self.0.check_name
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn url(&self) -> &str {
+        &self.0.url
+    }
+
+    /// Unix milliseconds.
+    async fn start_time(&self) -> Option<u64> {
+        self.0.start_time
+    }
+
+    /// Unix milliseconds.
+    async fn settle_time(&self) -> Option<u64> {
+        self.0.settle_time
+    }
+
+    async fn app_id(&self) -> Option<u64> {
+        self.0.app_id
+    }
+}
+
+/// The aggregate build of a commit.
+pub struct Build(pub GitCommit);
+
+#[Object]
+impl Build {
+    async fn commit(&self) -> Commit {
+        Commit(self.0.clone())
+    }
+
+    async fn repository(&self, ctx: &Context<'_>) -> Result<Option<Repository>> {
+        let conn = conn(ctx)?;
+        Ok(GitRepo::get_by_id(&self.0.repo_id, &conn)?.map(Repository))
+    }
+
+    async fn status(&self, ctx: &Context<'_>) -> Result<String> {
+        let conn = conn(ctx)?;
+        let status: BuildStatus = self.0.get_build_status(&conn)?.into();
+        Ok(status.into())
+    }
+
+    /// The failing or pending check's URL, otherwise the first check's.
+    async fn url(&self, ctx: &Context<'_>) -> Result<Option<String>> {
+        let conn = conn(ctx)?;
+        Ok(self.0.get_build_status(&conn)?.map(|b| b.url))
+    }
+
+    async fn branches(&self, ctx: &Context<'_>) -> Result<Vec<Branch>> {
+        let conn = conn(ctx)?;
+        Ok(self
+            .0
+            .get_branches(&conn)?
+            .into_iter()
+            .map(Branch)
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Version {
+    sha: String,
+    branch: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct DriftedResource {
+    kind: String,
+    name: String,
+    fields: Vec<String>,
+}
+
+pub struct DeployConfig(pub KubeDeployConfig);
+
+impl DeployConfig {
+    fn versions(&self) -> (&'static str, Option<Version>, Option<Version>) {
+        match self.0.deployment_state() {
+            DeploymentState::DeployedWithArtifact { artifact, config } => (
+                "deployed",
+                Some(Version {
+                    sha: artifact.sha,
+                    branch: artifact.branch,
+                }),
+                Some(Version {
+                    sha: config.sha,
+                    branch: config.branch,
+                }),
+            ),
+            DeploymentState::DeployedOnlyConfig { config } => (
+                "deployed_config_only",
+                None,
+                Some(Version {
+                    sha: config.sha,
+                    branch: config.branch,
+                }),
+            ),
+            DeploymentState::Undeployed => ("undeployed", None, None),
+        }
+    }
+}
+
+#[Object]
+impl DeployConfig {
+    async fn name(&self) -> String {
+        self.0.name_any()
+    }
+
+    async fn namespace(&self) -> String {
+        self.0.namespace().unwrap_or_else(|| "default".to_string())
+    }
+
+    async fn team(&self) -> &str {
+        self.0.team()
+    }
+
+    async fn kind(&self) -> &str {
+        self.0.kind()
+    }
+
+    /// `deployed`, `deployed_config_only` or `undeployed`.
+    async fn state(&self) -> &'static str {
+        self.versions().0
+    }
+
+    async fn artifact(&self) -> Option<Version> {
+        self.versions().1
+    }
+
+    async fn config(&self) -> Option<Version> {
+        self.versions().2
+    }
+
+    async fn autodeploy(&self) -> bool {
+        self.0.autodeploy()
+    }
+
+    async fn orphaned(&self) -> bool {
+        self.0.is_orphaned()
+    }
+
+    async fn orphan_reason(&self) -> Option<&str> {
+        self.0.orphan_reason()
+    }
+
+    async fn orphan_kept(&self) -> bool {
+        self.0.is_orphan_kept()
+    }
+
+    async fn supports_bounce(&self) -> bool {
+        self.0.supports_bounce()
+    }
+
+    async fn supports_execute_job(&self) -> bool {
+        self.0.supports_execute_job()
+    }
+
+    /// `owner/repo` of the artifact repository, if any.
+    async fn artifact_repo(&self) -> Option<String> {
+        self.0
+            .artifact_repository()
+            .map(|r| format!("{}/{}", r.owner, r.repo))
+    }
+
+    async fn config_repo(&self) -> String {
+        let repo = self.0.config_repository();
+        format!("{}/{}", repo.owner, repo.repo)
+    }
+
+    async fn drifted(&self) -> Vec<DriftedResource> {
+        self.0
+            .drifted_resources()
+            .iter()
+            .map(|d| DriftedResource {
+                kind: d.kind.clone(),
+                name: d.name.clone(),
+                fields: d.fields.clone(),
+            })
+            .collect()
+    }
+
+    /// Child resources and their live status, as shown on the deploy page.
+    async fn resources(&self, ctx: &Context<'_>) -> Result<Json<Vec<Value>>> {
+        let client = kube_client(ctx)?;
+        let namespace = self.0.namespace().unwrap_or_else(|| "default".to_string());
+        let namespaced_objs = list_namespace_objects(client, &namespace, ListMode::All).await?;
+        Ok(Json(self.0.format_resources_json(&namespaced_objs)))
+    }
+
+    /// Recent deploy events, newest first.
+    async fn history(&self, ctx: &Context<'_>, limit: Option<u32>) -> Result<Vec<DeployEvent>> {
+        let conn = conn(ctx)?;
+        Ok(
+            DbDeployEvent::get_recent_by_name(&self.0.name_any(), clamp_limit(limit), &conn)?
+                .into_iter()
+                .map(DeployEvent)
+                .collect(),
+        )
+    }
+}
+
+pub struct DeployEvent(pub DbDeployEvent);
+
+#[Object]
+impl DeployEvent {
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Unix milliseconds.
+    async fn timestamp(&self) -> i64 {
+        self.0.timestamp
+    }
+
+    async fn initiator(&self) -> &str {
+        &self.0.initiator
+    }
+
+    async fn artifact_sha(&self) -> Option<&str> {
+        self.0.artifact_sha.as_deref()
+    }
+
+    async fn artifact_branch(&self) -> Option<&str> {
+        self.0.artifact_branch.as_deref()
+    }
+
+    async fn config_sha(&self) -> Option<&str> {
+        self.0.config_sha.as_deref()
+    }
+
+    async fn config_branch(&self) -> Option<&str> {
+        self.0.config_branch.as_deref()
+    }
+
+    async fn prev_artifact_sha(&self) -> Option<&str> {
+        self.0.prev_artifact_sha.as_deref()
+    }
+
+    async fn prev_config_sha(&self) -> Option<&str> {
+        self.0.prev_config_sha.as_deref()
+    }
+}
+
+/// A check run changed status.
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct BuildEvent {
+    pub repo_id: ID,
+    pub commit_sha: String,
+    pub check_name: String,
+    pub status: String,
+    pub url: String,
+}
+
+#[async_graphql::ComplexObject]
+impl BuildEvent {
+    async fn commit(&self, ctx: &Context<'_>) -> Result<Option<Commit>> {
+        let conn = conn(ctx)?;
+        let repo_id: u64 = self.repo_id.parse()?;
+        Ok(GitCommit::get_by_sha(&self.commit_sha, repo_id, &conn)?.map(Commit))
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DeployActionResult {
+    pub name: String,
+    pub action: String,
+}
@@ -1,4 +1,5 @@
-use crate::kubernetes::{DeployConfig, DeployConfigStatusBuilder};
+use crate::kubernetes::deploy_config::{defining_repo_selector, CONFIG_REPO_LABEL};
+use crate::kubernetes::{DeployConfig, DeployConfigStatusBuilder, Repository};
 use crate::prelude::*;
 use k8s_openapi::api::core::v1::Namespace;
 use kube::api::{DeleteParams, GroupVersionKind, PostParams, TypeMeta};
@@ -85,6 +86,38 @@ pub async fn delete_dynamic_object(client: Client, obj: &DynamicObject) -> AppRe
     }
 }
 
+/// Fetch the live version of `obj` (matched by GVK and name), if it exists
+pub async fn get_dynamic_object(
+    client: &Client,
+    ns: &str,
+    obj: &DynamicObject,
+) -> AppResult<Option<DynamicObject>> {
+    let name = obj.name_any();
+    let types = obj
+        .types
+        .as_ref()
+        .ok_or_else(|| AppError::Internal("missing types on DynamicObject".to_string()))?;
+    let gvk = GroupVersionKind::try_from(types)
+        .map_err(|e| AppError::Internal(format!("failed parsing GVK: {}", e)))?;
+
+    let (ar, caps) = pinned_kind(client, &gvk)
+        .await
+        .map_err(|e| AppError::Internal(format!("GVK {gvk:?} not found via discovery: {}", e)))?;
+
+    let api: Api<DynamicObject> = match caps.scope {
+        discovery::Scope::Namespaced => Api::namespaced_with(client.clone(), ns, &ar),
+        discovery::Scope::Cluster => Api::all_with(client.clone(), &ar),
+    };
+
+    match api.get_opt(&name).await.map_err(AppError::Kubernetes)? {
+        Some(mut live) => {
+            live.types = live.types.or(Some(types.clone()));
+            Ok(Some(live))
+        }
+        None => Ok(None),
+    }
+}
+
 pub enum ListMode {
     All,
     Owned,
@@ -212,6 +245,32 @@ pub async fn get_all_deploy_configs(client: &Client) -> AppResult<Vec<DeployConf
     Ok(deploy_configs)
 }
 
+/// All DeployConfigs defined by the `.deploy` directory of `repo`, found by
+/// the defining-repo labels (plus unlabelled legacy configs whose `spec.config`
+/// points at `repo`).
+pub async fn get_deploy_configs_for_config_repo(
+    client: &Client,
+    repo: &Repository,
+) -> AppResult<Vec<DeployConfig>> {
+    let deploy_configs_api: Api<DeployConfig> = Api::all(client.clone());
+
+    let labelled = deploy_configs_api
+        .list(&ListParams::default().labels(&defining_repo_selector(repo)))
+        .await
+        .map_err(AppError::Kubernetes)?
+        .items;
+
+    let legacy = deploy_configs_api
+        .list(&ListParams::default().labels(&format!("!{}", CONFIG_REPO_LABEL)))
+        .await
+        .map_err(AppError::Kubernetes)?
+        .items
+        .into_iter()
+        .filter(|dc| dc.is_defined_by(repo));
+
+    Ok(labelled.into_iter().chain(legacy).collect())
+}
+
 pub async fn get_deploy_config(client: &Client, name: &str) -> AppResult<Option<DeployConfig>> {
     let deploy_configs = get_all_deploy_configs(client).await?;
     let deploy_config = deploy_configs
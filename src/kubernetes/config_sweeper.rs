@@ -0,0 +1,132 @@
+use crate::audit::{AuditRecord, AuditSource};
+use crate::crab_ext::{OctocrabExt, Octocrabs};
+use crate::db::deploy_config::DeployConfig as DbDeployConfig;
+use crate::kubernetes::api::get_all_deploy_configs;
+use crate::kubernetes::webhook_handlers::delete_deploy_config;
+use crate::kubernetes::{DeployConfig, Repository};
+use crate::prelude::*;
+use crate::webhooks::config_sync::fetch_deploy_config_names_by_ref;
+use itertools::Itertools;
+use kube::{Client, ResourceExt};
+use std::time::Duration;
+
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 15 * 60;
+
+/// Periodically compare every config repo's `.deploy` directory against the
+/// DeployConfigs in the cluster, cleaning up configs whose deletion was missed
+/// (dropped webhooks, downtime, configs the database never knew about).
+///
+/// The interval is read from `CONFIG_SWEEP_INTERVAL_SECS` (default 15 minutes).
+pub async fn start_config_sweeper(
+    client: Client,
+    pool: Pool<SqliteConnectionManager>,
+    octocrabs: Octocrabs,
+) {
+    let interval_secs = std::env::var("CONFIG_SWEEP_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_SWEEP_INTERVAL_SECS);
+    log::info!("Starting deleted config sweeper (every {}s)", interval_secs);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    loop {
+        interval.tick().await;
+        if let Err(e) = sweep_deleted_configs(&client, &pool, &octocrabs).await {
+            log::error!("Deleted config sweep failed: {}", e);
+        }
+    }
+}
+
+/// One sweep over all config repos. Repos that can't be read are skipped so a
+/// GitHub error never gets mistaken for every config having been deleted, and
+/// configs that can't be removed are left for the next sweep.
+pub async fn sweep_deleted_configs(
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+) -> AppResult<()> {
+    let deploy_configs = get_all_deploy_configs(client).await?;
+    let config_repos: Vec<Repository> = deploy_configs
+        .iter()
+        .map(|dc| dc.config_repository())
+        .unique()
+        .collect();
+
+    for repo in config_repos {
+        let removed = match find_removed_configs(octocrabs, &repo, &deploy_configs).await {
+            Ok(removed) => removed,
+            Err(e) => {
+                log::warn!("Skipping sweep of {}/{}: {}", repo.owner, repo.repo, e);
+                continue;
+            }
+        };
+
+        for dc in removed {
+            log::info!(
+                "Sweeper found {} deleted from {}/{}",
+                dc.name_any(),
+                repo.owner,
+                repo.repo
+            );
+            let deleted = delete_deploy_config(client, dc).await;
+            AuditRecord::new(
+                "CONFIG_SWEEPER",
+                AuditSource::Automation,
+                "delete-config",
+                &dc.name_any(),
+            )
+            .params(serde_json::json!({ "repo": format!("{}/{}", repo.owner, repo.repo) }))
+            .record(pool, &deleted);
+            if let Err(e) = deleted {
+                log::warn!(
+                    "Sweeper failed to remove {} from {}/{}: {}",
+                    dc.name_any(),
+                    repo.owner,
+                    repo.repo,
+                    e
+                );
+                continue;
+            }
+
+            let conn = pool.get()?;
+            DbDeployConfig::mark_inactive(&dc.name_any(), &conn)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Configs defined by `repo` that no longer exist in its default branch.
+async fn find_removed_configs<'a>(
+    octocrabs: &Octocrabs,
+    repo: &Repository,
+    deploy_configs: &'a [DeployConfig],
+) -> AppResult<Vec<&'a DeployConfig>> {
+    let crab = octocrabs.crab_for(repo).await.ok_or_else(|| {
+        AppError::NotFound(format!(
+            "No octocrab found for repo {}/{}",
+            repo.owner, repo.repo
+        ))
+    })?;
+    let default_branch = crab
+        .repos(&repo.owner, &repo.repo)
+        .get()
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(repo, e))?
+        .default_branch
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "No default branch for {}/{}",
+                repo.owner, repo.repo
+            ))
+        })?;
+
+    let names = fetch_deploy_config_names_by_ref(octocrabs, repo.clone(), &default_branch).await?;
+
+    Ok(deploy_configs
+        .iter()
+        .filter(|dc| dc.is_defined_by(repo))
+        .filter(|dc| !names.contains(&dc.name_any()))
+        .collect())
+}
@@ -1,25 +1,40 @@
 use super::DeployConfig;
 use crate::error::format_error_chain;
-use crate::kubernetes::api::ListMode;
+use crate::kubernetes::api::{get_dynamic_object, update_deploy_config_status, ListMode};
+use crate::kubernetes::drift::{
+    drift_condition, find_drift, DriftPolicy, DriftedResource, DRIFTED_CONDITION,
+};
 use crate::kubernetes::repo::DeploymentState;
 use crate::kubernetes::spec_editing::{WithInjectedEnv, WithVersion};
 use crate::kubernetes::{
     apply, delete_dynamic_object, ensure_namespace_exists, list_namespace_objects,
+    DeployConfigStatusBuilder,
 };
+use crate::notifications::{Notification, Notifiers};
 use crate::prelude::*;
+use crate::web::{check_deploy_config_health, HealthStatus};
 use futures_util::StreamExt;
 use kube::{
     api::{Api, DynamicObject, ResourceExt},
     client::Client,
     runtime::{controller::Action, watcher, Controller},
 };
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
 use std::{sync::Arc, time::Duration};
 
+/// How often each config's health is checked for watchdog alerts.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Context for the controller
-#[derive(Clone)]
 pub struct ControllerContext {
     /// Kubernetes client
     client: Client,
+    notifiers: Notifiers,
+    /// Per config: when its health was last checked and whether it was
+    /// alerting.
+    watchdog: Mutex<HashMap<String, (Instant, bool)>>,
 }
 
 /// The reconciliation function for DeployConfig resources
@@ -44,6 +59,9 @@ async fn reconcile(dc: Arc<DeployConfig>, ctx: Arc<ControllerContext>) -> AppRes
     // CICD_* env vars describing this deploy, injected into every container.
     let deploy_env_vars = dc.deploy_env_vars();
 
+    let mut drifted: Vec<DriftedResource> = Vec::new();
+    let mut corrected: Vec<DriftedResource> = Vec::new();
+
     // Create or update resources as needed
     for resource in dc.resource_specs() {
         let mut obj: DynamicObject = serde_json::from_value(resource.clone()).map_err(|e| {
@@ -62,9 +80,36 @@ async fn reconcile(dc: Arc<DeployConfig>, ctx: Arc<ControllerContext>) -> AppRes
         dc.ensure_owner_reference(&mut obj);
         dc.ensure_labels(&mut obj);
         dc.ensure_annotations(&mut obj);
+
+        if let Some(drift) = detect_drift(client, &ns, &dc, &obj).await {
+            match dc.drift_policy() {
+                DriftPolicy::Report => {
+                    log::info!(
+                        "Drift detected in {}/{} (report only): {}",
+                        ns,
+                        name,
+                        drift.summary()
+                    );
+                    drifted.push(drift);
+                    continue;
+                }
+                DriftPolicy::Correct => {
+                    log::info!(
+                        "Drift detected in {}/{}, re-applying: {}",
+                        ns,
+                        name,
+                        drift.summary()
+                    );
+                    corrected.push(drift);
+                }
+            }
+        }
+
         apply(client, &ns, obj).await?;
     }
 
+    update_drift_status(client, &ns, &dc, drifted, &corrected).await?;
+
     // Prune stale resources
     log::debug!("Pruning stale resources...");
     let objects = list_namespace_objects(client, &ns, ListMode::Owned).await?;
@@ -82,10 +127,171 @@ async fn reconcile(dc: Arc<DeployConfig>, ctx: Arc<ControllerContext>) -> AppRes
     }
     log::debug!("Pruning stale resources complete");
 
+    watch_health(&dc, &ctx).await;
+
     // Requeue reconciliation
     Ok(Action::requeue(Duration::from_secs(5)))
 }
 
+/// Notify when a config becomes unhealthy and when it recovers. Checks are
+/// throttled to [`WATCHDOG_INTERVAL`] per config.
+async fn watch_health(dc: &DeployConfig, ctx: &ControllerContext) {
+    let name = dc.name_any();
+    let was_alerting = {
+        let Ok(watchdog) = ctx.watchdog.lock() else {
+            return;
+        };
+        match watchdog.get(&name) {
+            Some((checked, _)) if checked.elapsed() < WATCHDOG_INTERVAL => return,
+            Some((_, alerting)) => *alerting,
+            None => false,
+        }
+    };
+
+    let (status, message) = match check_deploy_config_health(dc, &ctx.client).await {
+        Ok(health) => health,
+        Err(e) => {
+            log::warn!("Failed to check health of {}: {}", name, e);
+            return;
+        }
+    };
+    let alerting = match status {
+        HealthStatus::Error => true,
+        HealthStatus::Healthy => false,
+        // Keep the previous state until the outcome is clear.
+        HealthStatus::Warning | HealthStatus::Unknown | HealthStatus::Info => was_alerting,
+    };
+    if let Ok(mut watchdog) = ctx.watchdog.lock() {
+        watchdog.insert(name.clone(), (Instant::now(), alerting));
+    }
+
+    let namespace = dc.namespace().unwrap_or_else(|| "default".to_string());
+    let team = dc.team().to_string();
+    match (was_alerting, alerting) {
+        (false, true) => {
+            ctx.notifiers
+                .notify(Notification::WatchdogAlert {
+                    name,
+                    namespace,
+                    team,
+                    message: message.unwrap_or_else(|| "resources are failing".to_string()),
+                })
+                .await
+        }
+        (true, false) => {
+            ctx.notifiers
+                .notify(Notification::WatchdogRecovered {
+                    name,
+                    namespace,
+                    team,
+                })
+                .await
+        }
+        _ => {}
+    }
+}
+
+/// Compare the rendered `desired` child against its live counterpart.
+///
+/// Children that aren't at the deployed version yet are being rolled out, not
+/// drifted, so they're skipped. Lookup failures are logged and treated as no drift
+/// so that the apply still goes ahead.
+async fn detect_drift(
+    client: &Client,
+    ns: &str,
+    dc: &DeployConfig,
+    desired: &DynamicObject,
+) -> Option<DriftedResource> {
+    let live = match get_dynamic_object(client, ns, desired).await {
+        Ok(live) => live?,
+        Err(e) => {
+            log::warn!(
+                "Failed to fetch live {}/{} for drift check: {}",
+                ns,
+                desired.name_any(),
+                e
+            );
+            return None;
+        }
+    };
+
+    if !dc.child_is_up_to_date(&live) {
+        return None;
+    }
+
+    find_drift(desired, &live)
+}
+
+/// Record drift on the DeployConfig status, patching only when something changed.
+/// Each correction counts as detected drift, while reported drift counts once,
+/// when the child first becomes drifted.
+async fn update_drift_status(
+    client: &Client,
+    ns: &str,
+    dc: &DeployConfig,
+    drifted: Vec<DriftedResource>,
+    corrected: &[DriftedResource],
+) -> AppResult<()> {
+    let newly_drifted = drifted.iter().filter(|d| {
+        !dc.drifted_resources()
+            .iter()
+            .any(|p| p.kind == d.kind && p.name == d.name)
+    });
+    for drift in newly_drifted.chain(corrected) {
+        crate::metrics::get().drift_detected.add(
+            1,
+            &[
+                opentelemetry::KeyValue::new("name", dc.name_any()),
+                opentelemetry::KeyValue::new("kind", drift.kind.clone()),
+                opentelemetry::KeyValue::new(
+                    "policy",
+                    format!("{:?}", dc.drift_policy()).to_lowercase(),
+                ),
+            ],
+        );
+    }
+
+    let previous = dc.drift_condition();
+    let condition = drift_condition(&drifted, corrected, previous, dc.metadata.generation);
+
+    let unchanged = dc.drifted_resources() == drifted.as_slice()
+        && previous.is_some_and(|p| {
+            p.status == condition.status
+                && p.reason == condition.reason
+                && p.message == condition.message
+                && p.observed_generation == condition.observed_generation
+        });
+    if unchanged {
+        return Ok(());
+    }
+
+    let mut conditions: Vec<_> = dc
+        .status
+        .as_ref()
+        .and_then(|s| s.conditions.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|c| c.type_ != DRIFTED_CONDITION)
+        .collect();
+    conditions.push(condition);
+
+    let drifted = if drifted.is_empty() {
+        None
+    } else {
+        Some(drifted)
+    };
+
+    update_deploy_config_status(
+        client,
+        ns,
+        &dc.name_any(),
+        DeployConfigStatusBuilder::new()
+            .with_drifted(drifted)
+            .with_conditions(Some(conditions)),
+    )
+    .await
+}
+
 /// Error handler for the controller
 fn error_policy(_dc: Arc<DeployConfig>, error: &AppError, _ctx: Arc<ControllerContext>) -> Action {
     log::error!(
@@ -96,14 +302,11 @@ fn error_policy(_dc: Arc<DeployConfig>, error: &AppError, _ctx: Arc<ControllerCo
 }
 
 /// Start the Kubernetes controller
-pub async fn start_controller(
-    client: Client,
-    _pool: Pool<SqliteConnectionManager>,
-    // discord_notifier: Option<DiscordNotifier>,
-) -> AppResult<()> {
+pub async fn start_controller(client: Client, notifiers: Notifiers) -> AppResult<()> {
     let context = Arc::new(ControllerContext {
         client: client.clone(),
-        // discord_notifier,
+        notifiers,
+        watchdog: Mutex::new(HashMap::new()),
     });
 
     // Create the API for DeployConfig resources
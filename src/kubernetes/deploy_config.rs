@@ -1,11 +1,19 @@
 use std::collections::BTreeMap;
 
+use chrono::{DateTime, Duration, Utc};
+
 use crate::kubernetes::{
+    drift::{DriftPolicy, DriftedResource, DRIFTED_CONDITION},
     repo::{DeploymentState, RepositoryBranch, ShaMaybeBranch},
     Repository,
 };
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
-use kube::{api::DynamicObject, CustomResource, ResourceExt};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, OwnerReference};
+use kube::{api::DynamicObject, CustomResource, CustomResourceExt, ResourceExt};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{ArrayValidation, InstanceType, Schema, SchemaObject, SingleOrVec},
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
@@ -15,8 +23,38 @@ pub const DEPLOY_CONFIG_KIND: &str = if cfg!(feature = "test-crd") {
     "DeployConfig"
 };
 
+/// Labels recording which repo's `.deploy` directory defines a DeployConfig.
+pub const CONFIG_OWNER_LABEL: &str = "cicd.coolkev.com/config-owner";
+pub const CONFIG_REPO_LABEL: &str = "cicd.coolkev.com/config-repo";
+
+/// Label selector matching every DeployConfig defined by `repo`.
+pub fn defining_repo_selector(repo: &Repository) -> String {
+    format!(
+        "{}={},{}={}",
+        CONFIG_OWNER_LABEL, repo.owner, CONFIG_REPO_LABEL, repo.repo
+    )
+}
+
+/// Kubernetes label values are limited to 63 alphanumeric, `-`, `_` or `.`
+/// characters, starting and ending with an alphanumeric.
+fn is_valid_label_value(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 63
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && value.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && value.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
+fn parse_status_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
 /// DeployConfig status information
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct DeployConfigStatus {
     /// Information about the current state of the artifact.
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -33,10 +71,53 @@ pub struct DeployConfigStatus {
     /// Whether the deploy config is orphaned.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub orphaned: Option<bool>,
+
+    /// Why the config was orphaned.
+    #[serde(
+        default,
+        rename = "orphanReason",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub orphan_reason: Option<String>,
+
+    /// When the orphan cleanup timer started (RFC 3339). Shifted forward by the
+    /// time spent kept, so the deadline is always `orphanedAt + grace period`.
+    #[serde(
+        default,
+        rename = "orphanedAt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(schema_with = "date_time_schema")]
+    pub orphaned_at: Option<String>,
+
+    /// Set while a user has paused orphan cleanup with the "keep" override (RFC 3339).
+    #[serde(
+        default,
+        rename = "orphanKeptAt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(schema_with = "date_time_schema")]
+    pub orphan_kept_at: Option<String>,
+
+    /// Whether the upcoming orphan cleanup has already been notified.
+    #[serde(
+        default,
+        rename = "orphanWarned",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub orphan_warned: Option<bool>,
+
+    /// Child resources whose live state differs from the rendered spec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drifted: Option<Vec<DriftedResource>>,
+
+    /// Standard Kubernetes conditions (currently only `Drifted`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conditions: Option<Vec<Condition>>,
 }
 
 /// DeployConfig spec fields represent the desired state for a deployment
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct DeployConfigSpecFields {
     /// Team
     pub team: String,
@@ -54,11 +135,21 @@ pub struct DeployConfigSpecFields {
 
     /// Array of Kubernetes resource manifests
     #[serde(default)]
+    #[schemars(schema_with = "kubernetes_objects_schema")]
     pub specs: Vec<serde_json::Value>,
+
+    /// Whether drifted children are re-applied ("correct") or only reported ("report").
+    #[serde(
+        default,
+        rename = "driftPolicy",
+        skip_serializing_if = "DriftPolicy::is_default"
+    )]
+    #[schemars(schema_with = "drift_policy_schema")]
+    pub drift_policy: DriftPolicy,
 }
 
-/// The DeployConfig CustomResource
-#[derive(CustomResource, Clone, Debug, Deserialize, Serialize)]
+/// Desired state of a DeployConfig, synced from a config repo's `.deploy` directory.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[cfg_attr(
     feature = "test-crd",
     kube(kind = "TestDeployConfig", shortname = "tdc")
@@ -71,7 +162,7 @@ pub struct DeployConfigSpecFields {
     group = "cicd.coolkev.com",
     version = "v1",
     namespaced,
-    schema = "disabled",
+    doc = "A deployable unit defined by `.deploy/<name>.yaml` in a config repository",
     status = "DeployConfigStatus",
     printcolumn = r#"{"name":"Team", "jsonPath":".spec.team", "type": "string"}"#,
     printcolumn = r#"{"name":"Kind", "jsonPath":".spec.kind", "type": "string"}"#,
@@ -92,6 +183,65 @@ pub struct DeployConfigSpec {
 #[cfg(feature = "test-crd")]
 pub type DeployConfig = TestDeployConfig;
 
+/// Arbitrary Kubernetes manifests: objects whose fields the API server must keep
+/// as-is rather than prune.
+fn kubernetes_objects_schema(_: &mut SchemaGenerator) -> Schema {
+    let object = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        extensions: [(
+            "x-kubernetes-preserve-unknown-fields".to_string(),
+            serde_json::Value::Bool(true),
+        )]
+        .into(),
+        ..Default::default()
+    };
+    Schema::Object(SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(SingleOrVec::Single(Box::new(object.into()))),
+            ..Default::default()
+        })),
+        ..Default::default()
+    })
+}
+
+/// `driftPolicy` with its default, so the API server fills it in. The default
+/// isn't derived because the field is skipped when serializing the default.
+fn drift_policy_schema(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema = DriftPolicy::json_schema(gen).into_object();
+    schema.metadata().default = Some(serde_json::json!(DriftPolicy::default()));
+    schema.into()
+}
+
+/// An optional RFC 3339 timestamp stored as a string.
+fn date_time_schema(_: &mut SchemaGenerator) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        format: Some("date-time".to_string()),
+        ..Default::default()
+    };
+    schema
+        .extensions
+        .insert("nullable".to_string(), serde_json::Value::Bool(true));
+    schema.into()
+}
+
+/// Heads the generated CRD, so nobody edits the checked-in copy by hand.
+const CRD_HEADER: &str = "\
+# Generated from src/kubernetes/deploy_config.rs. Do not edit by hand, regenerate with:
+#   cargo run -- crd > kubernetes/deploy-config-crd.yaml
+";
+
+/// The CustomResourceDefinition for DeployConfig, as YAML. This is what
+/// `kubernetes/deploy-config-crd.yaml` is generated from (`cicd crd`).
+pub fn crd_yaml() -> Result<String, serde_yaml::Error> {
+    Ok(format!(
+        "{}{}",
+        CRD_HEADER,
+        serde_yaml::to_string(&DeployConfig::crd())?
+    ))
+}
+
 impl DeployConfig {
     pub fn autodeploy(&self) -> bool {
         self.status
@@ -107,6 +257,59 @@ impl DeployConfig {
             .unwrap_or(false)
     }
 
+    pub fn orphan_reason(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|s| s.orphan_reason.as_deref())
+    }
+
+    pub fn orphaned_at(&self) -> Option<DateTime<Utc>> {
+        parse_status_time(self.status.as_ref()?.orphaned_at.as_deref()?)
+    }
+
+    pub fn orphan_kept_at(&self) -> Option<DateTime<Utc>> {
+        parse_status_time(self.status.as_ref()?.orphan_kept_at.as_deref()?)
+    }
+
+    pub fn is_orphan_kept(&self) -> bool {
+        self.orphan_kept_at().is_some()
+    }
+
+    pub fn orphan_warned(&self) -> bool {
+        self.status
+            .as_ref()
+            .and_then(|s| s.orphan_warned)
+            .unwrap_or(false)
+    }
+
+    /// When this orphaned config will be undeployed and deleted, given the grace
+    /// period. `None` if it isn't orphaned, is being kept, or cleanup is disabled.
+    pub fn orphan_deadline(&self, grace_period: Option<Duration>) -> Option<DateTime<Utc>> {
+        if !self.is_orphaned() || self.is_orphan_kept() {
+            return None;
+        }
+        Some(self.orphaned_at()? + grace_period?)
+    }
+
+    pub fn drift_policy(&self) -> DriftPolicy {
+        self.spec.spec.drift_policy
+    }
+
+    /// Children that drifted from their desired spec, as last observed by the controller.
+    pub fn drifted_resources(&self) -> &[DriftedResource] {
+        self.status
+            .as_ref()
+            .and_then(|s| s.drifted.as_deref())
+            .unwrap_or_default()
+    }
+
+    pub fn drift_condition(&self) -> Option<&Condition> {
+        self.status
+            .as_ref()
+            .and_then(|s| s.conditions.as_ref())
+            .and_then(|c| c.iter().find(|c| c.type_ == DRIFTED_CONDITION))
+    }
+
     pub fn supports_bounce(&self) -> bool {
         self.resource_specs().iter().any(|spec| {
             spec.get("kind")
@@ -285,6 +488,40 @@ impl DeployConfig {
         }
     }
 
+    /// Whether this config is defined by the `.deploy` directory of `repo`.
+    ///
+    /// Configs created before the defining-repo labels existed fall back to
+    /// `spec.config` until the next sync labels them.
+    pub fn is_defined_by(&self, repo: &Repository) -> bool {
+        let labels = self.labels();
+        match (
+            labels.get(CONFIG_OWNER_LABEL),
+            labels.get(CONFIG_REPO_LABEL),
+        ) {
+            (Some(owner), Some(name)) => owner == &repo.owner && name == &repo.repo,
+            _ => &self.config_repository() == repo,
+        }
+    }
+
+    /// Label the config with the repo that defines it (from `spec.config`).
+    /// Repos whose names aren't valid label values are left unlabelled and are
+    /// matched through [`Self::is_defined_by`]'s `spec.config` fallback instead.
+    pub fn ensure_defining_repo_labels(&mut self) {
+        let repo = self.config_repository();
+        if !is_valid_label_value(&repo.owner) || !is_valid_label_value(&repo.repo) {
+            log::warn!(
+                "Config repo {}/{} can't be used as a label value, leaving {} unlabelled",
+                repo.owner,
+                repo.repo,
+                self.name_any()
+            );
+            return;
+        }
+        let labels = self.labels_mut();
+        labels.insert(CONFIG_OWNER_LABEL.to_string(), repo.owner);
+        labels.insert(CONFIG_REPO_LABEL.to_string(), repo.repo);
+    }
+
     /// Ensure the annotations are set on a child resource
     pub fn ensure_annotations<T: ResourceExt>(&self, resource: &mut T) {
         let annotations = resource
@@ -334,3 +571,29 @@ impl DeployConfig {
         }
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "test-crd"))]
+    fn checked_in_crd_matches_generated() {
+        assert!(
+            include_str!("../../kubernetes/deploy-config-crd.yaml") == crd_yaml().unwrap(),
+            "kubernetes/deploy-config-crd.yaml is stale, regenerate it with `cargo run -- crd`"
+        );
+    }
+
+    #[test]
+    fn specs_keep_unknown_fields() {
+        let crd = serde_json::to_value(DeployConfig::crd()).unwrap();
+        let specs = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]
+            ["properties"]["specs"];
+        assert_eq!(
+            specs["items"]["x-kubernetes-preserve-unknown-fields"],
+            serde_json::Value::Bool(true)
+        );
+    }
+}
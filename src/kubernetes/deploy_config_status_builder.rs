@@ -1,4 +1,6 @@
-use crate::kubernetes::repo::ShaMaybeBranch;
+use crate::kubernetes::{drift::DriftedResource, repo::ShaMaybeBranch};
+use chrono::{DateTime, Utc};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
 
 /// Builder for patch updates to DeployConfigStatus.
 /// Since the values are optional, we need to use Option<Option<String>> to represent them in this builder.
@@ -8,8 +10,14 @@ use crate::kubernetes::repo::ShaMaybeBranch;
 pub struct DeployConfigStatusBuilder {
     autodeploy: Option<Option<bool>>,
     orphaned: Option<Option<bool>>,
+    orphan_reason: Option<Option<String>>,
+    orphaned_at: Option<Option<String>>,
+    orphan_kept_at: Option<Option<String>>,
+    orphan_warned: Option<Option<bool>>,
     artifact: Option<Option<ShaMaybeBranch>>,
     config: Option<Option<ShaMaybeBranch>>,
+    drifted: Option<Option<Vec<DriftedResource>>>,
+    conditions: Option<Option<Vec<Condition>>>,
 }
 
 impl From<DeployConfigStatusBuilder> for serde_json::Value {
@@ -44,6 +52,30 @@ impl From<DeployConfigStatusBuilder> for serde_json::Value {
             status["orphaned"] = orphaned.into();
         }
 
+        if let Some(orphan_reason) = val.orphan_reason {
+            status["orphanReason"] = orphan_reason.into();
+        }
+
+        if let Some(orphaned_at) = val.orphaned_at {
+            status["orphanedAt"] = orphaned_at.into();
+        }
+
+        if let Some(orphan_kept_at) = val.orphan_kept_at {
+            status["orphanKeptAt"] = orphan_kept_at.into();
+        }
+
+        if let Some(orphan_warned) = val.orphan_warned {
+            status["orphanWarned"] = orphan_warned.into();
+        }
+
+        if let Some(drifted) = val.drifted {
+            status["drifted"] = serde_json::to_value(drifted).unwrap_or_default();
+        }
+
+        if let Some(conditions) = val.conditions {
+            status["conditions"] = serde_json::to_value(conditions).unwrap_or_default();
+        }
+
         serde_json::json!({
             "status": status,
         })
@@ -74,4 +106,52 @@ impl DeployConfigStatusBuilder {
         self.orphaned = Some(orphaned);
         self
     }
+
+    pub fn with_orphan_reason(mut self, orphan_reason: Option<String>) -> Self {
+        self.orphan_reason = Some(orphan_reason);
+        self
+    }
+
+    pub fn with_orphaned_at(mut self, orphaned_at: Option<DateTime<Utc>>) -> Self {
+        self.orphaned_at = Some(orphaned_at.map(|t| t.to_rfc3339()));
+        self
+    }
+
+    pub fn with_orphan_kept_at(mut self, orphan_kept_at: Option<DateTime<Utc>>) -> Self {
+        self.orphan_kept_at = Some(orphan_kept_at.map(|t| t.to_rfc3339()));
+        self
+    }
+
+    pub fn with_orphan_warned(mut self, orphan_warned: Option<bool>) -> Self {
+        self.orphan_warned = Some(orphan_warned);
+        self
+    }
+
+    /// Mark the config orphaned for `reason`, starting a fresh cleanup timer.
+    pub fn orphaned(self, reason: String) -> Self {
+        self.with_orphaned(Some(true))
+            .with_orphan_reason(Some(reason))
+            .with_orphaned_at(Some(Utc::now()))
+            .with_orphan_kept_at(None)
+            .with_orphan_warned(None)
+    }
+
+    /// Clear the orphaned flag and everything tracking its cleanup.
+    pub fn not_orphaned(self) -> Self {
+        self.with_orphaned(Some(false))
+            .with_orphan_reason(None)
+            .with_orphaned_at(None)
+            .with_orphan_kept_at(None)
+            .with_orphan_warned(None)
+    }
+
+    pub fn with_drifted(mut self, drifted: Option<Vec<DriftedResource>>) -> Self {
+        self.drifted = Some(drifted);
+        self
+    }
+
+    pub fn with_conditions(mut self, conditions: Option<Vec<Condition>>) -> Self {
+        self.conditions = Some(conditions);
+        self
+    }
 }
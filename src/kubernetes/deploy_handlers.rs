@@ -37,6 +37,10 @@ pub enum DeployAction {
     ToggleAutodeploy {
         name: String,
     },
+    /// Pause or resume the cleanup timer of an orphaned config.
+    ToggleOrphanKeep {
+        name: String,
+    },
 }
 
 impl DeployAction {
@@ -47,6 +51,7 @@ impl DeployAction {
             DeployAction::Deploy { name, .. } => name,
             DeployAction::Undeploy { name } => name,
             DeployAction::ToggleAutodeploy { name } => name,
+            DeployAction::ToggleOrphanKeep { name } => name,
         }
     }
 
@@ -57,6 +62,7 @@ impl DeployAction {
             DeployAction::Deploy { .. } => "deploy",
             DeployAction::Undeploy { .. } => "undeploy",
             DeployAction::ToggleAutodeploy { .. } => "toggle_autodeploy",
+            DeployAction::ToggleOrphanKeep { .. } => "toggle_orphan_keep",
         }
     }
 
@@ -184,6 +190,38 @@ impl DeployAction {
                 Ok(())
             }
 
+            DeployAction::ToggleOrphanKeep { name } => {
+                let current_config = get_deploy_config(client, name)
+                    .await?
+                    .ok_or(AppError::NotFound("Current config not found".to_owned()))?;
+
+                if !current_config.is_orphaned() {
+                    return Err(AppError::InvalidInput(format!("{} is not orphaned", name)));
+                }
+
+                let namespace = current_config.namespace().unwrap_or_default();
+
+                let update = match current_config.orphan_kept_at() {
+                    // Resuming: push the timer forward by the time spent kept so
+                    // the remaining grace period is unchanged.
+                    Some(kept_at) => DeployConfigStatusBuilder::default()
+                        .with_orphaned_at(
+                            current_config
+                                .orphaned_at()
+                                .map(|t| t + (Utc::now() - kept_at)),
+                        )
+                        .with_orphan_kept_at(None)
+                        .with_orphan_warned(None),
+                    None => {
+                        DeployConfigStatusBuilder::default().with_orphan_kept_at(Some(Utc::now()))
+                    }
+                };
+
+                update_deploy_config_status(client, &namespace, name, update).await?;
+
+                Ok(())
+            }
+
             DeployAction::Bounce { name } => {
                 log::debug!("Bounce action: name={}", name);
                 let current_config = get_deploy_config(client, name)
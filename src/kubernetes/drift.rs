@@ -0,0 +1,422 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use kube::api::{DynamicObject, ResourceExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Condition type used on DeployConfig status to report drift.
+pub const DRIFTED_CONDITION: &str = "Drifted";
+
+/// What the controller does when a live child no longer matches its rendered spec.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DriftPolicy {
+    /// Re-apply the desired spec, overwriting the out-of-band change.
+    #[default]
+    Correct,
+    /// Leave the live object alone and only surface the drift.
+    Report,
+}
+
+impl DriftPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == DriftPolicy::default()
+    }
+}
+
+/// A single child resource whose managed fields differ from the desired spec.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct DriftedResource {
+    pub kind: String,
+    pub name: String,
+    /// Paths of the drifted fields, e.g. `spec.replicas`.
+    pub fields: Vec<String>,
+}
+
+impl DriftedResource {
+    pub fn summary(&self) -> String {
+        format!("{} {}: {}", self.kind, self.name, self.fields.join(", "))
+    }
+}
+
+/// Compare a live object against the rendered desired object.
+///
+/// Only fields present in the desired object are checked (the ones our field
+/// manager applies), so defaults filled in by the API server and fields owned
+/// by other managers are ignored. From `metadata` only labels and annotations
+/// are compared.
+pub fn find_drift(desired: &DynamicObject, live: &DynamicObject) -> Option<DriftedResource> {
+    let mut fields = Vec::new();
+
+    let desired_labels = serde_json::to_value(&desired.metadata.labels).unwrap_or_default();
+    let live_labels = serde_json::to_value(&live.metadata.labels).unwrap_or_default();
+    diff_value(
+        &desired_labels,
+        &live_labels,
+        "metadata.labels",
+        &mut fields,
+    );
+
+    let desired_annotations =
+        serde_json::to_value(&desired.metadata.annotations).unwrap_or_default();
+    let live_annotations = serde_json::to_value(&live.metadata.annotations).unwrap_or_default();
+    diff_value(
+        &desired_annotations,
+        &live_annotations,
+        "metadata.annotations",
+        &mut fields,
+    );
+
+    if let Value::Object(desired_data) = &desired.data {
+        for (key, value) in desired_data {
+            if key == "status" {
+                continue;
+            }
+            let live_value = live.data.get(key).unwrap_or(&Value::Null);
+            diff_value(value, live_value, key, &mut fields);
+        }
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    Some(DriftedResource {
+        kind: desired
+            .types
+            .as_ref()
+            .map(|t| t.kind.clone())
+            .unwrap_or_default(),
+        name: desired.name_any(),
+        fields,
+    })
+}
+
+/// Recursively collect paths where `live` does not satisfy `desired`.
+fn diff_value(desired: &Value, live: &Value, path: &str, out: &mut Vec<String>) {
+    match (desired, live) {
+        (Value::Null, _) => {}
+        (Value::Object(desired), Value::Object(live)) => {
+            for (key, value) in desired {
+                let live_value = live.get(key).unwrap_or(&Value::Null);
+                diff_value(value, live_value, &format!("{}.{}", path, key), out);
+            }
+        }
+        (Value::Array(desired), Value::Array(live)) => diff_array(desired, live, path, out),
+        _ => {
+            if !scalars_match(desired, live) {
+                out.push(path.to_string());
+            }
+        }
+    }
+}
+
+/// Lists of named objects (containers, env, ports, volumes) are merged by name
+/// on the server, so match them by name. Everything else is an atomic list and
+/// must match element for element.
+fn diff_array(desired: &[Value], live: &[Value], path: &str, out: &mut Vec<String>) {
+    let is_named_list = !desired.is_empty()
+        && desired
+            .iter()
+            .all(|v| v.get("name").and_then(|n| n.as_str()).is_some());
+
+    if !is_named_list {
+        if desired.len() != live.len() {
+            out.push(path.to_string());
+            return;
+        }
+        for (idx, (d, l)) in desired.iter().zip(live).enumerate() {
+            diff_value(d, l, &format!("{}[{}]", path, idx), out);
+        }
+        return;
+    }
+
+    for item in desired {
+        let name = item
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or_default();
+        let item_path = format!("{}[{}]", path, name);
+        let candidates: Vec<&Value> = live
+            .iter()
+            .filter(|v| v.get("name").and_then(|n| n.as_str()) == Some(name))
+            .collect();
+
+        if candidates.is_empty() {
+            out.push(item_path);
+            continue;
+        }
+
+        // Names aren't always unique (e.g. volumeMounts), so any clean match wins.
+        let mut best: Option<Vec<String>> = None;
+        for candidate in candidates {
+            let mut found = Vec::new();
+            diff_value(item, candidate, &item_path, &mut found);
+            if best.as_ref().is_none_or(|b| found.len() < b.len()) {
+                best = Some(found);
+            }
+        }
+        out.extend(best.unwrap_or_default());
+    }
+}
+
+/// Scalars are compared loosely: the API server normalizes numbers written as
+/// strings (and vice versa) and canonicalizes resource quantities.
+fn scalars_match(desired: &Value, live: &Value) -> bool {
+    if desired == live {
+        return true;
+    }
+
+    let (Some(desired), Some(live)) = (scalar_string(desired), scalar_string(live)) else {
+        return false;
+    };
+    if desired == live {
+        return true;
+    }
+
+    match (parse_quantity(&desired), parse_quantity(&live)) {
+        (Some(d), Some(l)) => (d - l).abs() <= f64::EPSILON * d.abs().max(l.abs()).max(1.0),
+        _ => false,
+    }
+}
+
+fn scalar_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Parse a Kubernetes resource quantity (`500m`, `1Gi`, `2`) into base units.
+fn parse_quantity(s: &str) -> Option<f64> {
+    const SUFFIXES: &[(&str, f64)] = &[
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Pi", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Ei", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("m", 1e-3),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+
+    for (suffix, multiplier) in SUFFIXES {
+        if let Some(number) = s.strip_suffix(suffix) {
+            return number.parse::<f64>().ok().map(|n| n * multiplier);
+        }
+    }
+    s.parse::<f64>().ok()
+}
+
+/// Build the `Drifted` condition for the given drift results, keeping the
+/// previous transition time when the condition status hasn't changed.
+pub fn drift_condition(
+    drifted: &[DriftedResource],
+    corrected: &[DriftedResource],
+    previous: Option<&Condition>,
+    observed_generation: Option<i64>,
+) -> Condition {
+    let (status, reason, message) = if !drifted.is_empty() {
+        (
+            "True",
+            "DriftDetected",
+            drifted
+                .iter()
+                .map(|d| d.summary())
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    } else if !corrected.is_empty() {
+        (
+            "False",
+            "DriftCorrected",
+            format!(
+                "Re-applied desired spec over: {}",
+                corrected
+                    .iter()
+                    .map(|d| d.summary())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        )
+    } else {
+        (
+            "False",
+            "InSync",
+            "Live resources match the desired specs".to_string(),
+        )
+    };
+
+    let last_transition_time = previous
+        .filter(|c| c.status == status)
+        .map(|c| c.last_transition_time.clone())
+        .unwrap_or_else(|| Time(chrono::Utc::now()));
+
+    Condition {
+        type_: DRIFTED_CONDITION.to_string(),
+        status: status.to_string(),
+        reason: reason.to_string(),
+        message,
+        last_transition_time,
+        observed_generation,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> DynamicObject {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn deployment(replicas: Value, image: &str) -> Value {
+        json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "app", "labels": { "app.kubernetes.io/managed-by": "cicd-controller" } },
+            "spec": {
+                "replicas": replicas,
+                "template": { "spec": { "containers": [
+                    {
+                        "name": "app",
+                        "image": image,
+                        "resources": { "requests": { "cpu": "0.5", "memory": "1024Mi" } }
+                    }
+                ] } }
+            }
+        })
+    }
+
+    #[test]
+    fn no_drift_when_live_only_adds_defaults() {
+        let desired = object(deployment(json!(2), "app:abc"));
+        let mut live_json = deployment(json!(2), "app:abc");
+        live_json["spec"]["strategy"] = json!({ "type": "RollingUpdate" });
+        live_json["spec"]["template"]["spec"]["containers"][0]["imagePullPolicy"] =
+            json!("IfNotPresent");
+        live_json["spec"]["template"]["spec"]["containers"][0]["resources"]["requests"] =
+            json!({ "cpu": "500m", "memory": "1Gi" });
+        live_json["status"] = json!({ "replicas": 5 });
+        let live = object(live_json);
+
+        assert_eq!(find_drift(&desired, &live), None);
+    }
+
+    #[test]
+    fn detects_changed_scalar_fields() {
+        let desired = object(deployment(json!(2), "app:abc"));
+        let live = object(deployment(json!(5), "app:hotfix"));
+
+        let drift = find_drift(&desired, &live).unwrap();
+        assert_eq!(drift.kind, "Deployment");
+        assert_eq!(drift.name, "app");
+        assert_eq!(
+            drift.fields,
+            vec![
+                "spec.replicas".to_string(),
+                "spec.template.spec.containers[app].image".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn named_lists_match_by_name_not_position() {
+        let desired = object(json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": { "name": "svc" },
+            "spec": { "ports": [
+                { "name": "http", "port": 80 },
+                { "name": "grpc", "port": 9000 }
+            ] }
+        }));
+        let live = object(json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": { "name": "svc" },
+            "spec": { "ports": [
+                { "name": "grpc", "port": "9000", "protocol": "TCP" },
+                { "name": "http", "port": 80, "protocol": "TCP" },
+                { "name": "debug", "port": 6060 }
+            ] }
+        }));
+
+        assert_eq!(find_drift(&desired, &live), None);
+    }
+
+    #[test]
+    fn missing_named_entry_and_atomic_list_change_are_drift() {
+        let desired = object(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "p" },
+            "spec": { "containers": [
+                { "name": "app", "args": ["--port", "80"], "env": [ { "name": "A", "value": "1" } ] }
+            ] }
+        }));
+        let live = object(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "p" },
+            "spec": { "containers": [
+                { "name": "app", "args": ["--port", "80", "--debug"], "env": [] }
+            ] }
+        }));
+
+        let drift = find_drift(&desired, &live).unwrap();
+        assert_eq!(
+            drift.fields,
+            vec![
+                "spec.containers[app].args".to_string(),
+                "spec.containers[app].env[A]".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_label_is_drift() {
+        let desired = object(deployment(json!(1), "app:abc"));
+        let mut live_json = deployment(json!(1), "app:abc");
+        live_json["metadata"]["labels"] = json!({ "team": "other" });
+        let live = object(live_json);
+
+        let drift = find_drift(&desired, &live).unwrap();
+        assert_eq!(
+            drift.fields,
+            vec!["metadata.labels.app.kubernetes.io/managed-by".to_string()]
+        );
+    }
+
+    #[test]
+    fn condition_keeps_transition_time_while_status_is_unchanged() {
+        let drifted = vec![DriftedResource {
+            kind: "Deployment".to_string(),
+            name: "app".to_string(),
+            fields: vec!["spec.replicas".to_string()],
+        }];
+        let first = drift_condition(&drifted, &[], None, Some(1));
+        assert_eq!(first.status, "True");
+        assert_eq!(first.reason, "DriftDetected");
+        assert_eq!(first.message, "Deployment app: spec.replicas");
+
+        let mut earlier = first.clone();
+        earlier.last_transition_time = Time(chrono::DateTime::UNIX_EPOCH);
+        let second = drift_condition(&drifted, &[], Some(&earlier), Some(1));
+        assert_eq!(second.last_transition_time, earlier.last_transition_time);
+
+        let cleared = drift_condition(&[], &[], Some(&earlier), Some(1));
+        assert_eq!(cleared.status, "False");
+        assert_eq!(cleared.reason, "InSync");
+        assert_ne!(cleared.last_transition_time, earlier.last_transition_time);
+    }
+}
@@ -1,8 +1,11 @@
 pub mod api;
+pub mod config_sweeper;
 pub mod controller;
 pub mod deploy_config;
 pub mod deploy_config_status_builder;
 pub mod deploy_handlers;
+pub mod drift;
+pub mod orphan_lifecycle;
 pub mod repo;
 pub mod spec_editing;
 pub mod webhook_handlers;
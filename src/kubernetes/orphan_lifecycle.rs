@@ -0,0 +1,285 @@
+use crate::audit::{AuditRecord, AuditSource};
+use crate::crab_ext::Octocrabs;
+use crate::db::deploy_event::DeployEvent;
+use crate::kubernetes::api::{get_all_deploy_configs, update_deploy_config_status};
+use crate::kubernetes::deploy_handlers::DeployAction;
+use crate::kubernetes::{DeployConfig, DeployConfigStatusBuilder};
+use crate::notifications::{Notification, Notifiers};
+use crate::prelude::*;
+use chrono::Duration;
+use kube::{Client, ResourceExt};
+
+const REAP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+const DEFAULT_GRACE_PERIOD_HOURS: i64 = 7 * 24;
+const DEFAULT_WARNING_HOURS: i64 = 24;
+
+/// The initiator of cleanups in the deploy history and audit log.
+const ORPHAN_CLEANUP: &str = "ORPHAN_CLEANUP";
+
+/// How long orphaned configs are kept around before being cleaned up.
+#[derive(Clone, Debug)]
+pub struct OrphanPolicy {
+    /// `None` disables automatic cleanup.
+    pub grace_period: Option<Duration>,
+    /// How long before the deadline to send the cleanup notification.
+    pub warning_lead: Duration,
+}
+
+impl OrphanPolicy {
+    /// Read `ORPHAN_GRACE_PERIOD_HOURS` (default 168, `0` disables cleanup) and
+    /// `ORPHAN_WARNING_HOURS` (default 24).
+    pub fn from_env() -> Self {
+        let hours = |var: &str, default: i64| {
+            std::env::var(var)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 0)
+                .unwrap_or(default)
+        };
+
+        let grace_hours = hours("ORPHAN_GRACE_PERIOD_HOURS", DEFAULT_GRACE_PERIOD_HOURS);
+        Self {
+            grace_period: (grace_hours > 0).then(|| Duration::hours(grace_hours)),
+            warning_lead: Duration::hours(hours("ORPHAN_WARNING_HOURS", DEFAULT_WARNING_HOURS)),
+        }
+    }
+
+    /// Whether `deadline` is close enough that users should be warned.
+    pub fn is_within_warning(&self, deadline: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        now >= deadline - self.warning_lead
+    }
+}
+
+/// What the reaper should do with a config on this pass.
+#[derive(Debug, PartialEq)]
+enum OrphanStep {
+    /// Not orphaned, kept, or cleanup disabled.
+    Nothing,
+    /// Orphaned before timers existed: start one now.
+    StartTimer,
+    /// Deadline is close: notify once.
+    Warn(DateTime<Utc>),
+    /// Deadline passed: undeploy and delete.
+    CleanUp,
+}
+
+fn next_step(dc: &DeployConfig, policy: &OrphanPolicy, now: DateTime<Utc>) -> OrphanStep {
+    if !dc.is_orphaned() || dc.is_orphan_kept() || policy.grace_period.is_none() {
+        return OrphanStep::Nothing;
+    }
+    let Some(deadline) = dc.orphan_deadline(policy.grace_period) else {
+        return OrphanStep::StartTimer;
+    };
+
+    if now >= deadline {
+        OrphanStep::CleanUp
+    } else if policy.is_within_warning(deadline, now) && !dc.orphan_warned() {
+        OrphanStep::Warn(deadline)
+    } else {
+        OrphanStep::Nothing
+    }
+}
+
+/// Periodically notify about and clean up orphaned configs whose grace period
+/// has run out.
+pub async fn start_orphan_reaper(
+    client: Client,
+    pool: Pool<SqliteConnectionManager>,
+    octocrabs: Octocrabs,
+    notifiers: Notifiers,
+) {
+    let policy = OrphanPolicy::from_env();
+    match policy.grace_period {
+        Some(grace) => log::info!(
+            "Starting orphan reaper (grace period {}h, warning {}h before)",
+            grace.num_hours(),
+            policy.warning_lead.num_hours()
+        ),
+        None => log::info!("ORPHAN_GRACE_PERIOD_HOURS=0, orphaned configs are never cleaned up"),
+    }
+
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let configs = match get_all_deploy_configs(&client).await {
+            Ok(configs) => configs,
+            Err(e) => {
+                log::error!("Orphan reaper failed to list DeployConfigs: {}", e);
+                continue;
+            }
+        };
+
+        for dc in configs.iter().filter(|dc| dc.is_orphaned()) {
+            if let Err(e) = reap_orphan(&client, &pool, &octocrabs, &notifiers, &policy, dc).await {
+                log::error!("Orphan reaper failed for {}: {}", dc.name_any(), e);
+            }
+        }
+    }
+}
+
+async fn reap_orphan(
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+    notifiers: &Notifiers,
+    policy: &OrphanPolicy,
+    dc: &DeployConfig,
+) -> AppResult<()> {
+    let name = dc.name_any();
+    let namespace = dc.namespace().unwrap_or_default();
+
+    match next_step(dc, policy, Utc::now()) {
+        OrphanStep::Nothing => {}
+        OrphanStep::StartTimer => {
+            log::info!("Starting orphan cleanup timer for {}/{}", namespace, name);
+            update_deploy_config_status(
+                client,
+                &namespace,
+                &name,
+                DeployConfigStatusBuilder::new().with_orphaned_at(Some(Utc::now())),
+            )
+            .await?;
+        }
+        OrphanStep::Warn(deadline) => {
+            notifiers
+                .notify(Notification::OrphanCleanupScheduled {
+                    name: name.clone(),
+                    namespace: namespace.clone(),
+                    team: dc.team().to_string(),
+                    reason: dc.orphan_reason().map(|r| r.to_string()),
+                    deadline,
+                })
+                .await;
+            update_deploy_config_status(
+                client,
+                &namespace,
+                &name,
+                DeployConfigStatusBuilder::new().with_orphan_warned(Some(true)),
+            )
+            .await?;
+        }
+        OrphanStep::CleanUp => {
+            log::info!(
+                "Grace period expired for orphaned {}/{}, undeploying",
+                namespace,
+                name
+            );
+            let deploy_action = DeployAction::Undeploy { name: name.clone() };
+            let result = deploy_action
+                .execute(client, octocrabs, dc.config_repository())
+                .await;
+            crate::metrics::get().deploy_actions.add(
+                1,
+                &[
+                    opentelemetry::KeyValue::new("name", name.clone()),
+                    opentelemetry::KeyValue::new("action", deploy_action.action_type()),
+                    opentelemetry::KeyValue::new(
+                        "result",
+                        if result.is_ok() { "success" } else { "error" },
+                    ),
+                ],
+            );
+            AuditRecord::new(ORPHAN_CLEANUP, AuditSource::Automation, "undeploy", &name)
+                .params(serde_json::json!({ "reason": dc.orphan_reason().map(|r| r.to_string()) }))
+                .record(pool, &result);
+            result?;
+
+            crate::github_deployments::report_deploy_action(octocrabs, dc, &deploy_action).await;
+
+            let conn = pool.get()?;
+            if let Some(event) =
+                DeployEvent::from_user_deploy_action(&deploy_action, &conn, dc, ORPHAN_CLEANUP)?
+            {
+                event.insert(&conn)?;
+            }
+
+            notifiers
+                .notify(Notification::OrphanCleanedUp {
+                    name,
+                    namespace,
+                    team: dc.team().to_string(),
+                })
+                .await;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy() -> OrphanPolicy {
+        OrphanPolicy {
+            grace_period: Some(Duration::hours(48)),
+            warning_lead: Duration::hours(24),
+        }
+    }
+
+    fn config(status: serde_json::Value) -> DeployConfig {
+        serde_json::from_value(json!({
+            "apiVersion": "cicd.coolkev.com/v1",
+            "kind": "DeployConfig",
+            "metadata": { "name": "app", "namespace": "apps" },
+            "spec": {
+                "team": "platform",
+                "kind": "service",
+                "config": { "owner": "o", "repo": "r" },
+                "specs": []
+            },
+            "status": status
+        }))
+        .unwrap()
+    }
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + Duration::hours(hours)
+    }
+
+    #[test]
+    fn ignores_configs_that_are_not_orphaned() {
+        let dc = config(json!({ "orphaned": false }));
+        assert_eq!(next_step(&dc, &policy(), at(100)), OrphanStep::Nothing);
+    }
+
+    #[test]
+    fn starts_timer_for_legacy_orphans() {
+        let dc = config(json!({ "orphaned": true }));
+        assert_eq!(next_step(&dc, &policy(), at(100)), OrphanStep::StartTimer);
+    }
+
+    #[test]
+    fn warns_once_inside_warning_window_then_cleans_up() {
+        let dc = config(json!({ "orphaned": true, "orphanedAt": at(0).to_rfc3339() }));
+        assert_eq!(next_step(&dc, &policy(), at(10)), OrphanStep::Nothing);
+        assert_eq!(next_step(&dc, &policy(), at(30)), OrphanStep::Warn(at(48)));
+        assert_eq!(next_step(&dc, &policy(), at(48)), OrphanStep::CleanUp);
+
+        let warned = config(json!({
+            "orphaned": true,
+            "orphanedAt": at(0).to_rfc3339(),
+            "orphanWarned": true
+        }));
+        assert_eq!(next_step(&warned, &policy(), at(30)), OrphanStep::Nothing);
+    }
+
+    #[test]
+    fn kept_orphans_and_disabled_cleanup_are_left_alone() {
+        let kept = config(json!({
+            "orphaned": true,
+            "orphanedAt": at(0).to_rfc3339(),
+            "orphanKeptAt": at(1).to_rfc3339()
+        }));
+        assert_eq!(next_step(&kept, &policy(), at(100)), OrphanStep::Nothing);
+
+        let dc = config(json!({ "orphaned": true, "orphanedAt": at(0).to_rfc3339() }));
+        let disabled = OrphanPolicy {
+            grace_period: None,
+            ..policy()
+        };
+        assert_eq!(next_step(&dc, &disabled, at(100)), OrphanStep::Nothing);
+    }
+}
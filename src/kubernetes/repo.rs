@@ -1,5 +1,6 @@
 use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -28,7 +29,7 @@ impl IRepo for RepositoryBranch {
 }
 
 /// Represents repository information (without branch) for a DeployConfig
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, JsonSchema)]
 pub struct Repository {
     /// GitHub username or organization
     pub owner: String,
@@ -57,7 +58,7 @@ impl RepositoryBranch {
 }
 
 /// Represents repository information (including branch) for a DeployConfig
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct RepositoryBranch {
     /// GitHub username or organization
     pub owner: String,
@@ -70,7 +71,7 @@ pub struct RepositoryBranch {
 }
 
 /// Represents a SHA and optionally a branch that the SHA came from.
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
 pub struct ShaMaybeBranch {
     #[serde(default)]
     pub sha: String,
@@ -139,4 +140,11 @@ impl DeploymentState {
             DeploymentState::Undeployed => None,
         }
     }
+    /// The deployed artifact commit, if an artifact is deployed.
+    pub fn artifact_sha(&self) -> Option<&str> {
+        match self {
+            DeploymentState::DeployedWithArtifact { artifact, .. } => Some(artifact.sha.as_str()),
+            DeploymentState::DeployedOnlyConfig { .. } | DeploymentState::Undeployed => None,
+        }
+    }
 }
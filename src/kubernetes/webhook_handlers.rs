@@ -1,8 +1,9 @@
 use super::DeployConfig;
 use super::Repository;
 use crate::error::format_error_chain;
+use crate::kubernetes::api::{get_deploy_configs_for_config_repo, update_deploy_config_status};
 use crate::kubernetes::repo::DeploymentState;
-use crate::kubernetes::{ensure_namespace_exists, Error};
+use crate::kubernetes::{ensure_namespace_exists, DeployConfigStatusBuilder, Error};
 use crate::prelude::*;
 use itertools::Itertools;
 use kube::api::{DeleteParams, PostParams};
@@ -41,22 +42,19 @@ async fn update_deploy_config(
         .await
         .map_err(Error::App)?;
 
-    // We always use the existing config's specs, since specs are only updated by deploy events.
-    let mut merge_patch = final_config.clone();
-    merge_patch.spec.spec.specs = existing_config.spec.spec.specs.clone();
-
     let api: Api<DeployConfig> = Api::namespaced(client.clone(), &ns);
-    api.patch(&name, &PatchParams::default(), &Patch::Merge(&merge_patch))
-        .await?;
-
-    api.patch_status(
+    api.patch(
         &name,
         &PatchParams::default(),
-        &Patch::Merge(&serde_json::json!({
-            "status": {
-              "orphaned": false,
-            }
-        })),
+        &Patch::Merge(&merge_patch(existing_config, final_config)?),
+    )
+    .await?;
+
+    update_deploy_config_status(
+        client,
+        &ns,
+        &name,
+        DeployConfigStatusBuilder::new().not_orphaned(),
     )
     .await?;
 
@@ -65,6 +63,22 @@ async fn update_deploy_config(
     Ok(())
 }
 
+/// The merge patch bringing `existing_config` in line with `final_config`.
+fn merge_patch(
+    existing_config: &DeployConfig,
+    final_config: &DeployConfig,
+) -> Result<serde_json::Value, Error> {
+    // We always use the existing config's specs, since specs are only updated by deploy events.
+    let mut merge_patch = final_config.clone();
+    merge_patch.spec.spec.specs = existing_config.spec.spec.specs.clone();
+
+    let mut patch = serde_json::to_value(&merge_patch).map_err(anyhow::Error::from)?;
+    // The default policy isn't serialized, but must still replace a stored
+    // non-default one.
+    patch["spec"]["driftPolicy"] = serde_json::json!(final_config.drift_policy());
+    Ok(patch)
+}
+
 async fn create_deploy_config(client: &Client, final_config: &DeployConfig) -> Result<(), Error> {
     let ns = final_config
         .namespace()
@@ -101,7 +115,9 @@ async fn create_deploy_config(client: &Client, final_config: &DeployConfig) -> R
     Ok(())
 }
 
-async fn delete_deploy_config(
+/// Remove a config that no longer exists in its defining repo: delete it if
+/// it's undeployed, otherwise mark it orphaned.
+pub async fn delete_deploy_config(
     client: &Client,
     existing_config: &DeployConfig,
 ) -> Result<(), Error> {
@@ -115,17 +131,27 @@ async fn delete_deploy_config(
         api.delete(&name, &DeleteParams::default()).await?;
 
         log::info!("Deleted DeployConfig {}/{}", ns, name);
+    } else if existing_config.is_orphaned() {
+        log::debug!("DeployConfig {}/{} is already orphaned", ns, name);
     } else {
-        api.patch_status(
+        let config_repo = existing_config.config_repository();
+        let reason = format!(
+            "Removed from .deploy/ in {}/{}",
+            config_repo.owner, config_repo.repo
+        );
+        update_deploy_config_status(
+            client,
+            &ns,
             &name,
-            &PatchParams::default(),
-            &Patch::Merge(&serde_json::json!({
-                "status": {
-                  "orphaned": true,
-                }
-            })),
+            DeployConfigStatusBuilder::new().orphaned(reason.clone()),
         )
         .await?;
+        crate::events::publish(crate::events::Event::ConfigOrphaned {
+            name: name.clone(),
+            namespace: ns.clone(),
+            team: existing_config.team().to_string(),
+            reason: Some(reason),
+        });
 
         log::info!(
             "DeployConfig {}/{} currently deployed, marking as orphaned instead of deleting",
@@ -137,13 +163,19 @@ async fn delete_deploy_config(
     Ok(())
 }
 
+/// Bring the DeployConfigs defined by `defining_repo` in line with `final_deploy_configs`.
+///
+/// Deletions are worked out from the cluster: any DeployConfig labelled as
+/// defined by `defining_repo` that is missing from `final_deploy_configs` is
+/// deleted (if undeployed) or marked orphaned. Returns the names of those
+/// removed configs.
 pub async fn update_deploy_configs_by_defining_repo(
     client: &Client,
     final_deploy_configs: &[DeployConfig],
-    deleted_deploy_config_names: &[String],
-    __defining_repo: &Repository,
-) -> Result<(), Error> {
-    // Find all existing deploy configs for the defining repo
+    defining_repo: &Repository,
+) -> Result<Vec<String>, Error> {
+    // Configs with the same name may already exist (e.g. created before the
+    // defining-repo labels), so creates/updates look at every config.
     let deploy_configs_api: Api<DeployConfig> = Api::all(client.clone());
     let deploy_configs = match deploy_configs_api.list(&Default::default()).await {
         Ok(list) => list.items,
@@ -153,55 +185,85 @@ pub async fn update_deploy_configs_by_defining_repo(
         }
     };
 
-    let new_deploy_config_names = final_deploy_configs
-        .iter()
-        .map(|dc| dc.name_any())
-        .collect::<Vec<String>>();
+    for final_config in final_deploy_configs {
+        let name = final_config.name_any();
+        log::info!("Updating DeployConfig {}", name);
 
-    // FIXME: We should do the check based on the defining_repo,
-    // that way deploy configs get removed properly
-    // But I'm having trouble with `status` right now.
-    // ChatGPT says we should stop using status and use labels or annotations instead.
+        let mut final_config = final_config.clone();
+        final_config.ensure_defining_repo_labels();
 
-    // TODO: Right now we're always filtering to just configs that are present
-    //in the new set, so we never clean up any configs.
-    let matching_configs = deploy_configs
-        .iter()
-        // FIXME: This is not the best way to handle the deleted configs. We should
-        // get this out of the kubernetes API instead of the database.
-        .filter(|dc| {
-            deleted_deploy_config_names.contains(&dc.name_any())
-                || new_deploy_config_names.contains(&dc.name_any())
-        })
-        .collect::<Vec<&DeployConfig>>();
-
-    let all_names = matching_configs
+        match deploy_configs.iter().find(|dc| dc.name_any() == name) {
+            Some(existing_config) => {
+                update_deploy_config(client, existing_config, &final_config).await?;
+            }
+            None => {
+                create_deploy_config(client, &final_config).await?;
+            }
+        }
+    }
+
+    let removed = find_removed_deploy_configs(client, final_deploy_configs, defining_repo).await?;
+    for existing_config in &removed {
+        delete_deploy_config(client, existing_config).await?;
+    }
+
+    Ok(removed.iter().map(|dc| dc.name_any()).collect())
+}
+
+/// DeployConfigs in the cluster defined by `defining_repo` whose names are not
+/// in `current_deploy_configs`.
+async fn find_removed_deploy_configs(
+    client: &Client,
+    current_deploy_configs: &[DeployConfig],
+    defining_repo: &Repository,
+) -> Result<Vec<DeployConfig>, Error> {
+    let current_names = current_deploy_configs
         .iter()
         .map(|dc| dc.name_any())
-        .chain(final_deploy_configs.iter().map(|dc| dc.name_any()))
-        .unique()
         .collect::<Vec<String>>();
 
-    for name in all_names {
-        log::info!("Updating DeployConfig {}", name);
-        let existing_config = matching_configs.iter().find(|dc| dc.name_any() == name);
-        let final_config = final_deploy_configs.iter().find(|dc| dc.name_any() == name);
+    let existing = get_deploy_configs_for_config_repo(client, defining_repo).await?;
 
-        match (existing_config, final_config) {
-            (Some(existing_config), Some(final_config)) => {
-                update_deploy_config(client, existing_config, final_config).await?;
-            }
-            (None, Some(final_config)) => {
-                create_deploy_config(client, final_config).await?;
-            }
-            (Some(existing_config), None) => {
-                delete_deploy_config(client, existing_config).await?;
-            }
-            (None, None) => {
-                // Do nothing
-            }
+    Ok(existing
+        .into_iter()
+        .filter(|dc| !current_names.contains(&dc.name_any()))
+        .unique_by(|dc| dc.name_any())
+        .collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(drift_policy: Option<&str>) -> DeployConfig {
+        let mut spec = json!({
+            "team": "platform",
+            "kind": "service",
+            "config": { "owner": "o", "repo": "r" },
+            "specs": [{ "kind": "Deployment" }]
+        });
+        if let Some(policy) = drift_policy {
+            spec["driftPolicy"] = json!(policy);
         }
+        serde_json::from_value(json!({
+            "apiVersion": "cicd.coolkev.com/v1",
+            "kind": "DeployConfig",
+            "metadata": { "name": "app", "namespace": "apps" },
+            "spec": spec
+        }))
+        .unwrap()
     }
 
-    Ok(())
+    #[test]
+    fn patches_drift_policy_back_to_default() {
+        let existing = config(Some("report"));
+        let patch = merge_patch(&existing, &config(None)).unwrap();
+        assert_eq!(patch["spec"]["driftPolicy"], "correct");
+        assert_eq!(patch["spec"]["specs"], json!([{ "kind": "Deployment" }]));
+
+        let patch = merge_patch(&config(None), &config(Some("report"))).unwrap();
+        assert_eq!(patch["spec"]["driftPolicy"], "report");
+    }
 }
@@ -29,21 +29,38 @@ pub mod prelude {
     pub use std::time::{SystemTime, UNIX_EPOCH};
 }
 
+mod api;
+mod audit;
+mod auth;
 mod build_status;
 mod crab_ext;
 mod db;
+mod discord;
 mod error;
+mod events;
+mod github_actions;
 mod github_deployments;
+mod graphql;
 mod kubernetes;
 mod mcp;
 mod metrics;
+mod notifications;
+mod outgoing_webhooks;
 mod web;
 mod webhooks;
+use std::sync::Arc;
+
 use crate::crab_ext::{initialize_octocrabs, Octocrabs};
 use crate::db::migrations::migrate;
+use crate::discord::DiscordNotifier;
+use crate::kubernetes::config_sweeper::start_config_sweeper;
 use crate::kubernetes::controller::start_controller;
+use crate::kubernetes::orphan_lifecycle::start_orphan_reaper;
+use crate::notifications::{forward_deploy_events, LogNotifier, Notifiers};
+use crate::outgoing_webhooks::WebhookNotifier;
 use crate::prelude::*;
 use crate::web::{branch_grid_fragment, build_grid_fragment, deploy_configs, deploy_preview};
+use crate::webhooks::catchup::CatchUp;
 use crate::webhooks::config_sync::ConfigSyncHandler;
 use crate::webhooks::database::DatabaseHandler;
 use crate::webhooks::manager::WebhookManager;
@@ -59,6 +76,7 @@ async fn start_http(
     registry: prometheus::Registry,
     pool: Pool<SqliteConnectionManager>,
     octocrabs: Octocrabs,
+    webhook_manager: Arc<WebhookManager>,
 ) -> Result<(), std::io::Error> {
     log::info!("Starting HTTP server at http://localhost:8080/api");
 
@@ -78,6 +96,11 @@ async fn start_http(
         }
     };
 
+    let schema = graphql::build_schema(pool.clone(), octocrabs.clone(), kube_client.clone());
+    let auth_state = auth::AuthState::from_env(pool.clone())
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let session_key = auth::session_key().map_err(|e| std::io::Error::other(e.to_string()))?;
+
     HttpServer::new(move || {
         let mut app = App::new();
 
@@ -89,20 +112,27 @@ async fn start_http(
                 .route("/mcp", actix_web::web::post().to(mcp::handle_mcp))
         }
 
-        app.wrap(RequestTracing::new())
+        app.wrap(auth::RequireAuth::new(auth_state.clone()))
+            .wrap(RequestTracing::new())
             .wrap(RequestMetrics::default())
             .route(
                 "/api/metrics",
                 web_get().to(PrometheusMetricsHandler::new(registry.clone())),
             )
             .wrap(
-                SessionMiddleware::builder(CookieSessionStore::default(), Key::from(&[0; 64]))
-                    .cookie_secure(false)
+                SessionMiddleware::builder(CookieSessionStore::default(), session_key.clone())
+                    .cookie_secure(auth_state.secure_cookies())
                     .build(),
             )
             .app_data(Data::new(octocrabs.clone()))
             .app_data(Data::new(pool.clone()))
+            .app_data(Data::new(auth_state.clone()))
+            .app_data(Data::new(webhook_manager.clone()))
             .wrap(middleware::Logger::default())
+            .service(auth::routes())
+            .service(webhooks::http::resource())
+            .service(api::v1_scope())
+            .service(graphql::resources(schema.clone()))
             .service(root)
             .service(deploy_configs)
             .service(index)
@@ -123,6 +153,22 @@ async fn start_http(
             .service(web::rate_limits)
             .service(toggle_team)
             .service(toggle_repo)
+            .service(web::create_webhook)
+            .service(web::delete_webhook)
+            .service(web::test_webhook)
+            .service(web::create_role_binding)
+            .service(web::audit_page)
+            .service(web::audit_export)
+            .service(web::webhook_events_page)
+            .service(web::webhook_event_page)
+            .service(web::replay_webhook_events)
+            .service(web::commit_page)
+            .service(web::commit_dispatch_form)
+            .service(web::rerun_commit_checks)
+            .service(web::dispatch_commit_workflow)
+            .service(web::delete_role_binding)
+            .service(web::create_api_token)
+            .service(web::delete_api_token)
             .service(deploy_preview)
             .service(resource_logs_page)
             .service(resource_logs_fragment)
@@ -144,9 +190,10 @@ async fn poll_github_rate_limits(octocrabs: Octocrabs) {
     let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
     loop {
         interval.tick().await;
-        for (idx, crab) in octocrabs.iter().enumerate() {
-            let label = (idx + 1).to_string();
-            if let Ok(rate_info) = crab.ratelimit().get().await {
+        for client in octocrabs.clients() {
+            let label = client.label;
+            if let Ok(rate_info) = client.crab.ratelimit().get().await {
+                octocrabs.record_quota(&label, rate_info.resources.core.remaining as u64);
                 let m = metrics::get();
                 m.github_rate_limit_remaining
                     .with_label_values(&[&label])
@@ -160,7 +207,7 @@ async fn poll_github_rate_limits(octocrabs: Octocrabs) {
 }
 
 async fn start_kubernetes_controller(
-    pool: Pool<SqliteConnectionManager>,
+    notifiers: Notifiers,
 ) -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Starting Kubernetes controller");
 
@@ -168,7 +215,7 @@ async fn start_kubernetes_controller(
     let client = kube::Client::try_default().await?;
 
     // Start the controller
-    start_controller(client, pool).await?;
+    start_controller(client, notifiers).await?;
 
     Ok(())
 }
@@ -176,6 +223,15 @@ async fn start_kubernetes_controller(
 #[actix_web::main]
 #[allow(clippy::expect_used)]
 async fn main() -> std::io::Result<()> {
+    // `cicd crd` prints the DeployConfig CRD and exits.
+    if std::env::args().nth(1).as_deref() == Some("crd") {
+        print!(
+            "{}",
+            kubernetes::deploy_config::crd_yaml().expect("Failed to serialize CRD")
+        );
+        return Ok(());
+    }
+
     let octocrabs: Octocrabs = initialize_octocrabs();
 
     // Configure logger with custom filter to prioritize Discord logs
@@ -209,6 +265,11 @@ async fn main() -> std::io::Result<()> {
         migrate(conn).expect("Failed to run database migrations");
     }
 
+    let octocrabs = octocrabs.with_cache(pool.clone());
+    if let Err(e) = octocrabs.load_installations().await {
+        log::error!("Failed to load GitHub App installations: {}", e);
+    }
+
     // Initialize Kubernetes client
     let client = kube::Client::try_default()
         .await
@@ -221,9 +282,10 @@ async fn main() -> std::io::Result<()> {
         log::debug!("TEMPLATE_NAMESPACE not set, namespace resource copying disabled");
     }
 
-    let mut webhook_manager = WebhookManager::new(
-        std::env::var("WEBSOCKET_URL").expect("WEBSOCKET_URL must be set"),
-        std::env::var("CLIENT_SECRET").expect("CLIENT_SECRET must be set"),
+    let mut webhook_manager =
+        WebhookManager::from_env(pool.clone()).expect("Failed to configure GitHub event delivery");
+    webhook_manager.set_catchup(
+        CatchUp::from_env(pool.clone(), octocrabs.clone()).expect("Invalid GITHUB_WEBHOOK_HOOKS"),
     );
     webhook_manager.add_handler(DatabaseHandler::new(pool.clone(), octocrabs.clone()));
     webhook_manager.add_handler(MetricsHandler::new());
@@ -233,17 +295,46 @@ async fn main() -> std::io::Result<()> {
         octocrabs.clone(),
     ));
 
+    let mut notifiers = Notifiers::new();
+    notifiers.add(LogNotifier);
+    let outgoing = WebhookNotifier::new(pool.clone());
+    webhook_manager.add_handler(outgoing.clone());
+    notifiers.add(outgoing);
+    if let Some(discord) = DiscordNotifier::from_env(pool.clone()) {
+        webhook_manager.add_handler(discord.clone());
+        notifiers.add(discord);
+    }
+
+    let webhook_manager = Arc::new(webhook_manager);
+
     tokio::select! {
         _ = Box::pin(start_http(
             registry,
             pool.clone(),
             octocrabs.clone(),
+            webhook_manager.clone(),
         )) => {},
         _ = Box::pin(webhook_manager.start()) => {},
+        _ = Box::pin(webhook_manager.prune_events()) => {},
         _ = Box::pin(start_kubernetes_controller(
-            pool.clone()
+            notifiers.clone()
+        )) => {},
+        _ = Box::pin(forward_deploy_events(
+            pool.clone(),
+            notifiers.clone(),
         )) => {},
         _ = Box::pin(poll_github_rate_limits(octocrabs.clone())) => {},
+        _ = Box::pin(start_config_sweeper(
+            client.clone(),
+            pool.clone(),
+            octocrabs.clone(),
+        )) => {},
+        _ = Box::pin(start_orphan_reaper(
+            client.clone(),
+            pool.clone(),
+            octocrabs.clone(),
+            notifiers.clone(),
+        )) => {},
     };
 
     Ok(())
@@ -4,16 +4,18 @@ use r2d2_sqlite::SqliteConnectionManager;
 use serde_json::json;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::auth::CurrentUser;
 use crate::crab_ext::Octocrabs;
 
 use super::protocol::{InitializeResult, JsonRpcRequest, JsonRpcResponse};
-use super::tools;
+use super::{prompts, resources, tools};
 
 pub async fn handle_mcp(
     body: web::Json<JsonRpcRequest>,
     client: web::Data<kube::Client>,
     pool: web::Data<Pool<SqliteConnectionManager>>,
     octocrabs: web::Data<Octocrabs>,
+    user: CurrentUser,
 ) -> HttpResponse {
     let request = body.into_inner();
 
@@ -29,8 +31,26 @@ pub async fn handle_mcp(
         "ping" => JsonRpcResponse::success(request.id, json!({})),
         "tools/list" => handle_tools_list(request.id),
         "tools/call" => {
-            handle_tools_call(request.id, request.params, &client, &pool, &octocrabs).await
+            handle_tools_call(
+                request.id,
+                request.params,
+                &client,
+                &pool,
+                &octocrabs,
+                &user,
+            )
+            .await
         }
+        "resources/list" => {
+            let result = json!({ "resources": resources::list(&client).await });
+            JsonRpcResponse::success(request.id, result)
+        }
+        "resources/read" => handle_resources_read(request.id, request.params, &client, &pool).await,
+        "prompts/list" => {
+            let result = json!({ "prompts": prompts::prompt_definitions() });
+            JsonRpcResponse::success(request.id, result)
+        }
+        "prompts/get" => handle_prompts_get(request.id, request.params),
         _ => JsonRpcResponse::method_not_found(request.id),
     };
 
@@ -54,6 +74,8 @@ fn handle_initialize(id: Option<serde_json::Value>) -> JsonRpcResponse {
         protocol_version: "2025-03-26".to_string(),
         capabilities: super::protocol::Capabilities {
             tools: super::protocol::ToolsCapability {},
+            resources: super::protocol::ResourcesCapability {},
+            prompts: super::protocol::PromptsCapability {},
         },
         server_info: super::protocol::ServerInfo {
             name: "cicd-mcp".to_string(),
@@ -76,6 +98,7 @@ async fn handle_tools_call(
     client: &kube::Client,
     pool: &Pool<SqliteConnectionManager>,
     octocrabs: &Octocrabs,
+    user: &CurrentUser,
 ) -> JsonRpcResponse {
     let params = match params {
         Some(p) => p,
@@ -96,7 +119,53 @@ async fn handle_tools_call(
         .cloned()
         .unwrap_or(serde_json::json!({}));
 
-    let result = tools::dispatch(&tool_name, arguments, client, pool, octocrabs).await;
+    let result = tools::dispatch(&tool_name, arguments, client, pool, octocrabs, user).await;
 
     JsonRpcResponse::success(id, serde_json::to_value(result).unwrap_or_default())
 }
+
+async fn handle_resources_read(
+    id: Option<serde_json::Value>,
+    params: Option<serde_json::Value>,
+    client: &kube::Client,
+    pool: &Pool<SqliteConnectionManager>,
+) -> JsonRpcResponse {
+    let Some(uri) = params
+        .as_ref()
+        .and_then(|p| p.get("uri"))
+        .and_then(|v| v.as_str())
+    else {
+        return JsonRpcResponse::invalid_params(id, "Missing resource uri".to_string());
+    };
+
+    match resources::read(uri, client, pool).await {
+        Ok(Some(contents)) => JsonRpcResponse::success(id, json!({ "contents": [contents] })),
+        Ok(None) => JsonRpcResponse::resource_not_found(id, uri),
+        Err(e) => JsonRpcResponse::internal_error(id, e),
+    }
+}
+
+fn handle_prompts_get(
+    id: Option<serde_json::Value>,
+    params: Option<serde_json::Value>,
+) -> JsonRpcResponse {
+    let Some(name) = params
+        .as_ref()
+        .and_then(|p| p.get("name"))
+        .and_then(|v| v.as_str())
+    else {
+        return JsonRpcResponse::invalid_params(id, "Missing prompt name".to_string());
+    };
+    let arguments = params
+        .as_ref()
+        .and_then(|p| p.get("arguments"))
+        .cloned()
+        .unwrap_or(json!({}));
+
+    match prompts::get_prompt(name, &arguments) {
+        Ok(result) => {
+            JsonRpcResponse::success(id, serde_json::to_value(result).unwrap_or_default())
+        }
+        Err(e) => JsonRpcResponse::invalid_params(id, e),
+    }
+}
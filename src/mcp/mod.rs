@@ -1,5 +1,7 @@
 mod handler;
+mod prompts;
 mod protocol;
+mod resources;
 mod tools;
 
 pub use handler::handle_mcp;
@@ -0,0 +1,130 @@
+use serde_json::Value;
+
+use super::protocol::{GetPromptResult, Prompt, PromptArgument, PromptMessage, TextContent};
+
+/// A runbook an agent can be started with. `{name}` style placeholders in the
+/// text are replaced by the arguments.
+struct Runbook {
+    name: &'static str,
+    description: &'static str,
+    /// Name, description and whether it is required.
+    arguments: &'static [(&'static str, &'static str, bool)],
+    text: &'static str,
+}
+
+const RUNBOOKS: [Runbook; 3] = [
+    Runbook {
+        name: "diagnose_deploy_config",
+        description: "Find out why a deploy config is unhealthy",
+        arguments: &[("name", "Name of the deploy config", true)],
+        text: "Diagnose the deploy config {name}.\n\n\
+            1. Call get_deploy_config for {name} and note its state and any resource that is not healthy.\n\
+            2. Call get_watchdog with its team and unhealthy_only to see whether other configs or the builds are affected too.\n\
+            3. Call get_logs for {name}, or get_resource_logs for a specific pod, and look for errors around the time things went wrong.\n\
+            4. Call get_deploy_history for {name}. If it was deployed recently, call get_commit_range between the previous and the current artifact SHA to see what changed.\n\
+            5. Summarize the most likely cause with the evidence for it. Do not deploy, undeploy or bounce anything without asking first.",
+    },
+    Runbook {
+        name: "rollback_deploy_config",
+        description: "Roll a deploy config back to the version deployed before the current one",
+        arguments: &[
+            ("name", "Name of the deploy config", true),
+            ("reason", "Why it is being rolled back", false),
+        ],
+        text: "Roll back the deploy config {name}. Reason: {reason}\n\n\
+            1. Call get_deploy_history for {name} and take the artifact SHA deployed before the current one.\n\
+            2. Call get_build_status or get_commit_range to check that the build of that SHA succeeded.\n\
+            3. Call preview_deploy for {name} with that sha. Check that it is allowed and permitted, and list the commits it rolls back.\n\
+            4. Show the preview and ask for confirmation, then call deploy for {name} with that sha.\n\
+            5. Call get_deploy_config until every resource is healthy, and report the result.",
+    },
+    Runbook {
+        name: "triage_incident",
+        description: "Survey everything that is currently unhealthy, optionally for one team",
+        arguments: &[("team", "Only this team's deploy configs", false)],
+        text: "Triage the current incident. Team: {team}\n\n\
+            1. Call get_watchdog with unhealthy_only, and with the team unless it is 'any'.\n\
+            2. For each unhealthy deploy config, call get_deploy_history with limit 3 and note deploys in the last few hours.\n\
+            3. For configs with a recent deploy, call get_commit_range between the previous and current artifact SHA.\n\
+            4. For configs without one, call get_logs and look for the first errors.\n\
+            5. Report a table of affected configs with status, last deploy, suspected cause and a suggested action such as preview_deploy of a rollback. Change nothing yourself.",
+    },
+];
+
+pub fn prompt_definitions() -> Vec<Prompt> {
+    RUNBOOKS
+        .iter()
+        .map(|runbook| Prompt {
+            name: runbook.name.to_string(),
+            description: runbook.description.to_string(),
+            arguments: runbook
+                .arguments
+                .iter()
+                .map(|(name, description, required)| PromptArgument {
+                    name: name.to_string(),
+                    description: description.to_string(),
+                    required: *required,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// The runbook `name` filled in with `arguments`. Missing optional arguments
+/// read as `any` or `not given`.
+pub fn get_prompt(name: &str, arguments: &Value) -> Result<GetPromptResult, String> {
+    let runbook = RUNBOOKS
+        .iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| format!("Unknown prompt: {}", name))?;
+
+    let mut text = runbook.text.to_string();
+    for (argument, _, required) in runbook.arguments {
+        let value = arguments
+            .get(argument)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty());
+        let value = match (value, required) {
+            (Some(value), _) => value,
+            (None, true) => return Err(format!("Missing required argument: {}", argument)),
+            (None, false) if *argument == "team" => "any",
+            (None, false) => "not given",
+        };
+        text = text.replace(&format!("{{{}}}", argument), value);
+    }
+
+    Ok(GetPromptResult {
+        description: runbook.description.to_string(),
+        messages: vec![PromptMessage {
+            role: "user".to_string(),
+            content: TextContent {
+                content_type: "text".to_string(),
+                text,
+            },
+        }],
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fills_in_runbook_arguments() {
+        let prompt = get_prompt("rollback_deploy_config", &json!({ "name": "api" })).unwrap();
+        let text = &prompt.messages[0].content.text;
+        assert!(text.starts_with("Roll back the deploy config api. Reason: not given"));
+        assert!(!text.contains('{'));
+
+        let triage = get_prompt("triage_incident", &json!({})).unwrap();
+        assert!(triage.messages[0].content.text.contains("Team: any"));
+
+        assert_eq!(
+            get_prompt("diagnose_deploy_config", &json!({})).err(),
+            Some("Missing required argument: name".to_string())
+        );
+        assert!(get_prompt("nope", &json!({})).is_err());
+    }
+}
@@ -61,7 +61,10 @@ impl JsonRpcResponse {
         Self::error(id, -32602, msg)
     }
 
-    #[allow(dead_code)]
+    pub fn resource_not_found(id: Option<Value>, uri: &str) -> Self {
+        Self::error(id, -32002, format!("Resource not found: {}", uri))
+    }
+
     pub fn internal_error(id: Option<Value>, msg: String) -> Self {
         Self::error(id, -32603, msg)
     }
@@ -76,9 +79,17 @@ pub struct ServerInfo {
 #[derive(Debug, Serialize)]
 pub struct ToolsCapability {}
 
+#[derive(Debug, Serialize)]
+pub struct ResourcesCapability {}
+
+#[derive(Debug, Serialize)]
+pub struct PromptsCapability {}
+
 #[derive(Debug, Serialize)]
 pub struct Capabilities {
     pub tools: ToolsCapability,
+    pub resources: ResourcesCapability,
+    pub prompts: PromptsCapability,
 }
 
 #[derive(Debug, Serialize)]
@@ -133,3 +144,46 @@ impl ToolCallResult {
         }
     }
 }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    pub uri: String,
+    pub name: String,
+    pub description: String,
+    pub mime_type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    pub uri: String,
+    pub mime_type: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Prompt {
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<PromptArgument>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptArgument {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptMessage {
+    pub role: String,
+    pub content: TextContent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetPromptResult {
+    pub description: String,
+    pub messages: Vec<PromptMessage>,
+}
@@ -0,0 +1,107 @@
+use kube::{Client, ResourceExt};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde_json::Value;
+
+use crate::db::audit_event::{AuditEvent, AuditFilter};
+use crate::db::deploy_event::DeployEvent;
+use crate::kubernetes::api::get_all_deploy_configs;
+
+use super::protocol::{Resource, ResourceContents};
+use super::tools::{deploy_config_json, deploy_configs_json, deploy_event_json};
+
+const DEPLOY_CONFIGS_URI: &str = "cicd://deploy-configs";
+const DEPLOY_CONFIG_URI_PREFIX: &str = "cicd://deploy-configs/";
+const DEPLOY_EVENTS_URI: &str = "cicd://deploy-events/recent";
+const AUDIT_EVENTS_URI: &str = "cicd://audit-events/recent";
+
+/// Events served by the recent-events resources.
+const RECENT_EVENTS: u32 = 50;
+
+fn resource(uri: String, name: String, description: String) -> Resource {
+    Resource {
+        uri,
+        name,
+        description,
+        mime_type: "application/json".to_string(),
+    }
+}
+
+/// The fixed resources, then one per deploy config.
+pub async fn list(client: &Client) -> Vec<Resource> {
+    let mut resources = vec![
+        resource(
+            DEPLOY_CONFIGS_URI.to_string(),
+            "Deploy configs".to_string(),
+            "All deploy configs with their deployed versions".to_string(),
+        ),
+        resource(
+            DEPLOY_EVENTS_URI.to_string(),
+            "Recent deploys".to_string(),
+            format!(
+                "The last {} deploy events across all configs",
+                RECENT_EVENTS
+            ),
+        ),
+        resource(
+            AUDIT_EVENTS_URI.to_string(),
+            "Recent audit events".to_string(),
+            format!(
+                "The last {} actions from the UI, APIs, webhooks and automation",
+                RECENT_EVENTS
+            ),
+        ),
+    ];
+
+    match get_all_deploy_configs(client).await {
+        Ok(configs) => resources.extend(configs.iter().map(|config| {
+            resource(
+                format!("{}{}", DEPLOY_CONFIG_URI_PREFIX, config.name_any()),
+                config.name_any(),
+                format!(
+                    "Deploy config of team {} with the status of its resources",
+                    config.team()
+                ),
+            )
+        })),
+        Err(e) => log::warn!("Failed to list deploy configs for MCP resources: {}", e),
+    }
+
+    resources
+}
+
+/// The contents of a resource, or `None` for an unknown URI.
+pub async fn read(
+    uri: &str,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+) -> Result<Option<ResourceContents>, String> {
+    let value = match uri {
+        DEPLOY_CONFIGS_URI => deploy_configs_json(client).await?,
+        DEPLOY_EVENTS_URI => {
+            let conn = pool.get().map_err(|e| format!("Database error: {}", e))?;
+            let events = DeployEvent::get_recent(RECENT_EVENTS, &conn)
+                .map_err(|e| format!("Failed to get deploy events: {}", e))?;
+            Value::Array(events.iter().map(deploy_event_json).collect())
+        }
+        AUDIT_EVENTS_URI => {
+            let conn = pool.get().map_err(|e| format!("Database error: {}", e))?;
+            let events = AuditEvent::search(&AuditFilter::default(), RECENT_EVENTS, &conn)
+                .map_err(|e| format!("Failed to get audit events: {}", e))?;
+            serde_json::to_value(events).unwrap_or_default()
+        }
+        _ => match uri.strip_prefix(DEPLOY_CONFIG_URI_PREFIX) {
+            Some(name) if !name.is_empty() => match deploy_config_json(client, name).await? {
+                Some(value) => value,
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        },
+    };
+
+    Ok(Some(ResourceContents {
+        uri: uri.to_string(),
+        mime_type: "application/json".to_string(),
+        text: serde_json::to_string_pretty(&value).unwrap_or_default(),
+    }))
+}
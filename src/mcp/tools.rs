@@ -1,23 +1,32 @@
 use kube::{Client, ResourceExt};
-use r2d2::Pool;
+use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
 use serde_json::{json, Value};
 
+use crate::audit::{AuditRecord, AuditSource};
+use crate::auth::CurrentUser;
 use crate::build_status::BuildStatus;
 use crate::crab_ext::Octocrabs;
 use crate::db::deploy_event::DeployEvent;
 use crate::db::git_branch::GitBranch;
+use crate::db::git_commit::GitCommit;
 use crate::db::git_repo::GitRepo;
+use crate::github_actions::{self, RerunScope};
 use crate::kubernetes::api::{
     get_all_deploy_configs, get_deploy_config, list_namespace_objects, ListMode,
 };
-use crate::kubernetes::deploy_handlers::DeployAction;
 use crate::kubernetes::repo::DeploymentState;
-use crate::web::Action;
-use crate::web::ResourceStatuses;
+use crate::web::{
+    check_action_allowed, check_action_permitted, check_deploy_config_health, check_repo_health,
+    execute_action, Action, HealthStatus,
+};
+use crate::web::{get_deploy_config_logs, get_named_resource_logs, ResourceStatuses};
 
 use super::protocol::{Tool, ToolCallResult};
 
+/// Commits listed at most by `preview_deploy`.
+const COMMIT_RANGE_PREVIEW: usize = 100;
+
 pub fn tool_definitions() -> Vec<Tool> {
     vec![
         Tool {
@@ -52,6 +61,86 @@ pub fn tool_definitions() -> Vec<Tool> {
                 "required": ["repo"]
             }),
         },
+        Tool {
+            name: "get_deploy_history".to_string(),
+            description: "Get recent deploy events for a deploy config, or for every config of a team, newest first".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name of the deploy config" },
+                    "team": { "type": "string", "description": "Team whose configs to include, instead of name" },
+                    "limit": { "type": "integer", "description": "Maximum number of events (defaults to 20)" }
+                },
+                "required": []
+            }),
+        },
+        Tool {
+            name: "get_logs".to_string(),
+            description: "Get recent logs from a deploy config's Deployments, Jobs, CronJobs and Pods".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name of the deploy config" },
+                    "resource": { "type": "string", "description": "Only this child resource (by name)" },
+                    "tail_lines": { "type": "integer", "description": "Lines per pod (defaults to 100)" }
+                },
+                "required": ["name"]
+            }),
+        },
+        Tool {
+            name: "get_resource_logs".to_string(),
+            description: "Get recent logs from any Pod, Deployment, ReplicaSet, Job or CronJob, whether or not a deploy config owns it".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "namespace": { "type": "string", "description": "Namespace of the resource" },
+                    "kind": { "type": "string", "description": "Kind of the resource, e.g. Deployment" },
+                    "name": { "type": "string", "description": "Name of the resource" },
+                    "tail_lines": { "type": "integer", "description": "Lines per pod (defaults to 100)" }
+                },
+                "required": ["namespace", "kind", "name"]
+            }),
+        },
+        Tool {
+            name: "get_watchdog".to_string(),
+            description: "Get the watchdog health of repositories' default branch builds and of deploy configs' resources".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "team": { "type": "string", "description": "Only deploy configs of this team" },
+                    "unhealthy_only": { "type": "boolean", "description": "Leave out healthy entries (defaults to false)" }
+                },
+                "required": []
+            }),
+        },
+        Tool {
+            name: "preview_deploy".to_string(),
+            description: "Preview an action on a deploy config without performing it: the current and resulting versions, the commits it deploys or rolls back, the build status and whether it is allowed".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name of the deploy config" },
+                    "action": { "type": "string", "description": "deploy (default), undeploy, bounce, execute_job, toggle_autodeploy or toggle_orphan_keep" },
+                    "branch": { "type": "string", "description": "Branch to deploy from" },
+                    "sha": { "type": "string", "description": "Specific commit SHA to deploy" }
+                },
+                "required": ["name"]
+            }),
+        },
+        Tool {
+            name: "get_commit_range".to_string(),
+            description: "List the commits between two SHAs of a repository, newest first, with their build status".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "repo": { "type": "string", "description": "Repository in owner/name format" },
+                    "base": { "type": "string", "description": "Older commit SHA (excluded)" },
+                    "head": { "type": "string", "description": "Newer commit SHA (included)" },
+                    "limit": { "type": "integer", "description": "Maximum number of commits (defaults to 50)" }
+                },
+                "required": ["repo", "base", "head"]
+            }),
+        },
         Tool {
             name: "deploy".to_string(),
             description: "Deploy a config, optionally targeting a specific branch or SHA"
@@ -110,6 +199,55 @@ pub fn tool_definitions() -> Vec<Tool> {
                 "required": ["name"]
             }),
         },
+        Tool {
+            name: "toggle_orphan_keep".to_string(),
+            description: "Pause or resume the scheduled cleanup of an orphaned deploy config"
+                .to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name of the deploy config" }
+                },
+                "required": ["name"]
+            }),
+        },
+        Tool {
+            name: "rerun_checks".to_string(),
+            description: "Re-run the failed checks of a commit on GitHub, or every check of the failed suites".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "repo": { "type": "string", "description": "Repository in owner/name format" },
+                    "sha": { "type": "string", "description": "Commit SHA" },
+                    "scope": { "type": "string", "enum": ["failed", "suite"], "description": "Re-run only the failed checks (default) or their whole suites" }
+                },
+                "required": ["repo", "sha"]
+            }),
+        },
+        Tool {
+            name: "list_workflows".to_string(),
+            description: "List a repository's GitHub Actions workflows that can be started with dispatch_workflow".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "repo": { "type": "string", "description": "Repository in owner/name format" }
+                },
+                "required": ["repo"]
+            }),
+        },
+        Tool {
+            name: "dispatch_workflow".to_string(),
+            description: "Start a workflow_dispatch workflow on a branch".to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "repo": { "type": "string", "description": "Repository in owner/name format" },
+                    "workflow": { "type": "string", "description": "Workflow id or file name, as listed by list_workflows" },
+                    "branch": { "type": "string", "description": "Branch to run the workflow on (defaults to the repo's default branch)" }
+                },
+                "required": ["repo", "workflow"]
+            }),
+        },
     ]
 }
 
@@ -119,35 +257,73 @@ pub async fn dispatch(
     client: &Client,
     pool: &Pool<SqliteConnectionManager>,
     octocrabs: &Octocrabs,
+    user: &CurrentUser,
 ) -> ToolCallResult {
     match tool_name {
-        "list_deploy_configs" => handle_list_deploy_configs(client, pool).await,
+        "list_deploy_configs" => handle_list_deploy_configs(client).await,
         "get_deploy_config" => handle_get_deploy_config(arguments, client).await,
         "get_build_status" => handle_get_build_status(arguments, pool).await,
-        "deploy" => handle_deploy(arguments, client, pool, octocrabs).await,
-        "undeploy" => handle_action("undeploy", arguments, client, pool, octocrabs).await,
-        "bounce" => handle_action("bounce", arguments, client, pool, octocrabs).await,
-        "execute_job" => handle_action("execute_job", arguments, client, pool, octocrabs).await,
+        "get_deploy_history" => handle_get_deploy_history(arguments, client, pool).await,
+        "get_logs" => handle_get_logs(arguments, client).await,
+        "get_resource_logs" => handle_get_resource_logs(arguments, client).await,
+        "get_watchdog" => handle_get_watchdog(arguments, client, pool).await,
+        "preview_deploy" => handle_preview_deploy(arguments, client, pool, user).await,
+        "get_commit_range" => handle_get_commit_range(arguments, pool).await,
+        "deploy" => handle_deploy(arguments, client, pool, octocrabs, user).await,
+        "undeploy" => handle_action("undeploy", arguments, client, pool, octocrabs, user).await,
+        "bounce" => handle_action("bounce", arguments, client, pool, octocrabs, user).await,
+        "execute_job" => {
+            handle_action("execute_job", arguments, client, pool, octocrabs, user).await
+        }
         "toggle_autodeploy" => {
-            handle_action("toggle_autodeploy", arguments, client, pool, octocrabs).await
+            handle_action(
+                "toggle_autodeploy",
+                arguments,
+                client,
+                pool,
+                octocrabs,
+                user,
+            )
+            .await
+        }
+        "toggle_orphan_keep" => {
+            handle_action(
+                "toggle_orphan_keep",
+                arguments,
+                client,
+                pool,
+                octocrabs,
+                user,
+            )
+            .await
+        }
+        "rerun_checks" => handle_rerun_checks(arguments, client, pool, octocrabs, user).await,
+        "list_workflows" => handle_list_workflows(arguments, pool, octocrabs).await,
+        "dispatch_workflow" => {
+            handle_dispatch_workflow(arguments, client, pool, octocrabs, user).await
         }
         _ => ToolCallResult::error(format!("Unknown tool: {}", tool_name)),
     }
 }
 
-async fn handle_list_deploy_configs(
-    client: &Client,
-    pool: &Pool<SqliteConnectionManager>,
-) -> ToolCallResult {
-    let configs = match get_all_deploy_configs(client).await {
-        Ok(c) => c,
-        Err(e) => return ToolCallResult::error(format!("Failed to list deploy configs: {}", e)),
-    };
+/// Pretty-printed JSON, or the error, as a tool result.
+fn json_result(result: Result<Value, String>) -> ToolCallResult {
+    match result {
+        Ok(value) => ToolCallResult::text(serde_json::to_string_pretty(&value).unwrap_or_default()),
+        Err(e) => ToolCallResult::error(e),
+    }
+}
 
-    let conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => return ToolCallResult::error(format!("Database error: {}", e)),
-    };
+async fn handle_list_deploy_configs(client: &Client) -> ToolCallResult {
+    json_result(deploy_configs_json(client).await)
+}
+
+/// Every deploy config with its deployed versions. Also served as the
+/// `cicd://deploy-configs` resource.
+pub(super) async fn deploy_configs_json(client: &Client) -> Result<Value, String> {
+    let configs = get_all_deploy_configs(client)
+        .await
+        .map_err(|e| format!("Failed to list deploy configs: {}", e))?;
 
     let results: Vec<Value> = configs
         .iter()
@@ -196,10 +372,7 @@ async fn handle_list_deploy_configs(
         })
         .collect();
 
-    // Drop conn before await points
-    drop(conn);
-
-    ToolCallResult::text(serde_json::to_string_pretty(&results).unwrap_or_default())
+    Ok(Value::Array(results))
 }
 
 async fn handle_get_deploy_config(arguments: Value, client: &Client) -> ToolCallResult {
@@ -208,12 +381,23 @@ async fn handle_get_deploy_config(arguments: Value, client: &Client) -> ToolCall
         None => return ToolCallResult::error("Missing required parameter: name".to_string()),
     };
 
+    json_result(
+        deploy_config_json(client, name)
+            .await
+            .and_then(|config| config.ok_or_else(|| format!("Deploy config '{}' not found", name))),
+    )
+}
+
+/// A deploy config with the status of its resources, if it exists. Also
+/// served as the `cicd://deploy-configs/{name}` resource.
+pub(super) async fn deploy_config_json(
+    client: &Client,
+    name: &str,
+) -> Result<Option<Value>, String> {
     let config = match get_deploy_config(client, name).await {
         Ok(Some(c)) => c,
-        Ok(None) => {
-            return ToolCallResult::error(format!("Deploy config '{}' not found", name));
-        }
-        Err(e) => return ToolCallResult::error(format!("Failed to get deploy config: {}", e)),
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(format!("Failed to get deploy config: {}", e)),
     };
 
     let deployment_state = config.deployment_state();
@@ -257,6 +441,8 @@ async fn handle_get_deploy_config(arguments: Value, client: &Client) -> ToolCall
         "state": state,
         "autodeploy": config.autodeploy(),
         "orphaned": config.is_orphaned(),
+        "orphan_reason": config.orphan_reason(),
+        "orphan_kept": config.is_orphan_kept(),
         "supports_bounce": config.supports_bounce(),
         "supports_execute_job": config.supports_execute_job(),
         "artifact_repo": artifact_repo.as_ref().map(|r| format!("{}/{}", r.owner, r.repo)),
@@ -269,7 +455,7 @@ async fn handle_get_deploy_config(arguments: Value, client: &Client) -> ToolCall
         "resources": resources,
     });
 
-    ToolCallResult::text(serde_json::to_string_pretty(&result).unwrap_or_default())
+    Ok(Some(result))
 }
 
 async fn handle_get_build_status(
@@ -338,11 +524,482 @@ async fn handle_get_build_status(
     ToolCallResult::text(serde_json::to_string_pretty(&result).unwrap_or_default())
 }
 
+async fn handle_get_deploy_history(
+    arguments: Value,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+) -> ToolCallResult {
+    let limit = arguments
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(20)
+        .min(500) as u32;
+
+    let names = match (
+        arguments.get("name").and_then(|v| v.as_str()),
+        arguments.get("team").and_then(|v| v.as_str()),
+    ) {
+        (Some(name), _) => vec![name.to_string()],
+        (None, Some(team)) => match get_all_deploy_configs(client).await {
+            Ok(configs) => configs
+                .iter()
+                .filter(|c| c.team() == team)
+                .map(|c| c.name_any())
+                .collect(),
+            Err(e) => {
+                return ToolCallResult::error(format!("Failed to list deploy configs: {}", e))
+            }
+        },
+        (None, None) => {
+            return ToolCallResult::error("Missing required parameter: name or team".to_string())
+        }
+    };
+
+    let conn = match pool.get() {
+        Ok(c) => c,
+        Err(e) => return ToolCallResult::error(format!("Database error: {}", e)),
+    };
+
+    let mut events = vec![];
+    for name in &names {
+        match DeployEvent::get_recent_by_name(name, limit, &conn) {
+            Ok(config_events) => events.extend(config_events),
+            Err(e) => return ToolCallResult::error(format!("Failed to get deploy history: {}", e)),
+        }
+    }
+    events.sort_by_key(|e| std::cmp::Reverse(e.timestamp));
+    events.truncate(limit as usize);
+
+    let results: Vec<Value> = events.iter().map(deploy_event_json).collect();
+
+    ToolCallResult::text(serde_json::to_string_pretty(&results).unwrap_or_default())
+}
+
+pub(super) fn deploy_event_json(e: &DeployEvent) -> Value {
+    json!({
+        "name": e.name,
+        "timestamp": e.timestamp,
+        "initiator": e.initiator,
+        "artifact_sha": e.artifact_sha,
+        "artifact_branch": e.artifact_branch,
+        "config_sha": e.config_sha,
+        "config_branch": e.config_branch,
+        "prev_artifact_sha": e.prev_artifact_sha,
+        "prev_config_sha": e.prev_config_sha,
+    })
+}
+
+async fn handle_get_logs(arguments: Value, client: &Client) -> ToolCallResult {
+    let name = match arguments.get("name").and_then(|v| v.as_str()) {
+        Some(n) => n,
+        None => return ToolCallResult::error("Missing required parameter: name".to_string()),
+    };
+    let resource = arguments.get("resource").and_then(|v| v.as_str());
+    let tail_lines = arguments
+        .get("tail_lines")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(100);
+
+    let config = match get_deploy_config(client, name).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return ToolCallResult::error(format!("Deploy config '{}' not found", name)),
+        Err(e) => return ToolCallResult::error(format!("Failed to get deploy config: {}", e)),
+    };
+
+    match get_deploy_config_logs(client, &config, resource, Some(tail_lines)).await {
+        Ok(logs) => ToolCallResult::text(logs),
+        Err(e) => ToolCallResult::error(format!("Failed to get logs: {}", e)),
+    }
+}
+
+async fn handle_get_resource_logs(arguments: Value, client: &Client) -> ToolCallResult {
+    let mut missing = ["namespace", "kind", "name"]
+        .into_iter()
+        .filter(|p| arguments.get(*p).and_then(|v| v.as_str()).is_none());
+    if let Some(param) = missing.next() {
+        return ToolCallResult::error(format!("Missing required parameter: {}", param));
+    }
+    let param = |p: &str| {
+        arguments
+            .get(p)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+    };
+    let tail_lines = arguments
+        .get("tail_lines")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(100);
+
+    match get_named_resource_logs(
+        client,
+        param("namespace"),
+        param("kind"),
+        param("name"),
+        Some(tail_lines),
+    )
+    .await
+    {
+        Ok(logs) => ToolCallResult::text(logs),
+        Err(e) => ToolCallResult::error(format!("Failed to get logs: {}", e)),
+    }
+}
+
+async fn handle_get_watchdog(
+    arguments: Value,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+) -> ToolCallResult {
+    let team = arguments.get("team").and_then(|v| v.as_str());
+    let unhealthy_only = arguments
+        .get("unhealthy_only")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let include = |status: &HealthStatus| !unhealthy_only || *status != HealthStatus::Healthy;
+
+    let repos = {
+        let conn = match pool.get() {
+            Ok(c) => c,
+            Err(e) => return ToolCallResult::error(format!("Database error: {}", e)),
+        };
+        let repos = match GitRepo::get_all(&conn) {
+            Ok(repos) => repos,
+            Err(e) => return ToolCallResult::error(format!("Failed to list repos: {}", e)),
+        };
+        let mut results = vec![];
+        for repo in repos {
+            match check_repo_health(&repo, &conn) {
+                Ok((status, sha, message)) if include(&status) => results.push(json!({
+                    "repo": format!("{}/{}", repo.owner_name, repo.name),
+                    "status": status,
+                    "sha": sha,
+                    "message": message,
+                })),
+                Ok(_) => {}
+                Err(e) => {
+                    return ToolCallResult::error(format!(
+                        "Failed to check {}/{}: {}",
+                        repo.owner_name, repo.name, e
+                    ))
+                }
+            }
+        }
+        results
+    };
+
+    let configs = match get_all_deploy_configs(client).await {
+        Ok(c) => c,
+        Err(e) => return ToolCallResult::error(format!("Failed to list deploy configs: {}", e)),
+    };
+    let mut deploy_configs = vec![];
+    for config in configs
+        .iter()
+        .filter(|c| team.is_none_or(|team| c.team() == team))
+    {
+        match check_deploy_config_health(config, client).await {
+            Ok((status, message)) if include(&status) => deploy_configs.push(json!({
+                "name": config.name_any(),
+                "namespace": config.namespace().unwrap_or_else(|| "default".to_string()),
+                "team": config.team(),
+                "status": status,
+                "message": message,
+            })),
+            Ok(_) => {}
+            Err(e) => {
+                return ToolCallResult::error(format!(
+                    "Failed to check {}: {}",
+                    config.name_any(),
+                    e
+                ))
+            }
+        }
+    }
+
+    json_result(Ok(json!({
+        "repos": repos,
+        "deploy_configs": deploy_configs,
+    })))
+}
+
+/// Summaries of `commits`, with their build status.
+fn commits_json(commits: &[GitCommit], conn: &PooledConnection<SqliteConnectionManager>) -> Value {
+    commits
+        .iter()
+        .map(|commit| {
+            let build_status: BuildStatus = commit.get_build_status(conn).ok().flatten().into();
+            let build_status: String = build_status.into();
+            json!({
+                "sha": commit.sha,
+                "message": commit.message.lines().next().unwrap_or_default(),
+                "author": commit.author,
+                "timestamp": commit.timestamp,
+                "build_status": build_status,
+            })
+        })
+        .collect()
+}
+
+async fn handle_preview_deploy(
+    arguments: Value,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    user: &CurrentUser,
+) -> ToolCallResult {
+    let name = match arguments.get("name").and_then(|v| v.as_str()) {
+        Some(n) => n,
+        None => return ToolCallResult::error("Missing required parameter: name".to_string()),
+    };
+    let action_name = arguments
+        .get("action")
+        .and_then(|v| v.as_str())
+        .unwrap_or("deploy")
+        .replace('_', "-");
+    let Some(action) = Action::parse(
+        &action_name,
+        arguments.get("branch").and_then(|v| v.as_str()),
+        arguments.get("sha").and_then(|v| v.as_str()),
+    ) else {
+        return ToolCallResult::error(format!("Unknown action: {}", action_name));
+    };
+
+    let config = match get_deploy_config(client, name).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return ToolCallResult::error(format!("Deploy config '{}' not found", name)),
+        Err(e) => return ToolCallResult::error(format!("Failed to get deploy config: {}", e)),
+    };
+    let conn = match pool.get() {
+        Ok(c) => c,
+        Err(e) => return ToolCallResult::error(format!("Database error: {}", e)),
+    };
+
+    let current = config.deployment_state();
+    let target = DeploymentState::from_action(&action, &config, &conn);
+
+    // The commits deployed, or rolled back, between the current and target artifacts.
+    let mut commits = Value::Null;
+    let mut build_status = Value::Null;
+    if let (Some(repository), Ok(target)) = (config.artifact_repository(), &target) {
+        let repo = GitRepo::get_by_name(&repository.owner, &repository.repo, &conn)
+            .ok()
+            .flatten();
+        let find = |sha: Option<&str>| {
+            let (repo, sha) = (repo.as_ref()?, sha?);
+            GitCommit::get_by_sha(sha, repo.id, &conn).ok().flatten()
+        };
+        let from = find(current.artifact_sha());
+        let to = find(target.artifact_sha());
+        if let Some(to) = &to {
+            let status: BuildStatus = to.get_build_status(&conn).ok().flatten().into();
+            build_status = Value::String(status.into());
+        }
+        if let (Some(from), Some(to)) = (&from, &to) {
+            commits = match GitCommit::get_range(from, to, COMMIT_RANGE_PREVIEW, &conn) {
+                Ok((added, true)) => {
+                    json!({ "direction": "forward", "commits": commits_json(&added, &conn) })
+                }
+                _ => match GitCommit::get_range(to, from, COMMIT_RANGE_PREVIEW, &conn) {
+                    Ok((removed, true)) => {
+                        json!({ "direction": "rollback", "commits": commits_json(&removed, &conn) })
+                    }
+                    _ => json!({ "direction": "unknown", "commits": [] }),
+                },
+            };
+        }
+    }
+
+    json_result(Ok(json!({
+        "name": config.name_any(),
+        "team": config.team(),
+        "action": action.describe(),
+        "allowed": check_action_allowed(&action, &config).err().map(|e| e.to_string()).unwrap_or_else(|| "yes".to_string()),
+        "permitted": check_action_permitted(user, &action, &config).is_ok(),
+        "current": current,
+        "target": target.map_err(|e| e.to_string()),
+        "build_status": build_status,
+        "commits": commits,
+    })))
+}
+
+async fn handle_get_commit_range(
+    arguments: Value,
+    pool: &Pool<SqliteConnectionManager>,
+) -> ToolCallResult {
+    let param = |p: &str| arguments.get(p).and_then(|v| v.as_str());
+    let (Some(repo_str), Some(base), Some(head)) = (param("repo"), param("base"), param("head"))
+    else {
+        return ToolCallResult::error(
+            "Missing required parameter: repo, base and head are required".to_string(),
+        );
+    };
+    let limit = arguments
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(50)
+        .min(500) as usize;
+    let Some((owner, name)) = repo_str.split_once('/') else {
+        return ToolCallResult::error("repo must be in owner/name format".to_string());
+    };
+
+    let conn = match pool.get() {
+        Ok(c) => c,
+        Err(e) => return ToolCallResult::error(format!("Database error: {}", e)),
+    };
+    let repo = match GitRepo::get_by_name(owner, name, &conn) {
+        Ok(Some(r)) => r,
+        Ok(None) => return ToolCallResult::error(format!("Repository '{}' not found", repo_str)),
+        Err(e) => return ToolCallResult::error(format!("Failed to look up repo: {}", e)),
+    };
+    let find = |sha: &str| match GitCommit::get_by_sha(sha, repo.id, &conn) {
+        Ok(Some(c)) => Ok(c),
+        Ok(None) => Err(format!("Commit {} not found in {}", sha, repo_str)),
+        Err(e) => Err(format!("Failed to look up commit: {}", e)),
+    };
+    let (base_commit, head_commit) = match (find(base), find(head)) {
+        (Ok(b), Ok(h)) => (b, h),
+        (Err(e), _) | (_, Err(e)) => return ToolCallResult::error(e),
+    };
+
+    match GitCommit::get_range(&base_commit, &head_commit, limit, &conn) {
+        Ok((commits, complete)) => json_result(Ok(json!({
+            "repo": repo_str,
+            "base": base,
+            "head": head,
+            "complete": complete,
+            "commits": commits_json(&commits, &conn),
+        }))),
+        Err(e) => ToolCallResult::error(format!("Failed to walk commits: {}", e)),
+    }
+}
+
+/// The repository named by the `repo` argument.
+fn repo_argument(
+    arguments: &Value,
+    pool: &Pool<SqliteConnectionManager>,
+) -> Result<GitRepo, String> {
+    let repo_str = arguments
+        .get("repo")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing required parameter: repo")?;
+    let (owner, name) = repo_str
+        .split_once('/')
+        .ok_or("repo must be in owner/name format")?;
+    let conn = pool.get().map_err(|e| format!("Database error: {}", e))?;
+    match GitRepo::get_by_name(owner, name, &conn) {
+        Ok(Some(r)) => Ok(r),
+        Ok(None) => Err(format!("Repository '{}' not found", repo_str)),
+        Err(e) => Err(format!("Failed to look up repo: {}", e)),
+    }
+}
+
+async fn handle_rerun_checks(
+    arguments: Value,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+    user: &CurrentUser,
+) -> ToolCallResult {
+    let repo = match repo_argument(&arguments, pool) {
+        Ok(r) => r,
+        Err(e) => return ToolCallResult::error(e),
+    };
+    let Some(sha) = arguments.get("sha").and_then(|v| v.as_str()) else {
+        return ToolCallResult::error("Missing required parameter: sha".to_string());
+    };
+    let scope_str = arguments
+        .get("scope")
+        .and_then(|v| v.as_str())
+        .unwrap_or("failed");
+    let Some(scope) = RerunScope::parse(scope_str) else {
+        return ToolCallResult::error(format!("Unknown scope: {}", scope_str));
+    };
+    if let Err(e) = github_actions::authorize(user, Some(client), &repo, "Re-running checks").await
+    {
+        return ToolCallResult::error(e.to_string());
+    }
+
+    let result = github_actions::rerun_checks(octocrabs, &repo, sha, scope).await;
+    AuditRecord::new(
+        &user.login,
+        AuditSource::Mcp,
+        "rerun-checks",
+        &format!("{}/{}@{}", repo.owner_name, repo.name, sha),
+    )
+    .params(json!({ "scope": scope_str }))
+    .record(pool, &result);
+    json_result(
+        result
+            .map(|reruns| json!({ "sha": sha, "scope": scope_str, "reruns": reruns }))
+            .map_err(|e| e.to_string()),
+    )
+}
+
+async fn handle_list_workflows(
+    arguments: Value,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+) -> ToolCallResult {
+    let repo = match repo_argument(&arguments, pool) {
+        Ok(r) => r,
+        Err(e) => return ToolCallResult::error(e),
+    };
+    json_result(
+        github_actions::dispatchable_workflows(octocrabs, &repo)
+            .await
+            .map(|workflows| {
+                json!(workflows
+                    .iter()
+                    .map(|w| json!({ "id": w.id, "name": w.name, "path": w.path }))
+                    .collect::<Vec<_>>())
+            })
+            .map_err(|e| format!("Failed to list workflows: {}", e)),
+    )
+}
+
+async fn handle_dispatch_workflow(
+    arguments: Value,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+    user: &CurrentUser,
+) -> ToolCallResult {
+    let repo = match repo_argument(&arguments, pool) {
+        Ok(r) => r,
+        Err(e) => return ToolCallResult::error(e),
+    };
+    let Some(workflow) = arguments.get("workflow").and_then(|v| v.as_str()) else {
+        return ToolCallResult::error("Missing required parameter: workflow".to_string());
+    };
+    let branch = arguments
+        .get("branch")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&repo.default_branch);
+    if let Err(e) =
+        github_actions::authorize(user, Some(client), &repo, "Dispatching workflows").await
+    {
+        return ToolCallResult::error(e.to_string());
+    }
+
+    let result = github_actions::dispatch_workflow(octocrabs, &repo, workflow, branch).await;
+    AuditRecord::new(
+        &user.login,
+        AuditSource::Mcp,
+        "dispatch-workflow",
+        &format!("{}/{}", repo.owner_name, repo.name),
+    )
+    .params(json!({ "workflow": workflow, "branch": branch }))
+    .record(pool, &result);
+    json_result(
+        result
+            .map(|()| json!({ "workflow": workflow, "branch": branch, "dispatched": true }))
+            .map_err(|e| e.to_string()),
+    )
+}
+
 async fn handle_deploy(
     arguments: Value,
     client: &Client,
     pool: &Pool<SqliteConnectionManager>,
     octocrabs: &Octocrabs,
+    user: &CurrentUser,
 ) -> ToolCallResult {
     let name = match arguments.get("name").and_then(|v| v.as_str()) {
         Some(n) => n,
@@ -389,7 +1046,7 @@ async fn handle_deploy(
         Action::DeployLatest
     };
 
-    execute_deploy_action(&action, name, &config, client, pool, octocrabs).await
+    execute_deploy_action(&action, name, &config, client, pool, octocrabs, user).await
 }
 
 async fn handle_action(
@@ -398,6 +1055,7 @@ async fn handle_action(
     client: &Client,
     pool: &Pool<SqliteConnectionManager>,
     octocrabs: &Octocrabs,
+    user: &CurrentUser,
 ) -> ToolCallResult {
     let name = match arguments.get("name").and_then(|v| v.as_str()) {
         Some(n) => n,
@@ -434,10 +1092,16 @@ async fn handle_action(
             }
             Action::ToggleAutodeploy
         }
+        "toggle_orphan_keep" => {
+            if !config.is_orphaned() {
+                return ToolCallResult::error("Only orphaned configs can be kept.".to_string());
+            }
+            Action::ToggleOrphanKeep
+        }
         _ => return ToolCallResult::error(format!("Unknown action: {}", action_type)),
     };
 
-    execute_deploy_action(&action, name, &config, client, pool, octocrabs).await
+    execute_deploy_action(&action, name, &config, client, pool, octocrabs, user).await
 }
 
 async fn execute_deploy_action(
@@ -447,84 +1111,25 @@ async fn execute_deploy_action(
     client: &Client,
     pool: &Pool<SqliteConnectionManager>,
     octocrabs: &Octocrabs,
+    user: &CurrentUser,
 ) -> ToolCallResult {
-    let conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => return ToolCallResult::error(format!("Database error: {}", e)),
-    };
-
-    let deployment_state = match DeploymentState::from_action(action, config, &conn) {
-        Ok(s) => s,
-        Err(e) => {
-            return ToolCallResult::error(format!("Failed to resolve deployment state: {}", e));
-        }
-    };
-
-    let deploy_action = match action {
-        Action::DeployLatest
-        | Action::DeployBranch { .. }
-        | Action::DeployCommit { .. }
-        | Action::Undeploy => match deployment_state {
-            DeploymentState::DeployedWithArtifact { artifact, config } => DeployAction::Deploy {
-                name: name.to_string(),
-                artifact: Some(artifact),
-                config,
-            },
-            DeploymentState::DeployedOnlyConfig { config } => DeployAction::Deploy {
-                name: name.to_string(),
-                artifact: None,
-                config,
-            },
-            DeploymentState::Undeployed => DeployAction::Undeploy {
-                name: name.to_string(),
-            },
-        },
-        Action::Bounce => DeployAction::Bounce {
-            name: name.to_string(),
-        },
-        Action::ExecuteJob => DeployAction::ExecuteJob {
-            name: name.to_string(),
-        },
-        Action::ToggleAutodeploy => DeployAction::ToggleAutodeploy {
-            name: name.to_string(),
-        },
-    };
-
-    if let Err(e) = deploy_action
-        .execute(client, octocrabs, config.config_repository())
-        .await
+    if let Err(e) = execute_action(
+        action,
+        config,
+        client,
+        pool,
+        octocrabs,
+        user,
+        AuditSource::Mcp,
+    )
+    .await
     {
         return ToolCallResult::error(format!("Failed to execute action: {}", e));
     }
 
-    // Best-effort: mirror the new state into the GitHub Deployments API.
-    crate::github_deployments::report_deploy_action(octocrabs, config, &deploy_action).await;
-
-    // Log deploy event
-    match DeployEvent::from_user_deploy_action(&deploy_action, &conn, config) {
-        Ok(Some(event)) => {
-            if let Err(e) = event.insert(&conn) {
-                log::error!("Failed to insert MCP deploy event: {}", e);
-            }
-        }
-        Ok(None) => {}
-        Err(e) => {
-            log::error!("Failed to create MCP deploy event: {}", e);
-        }
-    }
-
-    let action_desc = match action {
-        Action::DeployLatest => "Deploy (latest)".to_string(),
-        Action::DeployBranch { branch } => format!("Deploy (branch: {})", branch),
-        Action::DeployCommit { sha } => format!("Deploy (sha: {})", sha),
-        Action::Undeploy => "Undeploy".to_string(),
-        Action::Bounce => "Bounce".to_string(),
-        Action::ExecuteJob => "Execute job".to_string(),
-        Action::ToggleAutodeploy => "Toggle autodeploy".to_string(),
-    };
-
     ToolCallResult::text(format!(
         "Successfully executed: {} on {}",
-        action_desc, name
+        action.describe(),
+        name
     ))
 }
@@ -2,18 +2,30 @@ use std::sync::OnceLock;
 
 use opentelemetry::{
     global,
-    metrics::{Counter, Histogram},
+    metrics::{Counter, Histogram, UpDownCounter},
 };
 use prometheus::{IntGaugeVec, Opts};
 
 pub struct Metrics {
     pub deploy_actions: Counter<u64>,
+    pub drift_detected: Counter<u64>,
     pub commits_observed: Counter<u64>,
     pub builds_started: Counter<u64>,
     pub builds_resolved: Counter<u64>,
     pub build_duration_seconds: Histogram<f64>,
+    pub webhook_gap_seconds: Histogram<f64>,
+    pub webhook_events_recovered: Counter<u64>,
+    pub webhook_queue_depth: UpDownCounter<i64>,
+    pub webhook_queue_latency_seconds: Histogram<f64>,
+    pub webhook_processing_seconds: Histogram<f64>,
+    pub webhook_handler_retries: Counter<u64>,
     pub github_rate_limit_remaining: IntGaugeVec,
     pub github_rate_limit_limit: IntGaugeVec,
+    /// Lookups of the clients that can access a repo, by `result`: `hit` or
+    /// `miss`.
+    pub github_token_cache: Counter<u64>,
+    /// Clients chosen for a repo, by `token` label.
+    pub github_token_chosen: Counter<u64>,
 }
 
 static METRICS: OnceLock<Metrics> = OnceLock::new();
@@ -41,12 +53,25 @@ pub fn init(registry: &prometheus::Registry) -> Result<(), anyhow::Error> {
 
     let metrics = Metrics {
         deploy_actions: meter.u64_counter("cicd_deploy_actions").init(),
+        drift_detected: meter.u64_counter("cicd_drift_detected").init(),
         commits_observed: meter.u64_counter("cicd_commits_observed").init(),
         builds_started: meter.u64_counter("cicd_builds_started").init(),
         builds_resolved: meter.u64_counter("cicd_builds_resolved").init(),
         build_duration_seconds: meter.f64_histogram("cicd_build_duration_seconds").init(),
+        webhook_gap_seconds: meter.f64_histogram("cicd_webhook_gap_seconds").init(),
+        webhook_events_recovered: meter.u64_counter("cicd_webhook_events_recovered").init(),
+        webhook_queue_depth: meter.i64_up_down_counter("cicd_webhook_queue_depth").init(),
+        webhook_queue_latency_seconds: meter
+            .f64_histogram("cicd_webhook_queue_latency_seconds")
+            .init(),
+        webhook_processing_seconds: meter
+            .f64_histogram("cicd_webhook_processing_seconds")
+            .init(),
+        webhook_handler_retries: meter.u64_counter("cicd_webhook_handler_retries").init(),
         github_rate_limit_remaining,
         github_rate_limit_limit,
+        github_token_cache: meter.u64_counter("cicd_github_token_cache").init(),
+        github_token_chosen: meter.u64_counter("cicd_github_token_chosen").init(),
     };
 
     METRICS
@@ -0,0 +1,541 @@
+//! Outgoing notifications for events users need to hear about: finished
+//! builds, deploys, rollbacks, watchdog alerts and orphaned configs, when
+//! they are orphaned and when they are about to be cleaned up.
+//!
+//! Delivery is best-effort: a failing notifier is logged and never blocks the
+//! action that triggered it.
+
+use crate::db::deploy_config::DeployConfig;
+use crate::db::deploy_event::DeployEvent;
+use crate::events::{self, Event};
+use crate::prelude::*;
+use serenity::async_trait;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    /// Every check on a commit completed.
+    BuildFinished {
+        /// `owner/name`
+        repo: String,
+        /// Teams with a deploy config built from or defined in the repository.
+        teams: Vec<String>,
+        commit_sha: String,
+        /// The first failing check, or the first check when all passed.
+        check_name: String,
+        /// The commit's overall status.
+        status: String,
+        url: String,
+    },
+    /// A deployed config was removed from its repo and marked orphaned.
+    ConfigOrphaned {
+        name: String,
+        namespace: String,
+        team: String,
+        reason: Option<String>,
+    },
+    /// An orphaned config will be undeployed and deleted at `deadline`.
+    OrphanCleanupScheduled {
+        name: String,
+        namespace: String,
+        team: String,
+        reason: Option<String>,
+        deadline: DateTime<Utc>,
+    },
+    /// An orphaned config was undeployed and deleted after its grace period.
+    OrphanCleanedUp {
+        name: String,
+        namespace: String,
+        team: String,
+    },
+    /// A deploy or undeploy of a config was requested.
+    DeployStarted {
+        name: String,
+        team: String,
+        action: String,
+        initiator: String,
+    },
+    /// A deploy or undeploy of a config failed.
+    DeployFailed {
+        name: String,
+        team: String,
+        action: String,
+        initiator: String,
+        error: String,
+    },
+    /// A config was deployed.
+    Deployed {
+        name: String,
+        team: String,
+        initiator: String,
+        artifact_sha: Option<String>,
+        artifact_branch: Option<String>,
+        config_sha: Option<String>,
+    },
+    /// A config was deployed with an artifact older than the one it replaced.
+    RolledBack {
+        name: String,
+        team: String,
+        initiator: String,
+        artifact_sha: String,
+        prev_artifact_sha: String,
+    },
+    /// A config was undeployed.
+    Undeployed {
+        name: String,
+        team: String,
+        initiator: String,
+    },
+    /// The watchdog found a deployed config unhealthy.
+    WatchdogAlert {
+        name: String,
+        namespace: String,
+        team: String,
+        message: String,
+    },
+    /// A config the watchdog alerted on is healthy again.
+    WatchdogRecovered {
+        name: String,
+        namespace: String,
+        team: String,
+    },
+}
+
+/// Event kinds, as used by webhook subscriptions and in webhook payloads.
+pub const EVENT_KINDS: [&str; 7] = [
+    "build_finished",
+    "deploy_started",
+    "deploy_succeeded",
+    "deploy_failed",
+    "rollback",
+    "config_orphaned",
+    "watchdog_status",
+];
+
+impl Notification {
+    pub fn teams(&self) -> Vec<&str> {
+        match self {
+            Notification::BuildFinished { teams, .. } => teams.iter().map(String::as_str).collect(),
+            Notification::ConfigOrphaned { team, .. }
+            | Notification::OrphanCleanupScheduled { team, .. }
+            | Notification::OrphanCleanedUp { team, .. }
+            | Notification::DeployStarted { team, .. }
+            | Notification::DeployFailed { team, .. }
+            | Notification::Deployed { team, .. }
+            | Notification::RolledBack { team, .. }
+            | Notification::Undeployed { team, .. }
+            | Notification::WatchdogAlert { team, .. }
+            | Notification::WatchdogRecovered { team, .. } => vec![team],
+        }
+    }
+
+    /// The deploy config this is about, if any.
+    pub fn config_name(&self) -> Option<&str> {
+        match self {
+            Notification::BuildFinished { .. } => None,
+            Notification::ConfigOrphaned { name, .. }
+            | Notification::OrphanCleanupScheduled { name, .. }
+            | Notification::OrphanCleanedUp { name, .. }
+            | Notification::DeployStarted { name, .. }
+            | Notification::DeployFailed { name, .. }
+            | Notification::Deployed { name, .. }
+            | Notification::RolledBack { name, .. }
+            | Notification::Undeployed { name, .. }
+            | Notification::WatchdogAlert { name, .. }
+            | Notification::WatchdogRecovered { name, .. } => Some(name),
+        }
+    }
+
+    /// One of [`EVENT_KINDS`].
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::BuildFinished { .. } => "build_finished",
+            Notification::DeployStarted { .. } => "deploy_started",
+            Notification::Deployed { .. } | Notification::Undeployed { .. } => "deploy_succeeded",
+            Notification::DeployFailed { .. } => "deploy_failed",
+            Notification::RolledBack { .. } => "rollback",
+            Notification::ConfigOrphaned { .. }
+            | Notification::OrphanCleanupScheduled { .. }
+            | Notification::OrphanCleanedUp { .. } => "config_orphaned",
+            Notification::WatchdogAlert { .. } | Notification::WatchdogRecovered { .. } => {
+                "watchdog_status"
+            }
+        }
+    }
+
+    /// Plain-text summary, for notifiers without richer formatting.
+    pub fn message(&self) -> String {
+        match self {
+            Notification::BuildFinished {
+                repo,
+                commit_sha,
+                check_name,
+                status,
+                ..
+            } => format!(
+                "Build {} of {} at {}: {}.",
+                check_name,
+                repo,
+                short_sha(commit_sha),
+                status
+            ),
+            Notification::DeployStarted {
+                name,
+                action,
+                initiator,
+                ..
+            } => format!("{} started {} of {}.", initiator, action, name),
+            Notification::DeployFailed {
+                name,
+                action,
+                initiator,
+                error,
+                ..
+            } => format!("{} failed to {} {}: {}", initiator, action, name, error),
+            Notification::ConfigOrphaned {
+                name,
+                namespace,
+                reason,
+                ..
+            } => format!(
+                "Deploy config {}/{} is still deployed but no longer defined, so it was marked orphaned{}.",
+                namespace,
+                name,
+                reason
+                    .as_ref()
+                    .map(|r| format!(" ({})", r))
+                    .unwrap_or_default()
+            ),
+            Notification::OrphanCleanupScheduled {
+                name,
+                namespace,
+                reason,
+                deadline,
+                ..
+            } => format!(
+                "Orphaned deploy config {}/{} will be undeployed and deleted at {}{}. Use \"Keep\" on the deploy page to pause cleanup.",
+                namespace,
+                name,
+                deadline.to_rfc3339(),
+                reason
+                    .as_ref()
+                    .map(|r| format!(" ({})", r))
+                    .unwrap_or_default()
+            ),
+            Notification::OrphanCleanedUp {
+                name, namespace, ..
+            } => format!(
+                "Orphaned deploy config {}/{} was undeployed and deleted after its grace period.",
+                namespace, name
+            ),
+            Notification::Deployed {
+                name,
+                initiator,
+                artifact_sha,
+                artifact_branch,
+                config_sha,
+                ..
+            } => {
+                let version = match (artifact_sha, config_sha) {
+                    (Some(sha), _) => format!(
+                        " at {}{}",
+                        short_sha(sha),
+                        artifact_branch
+                            .as_ref()
+                            .map(|b| format!(" ({})", b))
+                            .unwrap_or_default()
+                    ),
+                    (None, Some(sha)) => format!(" at config {}", short_sha(sha)),
+                    (None, None) => String::new(),
+                };
+                format!("{} deployed {}{}.", initiator, name, version)
+            }
+            Notification::RolledBack {
+                name,
+                initiator,
+                artifact_sha,
+                prev_artifact_sha,
+                ..
+            } => format!(
+                "{} rolled back {} from {} to {}.",
+                initiator,
+                name,
+                short_sha(prev_artifact_sha),
+                short_sha(artifact_sha)
+            ),
+            Notification::Undeployed {
+                name, initiator, ..
+            } => format!("{} undeployed {}.", initiator, name),
+            Notification::WatchdogAlert {
+                name,
+                namespace,
+                message,
+                ..
+            } => format!("Deploy config {}/{} is unhealthy: {}", namespace, name, message),
+            Notification::WatchdogRecovered {
+                name, namespace, ..
+            } => format!("Deploy config {}/{} is healthy again.", namespace, name),
+        }
+    }
+
+    /// The notification for a recorded deploy event.
+    pub fn from_deploy_event(event: &DeployEvent, team: String, rollback: bool) -> Self {
+        let name = event.name.clone();
+        let initiator = event.initiator.clone();
+        match (&event.artifact_sha, &event.prev_artifact_sha) {
+            (Some(sha), Some(prev)) if rollback => Notification::RolledBack {
+                name,
+                team,
+                initiator,
+                artifact_sha: sha.clone(),
+                prev_artifact_sha: prev.clone(),
+            },
+            _ if event.config_sha.is_none() && event.artifact_sha.is_none() => {
+                Notification::Undeployed {
+                    name,
+                    team,
+                    initiator,
+                }
+            }
+            _ => Notification::Deployed {
+                name,
+                team,
+                initiator,
+                artifact_sha: event.artifact_sha.clone(),
+                artifact_branch: event.artifact_branch.clone(),
+                config_sha: event.config_sha.clone(),
+            },
+        }
+    }
+}
+
+fn short_sha(sha: &str) -> &str {
+    sha.get(..7).unwrap_or(sha)
+}
+
+#[async_trait]
+pub trait Notifier {
+    async fn notify(&self, notification: &Notification) -> AppResult<()>;
+}
+
+/// Writes notifications to the application log.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: &Notification) -> AppResult<()> {
+        log::warn!(
+            "[team {}] {}",
+            notification.teams().join(", "),
+            notification.message()
+        );
+        Ok(())
+    }
+}
+
+/// The set of configured notifiers.
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    notifiers: Vec<Arc<dyn Notifier + Send + Sync>>,
+}
+
+impl Notifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T: Notifier + Send + Sync + 'static>(&mut self, notifier: T) {
+        self.notifiers.push(Arc::new(notifier));
+    }
+
+    /// Deliver `notification` to every notifier.
+    pub async fn notify(&self, notification: Notification) {
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.notify(&notification).await {
+                log::error!("Failed to deliver notification: {}", e);
+            }
+        }
+    }
+}
+
+/// Notify about every deploy recorded or attempted, and every config
+/// orphaned, from now on.
+pub async fn forward_deploy_events(pool: Pool<SqliteConnectionManager>, notifiers: Notifiers) {
+    forward_events(events::subscribe(), pool, notifiers).await
+}
+
+async fn forward_events(
+    mut rx: broadcast::Receiver<Event>,
+    pool: Pool<SqliteConnectionManager>,
+    notifiers: Notifiers,
+) {
+    loop {
+        let notification = match rx.recv().await {
+            Ok(Event::Deploy(event)) => deploy_notification(&pool, &event),
+            Ok(Event::DeployStarted {
+                name,
+                action,
+                initiator,
+            }) => Notification::DeployStarted {
+                team: team_of(&pool, &name),
+                name,
+                action,
+                initiator,
+            },
+            Ok(Event::DeployFailed {
+                name,
+                action,
+                initiator,
+                error,
+            }) => Notification::DeployFailed {
+                team: team_of(&pool, &name),
+                name,
+                action,
+                initiator,
+                error,
+            },
+            Ok(Event::ConfigOrphaned {
+                name,
+                namespace,
+                team,
+                reason,
+            }) => Notification::ConfigOrphaned {
+                name,
+                namespace,
+                team,
+                reason,
+            },
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                log::warn!("Deploy notifications skipped {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        notifiers.notify(notification).await;
+    }
+}
+
+/// The team owning a config, or an empty string when it is unknown.
+fn team_of(pool: &Pool<SqliteConnectionManager>, name: &str) -> String {
+    pool.get()
+        .ok()
+        .and_then(|conn| DeployConfig::get_active_by_name(name, &conn).ok().flatten())
+        .map(|dc| dc.team)
+        .unwrap_or_default()
+}
+
+fn deploy_notification(pool: &Pool<SqliteConnectionManager>, event: &DeployEvent) -> Notification {
+    let rollback = match pool.get() {
+        Ok(conn) => event.is_rollback(&conn).unwrap_or_else(|e| {
+            log::warn!("Failed to check {} for a rollback: {}", event.name, e);
+            false
+        }),
+        Err(e) => {
+            log::error!("Failed to get database connection: {}", e);
+            false
+        }
+    };
+    Notification::from_deploy_event(event, team_of(pool, &event.name), rollback)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Keeps every notification it is given.
+    struct RecordingNotifier(Arc<Mutex<Vec<Notification>>>);
+
+    #[async_trait]
+    impl Notifier for RecordingNotifier {
+        async fn notify(&self, notification: &Notification) -> AppResult<()> {
+            self.0.lock().unwrap().push(notification.clone());
+            Ok(())
+        }
+    }
+
+    fn event(artifact_sha: Option<&str>, prev_artifact_sha: Option<&str>) -> DeployEvent {
+        DeployEvent {
+            name: "api".to_string(),
+            timestamp: 0,
+            initiator: "USER".to_string(),
+            config_sha: artifact_sha.map(|_| "c0ffee0123".to_string()),
+            artifact_sha: artifact_sha.map(str::to_string),
+            artifact_branch: Some("main".to_string()),
+            config_branch: None,
+            prev_artifact_sha: prev_artifact_sha.map(str::to_string),
+            prev_config_sha: None,
+            artifact_repo_id: None,
+            config_repo_id: None,
+            config_version_hash: None,
+            prev_config_version_hash: None,
+        }
+    }
+
+    #[test]
+    fn classifies_deploy_events() {
+        let deployed =
+            Notification::from_deploy_event(&event(Some("abcdef123"), None), "t".into(), false);
+        assert_eq!(deployed.message(), "USER deployed api at abcdef1 (main).");
+
+        let rolled_back = Notification::from_deploy_event(
+            &event(Some("abcdef123"), Some("9876543210")),
+            "t".into(),
+            true,
+        );
+        assert_eq!(
+            rolled_back.message(),
+            "USER rolled back api from 9876543 to abcdef1."
+        );
+
+        let undeployed = Notification::from_deploy_event(&event(None, None), "t".into(), false);
+        assert!(matches!(undeployed, Notification::Undeployed { .. }));
+    }
+
+    #[tokio::test]
+    async fn delivers_orphaned_configs() {
+        let pool = crate::db::test_pool();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let mut notifiers = Notifiers::new();
+        notifiers.add(RecordingNotifier(delivered.clone()));
+
+        let rx = events::subscribe();
+        events::publish(Event::ConfigOrphaned {
+            name: "api".to_string(),
+            namespace: "apps".to_string(),
+            team: "platform".to_string(),
+            reason: Some("Removed from .deploy/ in acme/config".to_string()),
+        });
+        let forwarding = tokio::spawn(forward_events(rx, pool, notifiers));
+        let orphaned = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let found = delivered
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|n| matches!(n, Notification::ConfigOrphaned { .. }))
+                    .cloned();
+                if let Some(notification) = found {
+                    return notification;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        forwarding.abort();
+
+        assert_eq!(orphaned.kind(), "config_orphaned");
+        assert_eq!(orphaned.teams(), vec!["platform"]);
+        assert_eq!(
+            orphaned.message(),
+            "Deploy config apps/api is still deployed but no longer defined, so it was marked orphaned (Removed from .deploy/ in acme/config)."
+        );
+    }
+}
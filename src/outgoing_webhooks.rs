@@ -0,0 +1,453 @@
+//! Outgoing HTTP webhooks for notifications, configured on the settings page.
+//!
+//! Each subscription receives either Slack-compatible payloads (`{"text": ..}`)
+//! or raw JSON, signed with HMAC-SHA256 in `X-Cicd-Signature-256` when a secret
+//! is set. Failed deliveries are retried a few times and every outcome is
+//! recorded in the delivery log.
+
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use serenity::async_trait;
+use sha2::Sha256;
+
+use crate::build_status::BuildStatus;
+use crate::db::build_notification::BuildNotification;
+use crate::db::deploy_config::DeployConfig;
+use crate::db::git_commit::GitCommit;
+use crate::db::git_commit_build::GitCommitBuild;
+use crate::db::git_repo::GitRepo;
+use crate::db::webhook_delivery::WebhookDelivery;
+use crate::db::webhook_subscription::WebhookSubscription;
+use crate::notifications::{Notification, Notifier};
+use crate::prelude::*;
+use crate::webhooks::models::{CheckRunEvent, Repository, StatusEvent};
+use crate::webhooks::WebhookHandler;
+
+/// Payload formats a subscription can choose from.
+pub const FORMATS: [&str; 2] = ["slack", "json"];
+
+/// Event kind used for test sends from the settings page.
+pub const TEST_EVENT: &str = "test";
+
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers notifications to every matching subscription. Also handles check
+/// run and status webhooks to report a commit's build once it finishes.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    pool: Pool<SqliteConnectionManager>,
+    http: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self { pool, http }
+    }
+
+    /// Send a test payload to one subscription and wait for the outcome.
+    pub async fn send_test(&self, subscription: &WebhookSubscription) -> WebhookDelivery {
+        let body = payload(
+            subscription,
+            TEST_EVENT,
+            "Test notification from cicd",
+            None,
+        );
+        deliver(&self.http, &self.pool, subscription, TEST_EVENT, body).await
+    }
+
+    /// `owner/name` of the repositories a notification relates to.
+    fn repos(&self, notification: &Notification) -> AppResult<Vec<String>> {
+        if let Notification::BuildFinished { repo, .. } = notification {
+            return Ok(vec![repo.clone()]);
+        }
+        let Some(name) = notification.config_name() else {
+            return Ok(vec![]);
+        };
+
+        let conn = self.pool.get()?;
+        let Some(config) = DeployConfig::get_active_by_name(name, &conn)? else {
+            return Ok(vec![]);
+        };
+        let mut repos = vec![];
+        for id in std::iter::once(config.config_repo_id).chain(config.artifact_repo_id) {
+            if let Some(repo) = GitRepo::get_by_id(&id, &conn)? {
+                repos.push(format!("{}/{}", repo.owner_name, repo.name));
+            }
+        }
+        Ok(repos)
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> AppResult<()> {
+        let subscriptions = WebhookSubscription::get_all(&self.pool.get()?)?;
+        if subscriptions.is_empty() {
+            return Ok(());
+        }
+
+        let kind = notification.kind();
+        let teams = notification.teams();
+        let repos = self.repos(notification)?;
+        let message = notification.message();
+        for subscription in subscriptions
+            .into_iter()
+            .filter(|s| s.matches(kind, &teams, &repos, notification.config_name()))
+        {
+            let body = payload(&subscription, kind, &message, Some(notification));
+            let http = self.http.clone();
+            let pool = self.pool.clone();
+            // Retries must not hold up other notifiers.
+            tokio::spawn(async move {
+                deliver(&http, &pool, &subscription, kind, body).await;
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WebhookHandler for WebhookNotifier {
+    async fn handle_check_run(&self, event: CheckRunEvent) -> Result<(), anyhow::Error> {
+        if event.check_run.status != "completed" {
+            return Ok(());
+        }
+        self.notify_build_finished(&event.repository, &event.check_run.check_suite.head_sha)
+            .await
+    }
+
+    async fn handle_status(&self, event: StatusEvent) -> Result<(), anyhow::Error> {
+        if event.state == "pending" {
+            return Ok(());
+        }
+        self.notify_build_finished(&event.repository, &event.sha)
+            .await
+    }
+}
+
+impl WebhookNotifier {
+    /// Announce a commit's build once none of its checks are pending. The
+    /// database handler has stored the triggering check by now; the outcome
+    /// is only sent again if a re-run changes it.
+    async fn notify_build_finished(
+        &self,
+        repository: &Repository,
+        sha: &str,
+    ) -> Result<(), anyhow::Error> {
+        let conn = self.pool.get()?;
+        let Some(commit) = GitCommit::get_by_sha(sha, repository.id, &conn)? else {
+            return Ok(());
+        };
+        let builds = GitCommitBuild::get_all_by_commit_id(&commit.id, &commit.repo_id, &conn)?;
+        let status = BuildStatus::from(commit.get_build_status(&conn)?);
+        if status == BuildStatus::None || builds.iter().any(|b| b.status == "Pending") {
+            return Ok(());
+        }
+        let status: String = status.into();
+        if !BuildNotification::record(repository.id, sha, &status, &conn)? {
+            return Ok(());
+        }
+
+        // Name the check that decided the outcome: the first failure, if any.
+        let Some(decisive) = builds
+            .iter()
+            .find(|b| b.status == status)
+            .or(builds.first())
+        else {
+            return Ok(());
+        };
+        let teams = DeployConfig::get_active_by_repo_id(repository.id, &conn)?
+            .into_iter()
+            .map(|dc| dc.team)
+            .collect();
+        drop(conn);
+        let notification = Notification::BuildFinished {
+            repo: format!("{}/{}", repository.owner.login, repository.name),
+            teams,
+            commit_sha: sha.to_string(),
+            check_name: decisive.check_name.clone(),
+            status,
+            url: decisive.url.clone(),
+        };
+        self.notify(&notification).await?;
+        Ok(())
+    }
+}
+
+/// The request body for a subscription's format.
+fn payload(
+    subscription: &WebhookSubscription,
+    kind: &str,
+    message: &str,
+    notification: Option<&Notification>,
+) -> serde_json::Value {
+    if subscription.format == "slack" {
+        json!({ "text": message })
+    } else {
+        json!({
+            "event": kind,
+            "timestamp": Utc::now().to_rfc3339(),
+            "message": message,
+            "notification": notification,
+        })
+    }
+}
+
+/// Hex-encoded HMAC-SHA256 of `body`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    // HMAC accepts keys of any length.
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return String::new();
+    };
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// POST `body`, retrying transport errors, 429s and 5xx responses, and record
+/// the outcome.
+async fn deliver(
+    http: &reqwest::Client,
+    pool: &Pool<SqliteConnectionManager>,
+    subscription: &WebhookSubscription,
+    kind: &str,
+    body: serde_json::Value,
+) -> WebhookDelivery {
+    let body = body.to_string();
+    let mut delivery = WebhookDelivery {
+        subscription_id: subscription.id,
+        event: kind.to_string(),
+        timestamp: Utc::now().timestamp_millis(),
+        attempts: 0,
+        status_code: None,
+        error: None,
+        success: false,
+    };
+
+    while delivery.attempts < MAX_ATTEMPTS {
+        if delivery.attempts > 0 {
+            tokio::time::sleep(RETRY_DELAY * delivery.attempts).await;
+        }
+        delivery.attempts += 1;
+
+        let mut request = http
+            .post(&subscription.url)
+            .header("Content-Type", "application/json")
+            .header("X-Cicd-Event", kind);
+        if let Some(secret) = subscription.secret.as_deref().filter(|s| !s.is_empty()) {
+            request = request.header(
+                "X-Cicd-Signature-256",
+                format!("sha256={}", sign(secret, body.as_bytes())),
+            );
+        }
+
+        match request.body(body.clone()).send().await {
+            Ok(response) => {
+                let status = response.status();
+                delivery.status_code = Some(status.as_u16());
+                delivery.success = status.is_success();
+                delivery.error = (!status.is_success()).then(|| format!("HTTP {}", status));
+                if status.is_success() || !(status.is_server_error() || status.as_u16() == 429) {
+                    break;
+                }
+            }
+            Err(e) => {
+                delivery.status_code = None;
+                delivery.error = Some(e.to_string());
+            }
+        }
+    }
+
+    if !delivery.success {
+        log::warn!(
+            "Webhook delivery of {} to {} failed after {} attempts: {}",
+            kind,
+            subscription.url,
+            delivery.attempts,
+            delivery.error.as_deref().unwrap_or_default()
+        );
+    }
+    match pool.get() {
+        Ok(conn) => {
+            if let Err(e) = delivery.insert(&conn) {
+                log::error!("Failed to record webhook delivery: {}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to get database connection: {}", e),
+    }
+    delivery
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn subscription(format: &str) -> WebhookSubscription {
+        WebhookSubscription {
+            id: 1,
+            url: "http://example.invalid/hook".to_string(),
+            format: format.to_string(),
+            secret: None,
+            events: vec!["deploy_failed".to_string(), "rollback".to_string()],
+            team: Some("payments".to_string()),
+            repo: Some("acme/api".to_string()),
+            config: None,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn matches_filters() {
+        let sub = subscription("json");
+        let repos = vec!["Acme/API".to_string()];
+        assert!(sub.matches("rollback", &["payments"], &repos, Some("api")));
+        assert!(!sub.matches("deploy_started", &["payments"], &repos, Some("api")));
+        assert!(!sub.matches("rollback", &["search"], &repos, Some("api")));
+        assert!(!sub.matches("rollback", &["payments"], &[], Some("api")));
+
+        let all = WebhookSubscription {
+            events: vec![],
+            team: None,
+            repo: None,
+            config: Some("api".to_string()),
+            ..sub
+        };
+        assert!(all.matches("build_finished", &[], &[], Some("api")));
+        assert!(!all.matches("build_finished", &[], &[], None));
+    }
+
+    #[test]
+    fn formats_payloads() {
+        let slack = payload(&subscription("slack"), TEST_EVENT, "hello", None);
+        assert_eq!(slack, json!({ "text": "hello" }));
+
+        let notification = Notification::DeployStarted {
+            name: "api".to_string(),
+            team: "payments".to_string(),
+            action: "deploy".to_string(),
+            initiator: "octocat".to_string(),
+        };
+        let raw = payload(
+            &subscription("json"),
+            notification.kind(),
+            &notification.message(),
+            Some(&notification),
+        );
+        assert_eq!(raw["event"], "deploy_started");
+        assert_eq!(raw["notification"]["type"], "deploy_started");
+        assert_eq!(raw["notification"]["name"], "api");
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    fn build(commit_id: i64, check_name: &str, status: BuildStatus) -> GitCommitBuild {
+        GitCommitBuild {
+            repo_id: 1,
+            commit_id,
+            check_name: check_name.to_string(),
+            status: status.into(),
+            url: format!("https://ci.example.invalid/{}", check_name),
+            start_time: None,
+            settle_time: None,
+            app_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn notifies_settled_builds_once() {
+        let pool = crate::db::test_pool();
+        let conn = pool.get().unwrap();
+        GitRepo {
+            id: 1,
+            owner_name: "acme".to_string(),
+            name: "api".to_string(),
+            default_branch: "main".to_string(),
+            private: false,
+            language: None,
+        }
+        .upsert(&conn)
+        .unwrap();
+        let commit = GitCommit::upsert(
+            &crate::db::git_commit::GitCommitEgg {
+                sha: "abc".to_string(),
+                repo_id: 1,
+                message: "commit".to_string(),
+                author: "a".to_string(),
+                committer: "a".to_string(),
+                timestamp: 0,
+            },
+            &conn,
+        )
+        .unwrap();
+        drop(conn);
+
+        let repository = Repository {
+            id: 1,
+            name: "api".to_string(),
+            owner: crate::webhooks::models::RepoOwner {
+                login: "acme".to_string(),
+            },
+            private: false,
+            language: None,
+            default_branch: "main".to_string(),
+        };
+        let notifier = WebhookNotifier::new(pool.clone());
+        let notified = || {
+            pool.get()
+                .unwrap()
+                .query_row(
+                    "SELECT status FROM build_notification WHERE repo_id = 1 AND commit_sha = 'abc'",
+                    [],
+                    |row| row.get::<_, String>(0),
+                )
+                .ok()
+        };
+        let upsert = |b: GitCommitBuild| {
+            GitCommitBuild::upsert(&b, &pool.get().unwrap()).unwrap();
+        };
+        upsert(build(commit.id, "lint", BuildStatus::Success));
+        upsert(build(commit.id, "test", BuildStatus::Pending));
+        notifier
+            .notify_build_finished(&repository, "abc")
+            .await
+            .unwrap();
+        // Nothing is sent while a check is still running.
+        assert_eq!(notified(), None);
+
+        upsert(build(commit.id, "test", BuildStatus::Failure));
+        notifier
+            .notify_build_finished(&repository, "abc")
+            .await
+            .unwrap();
+        assert_eq!(notified().as_deref(), Some("Failure"));
+        // Redeliveries of the same outcome are not sent again.
+        assert!(!BuildNotification::record(1, "abc", "Failure", &pool.get().unwrap()).unwrap());
+
+        // A re-run that changes the outcome is announced again.
+        upsert(build(commit.id, "test", BuildStatus::Success));
+        notifier
+            .notify_build_finished(&repository, "abc")
+            .await
+            .unwrap();
+        assert_eq!(notified().as_deref(), Some("Success"));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::audit::AuditSource;
+use crate::db::audit_event::{AuditColumn, AuditEvent, AuditFilter};
+use crate::prelude::*;
+use crate::web::formatting::{format_relative_time, format_timestamp};
+use crate::web::header;
+
+const DEFAULT_LIMIT: u32 = 200;
+const MAX_LIMIT: u32 = 5000;
+
+/// Filters shared by the audit page and the JSON export. Empty values match
+/// everything.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    actor: Option<String>,
+    source: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    /// `success` or `failure`
+    result: Option<String>,
+    /// Only the last this many days.
+    days: Option<i64>,
+    limit: Option<u32>,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> AuditFilter {
+        let value = |v: &Option<String>| {
+            v.as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        AuditFilter {
+            actor: value(&self.actor),
+            source: value(&self.source),
+            action: value(&self.action),
+            target: value(&self.target),
+            success: match self.result.as_deref() {
+                Some("success") => Some(true),
+                Some("failure") => Some(false),
+                _ => None,
+            },
+            since: self
+                .days
+                .filter(|d| *d > 0)
+                .map(|d| (Utc::now() - chrono::Duration::days(d)).timestamp_millis()),
+        }
+    }
+
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// The same filters as a query string, for the export link.
+    fn to_query_string(&self) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for (key, value) in [
+            ("actor", &self.actor),
+            ("source", &self.source),
+            ("action", &self.action),
+            ("target", &self.target),
+            ("result", &self.result),
+        ] {
+            if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                query.append_pair(key, value);
+            }
+        }
+        if let Some(days) = self.days {
+            query.append_pair("days", &days.to_string());
+        }
+        query.append_pair("limit", &self.limit().to_string());
+        query.finish()
+    }
+}
+
+fn search(pool: &Pool<SqliteConnectionManager>, query: &AuditQuery) -> AppResult<Vec<AuditEvent>> {
+    AuditEvent::search(&query.filter(), query.limit(), &pool.get()?)
+}
+
+fn render_params(params: &serde_json::Value) -> String {
+    match params {
+        serde_json::Value::Object(map) if map.is_empty() => String::new(),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| match v {
+                serde_json::Value::String(s) => format!("{}={}", k, s),
+                v => format!("{}={}", k, v),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn render_select(name: &str, label: &str, options: &[String], selected: Option<&str>) -> Markup {
+    html! {
+        select name=(name) class="repo-input" {
+            option value="" { (label) }
+            @for option in options {
+                option value=(option) selected[selected == Some(option.as_str())] { (option) }
+            }
+        }
+    }
+}
+
+#[get("/audit")]
+pub async fn audit_page(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    query: web::Query<AuditQuery>,
+) -> AppResult<HttpResponse> {
+    let events = search(&pool, &query)?;
+    let (actors, actions) = {
+        let conn = pool.get()?;
+        (
+            AuditEvent::get_distinct(AuditColumn::Actor, &conn)?,
+            AuditEvent::get_distinct(AuditColumn::Action, &conn)?,
+        )
+    };
+    let sources: Vec<String> = AuditSource::ALL
+        .iter()
+        .map(|s| s.as_str().to_string())
+        .collect();
+    let results = vec!["success".to_string(), "failure".to_string()];
+
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="UTF-8";
+                meta name="viewport" content="width=device-width, initial-scale=1.0";
+                title { "Audit log" }
+                (header::stylesheet_link())
+                (header::scripts())
+            }
+            body.deploy-history-page {
+                (header::render("audit"))
+                div class="content" {
+                    header {
+                        h1 { "Audit log" }
+                        div class="subtitle" { "Every change made through the UI, the APIs, webhooks and automation." }
+                    }
+                    form class="audit-filters" action="/audit" method="get" {
+                        (render_select("actor", "Any actor", &actors, query.actor.as_deref()))
+                        (render_select("source", "Any source", &sources, query.source.as_deref()))
+                        (render_select("action", "Any action", &actions, query.action.as_deref()))
+                        input type="text" name="target" placeholder="Target" class="repo-input" value=(query.target.as_deref().unwrap_or_default());
+                        (render_select("result", "Any result", &results, query.result.as_deref()))
+                        input type="number" name="days" min="1" placeholder="Last N days" class="repo-input" value=(query.days.map(|d| d.to_string()).unwrap_or_default());
+                        button type="submit" class="bootstrap-button" { "Filter" }
+                        a class="link-button" href=(format!("/audit/export.json?{}", query.to_query_string())) { "Export JSON" }
+                    }
+                    @if events.is_empty() {
+                        div class="empty-state" {
+                            h2 { "No audit events" }
+                            p { "Nothing matches these filters." }
+                        }
+                    } @else {
+                        table class="history-table" {
+                            thead {
+                                tr {
+                                    th { "When" }
+                                    th { "Actor" }
+                                    th { "Source" }
+                                    th { "Action" }
+                                    th { "Target" }
+                                    th { "Parameters" }
+                                    th { "Result" }
+                                }
+                            }
+                            tbody {
+                                @for event in &events {
+                                    tr {
+                                        td class="time-cell" title=(format_timestamp(event.timestamp)) { (format_relative_time(event.timestamp)) }
+                                        td { (event.actor) }
+                                        td { (event.source) }
+                                        td { (event.action) }
+                                        td class="config-name" { (event.target) }
+                                        td { (render_params(&event.params)) }
+                                        td {
+                                            @if event.success {
+                                                span class="webhook-ok" { "ok" }
+                                            } @else {
+                                                span class="webhook-failed" {
+                                                    (event.error.as_deref().unwrap_or("failed"))
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string()))
+}
+
+/// The audit events matching the page's filters, as a JSON array.
+#[get("/audit/export.json")]
+pub async fn audit_export(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    query: web::Query<AuditQuery>,
+) -> AppResult<HttpResponse> {
+    let events = search(&pool, &query)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Disposition", "attachment; filename=\"audit.json\""))
+        .json(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_filters_from_query() {
+        let query = AuditQuery {
+            actor: Some("octocat".to_string()),
+            source: Some("".to_string()),
+            target: Some(" api ".to_string()),
+            result: Some("failure".to_string()),
+            limit: Some(100_000),
+            ..Default::default()
+        };
+        let filter = query.filter();
+        assert_eq!(filter.actor.as_deref(), Some("octocat"));
+        assert_eq!(filter.source, None);
+        assert_eq!(filter.target.as_deref(), Some("api"));
+        assert_eq!(filter.success, Some(false));
+        assert_eq!(filter.since, None);
+        assert_eq!(query.limit(), MAX_LIMIT);
+        assert_eq!(
+            query.to_query_string(),
+            "actor=octocat&target=+api+&result=failure&limit=5000"
+        );
+    }
+
+    #[test]
+    fn renders_params() {
+        assert_eq!(render_params(&serde_json::json!({})), "");
+        assert_eq!(
+            render_params(&serde_json::json!({ "branch": "main", "reason": null, "n": 2 })),
+            "branch=main, n=2"
+        );
+    }
+}
@@ -1,10 +1,13 @@
-use crate::crab_ext::Octocrabs;
+use crate::audit::{AuditRecord, AuditSource};
+use crate::auth::CurrentUser;
+use crate::crab_ext::{GitHubClient, Octocrabs};
 use crate::db::{
     git_branch::GitBranchEgg, git_commit::GitCommitEgg, git_commit_build::GitCommitBuild,
     git_repo::GitRepo,
 };
 use crate::prelude::*;
 use crate::webhooks::config_sync::sync_deploy_configs_for_commit;
+use crate::webhooks::config_validation::CHECK_RUN_NAME;
 use actix_web::http::StatusCode;
 use chrono::Utc;
 use kube::Client;
@@ -83,9 +86,38 @@ async fn log_rate_limit(crab: &Octocrab, label: &str) -> Option<usize> {
     }
 }
 
-async fn list_all_repos(crab: &Octocrab) -> anyhow::Result<Vec<octocrab::models::Repository>> {
+#[derive(Deserialize)]
+struct InstallationRepositories {
+    repositories: Vec<octocrab::models::Repository>,
+}
+
+/// The repos a client can scan: those an app installation was granted, or
+/// those owned by a personal token's user.
+async fn list_all_repos(
+    client: &GitHubClient,
+) -> anyhow::Result<Vec<octocrab::models::Repository>> {
+    let crab = &client.crab;
     let mut all: Vec<octocrab::models::Repository> = Vec::new();
     let mut page: u8 = 1;
+    if client.installation.is_some() {
+        loop {
+            let route = format!(
+                "/installation/repositories?per_page={}&page={}",
+                PER_PAGE, page
+            );
+            let repos = crab
+                .get::<InstallationRepositories, _, ()>(&route, None)
+                .await?
+                .repositories;
+            let count = repos.len() as u8;
+            all.extend(repos);
+            if count < PER_PAGE {
+                break;
+            }
+            page += 1;
+        }
+        return Ok(all);
+    }
     loop {
         let resp = crab
             .current()
@@ -149,8 +181,8 @@ async fn list_commits_for_branch(
 
 /// One resolved check for a commit, ready to persist as a GitCommitBuild.
 struct CheckResult {
-    /// Stable per-commit key: `run-<id>` for Checks API runs, `legacy-status`
-    /// for the collapsed older Statuses API.
+    /// Stable per-commit key: `run-<id>` for Checks API runs, `status-<context>`
+    /// for the older Statuses API.
     check_name: String,
     status: String,
     url: String,
@@ -178,6 +210,7 @@ struct ScanCheckRunsResponse {
 #[derive(serde::Deserialize)]
 struct ScanCheckRun {
     id: u64,
+    name: String,
     conclusion: Option<String>,
     started_at: Option<chrono::DateTime<chrono::Utc>>,
     completed_at: Option<chrono::DateTime<chrono::Utc>>,
@@ -191,6 +224,20 @@ struct ScanApp {
     id: u64,
 }
 
+// The parts of the "get the combined status for a ref" response we use.
+#[derive(serde::Deserialize)]
+struct ScanCombinedStatus {
+    statuses: Vec<ScanStatus>,
+}
+
+#[derive(serde::Deserialize)]
+struct ScanStatus {
+    context: String,
+    state: String,
+    target_url: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
 /// Fetch the build state of a commit from GitHub at the check-RUN level.
 ///
 /// We deliberately use check runs (the real units of work) rather than check
@@ -208,49 +255,31 @@ async fn get_check_runs_for_sha(
 ) -> anyhow::Result<Vec<CheckResult>> {
     let mut results = Vec::new();
 
-    // Older Statuses API: kept as a fallback for third-party tools that still
-    // post commit statuses. GitHub Actions does not use this. Collapsed into a
-    // single entry since legacy statuses carry no per-run identity or timing.
-    match crab
-        .repos(owner, repo)
-        .list_statuses(sha.to_string())
-        .per_page(100)
-        .page(1u32)
-        .send()
-        .await
-    {
+    // Older Statuses API, used by external CI that doesn't create check runs.
+    // The combined status holds the latest status of each context, stored like
+    // a check named after the context.
+    let route = format!(
+        "/repos/{}/{}/commits/{}/status?per_page=100",
+        owner, repo, sha
+    );
+    match crab.get::<ScanCombinedStatus, _, ()>(&route, None).await {
         Ok(resp) => {
-            if !resp.items.is_empty() {
-                let mut any_error_or_failure = false;
-                let mut any_pending = false;
-                let mut any_success = false;
-                for st in &resp.items {
-                    let state_str = format!("{:?}", st.state).to_lowercase();
-                    match state_str.as_str() {
-                        "error" | "failure" => any_error_or_failure = true,
-                        "pending" => any_pending = true,
-                        "success" => any_success = true,
-                        _ => {}
-                    }
-                }
-                let status = if any_error_or_failure {
-                    "Failure"
-                } else if any_pending {
-                    "Pending"
-                } else if any_success {
-                    "Success"
-                } else {
-                    "None"
-                };
+            for status in resp.statuses {
+                let build_status =
+                    crate::build_status::BuildStatus::from_commit_state(&status.state);
+                let reported_at = datetime_to_millis(Some(status.created_at));
+                let pending = build_status == crate::build_status::BuildStatus::Pending;
                 results.push(CheckResult {
-                    check_name: "legacy-status".to_string(),
-                    status: status.to_string(),
-                    url: format!(
-                        "https://github.com/{}/{}/commit/{}/checks",
-                        owner, repo, sha
-                    ),
-                    start_time: None,
-                    settle_time: None,
+                    check_name: GitCommitBuild::status_check_name(&status.context),
+                    status: build_status.into(),
+                    url: status
+                        .target_url
+                        .filter(|u| !u.is_empty())
+                        .unwrap_or_else(|| {
+                            format!("https://github.com/{}/{}/commit/{}", owner, repo, sha)
+                        }),
+                    start_time: if pending { reported_at } else { None },
+                    settle_time: if pending { None } else { reported_at },
                     app_id: None,
                 });
             }
@@ -274,7 +303,12 @@ async fn get_check_runs_for_sha(
                 &sha[..7],
                 resp.check_runs.len()
             );
-            for run in resp.check_runs {
+            // Deploy config validation isn't a build of the commit.
+            for run in resp
+                .check_runs
+                .into_iter()
+                .filter(|run| run.name != CHECK_RUN_NAME)
+            {
                 // The list-check-runs payload has no separate status field; a
                 // missing conclusion means the run hasn't finished yet.
                 let status: String =
@@ -385,10 +419,11 @@ async fn run_owner_bootstrap_impl(
         }
     };
 
-    for (idx, crab) in octocrabs.iter().enumerate() {
-        let token_label = format!("token-{}", idx + 1);
+    for github in octocrabs.clients() {
+        let crab = &github.crab;
+        let token_label = format!("token-{}", github.label);
         let start_remaining = log_rate_limit(crab, &format!("start-{}", token_label)).await;
-        match list_all_repos(crab).await {
+        match list_all_repos(&github).await {
             Ok(repos) => {
                 log_append(format!("Discovered {} repositories", repos.len()));
                 for r in repos {
@@ -712,8 +747,9 @@ async fn run_repo_bootstrap_impl(
 
     log_append(format!("Scanning repo {}/{}", owner, repo_name));
 
-    for (idx, crab) in octocrabs.iter().enumerate() {
-        let token_label = format!("token-{}", idx + 1);
+    for github in octocrabs.clients() {
+        let crab = &github.crab;
+        let token_label = format!("token-{}", github.label);
         let start_remaining = log_rate_limit(crab, &format!("start-{}", token_label)).await;
 
         // Fetch the specific repository
@@ -1038,8 +1074,9 @@ async fn run_repo_resync_impl(
 
     log_append(format!("Resyncing repo {}/{}", owner, repo_name));
 
-    for (idx, crab) in octocrabs.iter().enumerate() {
-        let token_label = format!("token-{}", idx + 1);
+    for github in octocrabs.clients() {
+        let crab = &github.crab;
+        let token_label = format!("token-{}", github.label);
         let start_remaining = log_rate_limit(crab, &format!("start-{}", token_label)).await;
 
         // Fetch the specific repository
@@ -1325,12 +1362,71 @@ async fn run_bootstrap(pool: Pool<SqliteConnectionManager>, octocrabs: Octocrabs
     run_bootstrap_with_mode(pool, octocrabs, client, BootstrapMode::Quick).await;
 }
 
+/// Record a bootstrap request in the audit log and answer it.
+fn respond(
+    pool: &Pool<SqliteConnectionManager>,
+    user: &CurrentUser,
+    action: &str,
+    target: &str,
+    status: StatusCode,
+    body: String,
+) -> HttpResponse {
+    let result = if status.is_success() {
+        Ok(())
+    } else {
+        Err(body.clone())
+    };
+    AuditRecord::new(&user.login, AuditSource::Ui, action, target).record(pool, &result);
+
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .body(body)
+}
+
+fn already_running(
+    pool: &Pool<SqliteConnectionManager>,
+    user: &CurrentUser,
+    action: &str,
+    target: &str,
+) -> HttpResponse {
+    respond(
+        pool,
+        user,
+        action,
+        target,
+        StatusCode::CONFLICT,
+        "A bootstrap task is already running. Please wait for it to complete.".to_string(),
+    )
+}
+
+/// Whether any token can see the repository.
+async fn repo_exists(octocrabs: &Octocrabs, owner: &str, repo: &str) -> bool {
+    for client in octocrabs.clients() {
+        if (client.crab.repos(owner, repo).get().await).is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
 #[post("/bootstrap")]
 pub async fn bootstrap(
     pool: web::Data<Pool<SqliteConnectionManager>>,
     octocrabs: web::Data<Octocrabs>,
     client: web::Data<Client>,
+    user: CurrentUser,
 ) -> impl Responder {
+    if let Err(e) = user.authorize_admin("Bootstrap") {
+        return respond(
+            &pool,
+            &user,
+            "bootstrap",
+            "*",
+            StatusCode::FORBIDDEN,
+            e.to_string(),
+        );
+    }
+
     run_bootstrap(
         pool.get_ref().clone(),
         octocrabs.get_ref().clone(),
@@ -1338,9 +1434,14 @@ pub async fn bootstrap(
     )
     .await;
 
-    HttpResponse::build(StatusCode::ACCEPTED)
-        .content_type("text/html; charset=utf-8")
-        .body("Bootstrap started. This may take a few minutes.")
+    respond(
+        &pool,
+        &user,
+        "bootstrap",
+        "*",
+        StatusCode::ACCEPTED,
+        "Bootstrap started. This may take a few minutes.".to_string(),
+    )
 }
 
 #[post("/bootstrap/quick")]
@@ -1348,11 +1449,21 @@ pub async fn bootstrap_quick(
     pool: web::Data<Pool<SqliteConnectionManager>>,
     octocrabs: web::Data<Octocrabs>,
     client: web::Data<Client>,
+    user: CurrentUser,
 ) -> impl Responder {
+    let action = "bootstrap-quick";
+    if let Err(e) = user.authorize_admin("Bootstrap") {
+        return respond(
+            &pool,
+            &user,
+            action,
+            "*",
+            StatusCode::FORBIDDEN,
+            e.to_string(),
+        );
+    }
     if !try_acquire_lock() {
-        return HttpResponse::build(StatusCode::CONFLICT)
-            .content_type("text/html; charset=utf-8")
-            .body("A bootstrap task is already running. Please wait for it to complete.");
+        return already_running(&pool, &user, action, "*");
     }
 
     run_bootstrap_with_mode(
@@ -1363,9 +1474,14 @@ pub async fn bootstrap_quick(
     )
     .await;
 
-    HttpResponse::build(StatusCode::ACCEPTED)
-        .content_type("text/html; charset=utf-8")
-        .body("Quick scan started.")
+    respond(
+        &pool,
+        &user,
+        action,
+        "*",
+        StatusCode::ACCEPTED,
+        "Quick scan started.".to_string(),
+    )
 }
 
 #[post("/bootstrap/owner")]
@@ -1373,11 +1489,21 @@ pub async fn bootstrap_owner(
     pool: web::Data<Pool<SqliteConnectionManager>>,
     octocrabs: web::Data<Octocrabs>,
     client: web::Data<Client>,
+    user: CurrentUser,
 ) -> impl Responder {
+    let action = "bootstrap-owner";
+    if let Err(e) = user.authorize_admin("Bootstrap") {
+        return respond(
+            &pool,
+            &user,
+            action,
+            "*",
+            StatusCode::FORBIDDEN,
+            e.to_string(),
+        );
+    }
     if !try_acquire_lock() {
-        return HttpResponse::build(StatusCode::CONFLICT)
-            .content_type("text/html; charset=utf-8")
-            .body("A bootstrap task is already running. Please wait for it to complete.");
+        return already_running(&pool, &user, action, "*");
     }
 
     run_bootstrap_with_mode(
@@ -1388,9 +1514,14 @@ pub async fn bootstrap_owner(
     )
     .await;
 
-    HttpResponse::build(StatusCode::ACCEPTED)
-        .content_type("text/html; charset=utf-8")
-        .body("Owner sync started.")
+    respond(
+        &pool,
+        &user,
+        action,
+        "*",
+        StatusCode::ACCEPTED,
+        "Owner sync started.".to_string(),
+    )
 }
 
 #[derive(serde::Deserialize)]
@@ -1405,30 +1536,34 @@ pub async fn bootstrap_repo(
     octocrabs: web::Data<Octocrabs>,
     client: web::Data<Client>,
     req: web::Json<RepoBootstrapRequest>,
+    user: CurrentUser,
 ) -> impl Responder {
-    if !try_acquire_lock() {
-        return HttpResponse::build(StatusCode::CONFLICT)
-            .content_type("text/html; charset=utf-8")
-            .body("A bootstrap task is already running. Please wait for it to complete.");
+    let action = "bootstrap-repo";
+    let target = format!("{}/{}", req.owner, req.repo);
+    if let Err(e) = user.authorize_admin("Bootstrap") {
+        return respond(
+            &pool,
+            &user,
+            action,
+            &target,
+            StatusCode::FORBIDDEN,
+            e.to_string(),
+        );
     }
-
-    // Validate that the repo exists
-    let mut repo_found = false;
-    for crab in octocrabs.iter() {
-        if (crab.repos(&req.owner, &req.repo).get().await).is_ok() {
-            repo_found = true;
-            break;
-        }
+    if !try_acquire_lock() {
+        return already_running(&pool, &user, action, &target);
     }
 
-    if !repo_found {
+    if !repo_exists(&octocrabs, &req.owner, &req.repo).await {
         release_lock();
-        return HttpResponse::build(StatusCode::NOT_FOUND)
-            .content_type("text/html; charset=utf-8")
-            .body(format!(
-                "Repository {}/{} not found or not accessible.",
-                req.owner, req.repo
-            ));
+        return respond(
+            &pool,
+            &user,
+            action,
+            &target,
+            StatusCode::NOT_FOUND,
+            format!("Repository {} not found or not accessible.", target),
+        );
     }
 
     run_bootstrap_with_mode(
@@ -1442,12 +1577,14 @@ pub async fn bootstrap_repo(
     )
     .await;
 
-    HttpResponse::build(StatusCode::ACCEPTED)
-        .content_type("text/html; charset=utf-8")
-        .body(format!(
-            "Deep repo scan started for {}/{}.",
-            req.owner, req.repo
-        ))
+    respond(
+        &pool,
+        &user,
+        action,
+        &target,
+        StatusCode::ACCEPTED,
+        format!("Deep repo scan started for {}.", target),
+    )
 }
 
 #[post("/bootstrap/repo/resync")]
@@ -1456,30 +1593,34 @@ pub async fn bootstrap_repo_resync(
     octocrabs: web::Data<Octocrabs>,
     client: web::Data<Client>,
     req: web::Json<RepoBootstrapRequest>,
+    user: CurrentUser,
 ) -> impl Responder {
-    if !try_acquire_lock() {
-        return HttpResponse::build(StatusCode::CONFLICT)
-            .content_type("text/html; charset=utf-8")
-            .body("A bootstrap task is already running. Please wait for it to complete.");
+    let action = "resync-repo";
+    let target = format!("{}/{}", req.owner, req.repo);
+    if let Err(e) = user.authorize_admin("Bootstrap") {
+        return respond(
+            &pool,
+            &user,
+            action,
+            &target,
+            StatusCode::FORBIDDEN,
+            e.to_string(),
+        );
     }
-
-    // Validate that the repo exists
-    let mut repo_found = false;
-    for crab in octocrabs.iter() {
-        if (crab.repos(&req.owner, &req.repo).get().await).is_ok() {
-            repo_found = true;
-            break;
-        }
+    if !try_acquire_lock() {
+        return already_running(&pool, &user, action, &target);
     }
 
-    if !repo_found {
+    if !repo_exists(&octocrabs, &req.owner, &req.repo).await {
         release_lock();
-        return HttpResponse::build(StatusCode::NOT_FOUND)
-            .content_type("text/html; charset=utf-8")
-            .body(format!(
-                "Repository {}/{} not found or not accessible.",
-                req.owner, req.repo
-            ));
+        return respond(
+            &pool,
+            &user,
+            action,
+            &target,
+            StatusCode::NOT_FOUND,
+            format!("Repository {} not found or not accessible.", target),
+        );
     }
 
     run_bootstrap_with_mode(
@@ -1493,9 +1634,14 @@ pub async fn bootstrap_repo_resync(
     )
     .await;
 
-    HttpResponse::build(StatusCode::ACCEPTED)
-        .content_type("text/html; charset=utf-8")
-        .body(format!("Resync started for {}/{}.", req.owner, req.repo))
+    respond(
+        &pool,
+        &user,
+        action,
+        &target,
+        StatusCode::ACCEPTED,
+        format!("Resync started for {}.", target),
+    )
 }
 
 #[get("/bootstrap/log")]
@@ -1523,9 +1669,9 @@ pub async fn bootstrap_log() -> impl Responder {
 pub async fn rate_limits(octocrabs: web::Data<Octocrabs>) -> impl Responder {
     let mut results = Vec::new();
 
-    for (idx, crab) in octocrabs.iter().enumerate() {
-        let token_num = idx + 1;
-        match crab.ratelimit().get().await {
+    for client in octocrabs.clients() {
+        let token_num = client.label;
+        match client.crab.ratelimit().get().await {
             Ok(rate_info) => {
                 // Convert Unix timestamp to New York time
                 use chrono::{TimeZone, Utc};
@@ -0,0 +1,579 @@
+use crate::audit::{AuditRecord, AuditSource};
+use crate::auth::{is_local_path, CurrentUser};
+use crate::build_status::BuildStatus;
+use crate::crab_ext::Octocrabs;
+use crate::db::git_commit::GitCommit;
+use crate::db::git_commit_build::GitCommitBuild;
+use crate::db::git_repo::GitRepo;
+use crate::db::workflow_job::WorkflowJob;
+use crate::db::workflow_run::WorkflowRun;
+use crate::github_actions::{self, DispatchableWorkflow, RerunScope};
+use crate::prelude::*;
+use crate::web::formatting::{format_duration_ms, format_short_sha, format_timestamp};
+use crate::web::{build_status_helpers, header};
+
+/// The jobs of one attempt of a run. The run itself is missing when only
+/// its jobs' events were received.
+struct RunJobs<'a> {
+    run_id: u64,
+    run: Option<&'a WorkflowRun>,
+    attempt: u32,
+    jobs: Vec<&'a WorkflowJob>,
+}
+
+/// Group jobs by run, keeping only each run's latest attempt, in the order
+/// the runs were created.
+fn group_by_run<'a>(runs: &'a [WorkflowRun], jobs: &'a [WorkflowJob]) -> Vec<RunJobs<'a>> {
+    let mut groups: Vec<RunJobs> = runs
+        .iter()
+        .map(|run| RunJobs {
+            run_id: run.id,
+            run: Some(run),
+            attempt: run.run_attempt,
+            jobs: vec![],
+        })
+        .collect();
+    for job in jobs {
+        match groups.iter_mut().find(|g| g.run_id == job.run_id) {
+            Some(group) if group.run.is_none() && job.run_attempt > group.attempt => {
+                group.attempt = job.run_attempt;
+            }
+            Some(_) => {}
+            None => groups.push(RunJobs {
+                run_id: job.run_id,
+                run: None,
+                attempt: job.run_attempt,
+                jobs: vec![],
+            }),
+        }
+    }
+    for group in &mut groups {
+        group.jobs = jobs
+            .iter()
+            .filter(|j| j.run_id == group.run_id && j.run_attempt == group.attempt)
+            .collect();
+    }
+    groups
+}
+
+/// The stretch of time a run's jobs cover, from the first one queued until
+/// the last one finished, or now.
+#[derive(Debug, PartialEq)]
+struct Timeline {
+    start: u64,
+    end: u64,
+}
+
+impl Timeline {
+    fn of(jobs: &[&WorkflowJob], now: u64) -> Option<Self> {
+        let start = jobs.iter().map(|j| j.created_at).min()?;
+        let end = jobs
+            .iter()
+            .map(|j| j.completed_at.unwrap_or(now))
+            .max()?
+            .max(start + 1);
+        Some(Self { start, end })
+    }
+
+    /// CSS placing a bar from `from` to `to` on the timeline.
+    fn bar_style(&self, from: u64, to: u64) -> String {
+        let total = (self.end - self.start) as f64;
+        let from = from.clamp(self.start, self.end);
+        let to = to.clamp(from, self.end);
+        format!(
+            "left: {:.2}%; width: {:.2}%",
+            (from - self.start) as f64 * 100.0 / total,
+            (to - from) as f64 * 100.0 / total
+        )
+    }
+}
+
+fn status_class(status: &str, conclusion: Option<&str>) -> &'static str {
+    build_status_helpers::build_status_class(&BuildStatus::of(status, &conclusion))
+}
+
+/// "queued 12s, ran 3m 4s", with an estimate for jobs still running.
+fn describe_job(job: &WorkflowJob, avg_ms: Option<u64>, now: u64) -> String {
+    let queued = job.started_at.unwrap_or(now).saturating_sub(job.created_at);
+    let mut description = format!("queued {}", format_duration_ms(queued));
+    if let Some(started) = job.started_at {
+        let ran = job.completed_at.unwrap_or(now).saturating_sub(started);
+        description.push_str(&format!(", ran {}", format_duration_ms(ran)));
+    }
+    if job.status != "completed" {
+        if let Some(remaining) = job.remaining_ms(avg_ms, now) {
+            description.push_str(&format!(", about {} left", format_duration_ms(remaining)));
+        }
+    }
+    description
+}
+
+fn render_run(
+    group: &RunJobs,
+    repo: &GitRepo,
+    now: u64,
+    conn: &r2d2::PooledConnection<SqliteConnectionManager>,
+) -> Markup {
+    let timeline = Timeline::of(&group.jobs, now);
+    html! {
+        div class="workflow-run" {
+            h3 {
+                @match group.run {
+                    Some(run) => {
+                        span class=(format!("status-indicator {}", status_class(&run.status, run.conclusion.as_deref()))) {}
+                        " "
+                        a href=(run.html_url) target="_blank" { (run.name) " #" (run.run_number) }
+                        @if run.run_attempt > 1 {
+                            span class="workflow-run-meta" { " attempt " (run.run_attempt) }
+                        }
+                        span class="workflow-run-meta" { " · " (run.event) }
+                    }
+                    None => { "Run " (group.run_id) }
+                }
+            }
+            @if let Some(timeline) = &timeline {
+                div class="gantt" {
+                    @for job in &group.jobs {
+                        @let avg_ms = WorkflowJob::avg_duration_ms(repo.id, &job.name, 10, conn).ok().flatten();
+                        div class="gantt-row" {
+                            div class="gantt-label" {
+                                @match &job.html_url {
+                                    Some(url) => a href=(url) target="_blank" { (job.name) },
+                                    None => span { (job.name) },
+                                }
+                                @if let Some(runner) = &job.runner_name {
+                                    div class="gantt-runner" { (runner) }
+                                }
+                            }
+                            div class="gantt-track" {
+                                div class="gantt-queued"
+                                    style=(timeline.bar_style(job.created_at, job.started_at.unwrap_or(now))) {}
+                                @if let Some(started) = job.started_at {
+                                    div class=(format!("gantt-bar {}", status_class(&job.status, job.conclusion.as_deref())))
+                                        style=(timeline.bar_style(started, job.completed_at.unwrap_or(now))) {}
+                                }
+                            }
+                            div class="gantt-times" { (describe_job(job, avg_ms, now)) }
+                        }
+                        @if !job.steps.is_empty() {
+                            details class="gantt-steps" {
+                                summary { (job.steps.len()) " steps" }
+                                table class="history-table" {
+                                    tbody {
+                                        @for step in &job.steps {
+                                            tr {
+                                                td { span class=(format!("status-indicator {}", status_class(&step.status, step.conclusion.as_deref()))) {} }
+                                                td { (step.name) }
+                                                td {
+                                                    @if let Some(started) = step.started_at {
+                                                        (format_duration_ms(step.completed_at.unwrap_or(now).saturating_sub(started)))
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                div class="gantt-total" {
+                    (format_timestamp(timeline.start as i64)) " · "
+                    (format_duration_ms(timeline.end - timeline.start)) " in total"
+                }
+            } @else {
+                p class="workflow-run-meta" { "No jobs received yet." }
+            }
+        }
+    }
+}
+
+/// Buttons re-running a commit's failed checks, coming back to `next`.
+pub fn render_rerun_buttons(repo: &GitRepo, sha: &str, next: &str) -> Markup {
+    let action = format!("/commits/{}/{}/{}/rerun", repo.owner_name, repo.name, sha);
+    html! {
+        form class="rerun-form" action=(action) method="post" {
+            input type="hidden" name="next" value=(next);
+            button type="submit" class="link-button" name="scope" value="failed"
+                title="Re-run the failed checks" {
+                i class="fa fa-refresh" {} " Re-run failed"
+            }
+            button type="submit" class="link-button" name="scope" value="suite"
+                title="Re-run every check of the failed suites" {
+                "Re-run all"
+            }
+        }
+    }
+}
+
+fn render_dispatch_form(
+    repo: &GitRepo,
+    sha: &str,
+    workflows: &[DispatchableWorkflow],
+    branches: &[String],
+) -> Markup {
+    html! {
+        form class="dispatch-form" action=(format!("/commits/{}/{}/{}/dispatch", repo.owner_name, repo.name, sha)) method="post" {
+            select name="workflow" {
+                @for workflow in workflows {
+                    option value=(workflow.id) { (workflow.name) }
+                }
+            }
+            " on "
+            select name="branch" {
+                @for branch in branches {
+                    option value=(branch) { (branch) }
+                }
+            }
+            " "
+            button type="submit" class="link-button" { i class="fa fa-play" {} " Run workflow" }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RerunForm {
+    scope: String,
+    next: Option<String>,
+}
+
+/// Re-run a commit's failed checks on GitHub, then go back to `next` if it
+/// is on this site, or the commit. The new runs show up through the webhooks.
+#[post("/commits/{owner}/{repo}/{sha}/rerun")]
+pub async fn rerun_commit_checks(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    octocrabs: web::Data<Octocrabs>,
+    client: Option<web::Data<kube::Client>>,
+    path: web::Path<(String, String, String)>,
+    form: web::Form<RerunForm>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    let (owner, name, sha) = path.into_inner();
+    let repo = GitRepo::get_by_name(&owner, &name, &pool.get()?)?
+        .ok_or_else(|| AppError::NotFound(format!("Repository {}/{}", owner, name)))?;
+    github_actions::authorize(
+        &user,
+        client.as_ref().map(|c| c.get_ref()),
+        &repo,
+        "Re-running checks",
+    )
+    .await?;
+    let scope = RerunScope::parse(&form.scope)
+        .ok_or_else(|| AppError::InvalidInput(format!("Unknown scope {}", form.scope)))?;
+
+    let result = github_actions::rerun_checks(&octocrabs, &repo, &sha, scope).await;
+    AuditRecord::new(
+        &user.login,
+        AuditSource::Ui,
+        "rerun-checks",
+        &format!("{}/{}@{}", owner, name, sha),
+    )
+    .params(serde_json::json!({ "scope": form.scope }))
+    .record(&pool, &result);
+    result?;
+
+    let back = format!("/commits/{}/{}/{}", owner, name, sha);
+    Ok(HttpResponse::SeeOther()
+        .insert_header((
+            "Location",
+            form.next
+                .as_deref()
+                .filter(|next| is_local_path(next))
+                .unwrap_or(&back),
+        ))
+        .finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DispatchForm {
+    workflow: String,
+    branch: String,
+}
+
+/// Start a `workflow_dispatch` workflow on a branch, then go back to the
+/// commit. Its pending checks show up through the webhooks.
+#[post("/commits/{owner}/{repo}/{sha}/dispatch")]
+pub async fn dispatch_commit_workflow(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    octocrabs: web::Data<Octocrabs>,
+    client: Option<web::Data<kube::Client>>,
+    path: web::Path<(String, String, String)>,
+    form: web::Form<DispatchForm>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    let (owner, name, sha) = path.into_inner();
+    let repo = GitRepo::get_by_name(&owner, &name, &pool.get()?)?
+        .ok_or_else(|| AppError::NotFound(format!("Repository {}/{}", owner, name)))?;
+    github_actions::authorize(
+        &user,
+        client.as_ref().map(|c| c.get_ref()),
+        &repo,
+        "Dispatching workflows",
+    )
+    .await?;
+
+    let result =
+        github_actions::dispatch_workflow(&octocrabs, &repo, &form.workflow, &form.branch).await;
+    AuditRecord::new(
+        &user.login,
+        AuditSource::Ui,
+        "dispatch-workflow",
+        &format!("{}/{}", owner, name),
+    )
+    .params(serde_json::json!({ "workflow": form.workflow, "branch": form.branch }))
+    .record(&pool, &result);
+    result?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/commits/{}/{}/{}", owner, name, sha)))
+        .finish())
+}
+
+/// The form to dispatch one of the repo's workflows on a branch containing
+/// the commit, loaded when the commit page's "Run workflow" is opened.
+#[get("/commits/{owner}/{repo}/{sha}/dispatch-form")]
+pub async fn commit_dispatch_form(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    octocrabs: web::Data<Octocrabs>,
+    path: web::Path<(String, String, String)>,
+) -> AppResult<HttpResponse> {
+    let (owner, name, sha) = path.into_inner();
+    let (repo, branches) = {
+        let conn = pool.get()?;
+        let repo = GitRepo::get_by_name(&owner, &name, &conn)?
+            .ok_or_else(|| AppError::NotFound(format!("Repository {}/{}", owner, name)))?;
+        let branches: Vec<String> = match GitCommit::get_by_sha(&sha, repo.id, &conn)? {
+            Some(commit) => commit
+                .get_branches(&conn)?
+                .into_iter()
+                .map(|b| b.name)
+                .collect(),
+            None => vec![],
+        };
+        (repo, branches)
+    };
+    let workflows = github_actions::dispatchable_workflows(&octocrabs, &repo)
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to list workflows of {}/{}: {}", owner, name, e);
+            vec![]
+        });
+
+    let markup = html! {
+        @if workflows.is_empty() || branches.is_empty() {
+            p class="workflow-run-meta" { "No workflows can be dispatched for this commit." }
+        } @else {
+            (render_dispatch_form(&repo, &sha, &workflows, &branches))
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string()))
+}
+
+/// A commit with its checks and a timeline of its workflow runs' jobs.
+#[get("/commits/{owner}/{repo}/{sha}")]
+pub async fn commit_page(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    path: web::Path<(String, String, String)>,
+) -> AppResult<HttpResponse> {
+    let (owner, name, sha) = path.into_inner();
+    let conn = pool.get()?;
+    let repo = GitRepo::get_by_name(&owner, &name, &conn)?
+        .ok_or_else(|| AppError::NotFound(format!("Repository {}/{}", owner, name)))?;
+    let commit = GitCommit::get_by_sha(&sha, repo.id, &conn)?;
+    let builds = match &commit {
+        Some(commit) => GitCommitBuild::get_all_by_commit_id(&commit.id, &repo.id, &conn)?,
+        None => vec![],
+    };
+    let runs = WorkflowRun::get_by_commit(repo.id, &sha, &conn)?;
+    let jobs = WorkflowJob::get_by_commit(repo.id, &sha, &conn)?;
+    if commit.is_none() && runs.is_empty() && jobs.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "Commit {} in {}/{}",
+            sha, owner, name
+        )));
+    }
+    let now = Utc::now().timestamp_millis() as u64;
+    let failed = builds
+        .iter()
+        .any(|b| BuildStatus::from(b.clone()) == BuildStatus::Failure);
+    let branches: Vec<String> = match &commit {
+        Some(commit) => commit
+            .get_branches(&conn)?
+            .into_iter()
+            .map(|b| b.name)
+            .collect(),
+        None => vec![],
+    };
+
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="UTF-8";
+                meta name="viewport" content="width=device-width, initial-scale=1.0";
+                title { "Commit " (format_short_sha(&sha)) }
+                (header::stylesheet_link())
+                (header::scripts())
+            }
+            body.deploy-history-page.commit-page {
+                (header::render("branches"))
+                div class="content" {
+                    header {
+                        h1 { "Commit " (format_short_sha(&sha)) }
+                        div class="subtitle" {
+                            (repo.owner_name) "/" (repo.name) " · "
+                            a href=(format!("https://github.com/{}/{}/commit/{}", repo.owner_name, repo.name, sha)) target="_blank" {
+                                "View on GitHub"
+                            }
+                        }
+                    }
+                    @if let Some(commit) = &commit {
+                        pre class="commit-detail-message" { (commit.message) }
+                        div class="workflow-run-meta" {
+                            (commit.author) " · " (format_timestamp(commit.timestamp))
+                        }
+                    }
+                    h2 { "Checks" }
+                    @if failed {
+                        (render_rerun_buttons(&repo, &sha, &format!("/commits/{}/{}/{}", owner, name, sha)))
+                    }
+                    @if !branches.is_empty() {
+                        // Listing workflows reads every workflow file, so only
+                        // do it once the form is asked for.
+                        details class="dispatch-details"
+                            hx-get=(format!("/commits/{}/{}/{}/dispatch-form", owner, name, sha))
+                            hx-trigger="toggle once"
+                            hx-target="find .dispatch-workflows"
+                        {
+                            summary { "Run workflow" }
+                            div class="dispatch-workflows" {
+                                p class="workflow-run-meta" { "Loading workflows…" }
+                            }
+                        }
+                    }
+                    @if builds.is_empty() {
+                        p class="workflow-run-meta" { "No checks reported." }
+                    } @else {
+                        table class="history-table" {
+                            thead {
+                                tr {
+                                    th {}
+                                    th { "Check" }
+                                    th { "Duration" }
+                                    th {}
+                                }
+                            }
+                            tbody {
+                                @for build in &builds {
+                                    @let status: BuildStatus = build.clone().into();
+                                    tr {
+                                        td { span class=(format!("status-indicator {}", build_status_helpers::build_status_class(&status))) {} }
+                                        td { (build.check_name) }
+                                        td {
+                                            @if let Some(start) = build.start_time {
+                                                (format_duration_ms(build.settle_time.unwrap_or(now).saturating_sub(start)))
+                                            }
+                                        }
+                                        td class="actions-cell" {
+                                            a class="link-button" href=(build.url) target="_blank" { "Details" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    h2 { "Workflow runs" }
+                    @let groups = group_by_run(&runs, &jobs);
+                    @if groups.is_empty() {
+                        p class="workflow-run-meta" { "No workflow runs received for this commit." }
+                    }
+                    @for group in &groups {
+                        (render_run(group, &repo, now, &conn))
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn job(id: u64, run_id: u64, attempt: u32, created: u64, started: Option<u64>) -> WorkflowJob {
+        WorkflowJob {
+            id,
+            run_id,
+            run_attempt: attempt,
+            repo_id: 1,
+            head_sha: "abc".to_string(),
+            name: format!("job-{}", id),
+            status: "in_progress".to_string(),
+            conclusion: None,
+            runner_name: None,
+            html_url: None,
+            created_at: created,
+            started_at: started,
+            completed_at: None,
+            steps: vec![],
+        }
+    }
+
+    #[test]
+    fn groups_latest_attempts_by_run() {
+        let jobs = vec![
+            job(1, 10, 1, 0, None),
+            job(2, 20, 1, 0, None),
+            job(3, 10, 2, 0, None),
+        ];
+        let groups = group_by_run(&[], &jobs);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].run_id, 10);
+        assert_eq!(groups[0].attempt, 2);
+        assert_eq!(
+            groups[0].jobs.iter().map(|j| j.id).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(
+            groups[1].jobs.iter().map(|j| j.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn places_bars_on_the_timeline() {
+        let mut done = job(1, 10, 1, 1_000, Some(2_000));
+        done.status = "completed".to_string();
+        done.completed_at = Some(5_000);
+        let running = job(2, 10, 1, 3_000, Some(4_000));
+        let timeline = Timeline::of(&[&done, &running], 9_000).unwrap();
+        assert_eq!(
+            timeline,
+            Timeline {
+                start: 1_000,
+                end: 9_000
+            }
+        );
+        assert_eq!(
+            timeline.bar_style(2_000, 5_000),
+            "left: 12.50%; width: 37.50%"
+        );
+        assert_eq!(timeline.bar_style(0, 20_000), "left: 0.00%; width: 100.00%");
+
+        assert_eq!(running.remaining_ms(Some(8_000), 9_000), Some(3_000));
+        assert_eq!(running.remaining_ms(Some(4_000), 9_000), Some(0));
+        assert_eq!(
+            job(3, 10, 1, 0, None).remaining_ms(Some(4_000), 9_000),
+            Some(4_000)
+        );
+        assert_eq!(done.remaining_ms(None, 9_000), Some(0));
+        assert_eq!(running.remaining_ms(None, 9_000), None);
+    }
+}
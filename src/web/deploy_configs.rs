@@ -1,15 +1,21 @@
 #![allow(clippy::expect_used)]
 
+use crate::audit::{AuditRecord, AuditSource};
+use crate::auth::authz::Role;
+use crate::auth::CurrentUser;
 use crate::crab_ext::Octocrabs;
 use crate::db::deploy_config_version::DeployConfigVersion;
 use crate::db::deploy_event::DeployEvent;
 use crate::db::git_branch::GitBranch;
 use crate::db::git_commit::GitCommit;
+use crate::db::git_pull_request::GitPullRequest;
 use crate::db::git_repo::GitRepo;
+use crate::events::{self, Event};
 use crate::kubernetes::api::{
     get_all_deploy_configs, get_deploy_config, get_namespace_uid, ListMode,
 };
 use crate::kubernetes::deploy_handlers::DeployAction;
+use crate::kubernetes::orphan_lifecycle::OrphanPolicy;
 use crate::kubernetes::repo::{DeploymentState, ShaMaybeBranch};
 use crate::kubernetes::{list_namespace_objects, DeployConfig};
 use crate::prelude::*;
@@ -76,6 +82,58 @@ impl Render for HumanTime {
     }
 }
 
+/// Explains why a config is orphaned and when it will be cleaned up.
+struct OrphanBanner<'a>(&'a DeployConfig);
+
+impl Render for OrphanBanner<'_> {
+    fn render(&self) -> Markup {
+        let config = self.0;
+        let policy = OrphanPolicy::from_env();
+        let deadline = config.orphan_deadline(policy.grace_period);
+        let urgent = deadline.is_some_and(|d| policy.is_within_warning(d, Utc::now()));
+
+        html! {
+            div.alert.alert-warning[!urgent].alert-danger[urgent] {
+                div class="alert-header" {
+                    i class="fa fa-exclamation-triangle" {}
+                    " Orphaned Deploy Config"
+                }
+                div class="alert-content" {
+                    div class="details" {
+                        "This deploy config has been deleted from the config repository but is still deployed. Only undeploy or keep is available."
+                    }
+                    @if let Some(reason) = config.orphan_reason() {
+                        div class="details" {
+                            strong { "Reason: " }
+                            (reason)
+                        }
+                    }
+                    @if let Some(orphaned_at) = config.orphaned_at() {
+                        div class="details" {
+                            strong { "Orphaned since: " }
+                            (HumanTime(orphaned_at.timestamp_millis() as u64))
+                        }
+                    }
+                    div class="details" {
+                        strong { "Cleanup: " }
+                        @if let Some(kept_at) = config.orphan_kept_at() {
+                            "paused, kept since "
+                            (HumanTime(kept_at.timestamp_millis() as u64))
+                        } @else if let Some(deadline) = deadline {
+                            "will be undeployed and deleted on "
+                            (HumanTime(deadline.timestamp_millis() as u64))
+                        } @else if policy.grace_period.is_none() {
+                            "automatic cleanup is disabled"
+                        } @else {
+                            "scheduling"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 struct AutodeployStatus(bool);
 impl Render for AutodeployStatus {
     fn render(&self) -> Markup {
@@ -201,6 +259,7 @@ impl ResolvedVersion {
             Action::Bounce => ResolvedVersion::ResolutionFailed,
             Action::ExecuteJob => ResolvedVersion::ResolutionFailed,
             Action::ToggleAutodeploy => ResolvedVersion::ResolutionFailed,
+            Action::ToggleOrphanKeep => ResolvedVersion::ResolutionFailed,
             Action::Undeploy => ResolvedVersion::Undeployed,
         }
     }
@@ -635,9 +694,10 @@ impl DeploymentState {
                     branch: None,
                 },
             }),
-            (Action::Bounce, _) | (Action::ExecuteJob, _) | (Action::ToggleAutodeploy, _) => {
-                Ok(config.deployment_state())
-            }
+            (Action::Bounce, _)
+            | (Action::ExecuteJob, _)
+            | (Action::ToggleAutodeploy, _)
+            | (Action::ToggleOrphanKeep, _) => Ok(config.deployment_state()),
             (Action::Undeploy, _) => Ok(DeploymentState::Undeployed),
         }
     }
@@ -735,6 +795,20 @@ pub async fn render_preview_content(
                 }
             }
         }
+        Action::ToggleOrphanKeep => {
+            html! {
+                "Orphan cleanup "
+                @if selected_config.is_orphan_kept() {
+                    "paused"
+                    ( PreviewArrow {} )
+                    "scheduled"
+                } @else {
+                    "scheduled"
+                    ( PreviewArrow {} )
+                    "paused"
+                }
+            }
+        }
     };
 
     let mut alerts: Vec<Markup> = vec![];
@@ -801,32 +875,57 @@ pub enum Action {
     Bounce,
     ExecuteJob,
     ToggleAutodeploy,
+    ToggleOrphanKeep,
     Undeploy,
 }
 
 impl Action {
     pub fn from_query(query: &HashMap<String, String>) -> Self {
-        match query
-            .get("action")
-            .unwrap_or(&"deploy".to_string())
-            .as_str()
-        {
+        Self::parse(
+            query.get("action").map(String::as_str).unwrap_or("deploy"),
+            query.get("branch").map(String::as_str),
+            query.get("sha").map(String::as_str),
+        )
+        .unwrap_or(Action::DeployLatest)
+    }
+
+    /// Parse an action by its query-string name. A non-empty `sha` takes
+    /// precedence over `branch` for deploys.
+    pub fn parse(action: &str, branch: Option<&str>, sha: Option<&str>) -> Option<Self> {
+        let action = match action {
             "deploy" => {
-                if let Some(sha) = query.get("sha").filter(|s| !s.is_empty()) {
-                    Action::DeployCommit { sha: sha.clone() }
-                } else if let Some(branch) = query.get("branch").filter(|s| !s.is_empty()) {
+                if let Some(sha) = sha.filter(|s| !s.is_empty()) {
+                    Action::DeployCommit {
+                        sha: sha.to_string(),
+                    }
+                } else if let Some(branch) = branch.filter(|s| !s.is_empty()) {
                     Action::DeployBranch {
-                        branch: branch.clone(),
+                        branch: branch.to_string(),
                     }
                 } else {
                     Action::DeployLatest
                 }
             }
             "toggle-autodeploy" => Action::ToggleAutodeploy,
+            "toggle-orphan-keep" => Action::ToggleOrphanKeep,
             "undeploy" => Action::Undeploy,
             "bounce" => Action::Bounce,
             "execute-job" => Action::ExecuteJob,
-            _ => Action::DeployLatest,
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Action::DeployLatest => "Deploy (latest)".to_string(),
+            Action::DeployBranch { branch } => format!("Deploy (branch: {})", branch),
+            Action::DeployCommit { sha } => format!("Deploy (sha: {})", sha),
+            Action::Undeploy => "Undeploy".to_string(),
+            Action::Bounce => "Bounce".to_string(),
+            Action::ExecuteJob => "Execute job".to_string(),
+            Action::ToggleAutodeploy => "Toggle autodeploy".to_string(),
+            Action::ToggleOrphanKeep => "Toggle orphan keep".to_string(),
         }
     }
 
@@ -838,10 +937,47 @@ impl Action {
             Action::Bounce => "action=bounce".to_string(),
             Action::ExecuteJob => "action=execute-job".to_string(),
             Action::ToggleAutodeploy => "action=toggle-autodeploy".to_string(),
+            Action::ToggleOrphanKeep => "action=toggle-orphan-keep".to_string(),
             Action::Undeploy => "action=undeploy".to_string(),
         }
     }
 
+    /// The action name recorded in the audit log.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::DeployLatest | Action::DeployBranch { .. } | Action::DeployCommit { .. } => {
+                "deploy"
+            }
+            Action::Bounce => "bounce",
+            Action::ExecuteJob => "execute-job",
+            Action::ToggleAutodeploy => "toggle-autodeploy",
+            Action::ToggleOrphanKeep => "toggle-orphan-keep",
+            Action::Undeploy => "undeploy",
+        }
+    }
+
+    /// The parameters recorded in the audit log.
+    pub fn params(&self) -> serde_json::Value {
+        match self {
+            Action::DeployBranch { branch } => serde_json::json!({ "branch": branch }),
+            Action::DeployCommit { sha } => serde_json::json!({ "sha": sha }),
+            _ => serde_json::json!({}),
+        }
+    }
+
+    /// The role needed on the config's team to perform this action.
+    pub fn required_role(&self) -> Role {
+        match self {
+            Action::ToggleAutodeploy | Action::ToggleOrphanKeep => Role::Admin,
+            Action::DeployLatest
+            | Action::DeployBranch { .. }
+            | Action::DeployCommit { .. }
+            | Action::Bounce
+            | Action::ExecuteJob
+            | Action::Undeploy => Role::Deployer,
+        }
+    }
+
     fn is_deploy(&self) -> bool {
         matches!(
             self,
@@ -857,6 +993,10 @@ impl Action {
         matches!(self, Action::Undeploy)
     }
 
+    fn is_toggle_orphan_keep(&self) -> bool {
+        matches!(self, Action::ToggleOrphanKeep)
+    }
+
     fn is_bounce(&self) -> bool {
         matches!(self, Action::Bounce)
     }
@@ -872,6 +1012,7 @@ pub async fn deploy_configs(
     req: actix_web::HttpRequest,
     pool: web::Data<Pool<SqliteConnectionManager>>,
     query: web::Query<std::collections::HashMap<String, String>>,
+    user: CurrentUser,
 ) -> impl Responder {
     let conn = match pool.get() {
         Ok(c) => c,
@@ -1011,12 +1152,14 @@ pub async fn deploy_configs(
                                         div class="action-radio-group" {
                                             h4 { "Action" }
                                             @let is_orphaned = selected_config.is_orphaned();
+                                            @let can_deploy = user.can(selected_config.team(), Role::Deployer);
+                                            @let can_admin = user.can(selected_config.team(), Role::Admin);
                                             label class="action-radio" {
-                                                input type="radio" name="action" value="deploy" checked[action.is_deploy()] disabled[is_orphaned] onchange="this.form.submit()";
+                                                input type="radio" name="action" value="deploy" checked[action.is_deploy()] disabled[is_orphaned || !can_deploy] onchange="this.form.submit()";
                                                 "Deploy"
                                             }
                                             label class="action-radio" {
-                                                input type="radio" name="action" value="toggle-autodeploy" checked[action.is_toggle_autodeploy()] disabled[is_orphaned] onchange="this.form.submit()";
+                                                input type="radio" name="action" value="toggle-autodeploy" checked[action.is_toggle_autodeploy()] disabled[is_orphaned || !can_admin] onchange="this.form.submit()";
                                                 @if selected_config.autodeploy() {
                                                     "Disable autodeploy"
                                                 } @else {
@@ -1025,20 +1168,30 @@ pub async fn deploy_configs(
                                             }
                                             @if selected_config.supports_bounce() {
                                                 label class="action-radio" {
-                                                    input type="radio" name="action" value="bounce" checked[action.is_bounce()] disabled[is_orphaned] onchange="this.form.submit()";
+                                                    input type="radio" name="action" value="bounce" checked[action.is_bounce()] disabled[is_orphaned || !can_deploy] onchange="this.form.submit()";
                                                     "Bounce"
                                                 }
                                             }
                                             @if selected_config.supports_execute_job() {
                                                 label class="action-radio" {
-                                                    input type="radio" name="action" value="execute-job" checked[action.is_execute_job()] disabled[is_orphaned] onchange="this.form.submit()";
+                                                    input type="radio" name="action" value="execute-job" checked[action.is_execute_job()] disabled[is_orphaned || !can_deploy] onchange="this.form.submit()";
                                                     "Execute job"
                                                 }
                                             }
                                             label class="action-radio" {
-                                                input type="radio" name="action" value="undeploy" checked[action.is_undeploy()] onchange="this.form.submit()";
+                                                input type="radio" name="action" value="undeploy" checked[action.is_undeploy()] disabled[!can_deploy] onchange="this.form.submit()";
                                                 "Undeploy"
                                             }
+                                            @if is_orphaned {
+                                                label class="action-radio" {
+                                                    input type="radio" name="action" value="toggle-orphan-keep" checked[action.is_toggle_orphan_keep()] disabled[!can_admin] onchange="this.form.submit()";
+                                                    @if selected_config.is_orphan_kept() {
+                                                        "Resume cleanup"
+                                                    } @else {
+                                                        "Keep"
+                                                    }
+                                                }
+                                            }
                                         }
 
                                         @if action.is_deploy() && !selected_config.is_orphaned() {
@@ -1050,6 +1203,21 @@ pub async fn deploy_configs(
                                                 label for="sha" { "SHA override" }
                                                 input id="sha" type="text" name="sha" placeholder="Enter commit SHA" pattern="[0-9a-fA-F]{5,40}" value=(query.get("sha").unwrap_or(&"".to_string())) onblur="this.form.submit()";
                                             }
+                                            @let branch = query.get("branch").map(String::as_str).or(current_branch).unwrap_or_default();
+                                            @if let Some(pr) = branch_pull_request(selected_config, branch, &conn) {
+                                                div class="action-input pull-request-note" {
+                                                    (crate::web::index::render_pull_request_badge(&pr))
+                                                    " "
+                                                    a href=(pr.html_url) target="_blank" { (pr.title) }
+                                                    @if pr.state == "open" {
+                                                        div {
+                                                            a class="link-button" href=(pull_request_deploy_link(selected_config, &pr)) {
+                                                                "Deploy this PR's head"
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                            }
                                         }
                                     }
                                     form action=(format!("/api/deploy/{}/{}",
@@ -1061,7 +1229,8 @@ pub async fn deploy_configs(
                                         input type="hidden" name="sha" value=(query.get("sha").unwrap_or(&"".to_string()));
                                         input type="hidden" name="action" value=(query.get("action").unwrap_or(&"".to_string()));
                                         @let is_orphaned = selected_config.is_orphaned();
-                                        button.primary-action-button.danger-button[action.is_undeploy()] type="submit" disabled[is_orphaned && !action.is_undeploy()] {
+                                        @let permitted = user.can(selected_config.team(), action.required_role());
+                                        button.primary-action-button.danger-button[action.is_undeploy()] type="submit" disabled[!permitted || if is_orphaned { !action.is_undeploy() && !action.is_toggle_orphan_keep() } else { action.is_toggle_orphan_keep() }] {
                                             @match action {
                                                 Action::DeployLatest | Action::DeployBranch { .. } | Action::DeployCommit { .. } => {
                                                     "Deploy"
@@ -1082,6 +1251,18 @@ pub async fn deploy_configs(
                                                 Action::Undeploy => {
                                                     "Undeploy"
                                                 }
+                                                Action::ToggleOrphanKeep => {
+                                                    @if selected_config.is_orphan_kept() {
+                                                        "Resume cleanup"
+                                                    } @else {
+                                                        "Keep"
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        @if !permitted {
+                                            div class="permission-note" {
+                                                "Requires the " (action.required_role()) " role on team " (selected_config.team())
                                             }
                                         }
                                     }
@@ -1114,20 +1295,33 @@ pub async fn deploy_configs(
                                             Action::Undeploy => {
                                                 "Undeploy of "
                                             }
+                                            Action::ToggleOrphanKeep => {
+                                                "Orphan cleanup of "
+                                            }
                                         }
                                         strong {
                                             (format!("{}", selected_config.name_any()))
                                         }
                                     }
                                     @if selected_config.is_orphaned() {
+                                        (OrphanBanner(selected_config))
+                                    }
+                                    @let drifted = selected_config.drifted_resources();
+                                    @if !drifted.is_empty() {
                                         div.alert.alert-warning {
                                             div class="alert-header" {
                                                 i class="fa fa-exclamation-triangle" {}
-                                                " Orphaned Deploy Config"
+                                                " Drift Detected"
                                             }
                                             div class="alert-content" {
                                                 div class="details" {
-                                                    "This deploy config has been deleted from the config repository but is still deployed. Only undeploy is available."
+                                                    "These resources were changed outside of CI/CD and no longer match the deployed specs. This config only reports drift; redeploy to restore the desired state."
+                                                }
+                                                @for resource in drifted {
+                                                    div class="details" {
+                                                        strong { (resource.kind) " " (resource.name) }
+                                                        ": " (resource.fields.join(", "))
+                                                    }
                                                 }
                                             }
                                         }
@@ -1147,6 +1341,171 @@ pub async fn deploy_configs(
         .body(markup.into_string())
 }
 
+/// The pull request opened from `branch` of the config's artifact repo.
+fn branch_pull_request(
+    config: &DeployConfig,
+    branch: &str,
+    conn: &PooledConnection<SqliteConnectionManager>,
+) -> Option<GitPullRequest> {
+    let artifact_repository = config.artifact_repository()?;
+    let repo = GitRepo::get_by_name(&artifact_repository.owner, &artifact_repository.repo, conn)
+        .ok()
+        .flatten()?;
+    let branch = GitBranch::get_by_name(branch, repo.id, conn)
+        .ok()
+        .flatten()?;
+    GitPullRequest::get_for_branch(&branch, conn).ok().flatten()
+}
+
+/// This page with a deploy of the pull request's head commit selected.
+fn pull_request_deploy_link(config: &DeployConfig, pr: &GitPullRequest) -> String {
+    url::form_urlencoded::Serializer::new("/deploy?".to_string())
+        .append_pair("selected", &config.name_any())
+        .append_pair("action", "deploy")
+        .append_pair("branch", &pr.head_branch)
+        .append_pair("sha", &pr.head_sha)
+        .finish()
+}
+
+/// Orphaned configs can only be undeployed or kept, and only orphaned configs
+/// can be kept.
+pub fn check_action_allowed(action: &Action, config: &DeployConfig) -> AppResult<()> {
+    if config.is_orphaned() && !matches!(action, Action::Undeploy | Action::ToggleOrphanKeep) {
+        return Err(AppError::InvalidInput(
+            "Cannot perform this action on an orphaned deploy config. Only undeploy or keep is allowed.".to_string(),
+        ));
+    }
+    if !config.is_orphaned() && matches!(action, Action::ToggleOrphanKeep) {
+        return Err(AppError::InvalidInput(
+            "Only orphaned deploy configs can be kept.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails unless the user holds the role `action` requires on the config's team.
+pub fn check_action_permitted(
+    user: &CurrentUser,
+    action: &Action,
+    config: &DeployConfig,
+) -> AppResult<()> {
+    user.authorize(
+        config.team(),
+        action.required_role(),
+        &format!("{} on {}", action.describe(), config.name_any()),
+    )
+}
+
+/// Apply a user-initiated action to a DeployConfig and record it in metrics,
+/// GitHub Deployments, the deploy history and the audit log.
+pub async fn execute_action(
+    action: &Action,
+    config: &DeployConfig,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+    user: &CurrentUser,
+    source: AuditSource,
+) -> AppResult<DeployAction> {
+    let result = apply_action(action, config, client, pool, octocrabs, user).await;
+    AuditRecord::new(&user.login, source, action.name(), &config.name_any())
+        .params(action.params())
+        .record(pool, &result);
+    result
+}
+
+async fn apply_action(
+    action: &Action,
+    config: &DeployConfig,
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    octocrabs: &Octocrabs,
+    user: &CurrentUser,
+) -> AppResult<DeployAction> {
+    check_action_allowed(action, config)?;
+    check_action_permitted(user, action, config)?;
+    let initiator = user.login.as_str();
+
+    let name = config.name_any();
+    let deploy_action = match action {
+        Action::DeployLatest
+        | Action::DeployBranch { .. }
+        | Action::DeployCommit { .. }
+        | Action::Undeploy => match DeploymentState::from_action(action, config, &pool.get()?)? {
+            DeploymentState::DeployedWithArtifact { artifact, config } => DeployAction::Deploy {
+                name,
+                artifact: Some(artifact),
+                config,
+            },
+            DeploymentState::DeployedOnlyConfig { config } => DeployAction::Deploy {
+                name,
+                artifact: None,
+                config,
+            },
+            DeploymentState::Undeployed => DeployAction::Undeploy { name },
+        },
+        Action::Bounce => DeployAction::Bounce { name },
+        Action::ExecuteJob => DeployAction::ExecuteJob { name },
+        Action::ToggleAutodeploy => DeployAction::ToggleAutodeploy { name },
+        Action::ToggleOrphanKeep => DeployAction::ToggleOrphanKeep { name },
+    };
+
+    let is_deploy = matches!(
+        deploy_action,
+        DeployAction::Deploy { .. } | DeployAction::Undeploy { .. }
+    );
+    if is_deploy {
+        events::publish(Event::DeployStarted {
+            name: deploy_action.config_name().to_string(),
+            action: deploy_action.action_type().to_string(),
+            initiator: initiator.to_string(),
+        });
+    }
+
+    let result = deploy_action
+        .execute(client, octocrabs, config.config_repository())
+        .await;
+    crate::metrics::get().deploy_actions.add(
+        1,
+        &[
+            opentelemetry::KeyValue::new("name", deploy_action.config_name().to_string()),
+            opentelemetry::KeyValue::new("action", deploy_action.action_type()),
+            opentelemetry::KeyValue::new(
+                "result",
+                if result.is_ok() { "success" } else { "error" },
+            ),
+        ],
+    );
+    if let Err(e) = &result {
+        if is_deploy {
+            events::publish(Event::DeployFailed {
+                name: deploy_action.config_name().to_string(),
+                action: deploy_action.action_type().to_string(),
+                initiator: initiator.to_string(),
+                error: e.to_string(),
+            });
+        }
+    }
+    result?;
+
+    // Best-effort: mirror the new state into the GitHub Deployments API.
+    crate::github_deployments::report_deploy_action(octocrabs, config, &deploy_action).await;
+
+    // The action has already been applied, so a failure to record it is only logged.
+    let conn = pool.get()?;
+    match DeployEvent::from_user_deploy_action(&deploy_action, &conn, config, initiator) {
+        Ok(Some(event)) => {
+            if let Err(e) = event.insert(&conn) {
+                log::error!("Failed to insert deploy event: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to create deploy event: {}", e),
+    }
+
+    Ok(deploy_action)
+}
+
 /// Handler for updating a DeployConfig
 #[post("/api/deploy/{namespace}/{name}")]
 pub async fn deploy_config(
@@ -1155,14 +1514,8 @@ pub async fn deploy_config(
     pool: web::Data<Pool<SqliteConnectionManager>>,
     form: web::Form<HashMap<String, String>>,
     octocrabs: web::Data<Octocrabs>,
+    user: CurrentUser,
 ) -> impl Responder {
-    let conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            log::error!("Failed to get database connection: {}", e);
-            return HttpResponse::InternalServerError().body("Failed to connect to database");
-        }
-    };
     let action = Action::from_query(&form);
     let (namespace, name) = path.into_inner();
 
@@ -1190,11 +1543,15 @@ pub async fn deploy_config(
         }
     };
 
-    // Reject non-undeploy actions for orphaned configs
-    if config.is_orphaned() && !matches!(&action, Action::Undeploy) {
+    if let Err(e) = check_action_allowed(&action, &config) {
         return HttpResponse::BadRequest()
             .content_type("text/html; charset=utf-8")
-            .body("Cannot perform this action on an orphaned deploy config. Only undeploy is allowed.");
+            .body(e.to_string());
+    }
+    if let Err(e) = check_action_permitted(&user, &action, &config) {
+        return HttpResponse::Forbidden()
+            .content_type("text/html; charset=utf-8")
+            .body(e.to_string());
     }
 
     let return_url = format!(
@@ -1205,96 +1562,21 @@ pub async fn deploy_config(
         form.get("sha").unwrap_or(&"".to_string())
     );
 
-    let deployment_state = match DeploymentState::from_action(&action, &config, &conn) {
-        Ok(deployment_state) => deployment_state,
-        Err(e) => {
-            log::error!("Failed to get deployment state: {}", e);
-            return HttpResponse::InternalServerError()
-                .content_type("text/html; charset=utf-8")
-                .body("Failed to get deployment state");
-        }
-    };
-
-    let deploy_action = match &action {
-        Action::DeployLatest
-        | Action::DeployBranch { .. }
-        | Action::DeployCommit { .. }
-        | Action::Undeploy => match deployment_state {
-            DeploymentState::DeployedWithArtifact { artifact, config } => DeployAction::Deploy {
-                name: name.to_string(),
-                artifact: Some(artifact),
-                config,
-            },
-            DeploymentState::DeployedOnlyConfig { config } => DeployAction::Deploy {
-                name: name.to_string(),
-                artifact: None,
-                config,
-            },
-            DeploymentState::Undeployed => DeployAction::Undeploy {
-                name: name.to_string(),
-            },
-        },
-        Action::Bounce => DeployAction::Bounce {
-            name: name.to_string(),
-        },
-        Action::ExecuteJob => DeployAction::ExecuteJob {
-            name: name.to_string(),
-        },
-        Action::ToggleAutodeploy => DeployAction::ToggleAutodeploy {
-            name: name.to_string(),
-        },
-    };
-
-    match deploy_action
-        .execute(&client, &octocrabs, config.config_repository())
-        .await
+    if let Err(e) = execute_action(
+        &action,
+        &config,
+        &client,
+        &pool,
+        &octocrabs,
+        &user,
+        AuditSource::Ui,
+    )
+    .await
     {
-        Ok(()) => (),
-        Err(e) => {
-            log::error!("Failed to execute deploy action: {}", e);
-            crate::metrics::get().deploy_actions.add(
-                1,
-                &[
-                    opentelemetry::KeyValue::new("name", deploy_action.config_name().to_string()),
-                    opentelemetry::KeyValue::new("action", deploy_action.action_type()),
-                    opentelemetry::KeyValue::new("result", "error"),
-                ],
-            );
-            return HttpResponse::InternalServerError()
-                .content_type("text/html; charset=utf-8")
-                .body("Failed to execute deploy action");
-        }
-    }
-    crate::metrics::get().deploy_actions.add(
-        1,
-        &[
-            opentelemetry::KeyValue::new("name", deploy_action.config_name().to_string()),
-            opentelemetry::KeyValue::new("action", deploy_action.action_type()),
-            opentelemetry::KeyValue::new("result", "success"),
-        ],
-    );
-
-    // Best-effort: mirror the new state into the GitHub Deployments API.
-    crate::github_deployments::report_deploy_action(&octocrabs, &config, &deploy_action).await;
-
-    let Ok(maybe_deploy_event) =
-        DeployEvent::from_user_deploy_action(&deploy_action, &conn, &config)
-    else {
+        log::error!("Failed to execute deploy action on {}: {}", name, e);
         return HttpResponse::InternalServerError()
             .content_type("text/html; charset=utf-8")
-            .body("Failed to create deploy event");
-    };
-    if let Some(deploy_event) = maybe_deploy_event {
-        match deploy_event.insert(&conn) {
-            Ok(_) => (),
-            Err(e) => {
-                log::error!("Failed to insert deploy event: {}", e);
-
-                return HttpResponse::InternalServerError()
-                    .content_type("text/html; charset=utf-8")
-                    .body("Failed to insert deploy event");
-            }
-        }
+            .body("Failed to execute deploy action");
     }
 
     // Redirect back to the DeployConfig page with the selected config
@@ -20,6 +20,15 @@ pub fn format_relative_time(timestamp: i64) -> String {
     }
 }
 
+/// Format a timestamp as local date and time
+pub fn format_timestamp(timestamp: i64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp)
+        .single()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
 /// Format a git sha as a short version (7 chars)
 pub fn format_short_sha(sha: &str) -> &str {
     if sha.len() > 7 {
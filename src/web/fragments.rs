@@ -2,7 +2,10 @@
 
 use crate::{
     build_status::BuildStatus,
-    db::{git_commit::GitCommit, git_commit_build::GitCommitBuild, git_repo::GitRepo},
+    db::{
+        git_commit::GitCommit, git_commit_build::GitCommitBuild, git_repo::GitRepo,
+        workflow_job::WorkflowJob,
+    },
     kubernetes::{
         api::{get_deploy_config, ListMode},
         list_namespace_objects, DeployConfig,
@@ -320,13 +323,21 @@ pub async fn build_status(
         let now_ms = Utc::now().timestamp_millis() as u64;
         build_start_time.map(|start| {
             let elapsed_ms = now_ms.saturating_sub(start);
-            let avg_duration_ms = GitCommitBuild::avg_build_duration_ms(repo.id, 10, conn)
-                .ok()
-                .flatten();
-            let pct = avg_duration_ms
-                .filter(|&avg| avg > 0)
-                .map(|avg| ((elapsed_ms * 100) / avg).min(100));
-            let remaining_ms = avg_duration_ms.map(|avg| avg.saturating_sub(elapsed_ms));
+            // Prefer each running job's own history over the repo's average.
+            let remaining_ms =
+                WorkflowJob::estimate_remaining_ms(repo.id, &commit.sha, now_ms, conn)
+                    .ok()
+                    .flatten()
+                    .or_else(|| {
+                        GitCommitBuild::avg_build_duration_ms(repo.id, 10, conn)
+                            .ok()
+                            .flatten()
+                            .map(|avg| avg.saturating_sub(elapsed_ms))
+                    });
+            let pct = remaining_ms
+                .map(|remaining| elapsed_ms + remaining)
+                .filter(|&total| total > 0)
+                .map(|total| (elapsed_ms * 100) / total);
             PendingProgress {
                 elapsed_ms,
                 pct,
@@ -364,6 +375,9 @@ pub async fn build_status(
                     a href=(url) { "Build log" }
                     "."
                   }
+                  " "
+                  a href=(format!("/commits/{}/{}/{}", owner, repo.name, commit.sha)) { "Timeline" }
+                  "."
                 }
                 @if let Some(ref p) = pending_progress {
                   div {
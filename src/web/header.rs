@@ -14,6 +14,7 @@ pub fn render(active_page: &str) -> Markup {
                 div class="subheader-nav" {
                     a href="/deploy" class=(if active_page == "deploy" { "subheader-nav-item active" } else { "subheader-nav-item" }) { "Deploy" }
                     a href="/deploy-history" class=(if active_page == "history" { "subheader-nav-item active" } else { "subheader-nav-item" }) { "History" }
+                    a href="/audit" class=(if active_page == "audit" { "subheader-nav-item active" } else { "subheader-nav-item" }) { "Audit" }
                     a href="/watchdog" class=(if active_page == "watchdog" { "subheader-nav-item active" } else { "subheader-nav-item" }) { "Health" }
                     a href="/branches" class=(if active_page == "branches" { "subheader-nav-item active" } else { "subheader-nav-item" }) { "Branch heads" }
                     a href="/all-recent-builds" class=(if active_page == "builds" { "subheader-nav-item active" } else { "subheader-nav-item" }) { "All commits" }
@@ -1,12 +1,15 @@
 use crate::db::functions::get_branches_with_commits;
+use crate::db::git_pull_request::GitPullRequest;
 use crate::prelude::*;
 use crate::web::team_prefs::ReposCookie;
 use crate::web::{build_status_helpers, formatting, header};
+use std::collections::HashMap;
 
 /// Generate the HTML rows for the branch table tbody
 fn render_branch_rows(
     conn: &r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
     branch_data_list: &[crate::db::functions::BranchWithCommits],
+    pull_requests: &HashMap<(u64, String), GitPullRequest>,
 ) -> Markup {
     html! {
         @for data in branch_data_list {
@@ -39,6 +42,10 @@ fn render_branch_rows(
                         } @else {
                             span class="branch-badge" { (data.branch.name) }
                         }
+                        @if let Some(pr) = pull_requests.get(&(data.branch.repo_id, data.branch.name.clone())) {
+                            " "
+                            (render_pull_request_badge(pr))
+                        }
                     }
                     td class="latest-cell" {
                         @let status: crate::build_status::BuildStatus = build_status.clone().into();
@@ -77,6 +84,20 @@ fn render_branch_rows(
                                 i class="fa fa-file-text-o" {}
                             }
                         }
+                        a href=(format!("/commits/{}/{}/{}", data.repo.owner_name, data.repo.name, commit.sha))
+                            class="link-icon"
+                            title="Build timeline" {
+                            i class="fa fa-tasks" {}
+                        }
+                        @if matches!(build_status.clone().into(), crate::build_status::BuildStatus::Failure) {
+                            form class="rerun-inline" action=(format!("/commits/{}/{}/{}/rerun", data.repo.owner_name, data.repo.name, commit.sha)) method="post" {
+                                input type="hidden" name="scope" value="failed";
+                                input type="hidden" name="next" value="/branches";
+                                button type="submit" class="link-icon" title="Re-run failed checks" {
+                                    i class="fa fa-refresh" {}
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -84,6 +105,19 @@ fn render_branch_rows(
     }
 }
 
+/// A link to the pull request, showing its number and state
+pub fn render_pull_request_badge(pr: &GitPullRequest) -> Markup {
+    let state = pr.display_state();
+    html! {
+        a class=(format!("pr-badge {}", state))
+            href=(pr.html_url)
+            target="_blank"
+            title=(format!("{} by {} into {} ({})", pr.title, pr.author, pr.base_branch, state)) {
+            "#" (pr.number)
+        }
+    }
+}
+
 /// Generate the HTML fragment for the branch grid content (full table)
 pub fn render_branch_grid_fragment(
     pool: &Pool<SqliteConnectionManager>,
@@ -104,6 +138,9 @@ pub fn render_branch_grid_fragment(
 
     // Filter branches based on repo visibility cookie
     branch_data_list.retain(|data| repos_cookie.is_visible(&data.repo.owner_name));
+    let pull_requests =
+        GitPullRequest::get_for_branches(branch_data_list.iter().map(|d| &d.branch), &conn)
+            .unwrap_or_default();
 
     // Sort branches by timestamp of most recent commit
     branch_data_list.sort_by(|a, b| {
@@ -136,7 +173,7 @@ pub fn render_branch_grid_fragment(
                        hx-trigger="load, every 5s"
                        hx-swap="morph:innerHTML"
                        hx-ext="morph" {
-                    (render_branch_rows(&conn, &branch_data_list))
+                    (render_branch_rows(&conn, &branch_data_list, &pull_requests))
                 }
             }
         }
@@ -166,6 +203,9 @@ pub async fn branch_grid_fragment(
 
     // Filter branches based on repo visibility cookie
     branch_data_list.retain(|data| repos_cookie.is_visible(&data.repo.owner_name));
+    let pull_requests =
+        GitPullRequest::get_for_branches(branch_data_list.iter().map(|d| &d.branch), &conn)
+            .unwrap_or_default();
 
     // Sort branches by timestamp of most recent commit
     branch_data_list.sort_by(|a, b| {
@@ -174,7 +214,7 @@ pub async fn branch_grid_fragment(
         b_time.cmp(&a_time) // Reverse order for newest first
     });
 
-    let fragment = render_branch_rows(&conn, &branch_data_list);
+    let fragment = render_branch_rows(&conn, &branch_data_list, &pull_requests);
 
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
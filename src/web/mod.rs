@@ -1,6 +1,8 @@
 mod all_recent_builds;
+mod audit;
 mod bootstrap;
 mod build_status_helpers;
+mod commit;
 mod deploy_configs;
 mod deploy_history;
 mod formatting;
@@ -12,9 +14,12 @@ mod resource_status;
 mod settings;
 pub mod team_prefs;
 mod watchdog;
+mod webhook_events;
 
 pub use all_recent_builds::*;
+pub use audit::*;
 pub use bootstrap::*;
+pub use commit::*;
 pub use deploy_configs::*;
 pub use deploy_history::*;
 pub use fragments::*;
@@ -23,3 +28,4 @@ pub use resource_logs::*;
 pub use resource_status::*;
 pub use settings::*;
 pub use watchdog::*;
+pub use webhook_events::*;
@@ -222,6 +222,81 @@ async fn get_resource_logs(
     }
 }
 
+/// Get logs for the children of a deploy config that have logs (Deployments,
+/// Jobs, CronJobs, Pods), optionally only the child named `resource`.
+pub async fn get_deploy_config_logs(
+    client: &Client,
+    config: &crate::kubernetes::DeployConfig,
+    resource: Option<&str>,
+    tail_lines: Option<u64>,
+) -> AppResult<String> {
+    const LOG_KINDS: [&str; 5] = ["Pod", "Job", "CronJob", "Deployment", "ReplicaSet"];
+
+    let namespace = config.namespace().unwrap_or_else(|| "default".to_string());
+    let namespaced_objs =
+        list_namespace_objects(client, &namespace, crate::kubernetes::api::ListMode::All).await?;
+
+    let children: Vec<&DynamicObject> = config
+        .resource_specs()
+        .iter()
+        .filter_map(|spec| {
+            let kind = spec.get("kind")?.as_str()?;
+            let name = spec.get("metadata")?.get("name")?.as_str()?;
+            if !LOG_KINDS.contains(&kind) || resource.is_some_and(|r| r != name) {
+                return None;
+            }
+            namespaced_objs.iter().find(|o| {
+                o.types.as_ref().map(|t| t.kind.as_str()) == Some(kind) && o.name_any() == name
+            })
+        })
+        .collect();
+
+    if children.is_empty() {
+        return Err(AppError::NotFound(match resource {
+            Some(resource) => format!(
+                "No deployed resource named '{}' with logs in {}",
+                resource,
+                config.name_any()
+            ),
+            None => format!("No deployed resources with logs in {}", config.name_any()),
+        }));
+    }
+
+    let mut all_logs = Vec::new();
+    for child in &children {
+        let logs = get_resource_logs(client, child, &namespaced_objs, tail_lines).await?;
+        if children.len() == 1 {
+            return Ok(logs);
+        }
+        let (name, _, kind) = get_resource_info(child);
+        all_logs.push(format!("##### {} {} #####\n{}", kind, name, logs));
+    }
+    Ok(all_logs.join("\n\n"))
+}
+
+/// Get logs for the resource of `kind` named `name` in `namespace`, whether or
+/// not a deploy config owns it.
+pub async fn get_named_resource_logs(
+    client: &Client,
+    namespace: &str,
+    kind: &str,
+    name: &str,
+    tail_lines: Option<u64>,
+) -> AppResult<String> {
+    let namespaced_objs =
+        list_namespace_objects(client, namespace, crate::kubernetes::api::ListMode::All).await?;
+    let obj = namespaced_objs
+        .iter()
+        .find(|o| {
+            o.types
+                .as_ref()
+                .is_some_and(|t| t.kind.eq_ignore_ascii_case(kind))
+                && o.name_any() == name
+        })
+        .ok_or_else(|| AppError::NotFound(format!("{} {} in {}", kind, name, namespace)))?;
+    get_resource_logs(client, obj, &namespaced_objs, tail_lines).await
+}
+
 /// Get resource info (name, namespace, kind) for display
 fn get_resource_info(obj: &DynamicObject) -> (String, String, String) {
     let name = obj.name_any();
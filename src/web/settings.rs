@@ -5,8 +5,18 @@ use actix_web::web;
 use kube::Client;
 use maud::{html, Markup, DOCTYPE};
 
+use crate::audit::{AuditRecord, AuditSource};
+use crate::auth::authz::{Role, TokenScope, ALL_TEAMS, BINDING_KINDS, ROLES, TOKEN_SCOPES};
+use crate::auth::{generate_api_token, hash_token, CurrentUser};
+use crate::db::api_token::{ApiToken, ApiTokenEgg};
+use crate::db::role_binding::{RoleBinding, RoleBindingEgg};
+use crate::db::webhook_delivery::WebhookDelivery;
+use crate::db::webhook_subscription::{WebhookSubscription, WebhookSubscriptionEgg};
 use crate::kubernetes::api::get_all_deploy_configs;
+use crate::notifications::EVENT_KINDS;
+use crate::outgoing_webhooks::{WebhookNotifier, FORMATS};
 use crate::prelude::*;
+use crate::web::formatting::format_relative_time;
 use crate::web::header;
 use crate::web::team_prefs::{ReposCookie, TeamsCookie, REPOS_COOKIE, TEAMS_COOKIE};
 
@@ -60,7 +70,8 @@ fn render_repo_row(org: &str, is_visible: bool) -> Markup {
     }
 }
 
-fn render_sidebar(section: &str) -> Markup {
+/// Bootstrap and webhooks are only listed for admins.
+fn render_sidebar(section: &str, is_admin: bool) -> Markup {
     html! {
         nav class="settings-sidebar" {
             h2 class="settings-sidebar-title" { "Settings" }
@@ -97,23 +108,61 @@ fn render_sidebar(section: &str) -> Markup {
             {
                 "Repo visibility"
             }
+            @if is_admin {
+                a
+                    class=(if section == "bootstrap" { "settings-nav-link active" } else { "settings-nav-link" })
+                    href="/settings?section=bootstrap"
+                    hx-get="/settings-fragment?section=bootstrap"
+                    hx-target="#settings-content"
+                    hx-swap="morph:innerHTML"
+                    hx-push-url="/settings?section=bootstrap"
+                    onclick="document.querySelectorAll('.settings-nav-link').forEach(l => l.classList.remove('active')); this.classList.add('active');"
+                {
+                    "Bootstrap"
+                }
+                a
+                    class=(if section == "webhooks" { "settings-nav-link active" } else { "settings-nav-link" })
+                    href="/settings?section=webhooks"
+                    hx-get="/settings-fragment?section=webhooks"
+                    hx-target="#settings-content"
+                    hx-swap="morph:innerHTML"
+                    hx-push-url="/settings?section=webhooks"
+                    onclick="document.querySelectorAll('.settings-nav-link').forEach(l => l.classList.remove('active')); this.classList.add('active');"
+                {
+                    "Webhooks"
+                }
+                a class="settings-nav-link" href="/webhook-events" {
+                    "Webhook events"
+                }
+            }
+            a
+                class=(if section == "access" { "settings-nav-link active" } else { "settings-nav-link" })
+                href="/settings?section=access"
+                hx-get="/settings-fragment?section=access"
+                hx-target="#settings-content"
+                hx-swap="morph:innerHTML"
+                hx-push-url="/settings?section=access"
+                onclick="document.querySelectorAll('.settings-nav-link').forEach(l => l.classList.remove('active')); this.classList.add('active');"
+            {
+                "Access"
+            }
             a
-                class=(if section == "bootstrap" { "settings-nav-link active" } else { "settings-nav-link" })
-                href="/settings?section=bootstrap"
-                hx-get="/settings-fragment?section=bootstrap"
+                class=(if section == "api-tokens" { "settings-nav-link active" } else { "settings-nav-link" })
+                href="/settings?section=api-tokens"
+                hx-get="/settings-fragment?section=api-tokens"
                 hx-target="#settings-content"
                 hx-swap="morph:innerHTML"
-                hx-push-url="/settings?section=bootstrap"
+                hx-push-url="/settings?section=api-tokens"
                 onclick="document.querySelectorAll('.settings-nav-link').forEach(l => l.classList.remove('active')); this.classList.add('active');"
             {
-                "Bootstrap"
+                "API tokens"
             }
         }
     }
 }
 
 #[get("/settings")]
-pub async fn settings_index(req: actix_web::HttpRequest) -> impl Responder {
+pub async fn settings_index(req: actix_web::HttpRequest, user: CurrentUser) -> impl Responder {
     let query: web::Query<HashMap<String, String>> =
         web::Query::from_query(req.query_string()).unwrap_or_else(|_| web::Query(HashMap::new()));
     let section = query
@@ -134,7 +183,7 @@ pub async fn settings_index(req: actix_web::HttpRequest) -> impl Responder {
             body.settings-page hx-ext="morph" {
                 (header::render("settings"))
                 div class="settings-container" {
-                    (render_sidebar(section))
+                    (render_sidebar(section, user.is_admin()))
                     div class="settings-content-wrapper" {
                         div id="settings-content" {
                             div
@@ -158,6 +207,7 @@ pub async fn settings_index(req: actix_web::HttpRequest) -> impl Responder {
 pub async fn settings_fragment(
     req: actix_web::HttpRequest,
     query: web::Query<HashMap<String, String>>,
+    user: CurrentUser,
 ) -> impl Responder {
     let section = query
         .get("section")
@@ -173,11 +223,20 @@ pub async fn settings_fragment(
         }
     };
 
+    if matches!(section, "bootstrap" | "webhooks") {
+        if let Err(e) = user.authorize_admin("Managing settings") {
+            return forbidden(&e);
+        }
+    }
+
     match section {
         "team-visibility" => team_visibility_fragment(req).await,
         "repo-visibility" => repo_visibility_fragment(req, pool).await,
         "rate-limits" => rate_limits_fragment().await,
         "bootstrap" => bootstrap_fragment().await,
+        "webhooks" => webhooks_fragment(&pool, None),
+        "access" => access_fragment(&pool, &user, None),
+        "api-tokens" => api_tokens_fragment(&pool, &user, None, None),
         _ => team_visibility_fragment(req).await,
     }
 }
@@ -377,6 +436,719 @@ async fn bootstrap_fragment() -> HttpResponse {
         .body(markup.into_string())
 }
 
+/// How many deliveries the webhook settings show.
+const DELIVERY_LOG_LIMIT: u32 = 25;
+
+fn render_filters(subscription: &WebhookSubscription) -> String {
+    let filters: Vec<String> = [
+        ("team", &subscription.team),
+        ("repo", &subscription.repo),
+        ("config", &subscription.config),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| value.as_ref().map(|v| format!("{}: {}", label, v)))
+    .collect();
+    if filters.is_empty() {
+        "everything".to_string()
+    } else {
+        filters.join(", ")
+    }
+}
+
+fn render_delivery_result(delivery: &WebhookDelivery) -> Markup {
+    html! {
+        @if delivery.success {
+            span class="webhook-ok" {
+                "Delivered (" (delivery.status_code.unwrap_or_default()) ")"
+            }
+        } @else {
+            span class="webhook-failed" {
+                "Failed after " (delivery.attempts) " attempts: "
+                (delivery.error.as_deref().unwrap_or_default())
+            }
+        }
+    }
+}
+
+fn webhooks_fragment(pool: &Pool<SqliteConnectionManager>, error: Option<&str>) -> HttpResponse {
+    let loaded = pool.get().map_err(AppError::from).and_then(|conn| {
+        Ok((
+            WebhookSubscription::get_all(&conn)?,
+            WebhookDelivery::get_recent(DELIVERY_LOG_LIMIT, &conn)?,
+        ))
+    });
+    let (subscriptions, deliveries) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log::error!("Failed to load webhook subscriptions: {}", e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/html; charset=utf-8")
+                .body("Failed to load webhook subscriptions".to_string());
+        }
+    };
+    let url_of = |id: i64| {
+        subscriptions
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.url.as_str())
+            .unwrap_or("deleted")
+    };
+
+    let markup = html! {
+        header {
+            h1 { "Webhooks" }
+            div class="subtitle" { "Send notifications to HTTP endpoints, such as Slack incoming webhooks." }
+        }
+
+        @if let Some(error) = error {
+            div class="webhook-failed" { (error) }
+        }
+
+        @if subscriptions.is_empty() {
+            div class="empty-state" {
+                h2 { "No webhooks" }
+                p { "Add a webhook below to start receiving notifications." }
+            }
+        } @else {
+            table class="history-table" {
+                thead {
+                    tr {
+                        th { "URL" }
+                        th { "Format" }
+                        th { "Events" }
+                        th { "Filters" }
+                        th { "" }
+                    }
+                }
+                tbody {
+                    @for subscription in &subscriptions {
+                        tr {
+                            td class="config-name" { (subscription.url) }
+                            td {
+                                (subscription.format)
+                                @if subscription.secret.is_some() { " (signed)" }
+                            }
+                            td {
+                                @if subscription.events.is_empty() { "all" } @else { (subscription.events.join(", ")) }
+                            }
+                            td { (render_filters(subscription)) }
+                            td class="actions-cell" {
+                                button
+                                    class="link-button"
+                                    hx-post=(format!("/settings/webhooks/{}/test", subscription.id))
+                                    hx-target=(format!("#webhook-test-{}", subscription.id))
+                                { "Send test" }
+                                " "
+                                button
+                                    class="link-button"
+                                    hx-post=(format!("/settings/webhooks/{}/delete", subscription.id))
+                                    hx-target="#settings-content"
+                                    hx-confirm="Delete this webhook?"
+                                { "Delete" }
+                                div id=(format!("webhook-test-{}", subscription.id)) {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        div class="bootstrap-mode" {
+            h4 { "Add webhook" }
+            form class="webhook-form" hx-post="/settings/webhooks" hx-target="#settings-content" {
+                input type="url" name="url" placeholder="https://hooks.slack.com/services/..." class="repo-input" required;
+                select name="format" class="repo-input" {
+                    @for format in FORMATS {
+                        option value=(format) { (format) }
+                    }
+                }
+                input type="text" name="secret" placeholder="Signing secret (json only, optional)" class="repo-input";
+                input type="text" name="team" placeholder="Team (optional)" class="repo-input";
+                input type="text" name="repo" placeholder="owner/repo (optional)" class="repo-input";
+                input type="text" name="config" placeholder="Deploy config (optional)" class="repo-input";
+                div class="bootstrap-description" { "Events (none selected means all):" }
+                div class="webhook-events" {
+                    @for kind in EVENT_KINDS {
+                        label {
+                            input type="checkbox" name=(format!("event_{}", kind)) value="on";
+                            " " (kind)
+                        }
+                    }
+                }
+                button type="submit" class="bootstrap-button" { "Add webhook" }
+            }
+        }
+
+        h4 { "Recent deliveries" }
+        @if deliveries.is_empty() {
+            div class="bootstrap-description" { "No deliveries yet." }
+        } @else {
+            table class="history-table" {
+                thead {
+                    tr {
+                        th { "When" }
+                        th { "Webhook" }
+                        th { "Event" }
+                        th { "Result" }
+                    }
+                }
+                tbody {
+                    @for delivery in &deliveries {
+                        tr {
+                            td class="time-cell" { (format_relative_time(delivery.timestamp)) }
+                            td { (url_of(delivery.subscription_id)) }
+                            td { (delivery.event) }
+                            td { (render_delivery_result(delivery)) }
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string())
+}
+
+/// Parse the "Add webhook" form.
+fn parse_webhook_form(form: &HashMap<String, String>) -> Result<WebhookSubscriptionEgg, String> {
+    let field = |name: &str| {
+        form.get(name)
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let url = field("url").ok_or("A URL is required")?;
+    match url::Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+        _ => return Err(format!("{} is not an http(s) URL", url)),
+    }
+    let format = field("format").unwrap_or_else(|| "json".to_string());
+    if !FORMATS.contains(&format.as_str()) {
+        return Err(format!("Unknown format {}", format));
+    }
+
+    Ok(WebhookSubscriptionEgg {
+        url,
+        format,
+        secret: field("secret"),
+        events: EVENT_KINDS
+            .iter()
+            .filter(|kind| form.contains_key(&format!("event_{}", kind)))
+            .map(|kind| kind.to_string())
+            .collect(),
+        team: field("team"),
+        repo: field("repo"),
+        config: field("config"),
+    })
+}
+
+#[post("/settings/webhooks")]
+pub async fn create_webhook(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    form: web::Form<HashMap<String, String>>,
+    user: CurrentUser,
+) -> impl Responder {
+    if let Err(e) = user.authorize_admin("Managing webhooks") {
+        return forbidden(&e);
+    }
+    let egg = match parse_webhook_form(&form) {
+        Ok(egg) => egg,
+        Err(e) => return webhooks_fragment(&pool, Some(&e)),
+    };
+    let inserted = pool
+        .get()
+        .map_err(AppError::from)
+        .and_then(|conn| WebhookSubscription::insert(&egg, &conn));
+    AuditRecord::new(&user.login, AuditSource::Ui, "create-webhook", &egg.url)
+        .params(serde_json::json!({
+            "format": egg.format,
+            "events": egg.events,
+            "team": egg.team,
+            "repo": egg.repo,
+            "config": egg.config,
+        }))
+        .record(&pool, &inserted);
+    if let Err(e) = inserted {
+        log::error!("Failed to save webhook: {}", e);
+        return webhooks_fragment(&pool, Some("Failed to save webhook"));
+    }
+    webhooks_fragment(&pool, None)
+}
+
+#[post("/settings/webhooks/{id}/delete")]
+pub async fn delete_webhook(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    path: web::Path<i64>,
+    user: CurrentUser,
+) -> impl Responder {
+    if let Err(e) = user.authorize_admin("Managing webhooks") {
+        return forbidden(&e);
+    }
+    let id = path.into_inner();
+    let deleted = pool.get().map_err(AppError::from).and_then(|conn| {
+        let url = WebhookSubscription::get_by_id(id, &conn)?
+            .map(|s| s.url)
+            .unwrap_or_else(|| id.to_string());
+        WebhookSubscription::delete(id, &conn)?;
+        Ok(url)
+    });
+    AuditRecord::new(
+        &user.login,
+        AuditSource::Ui,
+        "delete-webhook",
+        deleted.as_deref().unwrap_or(&id.to_string()),
+    )
+    .record(&pool, &deleted);
+    if let Err(e) = deleted {
+        log::error!("Failed to delete webhook: {}", e);
+        return webhooks_fragment(&pool, Some("Failed to delete webhook"));
+    }
+    webhooks_fragment(&pool, None)
+}
+
+#[post("/settings/webhooks/{id}/test")]
+pub async fn test_webhook(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    path: web::Path<i64>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    user.authorize_admin("Managing webhooks")?;
+    let subscription = WebhookSubscription::get_by_id(path.into_inner(), &pool.get()?)?
+        .ok_or_else(|| AppError::NotFound("Webhook".to_string()))?;
+    let delivery = WebhookNotifier::new(pool.get_ref().clone())
+        .send_test(&subscription)
+        .await;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(render_delivery_result(&delivery).into_string()))
+}
+
+fn forbidden(error: &AppError) -> HttpResponse {
+    HttpResponse::Forbidden()
+        .content_type("text/html; charset=utf-8")
+        .body(error.to_string())
+}
+
+fn render_role(role: Option<Role>) -> &'static str {
+    role.map(|r| r.as_str()).unwrap_or("none")
+}
+
+/// The current user's roles, and for admins the role bindings.
+fn access_fragment(
+    pool: &Pool<SqliteConnectionManager>,
+    user: &CurrentUser,
+    error: Option<&str>,
+) -> HttpResponse {
+    let bindings = if user.is_admin() {
+        match pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|conn| RoleBinding::get_all(&conn))
+        {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                log::error!("Failed to load role bindings: {}", e);
+                return HttpResponse::InternalServerError()
+                    .content_type("text/html; charset=utf-8")
+                    .body("Failed to load role bindings".to_string());
+            }
+        }
+    } else {
+        vec![]
+    };
+
+    let markup = html! {
+        header {
+            h1 { "Access" }
+            div class="subtitle" { "Roles granted to users and groups on each team." }
+        }
+
+        @if let Some(error) = error {
+            div class="webhook-failed" { (error) }
+        }
+
+        h4 { "Signed in as " (user.login) }
+        div class="bootstrap-description" {
+            "All teams: " (render_role(user.roles.global_role()))
+            @for (team, role) in user.roles.team_roles() {
+                ", " (team) ": " (role)
+            }
+        }
+
+        @if user.is_admin() {
+            @if bindings.is_empty() {
+                div class="empty-state" {
+                    h2 { "No role bindings" }
+                    p { "Everyone has the default role on every team." }
+                }
+            } @else {
+                table class="history-table" {
+                    thead {
+                        tr {
+                            th { "Team" }
+                            th { "Kind" }
+                            th { "User or group" }
+                            th { "Role" }
+                            th { "" }
+                        }
+                    }
+                    tbody {
+                        @for binding in &bindings {
+                            tr {
+                                td class="config-name" {
+                                    @if binding.team == ALL_TEAMS { "all teams" } @else { (binding.team) }
+                                }
+                                td { (binding.kind) }
+                                td { (binding.subject) }
+                                td { (binding.role) }
+                                td class="actions-cell" {
+                                    button
+                                        class="link-button"
+                                        hx-post=(format!("/settings/access/{}/delete", binding.id))
+                                        hx-target="#settings-content"
+                                        hx-confirm="Remove this role binding?"
+                                    { "Remove" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            div class="bootstrap-mode" {
+                h4 { "Grant a role" }
+                div class="bootstrap-description" {
+                    "Groups are GitHub teams as org/team-slug, or the groups claim of the OIDC provider. Use * as the team to grant the role on every team."
+                }
+                form class="webhook-form" hx-post="/settings/access" hx-target="#settings-content" {
+                    select name="kind" class="repo-input" {
+                        @for kind in BINDING_KINDS {
+                            option value=(kind) { (kind) }
+                        }
+                    }
+                    input type="text" name="subject" placeholder="Login or group" class="repo-input" required;
+                    input type="text" name="team" placeholder="Team, or * for all" class="repo-input" required;
+                    select name="role" class="repo-input" {
+                        @for role in ROLES {
+                            option value=(role) { (role) }
+                        }
+                    }
+                    button type="submit" class="bootstrap-button" { "Grant" }
+                }
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string())
+}
+
+/// Parse the "Grant a role" form.
+fn parse_role_binding_form(form: &HashMap<String, String>) -> Result<RoleBindingEgg, String> {
+    let field = |name: &str| {
+        form.get(name)
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let kind = field("kind").unwrap_or_else(|| "user".to_string());
+    if !BINDING_KINDS.contains(&kind.as_str()) {
+        return Err(format!("Unknown kind {}", kind));
+    }
+    let role = field("role").ok_or("A role is required")?;
+    if Role::parse(&role).is_none() {
+        return Err(format!("Unknown role {}", role));
+    }
+
+    Ok(RoleBindingEgg {
+        kind,
+        subject: field("subject").ok_or("A login or group is required")?,
+        team: field("team").ok_or("A team is required")?,
+        role,
+    })
+}
+
+#[post("/settings/access")]
+pub async fn create_role_binding(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    form: web::Form<HashMap<String, String>>,
+    user: CurrentUser,
+) -> impl Responder {
+    if let Err(e) = user.authorize_admin("Managing roles") {
+        return forbidden(&e);
+    }
+    let egg = match parse_role_binding_form(&form) {
+        Ok(egg) => egg,
+        Err(e) => return access_fragment(&pool, &user, Some(&e)),
+    };
+    let saved = pool
+        .get()
+        .map_err(AppError::from)
+        .and_then(|conn| RoleBinding::upsert(&egg, &conn));
+    AuditRecord::new(&user.login, AuditSource::Ui, "grant-role", &egg.team)
+        .params(serde_json::json!({
+            "kind": egg.kind,
+            "subject": egg.subject,
+            "role": egg.role,
+        }))
+        .record(&pool, &saved);
+    if let Err(e) = saved {
+        log::error!("Failed to save role binding: {}", e);
+        return access_fragment(&pool, &user, Some("Failed to save role binding"));
+    }
+    access_fragment(&pool, &user, None)
+}
+
+#[post("/settings/access/{id}/delete")]
+pub async fn delete_role_binding(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    path: web::Path<i64>,
+    user: CurrentUser,
+) -> impl Responder {
+    if let Err(e) = user.authorize_admin("Managing roles") {
+        return forbidden(&e);
+    }
+    let id = path.into_inner();
+    let deleted = pool.get().map_err(AppError::from).and_then(|conn| {
+        let binding = RoleBinding::get_by_id(id, &conn)?;
+        RoleBinding::delete(id, &conn)?;
+        Ok(binding)
+    });
+    let binding = deleted.as_ref().ok().and_then(Option::as_ref);
+    AuditRecord::new(
+        &user.login,
+        AuditSource::Ui,
+        "revoke-role",
+        binding.map(|b| b.team.as_str()).unwrap_or_default(),
+    )
+    .params(match binding {
+        Some(b) => serde_json::json!({ "kind": b.kind, "subject": b.subject, "role": b.role }),
+        None => serde_json::json!({ "id": id }),
+    })
+    .record(&pool, &deleted);
+    if let Err(e) = deleted {
+        log::error!("Failed to delete role binding: {}", e);
+        return access_fragment(&pool, &user, Some("Failed to delete role binding"));
+    }
+    access_fragment(&pool, &user, None)
+}
+
+/// The current user's API tokens. `created` is a token just issued, shown
+/// this once.
+fn api_tokens_fragment(
+    pool: &Pool<SqliteConnectionManager>,
+    user: &CurrentUser,
+    created: Option<&str>,
+    error: Option<&str>,
+) -> HttpResponse {
+    let tokens = match user.id {
+        Some(id) => match pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|conn| ApiToken::get_by_user(id, &conn))
+        {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                log::error!("Failed to load API tokens: {}", e);
+                return HttpResponse::InternalServerError()
+                    .content_type("text/html; charset=utf-8")
+                    .body("Failed to load API tokens".to_string());
+            }
+        },
+        None => vec![],
+    };
+
+    let markup = html! {
+        header {
+            h1 { "API tokens" }
+            div class="subtitle" { "Bearer tokens for /mcp and /api/v1 that act as you, limited to their scope." }
+        }
+
+        @if user.id.is_none() {
+            div class="empty-state" {
+                h2 { "Authentication is disabled" }
+                p { "API tokens belong to a logged-in user; without authentication every request is already allowed." }
+            }
+        } @else {
+            @if let Some(error) = error {
+                div class="webhook-failed" { (error) }
+            }
+            @if let Some(token) = created {
+                div class="bootstrap-mode" {
+                    h4 { "New token" }
+                    div class="bootstrap-description" { "Copy it now, it won't be shown again." }
+                    code class="api-token" { (token) }
+                }
+            }
+
+            @if tokens.is_empty() {
+                div class="empty-state" {
+                    h2 { "No API tokens" }
+                    p { "Create one below to give a script or an agent access." }
+                }
+            } @else {
+                table class="history-table" {
+                    thead {
+                        tr {
+                            th { "Name" }
+                            th { "Scope" }
+                            th { "Created" }
+                            th { "Last used" }
+                            th { "" }
+                        }
+                    }
+                    tbody {
+                        @for token in &tokens {
+                            tr {
+                                td class="config-name" { (token.name) }
+                                td {
+                                    (token.scope)
+                                    @if !token.teams.is_empty() {
+                                        " (" (token.teams.join(", ")) ")"
+                                    }
+                                }
+                                td class="time-cell" { (format_relative_time(token.created_at)) }
+                                td class="time-cell" {
+                                    @match token.last_used_at {
+                                        Some(at) => (format_relative_time(at)),
+                                        None => "never",
+                                    }
+                                }
+                                td class="actions-cell" {
+                                    button
+                                        class="link-button"
+                                        hx-post=(format!("/settings/tokens/{}/delete", token.id))
+                                        hx-target="#settings-content"
+                                        hx-confirm="Revoke this token? Clients using it will stop working."
+                                    { "Revoke" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            div class="bootstrap-mode" {
+                h4 { "Create a token" }
+                div class="bootstrap-description" {
+                    "read: view only. deploy: deploy, undeploy, bounce and run jobs on the listed teams. admin: everything you can do. A token never exceeds your own roles."
+                }
+                form class="webhook-form" hx-post="/settings/tokens" hx-target="#settings-content" {
+                    input type="text" name="name" placeholder="Name, e.g. release agent" class="repo-input" required;
+                    select name="scope" class="repo-input" {
+                        @for scope in TOKEN_SCOPES {
+                            option value=(scope) { (scope) }
+                        }
+                    }
+                    input type="text" name="teams" placeholder="Teams for deploy, comma-separated" class="repo-input";
+                    button type="submit" class="bootstrap-button" { "Create" }
+                }
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string())
+}
+
+/// Parse the "Create a token" form into a name and scope.
+fn parse_api_token_form(form: &HashMap<String, String>) -> Result<(String, TokenScope), String> {
+    let name = form
+        .get("name")
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .ok_or("A name is required")?;
+    let teams: Vec<String> = form
+        .get("teams")
+        .map(|v| v.as_str())
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect();
+    let scope = form.get("scope").map(|v| v.trim()).unwrap_or("read");
+    let scope = TokenScope::parse(scope, &teams).ok_or(format!("Unknown scope {}", scope))?;
+    if let TokenScope::Deploy(teams) = &scope {
+        if teams.is_empty() {
+            return Err("A deploy token needs at least one team".to_string());
+        }
+    }
+    Ok((name, scope))
+}
+
+#[post("/settings/tokens")]
+pub async fn create_api_token(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    form: web::Form<HashMap<String, String>>,
+    user: CurrentUser,
+) -> impl Responder {
+    let Some(user_id) = user.id else {
+        return api_tokens_fragment(&pool, &user, None, None);
+    };
+    let (name, scope) = match parse_api_token_form(&form) {
+        Ok(parsed) => parsed,
+        Err(e) => return api_tokens_fragment(&pool, &user, None, Some(&e)),
+    };
+    let token = generate_api_token();
+    let egg = ApiTokenEgg {
+        user_id,
+        name,
+        token_hash: hash_token(&token),
+        scope: scope.as_str().to_string(),
+        teams: match &scope {
+            TokenScope::Deploy(teams) => teams.clone(),
+            _ => vec![],
+        },
+    };
+    let saved = pool
+        .get()
+        .map_err(AppError::from)
+        .and_then(|conn| ApiToken::insert(&egg, &conn));
+    AuditRecord::new(&user.login, AuditSource::Ui, "create-api-token", &egg.name)
+        .params(serde_json::json!({ "scope": egg.scope, "teams": egg.teams }))
+        .record(&pool, &saved);
+    if let Err(e) = saved {
+        log::error!("Failed to save API token: {}", e);
+        return api_tokens_fragment(&pool, &user, None, Some("Failed to save API token"));
+    }
+    api_tokens_fragment(&pool, &user, Some(&token), None)
+}
+
+#[post("/settings/tokens/{id}/delete")]
+pub async fn delete_api_token(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    path: web::Path<i64>,
+    user: CurrentUser,
+) -> impl Responder {
+    let Some(user_id) = user.id else {
+        return api_tokens_fragment(&pool, &user, None, None);
+    };
+    let id = path.into_inner();
+    let deleted = pool
+        .get()
+        .map_err(AppError::from)
+        .and_then(|conn| ApiToken::delete(id, user_id, &conn));
+    let name = deleted
+        .as_ref()
+        .ok()
+        .and_then(Option::as_ref)
+        .map(|t| t.name.clone())
+        .unwrap_or_else(|| id.to_string());
+    AuditRecord::new(&user.login, AuditSource::Ui, "revoke-api-token", &name)
+        .record(&pool, &deleted);
+    if let Err(e) = deleted {
+        log::error!("Failed to revoke API token: {}", e);
+        return api_tokens_fragment(&pool, &user, None, Some("Failed to revoke API token"));
+    }
+    api_tokens_fragment(&pool, &user, None, None)
+}
+
 #[post("/teams/toggle")]
 pub async fn toggle_team(
     req: actix_web::HttpRequest,
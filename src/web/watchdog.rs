@@ -4,6 +4,7 @@ use crate::{
     error::AppResult,
     kubernetes::{
         api::{get_all_deploy_configs, list_namespace_objects, ListMode},
+        orphan_lifecycle::OrphanPolicy,
         DeployConfig,
     },
     prelude::*,
@@ -25,8 +26,9 @@ use maud::{html, DOCTYPE};
 use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 
-#[derive(Clone, Debug, PartialEq)]
-enum HealthStatus {
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HealthStatus {
     Healthy,
     Warning,
     Error,
@@ -48,7 +50,7 @@ struct DeployConfigHealth {
 }
 
 /// Check if a repo's latest successful master build passed
-fn check_repo_health(
+pub(crate) fn check_repo_health(
     repo: &GitRepo,
     conn: &PooledConnection<SqliteConnectionManager>,
 ) -> AppResult<(HealthStatus, Option<String>, Option<String>)> {
@@ -109,15 +111,26 @@ fn check_repo_health(
 }
 
 /// Check if a deploy config's resources are healthy
-async fn check_deploy_config_health(
+pub(crate) async fn check_deploy_config_health(
     config: &DeployConfig,
     client: &Client,
 ) -> AppResult<(HealthStatus, Option<String>)> {
     // Check if config is orphaned first
     if config.is_orphaned() {
+        let cleanup = if config.is_orphan_kept() {
+            "Cleanup is paused.".to_string()
+        } else {
+            match config.orphan_deadline(OrphanPolicy::from_env().grace_period) {
+                Some(deadline) => format!("It will be cleaned up at {}.", deadline.to_rfc3339()),
+                None => "It will not be cleaned up automatically.".to_string(),
+            }
+        };
         return Ok((
             HealthStatus::Warning,
-            Some("This deploy config is orphaned. Restore the config or undeploy it.".to_string()),
+            Some(format!(
+                "This deploy config is orphaned. Restore the config or undeploy it. {}",
+                cleanup
+            )),
         ));
     }
 
@@ -176,6 +189,11 @@ async fn check_deploy_config_health(
     let mut warnings: Vec<String> = Vec::new();
     let mut info_messages: Vec<String> = Vec::new();
 
+    // Drift is reported by the controller on the config status
+    for resource in config.drifted_resources() {
+        warnings.push(format!("Drifted from desired spec: {}", resource.summary()));
+    }
+
     // First, check pods owned by the config (these have the most detailed error info)
     for obj in namespaced_objs.iter().filter(|o| {
         o.types
@@ -0,0 +1,376 @@
+use std::sync::Arc;
+
+use crate::audit::{AuditRecord, AuditSource};
+use crate::auth::CurrentUser;
+use crate::db::incoming_event::{HandlerOutcome, IncomingEvent, IncomingEventFilter};
+use crate::prelude::*;
+use crate::web::formatting::{format_relative_time, format_timestamp};
+use crate::web::header;
+use crate::webhooks::manager::WebhookManager;
+
+const DEFAULT_LIMIT: u32 = 200;
+const MAX_LIMIT: u32 = 2000;
+
+/// "Replay failed" replays at most this many events per click.
+const MAX_REPLAY: u32 = 100;
+
+const STATUSES: [&str; 3] = ["pending", "success", "failed"];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WebhookEventQuery {
+    event_type: Option<String>,
+    status: Option<String>,
+    limit: Option<u32>,
+}
+
+impl WebhookEventQuery {
+    fn filter(&self) -> IncomingEventFilter {
+        let value = |v: &Option<String>| v.as_deref().filter(|v| !v.is_empty()).map(str::to_string);
+        IncomingEventFilter {
+            event_type: value(&self.event_type),
+            status: value(&self.status),
+        }
+    }
+}
+
+/// The replay form: either the checked `id`s, or with `failed` every failed
+/// event of `event_type`. Repeated fields rule out a plain struct.
+#[derive(Debug, Default, PartialEq)]
+struct ReplayForm {
+    ids: Vec<i64>,
+    failed: bool,
+    event_type: Option<String>,
+    /// Where to go back to; only pages under `/webhook-events`.
+    next: Option<String>,
+}
+
+impl ReplayForm {
+    fn from_pairs(pairs: &[(String, String)]) -> AppResult<Self> {
+        let mut form = ReplayForm::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "id" => {
+                    form.ids.push(value.parse().map_err(|_| {
+                        AppError::InvalidInput(format!("Invalid event id: {}", value))
+                    })?)
+                }
+                "failed" => form.failed = true,
+                "event_type" if !value.is_empty() => form.event_type = Some(value.clone()),
+                "next" if value.starts_with("/webhook-events") && !value.starts_with("//") => {
+                    form.next = Some(value.clone())
+                }
+                _ => {}
+            }
+        }
+        Ok(form)
+    }
+}
+
+fn render_status(status: &str) -> Markup {
+    html! {
+        @match status {
+            "success" => span class="webhook-ok" { "success" },
+            "failed" => span class="webhook-failed" { "failed" },
+            other => span { (other) },
+        }
+    }
+}
+
+/// "3 ok", or the handlers that failed.
+fn summarize_outcomes(outcomes: &[HandlerOutcome]) -> String {
+    let failed: Vec<&str> = outcomes
+        .iter()
+        .filter(|o| o.error.is_some())
+        .map(|o| o.handler.as_str())
+        .collect();
+    if failed.is_empty() {
+        format!("{} ok", outcomes.len())
+    } else {
+        format!("failed in {}", failed.join(", "))
+    }
+}
+
+fn page(title: &str, content: Markup) -> HttpResponse {
+    let markup = html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="UTF-8";
+                meta name="viewport" content="width=device-width, initial-scale=1.0";
+                title { (title) }
+                (header::stylesheet_link())
+                (header::scripts())
+            }
+            body.deploy-history-page {
+                (header::render("settings"))
+                div class="content" { (content) }
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(markup.into_string())
+}
+
+#[get("/webhook-events")]
+pub async fn webhook_events_page(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    query: web::Query<WebhookEventQuery>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    user.authorize_admin("Inspecting webhook events")?;
+    let (events, event_types) = {
+        let conn = pool.get()?;
+        (
+            IncomingEvent::search(
+                &query.filter(),
+                query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+                &conn,
+            )?,
+            IncomingEvent::get_event_types(&conn)?,
+        )
+    };
+    let event_type = query.event_type.as_deref().unwrap_or_default();
+    let status = query.status.as_deref().unwrap_or_default();
+
+    Ok(page(
+        "Webhook events",
+        html! {
+            header {
+                h1 { "Webhook events" }
+                div class="subtitle" { "GitHub events as received, with what each handler made of them." }
+            }
+            div class="audit-filters" {
+                form class="audit-filters" action="/webhook-events" method="get" {
+                    select name="event_type" class="repo-input" {
+                        option value="" { "Any event" }
+                        @for t in &event_types {
+                            option value=(t) selected[t == event_type] { (t) }
+                        }
+                    }
+                    select name="status" class="repo-input" {
+                        option value="" { "Any status" }
+                        @for s in STATUSES {
+                            option value=(s) selected[s == status] { (s) }
+                        }
+                    }
+                    button type="submit" class="bootstrap-button" { "Filter" }
+                }
+                form class="audit-filters" action="/webhook-events/replay" method="post"
+                    onsubmit="return confirm('Replay the failed events through all handlers?')"
+                {
+                    input type="hidden" name="failed" value="1";
+                    input type="hidden" name="event_type" value=(event_type);
+                    button type="submit" class="bootstrap-button" { "Replay failed" }
+                }
+            }
+            @if events.is_empty() {
+                div class="empty-state" {
+                    h2 { "No webhook events" }
+                    p { "Nothing matches these filters." }
+                }
+            } @else {
+                form action="/webhook-events/replay" method="post" {
+                    table class="history-table" {
+                        thead {
+                            tr {
+                                th {}
+                                th { "Received" }
+                                th { "Event" }
+                                th { "Delivery" }
+                                th { "Source" }
+                                th { "Status" }
+                                th { "Handlers" }
+                                th { "Replays" }
+                                th {}
+                            }
+                        }
+                        tbody {
+                            @for event in &events {
+                                tr {
+                                    td { input type="checkbox" name="id" value=(event.id); }
+                                    td class="time-cell" title=(format_timestamp(event.received_at)) { (format_relative_time(event.received_at)) }
+                                    td { (event.event_type) }
+                                    td { (event.delivery_id.as_deref().unwrap_or("-")) }
+                                    td { (event.source) }
+                                    td { (render_status(&event.status)) }
+                                    td { (summarize_outcomes(&event.outcomes)) }
+                                    td { (event.replays) }
+                                    td class="actions-cell" {
+                                        a class="link-button" href=(format!("/webhook-events/{}", event.id)) { "View" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div class="audit-filters" {
+                        button type="submit" class="bootstrap-button" { "Replay selected" }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[get("/webhook-events/{id}")]
+pub async fn webhook_event_page(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    path: web::Path<i64>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    user.authorize_admin("Inspecting webhook events")?;
+    let id = path.into_inner();
+    let event = IncomingEvent::get_by_id(id, &pool.get()?)?
+        .ok_or_else(|| AppError::NotFound(format!("Webhook event {}", id)))?;
+
+    Ok(page(
+        &format!("Webhook event {}", id),
+        html! {
+            header {
+                h1 { (event.event_type) " event " (event.id) }
+                div class="subtitle" {
+                    a href="/webhook-events" { "All webhook events" }
+                }
+            }
+            table class="history-table" {
+                tbody {
+                    tr { th { "Delivery" } td { (event.delivery_id.as_deref().unwrap_or("-")) } }
+                    tr { th { "Source" } td { (event.source) } }
+                    tr { th { "Received" } td { (format_timestamp(event.received_at)) } }
+                    tr {
+                        th { "Processed" }
+                        td { (event.processed_at.map(format_timestamp).unwrap_or_else(|| "-".to_string())) }
+                    }
+                    tr { th { "Status" } td { (render_status(&event.status)) } }
+                    tr { th { "Replays" } td { (event.replays) } }
+                }
+            }
+            h2 { "Handlers" }
+            table class="history-table" {
+                thead {
+                    tr {
+                        th { "Handler" }
+                        th { "Attempts" }
+                        th { "Result" }
+                    }
+                }
+                tbody {
+                    @for outcome in &event.outcomes {
+                        tr {
+                            td { (outcome.handler) }
+                            td { (outcome.attempts) }
+                            td {
+                                @match &outcome.error {
+                                    Some(error) => span class="webhook-failed" { (error) },
+                                    None => span class="webhook-ok" { "ok" },
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            form class="audit-filters" action="/webhook-events/replay" method="post" {
+                input type="hidden" name="id" value=(event.id);
+                input type="hidden" name="next" value=(format!("/webhook-events/{}", event.id));
+                button type="submit" class="bootstrap-button" { "Replay" }
+            }
+            h2 { "Payload" }
+            pre class="webhook-payload" {
+                (serde_json::to_string_pretty(&event.payload).unwrap_or_default())
+            }
+        },
+    ))
+}
+
+/// Run the selected events through the handlers again, then go back.
+#[post("/webhook-events/replay")]
+pub async fn replay_webhook_events(
+    pool: web::Data<Pool<SqliteConnectionManager>>,
+    manager: web::Data<Arc<WebhookManager>>,
+    form: web::Form<Vec<(String, String)>>,
+    user: CurrentUser,
+) -> AppResult<HttpResponse> {
+    user.authorize_admin("Replaying webhook events")?;
+    let form = ReplayForm::from_pairs(&form)?;
+    let ids = if form.failed {
+        let filter = IncomingEventFilter {
+            event_type: form.event_type.clone(),
+            status: Some("failed".to_string()),
+        };
+        // Oldest first, as they were received.
+        IncomingEvent::search(&filter, MAX_REPLAY, &pool.get()?)?
+            .iter()
+            .rev()
+            .map(|e| e.id)
+            .collect()
+    } else {
+        form.ids
+    };
+    if ids.is_empty() {
+        return Err(AppError::InvalidInput("No events to replay".to_string()));
+    }
+
+    for id in ids {
+        let result =
+            manager
+                .replay(id)
+                .await
+                .and_then(|outcomes| match summarize_outcomes(&outcomes) {
+                    summary if summary.starts_with("failed") => Err(AppError::Webhook(summary)),
+                    summary => Ok(summary),
+                });
+        AuditRecord::new(
+            &user.login,
+            AuditSource::Ui,
+            "replay-webhook-event",
+            &id.to_string(),
+        )
+        .record(&pool, &result);
+    }
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((
+            "Location",
+            form.next.as_deref().unwrap_or("/webhook-events"),
+        ))
+        .finish())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_replay_form() {
+        let form = ReplayForm::from_pairs(&pairs(&[
+            ("id", "3"),
+            ("id", "7"),
+            ("next", "/webhook-events/3"),
+        ]))
+        .unwrap();
+        assert_eq!(form.ids, vec![3, 7]);
+        assert!(!form.failed);
+        assert_eq!(form.next.as_deref(), Some("/webhook-events/3"));
+
+        let form = ReplayForm::from_pairs(&pairs(&[
+            ("failed", "1"),
+            ("event_type", ""),
+            ("next", "https://example.com/webhook-events"),
+        ]))
+        .unwrap();
+        assert!(form.failed);
+        assert_eq!(form.event_type, None);
+        assert_eq!(form.next, None);
+
+        assert!(ReplayForm::from_pairs(&pairs(&[("id", "x")])).is_err());
+    }
+}
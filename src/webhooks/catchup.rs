@@ -0,0 +1,666 @@
+//! Recovering GitHub events missed while the websocket was disconnected.
+//!
+//! GitHub keeps three days of deliveries per webhook, so when the hooks are
+//! listed in `GITHUB_WEBHOOK_HOOKS` the missed deliveries are fetched and
+//! replayed as they were. Independently, branch heads and the check runs of
+//! unsettled heads are compared with GitHub, and the differences are turned
+//! into the push, delete and check_run events that would have produced them.
+//! Everything goes through [`WebhookManager::receive`], so events that did
+//! arrive are skipped as duplicates.
+//!
+//! [`WebhookManager::receive`]: crate::webhooks::manager::WebhookManager::receive
+
+use std::collections::{HashMap, HashSet};
+
+use octocrab::Octocrab;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde::Deserialize;
+
+use crate::build_status::BuildStatus;
+use crate::crab_ext::{OctocrabExt, Octocrabs};
+use crate::db::git_branch::GitBranch;
+use crate::db::git_commit::GitCommit;
+use crate::db::git_commit_build::GitCommitBuild;
+use crate::db::git_repo::GitRepo;
+use crate::prelude::*;
+use crate::webhooks::config_validation::CHECK_RUN_NAME;
+use crate::webhooks::models::{
+    CheckApp, CheckRun, CheckRunEvent, CheckSuite, CommitAuthor, DeleteEvent, PushCommit,
+    PushEvent, RepoOwner, Repository, WebhookEvent,
+};
+
+/// GitHub keeps hook deliveries for three days.
+pub const MAX_CATCHUP_WINDOW_MS: i64 = 3 * 24 * 60 * 60 * 1000;
+
+/// GitHub lists at most 20 commits in a push payload; synthesized pushes do
+/// the same.
+const MAX_PUSH_COMMITS: usize = 20;
+
+const PER_PAGE: usize = 100;
+
+/// Minimal shapes of the REST responses used here, deserialized through
+/// `crab.get` like the bootstrap's check run scan.
+#[derive(Deserialize)]
+struct ApiDelivery {
+    id: u64,
+    guid: String,
+    delivered_at: chrono::DateTime<chrono::Utc>,
+    event: String,
+}
+
+#[derive(Deserialize)]
+struct ApiDeliveryDetail {
+    request: ApiDeliveryRequest,
+}
+
+#[derive(Deserialize)]
+struct ApiDeliveryRequest {
+    payload: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct ApiBranch {
+    name: String,
+    commit: ApiSha,
+}
+
+#[derive(Deserialize)]
+struct ApiSha {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct ApiCommit {
+    sha: String,
+    commit: ApiCommitDetails,
+}
+
+#[derive(Deserialize)]
+struct ApiCommitDetails {
+    message: String,
+    author: Option<ApiGitUser>,
+    committer: Option<ApiGitUser>,
+}
+
+#[derive(Deserialize)]
+struct ApiGitUser {
+    name: String,
+    email: String,
+    date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiComparison {
+    /// Oldest first.
+    commits: Vec<ApiCommit>,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckRuns {
+    check_runs: Vec<ApiCheckRun>,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckRun {
+    id: u64,
+    name: String,
+    head_sha: String,
+    status: String,
+    conclusion: Option<String>,
+    details_url: Option<String>,
+    html_url: Option<String>,
+    started_at: Option<String>,
+    completed_at: Option<String>,
+    app: Option<CheckApp>,
+    check_suite: Option<ApiCheckSuiteRef>,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckSuiteRef {
+    id: u64,
+}
+
+/// The result of comparing branch heads with GitHub.
+#[derive(Default)]
+pub struct BranchChanges {
+    /// Synthesized push and delete events.
+    pub events: Vec<WebhookEvent>,
+    /// Repos and the heads that moved, whose check runs need a look.
+    pub heads: Vec<(GitRepo, String)>,
+}
+
+pub struct CatchUp {
+    pool: Pool<SqliteConnectionManager>,
+    octocrabs: Octocrabs,
+    /// API paths of the hooks to read deliveries from, like
+    /// `orgs/acme/hooks/123` or `repos/acme/api/hooks/456`.
+    hooks: Vec<String>,
+}
+
+impl CatchUp {
+    pub fn new(
+        pool: Pool<SqliteConnectionManager>,
+        octocrabs: Octocrabs,
+        hooks: Vec<String>,
+    ) -> Self {
+        Self {
+            pool,
+            octocrabs,
+            hooks,
+        }
+    }
+
+    /// With the hooks listed, comma-separated, in `GITHUB_WEBHOOK_HOOKS`.
+    pub fn from_env(pool: Pool<SqliteConnectionManager>, octocrabs: Octocrabs) -> AppResult<Self> {
+        let hooks = std::env::var("GITHUB_WEBHOOK_HOOKS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .map(|h| {
+                parse_hook_path(h).ok_or_else(|| {
+                    AppError::Config(format!(
+                        "Invalid hook {} in GITHUB_WEBHOOK_HOOKS, expected orgs/<org>/hooks/<id> or repos/<owner>/<repo>/hooks/<id>",
+                        h
+                    ))
+                })
+            })
+            .collect::<AppResult<Vec<_>>>()?;
+        Ok(Self::new(pool, octocrabs, hooks))
+    }
+
+    /// Read `route` of `hook`: with the client chosen for the repo of a repo
+    /// hook, or the first one that can read it for an org hook.
+    async fn get<R: serde::de::DeserializeOwned>(&self, hook: &str, route: &str) -> Option<R> {
+        if let Some(repo) = hook_repo(hook) {
+            let crab = self.octocrabs.crab_for(&repo).await?;
+            return match crab.get::<R, _, ()>(route, None).await {
+                Ok(response) => Some(response),
+                Err(e) => {
+                    let e = self.octocrabs.invalidate_on_error(&repo, e);
+                    log::debug!("Failed to get {}: {}", route, e);
+                    None
+                }
+            };
+        }
+        for client in self.octocrabs.clients() {
+            match client.crab.get::<R, _, ()>(route, None).await {
+                Ok(response) => return Some(response),
+                Err(e) => log::debug!("Failed to get {}: {}", route, e),
+            }
+        }
+        None
+    }
+
+    /// Deliveries of the configured hooks since `since` (ms), oldest first,
+    /// with their original delivery ids.
+    pub async fn missed_deliveries(&self, since: i64) -> Vec<WebhookEvent> {
+        let mut events = Vec::new();
+        for hook in &self.hooks {
+            let route = format!("/{}/deliveries?per_page={}", hook, PER_PAGE);
+            let Some(deliveries) = self.get::<Vec<ApiDelivery>>(hook, &route).await else {
+                log::warn!("Failed to list deliveries of {}", hook);
+                continue;
+            };
+            if deliveries.len() == PER_PAGE
+                && deliveries
+                    .iter()
+                    .all(|d| d.delivered_at.timestamp_millis() >= since)
+            {
+                log::warn!(
+                    "More than {} deliveries of {} since the gap began; older ones are left to the branch comparison",
+                    PER_PAGE,
+                    hook
+                );
+            }
+
+            let mut seen = HashSet::new();
+            for delivery in deliveries.iter().rev() {
+                if delivery.delivered_at.timestamp_millis() < since
+                    || delivery.event == "ping"
+                    || !seen.insert(delivery.guid.as_str())
+                {
+                    continue;
+                }
+                let route = format!("/{}/deliveries/{}", hook, delivery.id);
+                match self.get::<ApiDeliveryDetail>(hook, &route).await {
+                    Some(detail) => events.push(WebhookEvent {
+                        event_type: delivery.event.clone(),
+                        payload: detail.request.payload,
+                        delivery_id: Some(delivery.guid.clone()),
+                    }),
+                    None => log::warn!("Failed to get delivery {} of {}", delivery.guid, hook),
+                }
+            }
+        }
+        events
+    }
+
+    /// Compare every tracked repo's active branches with GitHub: moved or new
+    /// heads become pushes, vanished branches deletes.
+    pub async fn missed_branch_changes(&self) -> BranchChanges {
+        let repos = match self
+            .pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|conn| GitRepo::get_all(&conn))
+        {
+            Ok(repos) => repos,
+            Err(e) => {
+                log::error!("Failed to list repos to catch up on: {}", e);
+                return BranchChanges::default();
+            }
+        };
+
+        let mut changes = BranchChanges::default();
+        for repo in repos {
+            let Some(crab) = self.octocrabs.crab_for(&repo).await else {
+                log::debug!("No token can read {}/{}", repo.owner_name, repo.name);
+                continue;
+            };
+            let remote = match list_branches(&crab, &repo).await {
+                Ok(branches) => branches,
+                Err(e) => {
+                    let e = self.octocrabs.invalidate_on_error(&repo, e);
+                    // Without the full list, missing branches can't be told
+                    // from deleted ones.
+                    log::warn!(
+                        "Failed to list branches of {}/{}: {}",
+                        repo.owner_name,
+                        repo.name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let local: HashMap<String, String> = match self
+                .pool
+                .get()
+                .map_err(AppError::from)
+                .and_then(|conn| GitBranch::get_active_by_repo(repo.id, &conn))
+            {
+                Ok(branches) => branches
+                    .into_iter()
+                    .map(|b| (b.name, b.head_commit_sha))
+                    .collect(),
+                Err(e) => {
+                    log::error!(
+                        "Failed to get branches of {}/{}: {}",
+                        repo.owner_name,
+                        repo.name,
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            for branch in &remote {
+                let known = local.get(&branch.name);
+                if known == Some(&branch.commit.sha) {
+                    continue;
+                }
+                let commits =
+                    match new_commits(&crab, &repo, known.map(String::as_str), &branch.commit.sha)
+                        .await
+                    {
+                        Ok(commits) => commits,
+                        Err(e) => {
+                            log::warn!(
+                                "Failed to get commits of {}/{} {}: {}",
+                                repo.owner_name,
+                                repo.name,
+                                branch.name,
+                                e
+                            );
+                            continue;
+                        }
+                    };
+                let event = PushEvent {
+                    r#ref: format!("refs/heads/{}", branch.name),
+                    after: branch.commit.sha.clone(),
+                    repository: repository(&repo),
+                    head_commit: commits.last().cloned(),
+                    commits,
+                    deleted: false,
+                    sender: None,
+                };
+                changes.events.push(webhook_event("push", &event));
+                changes
+                    .heads
+                    .push((repo.clone(), branch.commit.sha.clone()));
+            }
+
+            let remote_names: HashSet<&str> = remote.iter().map(|b| b.name.as_str()).collect();
+            for name in local
+                .keys()
+                .filter(|name| !remote_names.contains(name.as_str()))
+            {
+                let event = DeleteEvent {
+                    r#ref: name.clone(),
+                    repository: repository(&repo),
+                    ref_type: "branch".to_string(),
+                };
+                changes.events.push(webhook_event("delete", &event));
+            }
+        }
+        changes
+    }
+
+    /// Check runs that changed without us hearing about it, on `heads` and on
+    /// every branch head whose build is still pending.
+    pub async fn missed_check_runs(&self, heads: &[(GitRepo, String)]) -> Vec<WebhookEvent> {
+        let mut heads = heads.to_vec();
+        heads.extend(self.pending_heads());
+
+        let mut events = Vec::new();
+        let mut seen = HashSet::new();
+        for (repo, sha) in heads {
+            if !seen.insert((repo.id, sha.clone())) {
+                continue;
+            }
+            let route = format!(
+                "/repos/{}/{}/commits/{}/check-runs?per_page={}",
+                repo.owner_name, repo.name, sha, PER_PAGE
+            );
+            let Some(crab) = self.octocrabs.crab_for(&repo).await else {
+                continue;
+            };
+            let runs = match crab.get::<ApiCheckRuns, _, ()>(&route, None).await {
+                Ok(response) => response.check_runs,
+                Err(e) => {
+                    let e = self.octocrabs.invalidate_on_error(&repo, e);
+                    log::warn!("Failed to get check runs of {}: {}", sha, e);
+                    continue;
+                }
+            };
+            let known = self.known_statuses(&repo, &sha);
+            events.extend(
+                missed_runs(runs, &known)
+                    .iter()
+                    .map(|run| webhook_event("check_run", &check_run_event(run, &repo))),
+            );
+        }
+        events
+    }
+
+    /// Active branch heads whose aggregate build is pending.
+    fn pending_heads(&self) -> Vec<(GitRepo, String)> {
+        let pending = || -> AppResult<Vec<(GitRepo, String)>> {
+            let conn = self.pool.get()?;
+            let mut heads = Vec::new();
+            for repo in GitRepo::get_all(&conn)? {
+                for branch in GitBranch::get_active_by_repo(repo.id, &conn)? {
+                    let Some(commit) =
+                        GitCommit::get_by_sha(&branch.head_commit_sha, repo.id, &conn)?
+                    else {
+                        continue;
+                    };
+                    let build =
+                        GitCommitBuild::get_aggregate_by_commit_id(&commit.id, &repo.id, &conn)?;
+                    if BuildStatus::from(build) == BuildStatus::Pending {
+                        heads.push((repo.clone(), branch.head_commit_sha));
+                    }
+                }
+            }
+            Ok(heads)
+        };
+        pending().unwrap_or_else(|e| {
+            log::error!("Failed to find pending builds to catch up on: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// Stored build status per check name of a commit.
+    fn known_statuses(&self, repo: &GitRepo, sha: &str) -> HashMap<String, String> {
+        let builds = || -> AppResult<Vec<GitCommitBuild>> {
+            let conn = self.pool.get()?;
+            match GitCommit::get_by_sha(sha, repo.id, &conn)? {
+                Some(commit) => GitCommitBuild::get_all_by_commit_id(&commit.id, &repo.id, &conn),
+                None => Ok(Vec::new()),
+            }
+        };
+        builds()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|b| (b.check_name, b.status))
+            .collect()
+    }
+}
+
+/// `orgs/<org>/hooks/<id>` or `repos/<owner>/<repo>/hooks/<id>`, without
+/// surrounding slashes.
+fn parse_hook_path(hook: &str) -> Option<String> {
+    let hook = hook.trim_matches('/');
+    let parts: Vec<&str> = hook.split('/').collect();
+    let valid = match parts.as_slice() {
+        ["orgs", org, "hooks", id] => !org.is_empty() && id.parse::<u64>().is_ok(),
+        ["repos", owner, repo, "hooks", id] => {
+            !owner.is_empty() && !repo.is_empty() && id.parse::<u64>().is_ok()
+        }
+        _ => false,
+    };
+    valid.then(|| hook.to_string())
+}
+
+/// The repo of a `repos/<owner>/<repo>/hooks/<id>` hook.
+fn hook_repo(hook: &str) -> Option<crate::kubernetes::Repository> {
+    match hook.split('/').collect::<Vec<_>>().as_slice() {
+        ["repos", owner, repo, "hooks", _] => Some(crate::kubernetes::Repository {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+async fn list_branches(crab: &Octocrab, repo: &GitRepo) -> Result<Vec<ApiBranch>, octocrab::Error> {
+    let mut all = Vec::new();
+    for page in 1.. {
+        let route = format!(
+            "/repos/{}/{}/branches?per_page={}&page={}",
+            repo.owner_name, repo.name, PER_PAGE, page
+        );
+        let branches: Vec<ApiBranch> = crab.get(&route, None::<&()>).await?;
+        let count = branches.len();
+        all.extend(branches);
+        if count < PER_PAGE {
+            break;
+        }
+    }
+    Ok(all)
+}
+
+/// The commits from `base` to `head`, oldest first; just the head for a new
+/// branch, or when `base` is gone after a force push.
+async fn new_commits(
+    crab: &Octocrab,
+    repo: &GitRepo,
+    base: Option<&str>,
+    head: &str,
+) -> Result<Vec<PushCommit>, octocrab::Error> {
+    if let Some(base) = base {
+        let route = format!(
+            "/repos/{}/{}/compare/{}...{}",
+            repo.owner_name, repo.name, base, head
+        );
+        match crab.get::<ApiComparison, _, ()>(&route, None).await {
+            Ok(comparison) if !comparison.commits.is_empty() => {
+                let skip = comparison.commits.len().saturating_sub(MAX_PUSH_COMMITS);
+                return Ok(comparison
+                    .commits
+                    .into_iter()
+                    .skip(skip)
+                    .map(push_commit)
+                    .collect());
+            }
+            Ok(_) => {}
+            Err(e) => log::debug!("Failed to compare {}...{}: {}", base, head, e),
+        }
+    }
+    let route = format!("/repos/{}/{}/commits/{}", repo.owner_name, repo.name, head);
+    let commit: ApiCommit = crab.get(&route, None::<&()>).await?;
+    Ok(vec![push_commit(commit)])
+}
+
+fn push_commit(commit: ApiCommit) -> PushCommit {
+    let user = |user: Option<&ApiGitUser>| CommitAuthor {
+        name: user.map(|u| u.name.clone()).unwrap_or_default(),
+        email: user.map(|u| u.email.clone()).unwrap_or_default(),
+    };
+    let details = &commit.commit;
+    PushCommit {
+        timestamp: details
+            .author
+            .as_ref()
+            .or(details.committer.as_ref())
+            .and_then(|u| u.date.clone())
+            .unwrap_or_else(|| Utc::now().to_rfc3339()),
+        author: user(details.author.as_ref()),
+        committer: user(details.committer.as_ref()),
+        message: details.message.clone(),
+        id: commit.sha,
+    }
+}
+
+/// Runs whose status differs from the stored one, or that weren't stored.
+/// Deploy config validation runs are never stored.
+fn missed_runs(runs: Vec<ApiCheckRun>, known: &HashMap<String, String>) -> Vec<ApiCheckRun> {
+    runs.into_iter()
+        .filter(|run| run.name != CHECK_RUN_NAME)
+        .filter(|run| {
+            let status: String = BuildStatus::of(&run.status, &run.conclusion.as_deref()).into();
+            known.get(&format!("run-{}", run.id)) != Some(&status)
+        })
+        .collect()
+}
+
+fn check_run_event(run: &ApiCheckRun, repo: &GitRepo) -> CheckRunEvent {
+    let completed = run.status == "completed";
+    CheckRunEvent {
+        action: if completed { "completed" } else { "created" }.to_string(),
+        check_run: CheckRun {
+            id: run.id,
+            name: run.name.clone(),
+            status: run.status.clone(),
+            conclusion: run.conclusion.clone(),
+            details_url: run
+                .details_url
+                .clone()
+                .or_else(|| run.html_url.clone())
+                .unwrap_or_default(),
+            html_url: run.html_url.clone(),
+            started_at: run.started_at.clone(),
+            completed_at: run.completed_at.clone(),
+            app: run.app.clone(),
+            check_suite: CheckSuite {
+                id: run.check_suite.as_ref().map(|s| s.id).unwrap_or_default(),
+                head_sha: run.head_sha.clone(),
+                head_commit: None,
+                status: run.status.clone(),
+                conclusion: run.conclusion.clone(),
+            },
+        },
+        repository: repository(repo),
+    }
+}
+
+fn repository(repo: &GitRepo) -> Repository {
+    Repository {
+        id: repo.id,
+        name: repo.name.clone(),
+        owner: RepoOwner {
+            login: repo.owner_name.clone(),
+        },
+        private: repo.private,
+        language: repo.language.clone(),
+        default_branch: repo.default_branch.clone(),
+    }
+}
+
+fn webhook_event(event_type: &str, payload: &impl Serialize) -> WebhookEvent {
+    WebhookEvent {
+        event_type: event_type.to_string(),
+        payload: serde_json::to_value(payload).unwrap_or_default(),
+        delivery_id: None,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hook_paths() {
+        assert_eq!(
+            parse_hook_path("/orgs/acme/hooks/123/").as_deref(),
+            Some("orgs/acme/hooks/123")
+        );
+        assert_eq!(
+            parse_hook_path("repos/acme/api/hooks/456").as_deref(),
+            Some("repos/acme/api/hooks/456")
+        );
+        assert_eq!(parse_hook_path("orgs/acme/hooks/latest"), None);
+        assert_eq!(parse_hook_path("repos/acme/hooks/1"), None);
+        assert_eq!(
+            parse_hook_path("https://api.github.com/orgs/acme/hooks/1"),
+            None
+        );
+
+        let repo = hook_repo("repos/acme/api/hooks/456").unwrap();
+        assert_eq!((repo.owner.as_str(), repo.repo.as_str()), ("acme", "api"));
+        assert!(hook_repo("orgs/acme/hooks/123").is_none());
+    }
+
+    #[test]
+    fn synthesizes_missed_check_runs() {
+        let run = |id: u64, status: &str, conclusion: Option<&str>| ApiCheckRun {
+            id,
+            name: "build".to_string(),
+            head_sha: "abc".to_string(),
+            status: status.to_string(),
+            conclusion: conclusion.map(str::to_string),
+            details_url: None,
+            html_url: Some(format!("https://github.com/acme/api/runs/{}", id)),
+            started_at: None,
+            completed_at: None,
+            app: Some(CheckApp { id: 15368 }),
+            check_suite: Some(ApiCheckSuiteRef { id: 9 }),
+        };
+        let known = HashMap::from([
+            ("run-1".to_string(), "Success".to_string()),
+            ("run-2".to_string(), "Pending".to_string()),
+        ]);
+        let runs = vec![
+            run(1, "completed", Some("success")),
+            run(2, "completed", Some("failure")),
+            run(3, "in_progress", None),
+            ApiCheckRun {
+                name: CHECK_RUN_NAME.to_string(),
+                ..run(4, "completed", Some("success"))
+            },
+        ];
+
+        let missed = missed_runs(runs, &known);
+        assert_eq!(missed.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 3]);
+
+        let repo = GitRepo {
+            id: 1,
+            owner_name: "acme".to_string(),
+            name: "api".to_string(),
+            default_branch: "main".to_string(),
+            private: false,
+            language: None,
+        };
+        let event = webhook_event("check_run", &check_run_event(&missed[0], &repo));
+        let parsed: CheckRunEvent = serde_json::from_value(event.payload).unwrap();
+        assert_eq!(parsed.action, "completed");
+        assert_eq!(parsed.check_run.check_suite.head_sha, "abc");
+        assert_eq!(
+            parsed.check_run.details_url,
+            "https://github.com/acme/api/runs/2"
+        );
+        assert_eq!(parsed.repository.owner.login, "acme");
+    }
+}
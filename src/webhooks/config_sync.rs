@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use itertools::Itertools;
 use kube::{api::ObjectMeta, Client, ResourceExt};
 use octocrab::models::repos::Content;
 use r2d2::Pool;
@@ -9,6 +10,7 @@ use serde_json::Value;
 use serenity::async_trait;
 
 use crate::{
+    audit::{AuditRecord, AuditSource},
     crab_ext::{IRepo, OctocrabExt, Octocrabs},
     db::{
         deploy_config::DeployConfig as DbDeployConfig, deploy_config_version::DeployConfigVersion,
@@ -17,11 +19,17 @@ use crate::{
     error::{AppError, AppResult},
     kubernetes::{
         deploy_config::{DeployConfig, DeployConfigSpec, DeployConfigSpecFields},
+        drift::DriftPolicy,
         repo::RepositoryBranch,
         webhook_handlers::update_deploy_configs_by_defining_repo,
         Repository,
     },
-    webhooks::{models::PushEvent, util::extract_branch_name, WebhookHandler},
+    webhooks::{
+        config_validation::{publish_check_run, validate_deploy_config_files, ValidationContext},
+        models::PushEvent,
+        util::extract_branch_name,
+        WebhookHandler,
+    },
 };
 
 pub struct ConfigSyncHandler {
@@ -40,6 +48,70 @@ impl ConfigSyncHandler {
     }
 }
 
+impl ConfigSyncHandler {
+    /// Validate the `.deploy` files at the pushed commit and report the
+    /// result as a check run. Repos without `.deploy` get no check run.
+    async fn validate_push(&self, event: &PushEvent, files: &[DeployConfigFiles]) -> AppResult<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let context = {
+            let conn = self.pool.get()?;
+            ValidationContext::load(files, event.repository.id, &conn)?
+        };
+        let issues = validate_deploy_config_files(files, &context);
+        for issue in &issues {
+            log::info!(
+                "{}:{}: {:?}: {}",
+                issue.path,
+                issue.line,
+                issue.severity,
+                issue.message
+            );
+        }
+        log::info!(
+            "Validated {} deploy configs in {}/{} at {}: {} issues",
+            files.len(),
+            event.repository.owner.login,
+            event.repository.name,
+            event.after,
+            issues.len()
+        );
+
+        publish_check_run(
+            &self.octocrabs,
+            event.repository.clone(),
+            &event.after,
+            files.len(),
+            &issues,
+        )
+        .await
+    }
+
+    /// Sync the configs in the `.deploy` files validated for a push.
+    async fn sync_validated(
+        &self,
+        event: &PushEvent,
+        files: Vec<DeployConfigFiles>,
+    ) -> Result<(), anyhow::Error> {
+        let repository = Repository {
+            owner: event.repository.owner.login.clone(),
+            repo: event.repository.name.clone(),
+        };
+        let deploy_configs = parse_deploy_config_files(&repository, files)?;
+        sync_deploy_configs(
+            &self.client,
+            &self.pool,
+            &repository,
+            event.repository.id,
+            &event.after,
+            deploy_configs,
+        )
+        .await
+    }
+}
+
 /// Sync deploy configs for a repository at a specific commit SHA.
 /// This is idempotent - calling multiple times with the same SHA won't create duplicates.
 pub async fn sync_deploy_configs_for_commit(
@@ -58,7 +130,27 @@ pub async fn sync_deploy_configs_for_commit(
 
     let deploy_configs =
         fetch_deploy_configs_by_sha(octocrabs, repository.clone(), commit_sha).await?;
+    sync_deploy_configs(
+        client,
+        pool,
+        &repository,
+        repo_id,
+        commit_sha,
+        deploy_configs,
+    )
+    .await
+}
 
+/// Sync the deploy configs defined by a repository at `commit_sha`, already
+/// read from its `.deploy` directory.
+async fn sync_deploy_configs(
+    client: &Client,
+    pool: &Pool<SqliteConnectionManager>,
+    repository: &Repository,
+    repo_id: u64,
+    commit_sha: &str,
+    deploy_configs: Vec<DeployConfig>,
+) -> Result<(), anyhow::Error> {
     // Get connection after async work is done
     let conn = pool.get()?;
 
@@ -104,27 +196,27 @@ pub async fn sync_deploy_configs_for_commit(
         )?;
     }
 
+    // Drop connection before async work
+    drop(conn);
+
+    // Deletions are detected from the cluster, which also catches configs the
+    // database never knew about.
+    let removed_deploy_config_names =
+        update_deploy_configs_by_defining_repo(client, &deploy_configs, repository).await?;
+
+    let conn = pool.get()?;
     let deleted_deploy_config_names = existing_deploy_configs
         .iter()
-        .filter(|dc| !current_deploy_config_names.contains(&dc.name))
-        .map(|dc| dc.name.clone())
-        .collect::<Vec<String>>();
+        .map(|dc| &dc.name)
+        .filter(|name| !current_deploy_config_names.contains(name))
+        .chain(removed_deploy_config_names.iter())
+        .unique()
+        .collect::<Vec<&String>>();
 
-    for deleted_deploy_config_name in &deleted_deploy_config_names {
+    for deleted_deploy_config_name in deleted_deploy_config_names {
         DbDeployConfig::mark_inactive(deleted_deploy_config_name, &conn)?;
     }
 
-    // Drop connection before async work
-    drop(conn);
-
-    update_deploy_configs_by_defining_repo(
-        client,
-        &deploy_configs,
-        &deleted_deploy_config_names,
-        &repository,
-    )
-    .await?;
-
     Ok(())
 }
 
@@ -133,6 +225,35 @@ impl WebhookHandler for ConfigSyncHandler {
     async fn handle_push(&self, event: PushEvent) -> Result<(), anyhow::Error> {
         log::debug!("Received push event:\n{:#?}", event);
 
+        // Validate .deploy on every branch, before syncing, so the check run is
+        // published even when the sync itself fails.
+        let mut files = None;
+        if !event.deleted && extract_branch_name(&event.r#ref).is_some() {
+            let validated = match fetch_deploy_config_files_by_sha(
+                &self.octocrabs,
+                event.repository.clone(),
+                &event.after,
+            )
+            .await
+            {
+                Ok(fetched) => {
+                    let validated = self.validate_push(&event, &fetched).await;
+                    files = Some(fetched);
+                    validated
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = validated {
+                log::warn!(
+                    "Failed to validate .deploy for {}/{} at {}: {}",
+                    event.repository.owner.login,
+                    event.repository.name,
+                    event.after,
+                    e
+                );
+            }
+        }
+
         // Push to default branch, so we need to sync the deploy configs
         if extract_branch_name(&event.r#ref) == Some(event.repository.default_branch.clone()) {
             #[allow(clippy::expect_used)]
@@ -143,16 +264,31 @@ impl WebhookHandler for ConfigSyncHandler {
                 .id
                 .clone();
 
-            sync_deploy_configs_for_commit(
-                &self.octocrabs,
-                &self.client,
-                &self.pool,
-                &event.repository.owner.login,
-                &event.repository.name,
-                event.repository.id,
-                &config_commit_sha,
+            // The files just validated are synced rather than fetched again.
+            let result = match files.filter(|_| config_commit_sha == event.after) {
+                Some(files) => self.sync_validated(&event, files).await,
+                None => {
+                    sync_deploy_configs_for_commit(
+                        &self.octocrabs,
+                        &self.client,
+                        &self.pool,
+                        &event.repository.owner.login,
+                        &event.repository.name,
+                        event.repository.id,
+                        &config_commit_sha,
+                    )
+                    .await
+                }
+            };
+            AuditRecord::new(
+                event.sender.as_ref().map_or("github", |s| s.login.as_str()),
+                AuditSource::Webhook,
+                "sync-configs",
+                &format!("{}/{}", event.repository.owner.login, event.repository.name),
             )
-            .await?;
+            .params(serde_json::json!({ "sha": config_commit_sha }))
+            .record(&self.pool, &result);
+            result?;
         }
 
         Ok(())
@@ -166,20 +302,23 @@ fn default_branch() -> String {
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
-struct GitHubArtifactRepo {
-    owner: String,
-    repo: String,
+pub(crate) struct GitHubArtifactRepo {
+    pub(crate) owner: String,
+    pub(crate) repo: String,
     #[serde(default = "default_branch")]
-    branch: String,
+    pub(crate) branch: String,
 }
 
+/// The shape of `.deploy/<name>.yaml`.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-struct GitHubDeployConfig {
+pub(crate) struct GitHubDeployConfig {
     #[serde(rename = "artifactRepo")]
-    artifact_repo: Option<GitHubArtifactRepo>,
-    team: String,
-    kind: String,
-    namespace: String,
+    pub(crate) artifact_repo: Option<GitHubArtifactRepo>,
+    pub(crate) team: String,
+    pub(crate) kind: String,
+    pub(crate) namespace: String,
+    #[serde(rename = "driftPolicy", default)]
+    pub(crate) drift_policy: DriftPolicy,
 }
 
 pub async fn fetch_deploy_config_by_sha(
@@ -215,13 +354,85 @@ pub async fn fetch_deploy_config_by_sha(
     Ok(config)
 }
 
+/// List the names of the configs in `.deploy` at `git_ref`, without reading them.
+///
+/// Unlike [`fetch_deploy_configs_by_sha`], only a missing `.deploy` directory
+/// counts as "no configs"; any other failure is returned as an error so callers
+/// never mistake an API hiccup for every config being deleted.
+pub async fn fetch_deploy_config_names_by_ref(
+    octocrabs: &Octocrabs,
+    repository: impl IRepo,
+    git_ref: &str,
+) -> AppResult<Vec<String>> {
+    let Some(crab) = octocrabs.crab_for(&repository).await else {
+        return Err(AppError::NotFound(format!(
+            "No octocrab found for repo {}/{}",
+            repository.owner(),
+            repository.repo()
+        )));
+    };
+
+    let content = match crab
+        .repos(repository.owner(), repository.repo())
+        .get_content()
+        .r#ref(git_ref)
+        .path(".deploy")
+        .send()
+        .await
+    {
+        Ok(content) => content,
+        Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+            return Ok(vec![]);
+        }
+        Err(e) => return Err(octocrabs.invalidate_on_error(&repository, e).into()),
+    };
+
+    Ok(content
+        .items
+        .iter()
+        .filter_map(|item| item.name.strip_suffix(".yaml"))
+        .map(|name| name.to_string())
+        .collect())
+}
+
 pub async fn fetch_deploy_configs_by_sha(
     octocrabs: &Octocrabs,
     repository: impl IRepo,
     sha: &str,
 ) -> AppResult<Vec<DeployConfig>> {
+    let repository = Repository {
+        owner: repository.owner().to_string(),
+        repo: repository.repo().to_string(),
+    };
+    let files = fetch_deploy_config_files_by_sha(octocrabs, repository.clone(), sha).await?;
+    parse_deploy_config_files(&repository, files)
+}
+
+/// A file read from `.deploy`, with its path relative to the repo root.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// The raw files making up one config: `.deploy/<name>.yaml` and the contents
+/// of `.deploy/<name>/`, if present.
+#[derive(Clone, Debug)]
+pub struct DeployConfigFiles {
+    pub name: String,
+    pub config: SourceFile,
+    pub children: Vec<SourceFile>,
+}
+
+/// Read every config in `.deploy` at `sha` without parsing it, so callers can
+/// validate the files and point at the offending lines.
+pub async fn fetch_deploy_config_files_by_sha(
+    octocrabs: &Octocrabs,
+    repository: impl IRepo,
+    sha: &str,
+) -> AppResult<Vec<DeployConfigFiles>> {
     log::debug!(
-        "fetch_deploy_configs_by_sha: repo={}/{}, sha={}",
+        "fetch_deploy_config_files_by_sha: repo={}/{}, sha={}",
         repository.owner(),
         repository.repo(),
         sha
@@ -257,10 +468,15 @@ pub async fn fetch_deploy_configs_by_sha(
         .await
     {
         Ok(content) => content,
+        // No .deploy directory means the repo defines no configs.
+        Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+            log::debug!("No .deploy directory in {}/{} at {}", owner, repo, sha);
+            return Ok(vec![]);
+        }
+        // Anything else must not be mistaken for "all configs were deleted".
         Err(e) => {
             log::warn!("Failed to fetch .deploy directory: {:?}", e);
-            // FIXME: Should actually confirm that this is a 404 before saying Ok(())
-            return Ok(vec![]);
+            return Err(octocrabs.invalidate_on_error(&repository, e).into());
         }
     };
 
@@ -276,13 +492,14 @@ pub async fn fetch_deploy_configs_by_sha(
         .keys()
         .filter(|name| name.ends_with(".yaml"))
         .filter_map(|name| name.strip_suffix(".yaml"))
+        .sorted()
         .collect();
 
-    let mut final_deploy_configs: Vec<DeployConfig> = vec![];
+    let mut config_files: Vec<DeployConfigFiles> = vec![];
 
     for config_name in configs {
         log::debug!("Processing config: {}", config_name);
-        let child_files = if entries.contains_key(config_name) {
+        let children = if entries.contains_key(config_name) {
             log::debug!("Found directory .deploy/{}", config_name);
             let Ok(content_items) = crab
                 .repos(&owner, &repo)
@@ -302,29 +519,27 @@ pub async fn fetch_deploy_configs_by_sha(
             let files = content_items.items;
             log::debug!("Found {} files in .deploy/{}", files.len(), config_name);
 
-            let mut result: Vec<String> = vec![];
+            let mut result: Vec<SourceFile> = vec![];
 
             for file in files {
                 log::debug!("  Reading file: {}", file.name);
+                let path = format!(".deploy/{}/{}", config_name, file.name);
                 let Ok(mut content) = crab
                     .repos(&owner, &repo)
                     .get_content()
                     .r#ref(sha)
-                    .path(format!(".deploy/{}/{}", config_name, file.name))
+                    .path(&path)
                     .send()
                     .await
                 else {
-                    log::error!("Failed to read .deploy/{}/{}", config_name, file.name);
-                    return Err(AppError::NotFound(format!(
-                        "Failed to read .deploy/{}/{}",
-                        config_name, file.name
-                    )));
+                    log::error!("Failed to read {}", path);
+                    return Err(AppError::NotFound(format!("Failed to read {}", path)));
                 };
 
                 let contents = content.take_items();
                 let c = &contents[0];
                 let Some(decoded_content) = c.decoded_content() else {
-                    log::error!("Failed to decode .deploy/{}/{}", config_name, file.name);
+                    log::error!("Failed to decode {}", path);
                     return Err(AppError::Internal(
                         "Failed to decode child file content".to_owned(),
                     ));
@@ -337,7 +552,10 @@ pub async fn fetch_deploy_configs_by_sha(
                 if decoded_content.is_empty() {
                     log::warn!("  WARNING: File {} is empty!", file.name);
                 }
-                result.push(decoded_content);
+                result.push(SourceFile {
+                    path,
+                    content: decoded_content,
+                });
             }
 
             result
@@ -349,18 +567,16 @@ pub async fn fetch_deploy_configs_by_sha(
             vec![]
         };
 
+        let path = format!(".deploy/{}.yaml", config_name);
         let Ok(mut config_content) = crab
             .repos(&owner, &repo)
             .get_content()
             .r#ref(sha)
-            .path(format!(".deploy/{}.yaml", config_name))
+            .path(&path)
             .send()
             .await
         else {
-            return Err(AppError::NotFound(format!(
-                "Failed to read .deploy/{}.yaml",
-                config_name
-            )));
+            return Err(AppError::NotFound(format!("Failed to read {}", path)));
         };
 
         let config_content = config_content.take_items();
@@ -371,26 +587,50 @@ pub async fn fetch_deploy_configs_by_sha(
             ));
         };
 
+        config_files.push(DeployConfigFiles {
+            name: config_name.to_owned(),
+            config: SourceFile {
+                path,
+                content: config_content,
+            },
+            children,
+        });
+    }
+
+    Ok(config_files)
+}
+
+/// Build DeployConfigs from raw `.deploy` files, failing on the first file
+/// that doesn't parse.
+pub fn parse_deploy_config_files(
+    repository: &Repository,
+    files: Vec<DeployConfigFiles>,
+) -> AppResult<Vec<DeployConfig>> {
+    let mut final_deploy_configs: Vec<DeployConfig> = vec![];
+
+    for files in files {
+        let config_name = files.name;
         let config: GitHubDeployConfig =
-            serde_yaml::from_str(&config_content).map_err(AppError::Yaml)?;
+            serde_yaml::from_str(&files.config.content).map_err(AppError::Yaml)?;
 
         log::debug!(
             "Parsing {} child YAML files for config {}",
-            child_files.len(),
+            files.children.len(),
             config_name
         );
-        let child_files: Vec<Value> = child_files
+        let child_files: Vec<Value> = files
+            .children
             .into_iter()
             .enumerate()
             .map(|(idx, file)| {
                 log::debug!("  Parsing child file [{}]", idx);
-                let parsed: Value = serde_yaml::from_str(&file).map_err(AppError::Yaml)?;
+                let parsed: Value = serde_yaml::from_str(&file.content).map_err(AppError::Yaml)?;
                 log::debug!("  Parsed child file [{}]: {}", idx, parsed);
                 if parsed.is_null() {
                     log::warn!(
                         "  WARNING: Child file [{}] parsed as null! Content was: {}",
                         idx,
-                        file
+                        file.content
                     );
                 }
                 Ok(parsed)
@@ -411,17 +651,15 @@ pub async fn fetch_deploy_configs_by_sha(
                         repo: artifact_repo.repo.clone(),
                         branch: artifact_repo.branch.clone(),
                     }),
-                    config: Repository {
-                        owner: owner.clone(),
-                        repo: repo.clone(),
-                    },
+                    config: repository.clone(),
                     kind: config.kind,
                     specs: child_files,
                     team: config.team.clone(),
+                    drift_policy: config.drift_policy,
                 },
             },
             metadata: ObjectMeta {
-                name: Some(config_name.to_owned()),
+                name: Some(config_name),
                 namespace: Some(config.namespace),
                 ..ObjectMeta::default()
             },
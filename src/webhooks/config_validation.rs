@@ -0,0 +1,545 @@
+//! Validation of `.deploy` configs, published as a GitHub check run on every
+//! push so broken configs are caught at review time instead of at sync or
+//! deploy time.
+
+use std::collections::{HashMap, HashSet};
+
+use kube::api::DynamicObject;
+use octocrab::params::checks::{
+    CheckRunConclusion, CheckRunOutput, CheckRunOutputAnnotation, CheckRunOutputAnnotationLevel,
+    CheckRunStatus,
+};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde_json::Value;
+
+use crate::{
+    crab_ext::{IRepo, OctocrabExt, Octocrabs},
+    db::{deploy_config::DeployConfig as DbDeployConfig, git_repo::GitRepo},
+    error::{AppError, AppResult},
+    webhooks::config_sync::{DeployConfigFiles, GitHubDeployConfig, SourceFile},
+};
+
+pub const CHECK_RUN_NAME: &str = "Deploy config validation";
+
+/// GitHub accepts at most 50 annotations per create/update request.
+const MAX_ANNOTATIONS_PER_REQUEST: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    Failure,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    pub path: String,
+    pub line: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// What validation needs to know beyond the files themselves.
+#[derive(Debug, Default)]
+pub struct ValidationContext {
+    /// Artifact repos (owner, repo) known to the database.
+    pub known_repos: HashSet<(String, String)>,
+    /// Config names already defined by a different config repo.
+    pub foreign_config_names: HashSet<String>,
+}
+
+impl ValidationContext {
+    /// Look up the repos and config names referenced by `files`.
+    pub fn load(
+        files: &[DeployConfigFiles],
+        config_repo_id: u64,
+        conn: &PooledConnection<SqliteConnectionManager>,
+    ) -> AppResult<Self> {
+        let mut context = Self::default();
+
+        for config in files {
+            if DbDeployConfig::get_active_by_name(&config.name, conn)?
+                .is_some_and(|existing| existing.config_repo_id != config_repo_id)
+            {
+                context.foreign_config_names.insert(config.name.clone());
+            }
+
+            let Ok(parsed) = serde_yaml::from_str::<GitHubDeployConfig>(&config.config.content)
+            else {
+                continue;
+            };
+            if let Some(artifact) = parsed.artifact_repo {
+                if GitRepo::get_by_name(&artifact.owner, &artifact.repo, conn)?.is_some() {
+                    context.known_repos.insert((artifact.owner, artifact.repo));
+                }
+            }
+        }
+
+        Ok(context)
+    }
+}
+
+/// Validate every config in a `.deploy` directory.
+pub fn validate_deploy_config_files(
+    files: &[DeployConfigFiles],
+    context: &ValidationContext,
+) -> Vec<ValidationIssue> {
+    let mut issues = vec![];
+    for config in files {
+        validate_config(config, context, &mut issues);
+    }
+    issues
+}
+
+fn validate_config(
+    files: &DeployConfigFiles,
+    context: &ValidationContext,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    let config_file = &files.config;
+    if files.children.is_empty() {
+        issues.push(ValidationIssue {
+            path: config_file.path.clone(),
+            line: 1,
+            severity: Severity::Warning,
+            message: format!(
+                "No manifests in .deploy/{}/, this config will not create any resources",
+                files.name
+            ),
+        });
+    }
+
+    let mut failure = |file: &SourceFile, line: u32, message: String| {
+        issues.push(ValidationIssue {
+            path: file.path.clone(),
+            line,
+            severity: Severity::Failure,
+            message,
+        })
+    };
+
+    if !is_dns_subdomain(&files.name) {
+        failure(
+            config_file,
+            1,
+            format!(
+                "Config name '{}' (from the file name) must be a lowercase RFC 1123 subdomain",
+                files.name
+            ),
+        );
+    }
+    if context.foreign_config_names.contains(&files.name) {
+        failure(
+            config_file,
+            1,
+            format!(
+                "A deploy config named '{}' is already defined by another config repository",
+                files.name
+            ),
+        );
+    }
+
+    let config = match serde_yaml::from_str::<GitHubDeployConfig>(&config_file.content) {
+        Ok(config) => config,
+        Err(e) => {
+            failure(config_file, yaml_error_line(&e), e.to_string());
+            return;
+        }
+    };
+
+    for (key, value) in [
+        ("team", &config.team),
+        ("kind", &config.kind),
+        ("namespace", &config.namespace),
+    ] {
+        if value.trim().is_empty() {
+            failure(
+                config_file,
+                key_line(&config_file.content, &[key]),
+                format!("'{}' must not be empty", key),
+            );
+        }
+    }
+    if !config.namespace.is_empty() && !is_dns_label(&config.namespace) {
+        failure(
+            config_file,
+            key_line(&config_file.content, &["namespace"]),
+            format!(
+                "Namespace '{}' must be a lowercase RFC 1123 label",
+                config.namespace
+            ),
+        );
+    }
+    if let Some(artifact) = &config.artifact_repo {
+        if !context
+            .known_repos
+            .contains(&(artifact.owner.clone(), artifact.repo.clone()))
+        {
+            failure(
+                config_file,
+                key_line(&config_file.content, &["artifactRepo"]),
+                format!(
+                    "Artifact repository {}/{} is not known. Use the bootstrap feature to sync it first.",
+                    artifact.owner, artifact.repo
+                ),
+            );
+        }
+    }
+
+    // (kind, name) -> path of the first file defining it
+    let mut seen: HashMap<(String, String), &str> = HashMap::new();
+
+    for child in &files.children {
+        let value = match serde_yaml::from_str::<Value>(&child.content) {
+            Ok(value) => value,
+            Err(e) => {
+                failure(child, yaml_error_line(&e), e.to_string());
+                continue;
+            }
+        };
+        if value.is_null() {
+            failure(
+                child,
+                1,
+                "File is empty. Delete it or add a Kubernetes object.".to_string(),
+            );
+            continue;
+        }
+        if !value.is_object() {
+            failure(
+                child,
+                1,
+                "Expected a single Kubernetes object (a mapping with apiVersion, kind and metadata)"
+                    .to_string(),
+            );
+            continue;
+        }
+
+        let mut missing = false;
+        for path in [&["apiVersion"][..], &["kind"], &["metadata", "name"]] {
+            let field = path.iter().try_fold(&value, |v, key| v.get(key));
+            if field.is_none_or(|f| f.as_str().is_none_or(|s| s.is_empty())) {
+                failure(
+                    child,
+                    key_line(&child.content, &path[..path.len() - 1]),
+                    format!("Missing required field '{}'", path.join(".")),
+                );
+                missing = true;
+            }
+        }
+        if missing {
+            continue;
+        }
+
+        let object: DynamicObject = match serde_json::from_value(value) {
+            Ok(object) => object,
+            Err(e) => {
+                failure(child, 1, format!("Not a valid Kubernetes object: {}", e));
+                continue;
+            }
+        };
+        let kind = object
+            .types
+            .as_ref()
+            .map(|t| t.kind.clone())
+            .unwrap_or_default();
+        let name = object.metadata.name.clone().unwrap_or_default();
+
+        if let Some(namespace) = &object.metadata.namespace {
+            if *namespace != config.namespace {
+                failure(
+                    child,
+                    key_line(&child.content, &["metadata", "namespace"]),
+                    format!(
+                        "Namespace '{}' does not match the config's namespace '{}'",
+                        namespace, config.namespace
+                    ),
+                );
+            }
+        }
+
+        match seen.get(&(kind.clone(), name.clone())) {
+            Some(first) => failure(
+                child,
+                key_line(&child.content, &["metadata", "name"]),
+                format!(
+                    "Duplicate {} '{}', already defined in {}",
+                    kind, name, first
+                ),
+            ),
+            None => {
+                seen.insert((kind, name), &child.path);
+            }
+        }
+    }
+}
+
+/// Publish `issues` as a completed check run on `sha`.
+pub async fn publish_check_run(
+    octocrabs: &Octocrabs,
+    repository: impl IRepo,
+    sha: &str,
+    config_count: usize,
+    issues: &[ValidationIssue],
+) -> AppResult<()> {
+    let crab = octocrabs.crab_for(&repository).await.ok_or_else(|| {
+        AppError::NotFound(format!(
+            "No octocrab found for repo {}/{}",
+            repository.owner(),
+            repository.repo()
+        ))
+    })?;
+    let checks = crab.checks(repository.owner(), repository.repo());
+
+    let failures = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Failure)
+        .count();
+    let warnings = issues.len() - failures;
+    let title = if failures > 0 {
+        format!("{} error(s) in .deploy", failures)
+    } else {
+        format!("{} deploy config(s) valid", config_count)
+    };
+    let summary = format!(
+        "Checked {} deploy config(s): {} error(s), {} warning(s).",
+        config_count, failures, warnings
+    );
+    let output = |annotations: Vec<CheckRunOutputAnnotation>| CheckRunOutput {
+        title: title.clone(),
+        summary: summary.clone(),
+        text: None,
+        annotations,
+        images: vec![],
+    };
+
+    let mut batches = issues
+        .chunks(MAX_ANNOTATIONS_PER_REQUEST)
+        .map(|batch| batch.iter().map(annotation).collect::<Vec<_>>());
+
+    let check_run = checks
+        .create_check_run(CHECK_RUN_NAME, sha)
+        .status(CheckRunStatus::Completed)
+        .conclusion(if failures > 0 {
+            CheckRunConclusion::Failure
+        } else {
+            CheckRunConclusion::Success
+        })
+        .output(output(batches.next().unwrap_or_default()))
+        .send()
+        .await
+        .map_err(|e| octocrabs.invalidate_on_error(&repository, e))?;
+
+    // Annotations beyond the first batch are appended by updating the run.
+    for batch in batches {
+        checks
+            .update_check_run(check_run.id)
+            .output(output(batch))
+            .send()
+            .await
+            .map_err(|e| octocrabs.invalidate_on_error(&repository, e))?;
+    }
+
+    Ok(())
+}
+
+fn annotation(issue: &ValidationIssue) -> CheckRunOutputAnnotation {
+    CheckRunOutputAnnotation {
+        path: issue.path.clone(),
+        start_line: issue.line,
+        end_line: issue.line,
+        start_column: None,
+        end_column: None,
+        annotation_level: match issue.severity {
+            Severity::Warning => CheckRunOutputAnnotationLevel::Warning,
+            Severity::Failure => CheckRunOutputAnnotationLevel::Failure,
+        },
+        message: issue.message.clone(),
+        title: None,
+        raw_details: None,
+    }
+}
+
+fn yaml_error_line(e: &serde_yaml::Error) -> u32 {
+    e.location().map(|l| l.line() as u32).unwrap_or(1).max(1)
+}
+
+/// Best-effort 1-based line of a nested mapping key, falling back to the
+/// deepest parent found (or line 1). An empty path means line 1.
+fn key_line(content: &str, path: &[&str]) -> u32 {
+    let mut line_number = 1;
+    let mut min_indent = 0;
+    let mut lines = content.lines().enumerate();
+
+    for (depth, key) in path.iter().enumerate() {
+        let prefix = format!("{}:", key);
+        let found = lines.find(|(_, line)| {
+            let indent = line.len() - line.trim_start().len();
+            let matches_depth = if depth == 0 {
+                indent == 0
+            } else {
+                indent >= min_indent
+            };
+            matches_depth && line.trim_start().starts_with(&prefix)
+        });
+        match found {
+            Some((idx, line)) => {
+                line_number = idx as u32 + 1;
+                min_indent = line.len() - line.trim_start().len() + 1;
+            }
+            None => break,
+        }
+    }
+
+    line_number
+}
+
+fn is_dns_label(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 63
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !value.starts_with('-')
+        && !value.ends_with('-')
+}
+
+fn is_dns_subdomain(value: &str) -> bool {
+    !value.is_empty() && value.len() <= 253 && value.split('.').all(is_dns_label)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, content: &str) -> SourceFile {
+        SourceFile {
+            path: path.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn config(name: &str, config: &str, children: Vec<SourceFile>) -> DeployConfigFiles {
+        DeployConfigFiles {
+            name: name.to_string(),
+            config: file(&format!(".deploy/{}.yaml", name), config),
+            children,
+        }
+    }
+
+    fn failures(issues: Vec<ValidationIssue>) -> Vec<ValidationIssue> {
+        issues
+            .into_iter()
+            .filter(|i| i.severity == Severity::Failure)
+            .collect()
+    }
+
+    const VALID_CONFIG: &str = "team: platform\nkind: service\nnamespace: apps\n";
+
+    fn deployment(name: &str, extra: &str) -> String {
+        format!(
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata:\n  name: {}\n{}spec: {{}}\n",
+            name, extra
+        )
+    }
+
+    #[test]
+    fn valid_config_has_no_issues() {
+        let files = vec![config(
+            "app",
+            "team: platform\nkind: service\nnamespace: apps\nartifactRepo:\n  owner: o\n  repo: r\n",
+            vec![file(".deploy/app/deployment.yaml", &deployment("app", ""))],
+        )];
+        let context = ValidationContext {
+            known_repos: HashSet::from([("o".to_string(), "r".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(validate_deploy_config_files(&files, &context), vec![]);
+    }
+
+    #[test]
+    fn reports_yaml_and_shape_errors_with_lines() {
+        let files = vec![
+            config("broken", "team: platform\nkind: [unclosed\n", vec![]),
+            config("missing", "team: platform\nkind: service\n", vec![]),
+        ];
+        let issues = failures(validate_deploy_config_files(
+            &files,
+            &ValidationContext::default(),
+        ));
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].path, ".deploy/broken.yaml");
+        assert!(issues[0].line >= 2);
+        assert_eq!(issues[1].path, ".deploy/missing.yaml");
+        assert!(issues[1].message.contains("namespace"));
+    }
+
+    #[test]
+    fn reports_unknown_artifact_repo_and_bad_namespace() {
+        let content = "team: platform\nkind: service\nnamespace: Apps_1\nartifactRepo:\n  owner: o\n  repo: r\n";
+        let issues = failures(validate_deploy_config_files(
+            &[config("app", content, vec![])],
+            &ValidationContext::default(),
+        ));
+        let lines: Vec<u32> = issues.iter().map(|i| i.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+        assert!(issues[1].message.contains("o/r"));
+    }
+
+    #[test]
+    fn reports_child_object_problems() {
+        let files = vec![config(
+            "app",
+            VALID_CONFIG,
+            vec![
+                file(".deploy/app/a.yaml", &deployment("web", "")),
+                file(".deploy/app/b.yaml", &deployment("web", "")),
+                file(
+                    ".deploy/app/c.yaml",
+                    &deployment("other", "  namespace: elsewhere\n"),
+                ),
+                file(
+                    ".deploy/app/d.yaml",
+                    "apiVersion: v1\nmetadata:\n  name: x\n",
+                ),
+            ],
+        )];
+        let issues = validate_deploy_config_files(&files, &ValidationContext::default());
+        let summary: Vec<(&str, u32)> = issues.iter().map(|i| (i.path.as_str(), i.line)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (".deploy/app/b.yaml", 4),
+                (".deploy/app/c.yaml", 5),
+                (".deploy/app/d.yaml", 1),
+            ]
+        );
+        assert!(issues[0].message.contains("Duplicate Deployment 'web'"));
+        assert!(issues[2].message.contains("'kind'"));
+    }
+
+    #[test]
+    fn reports_names_owned_by_other_repos() {
+        let context = ValidationContext {
+            foreign_config_names: HashSet::from(["app".to_string()]),
+            ..Default::default()
+        };
+        let issues = failures(validate_deploy_config_files(
+            &[config("app", VALID_CONFIG, vec![])],
+            &context,
+        ));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("another config repository"));
+    }
+
+    #[test]
+    fn warns_about_configs_without_manifests() {
+        let issues = validate_deploy_config_files(
+            &[config("app", VALID_CONFIG, vec![])],
+            &ValidationContext::default(),
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+    }
+}
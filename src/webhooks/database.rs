@@ -12,10 +12,17 @@ use crate::{
         git_branch::{GitBranch, GitBranchEgg},
         git_commit::{GitCommit, GitCommitEgg},
         git_commit_build::GitCommitBuild,
+        git_pull_request::GitPullRequest,
         git_repo::GitRepo,
+        workflow_job::{WorkflowJob, WorkflowStep},
+        workflow_run::WorkflowRun,
     },
     webhooks::{
-        models::{CheckRunEvent, DeleteEvent, PushEvent},
+        config_validation::CHECK_RUN_NAME,
+        models::{
+            CheckRunEvent, DeleteEvent, PullRequestEvent, PushEvent, StatusEvent, WorkflowJobEvent,
+            WorkflowRunEvent,
+        },
         util::{extract_branch_name, rfc3339_to_millis},
         WebhookHandler,
     },
@@ -94,7 +101,8 @@ impl WebhookHandler for DatabaseHandler {
                 .list_commits()
                 .sha(commit.sha.clone())
                 .send()
-                .await?
+                .await
+                .map_err(|e| self.octocrabs.invalidate_on_error(&repo, e))?
                 .items
                 .first()
                 .iter()
@@ -122,6 +130,10 @@ impl WebhookHandler for DatabaseHandler {
             .context("Failed to get database connection")?;
 
         let run = &payload.check_run;
+        // Our own validation of `.deploy` isn't a build of the commit.
+        if run.name == CHECK_RUN_NAME {
+            return Ok(());
+        }
 
         // We track build state at the check-run level (the actual unit of work),
         // not the check-suite level. GitHub auto-creates a suite per installed
@@ -161,6 +173,78 @@ impl WebhookHandler for DatabaseHandler {
         };
         GitCommitBuild::upsert(&commit_build, &conn).context("Error upserting commit build")?;
 
+        crate::events::publish(crate::events::Event::Build {
+            repo_id: repo.id,
+            commit_sha: head_commit.sha,
+            check_name: commit_build.check_name,
+            status: commit_build.status,
+            url: commit_build.url,
+        });
+
+        Ok(())
+    }
+
+    async fn handle_status(&self, payload: StatusEvent) -> Result<(), anyhow::Error> {
+        log::debug!("Received status event:\n{:#?}", payload);
+
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get database connection")?;
+
+        let repo: GitRepo = payload.repository.clone().into();
+        repo.upsert(&conn).context("Error upserting repository")?;
+
+        let commit = GitCommit::get_by_sha(&payload.sha, repo.id, &conn)
+            .context("Error getting commit")?
+            .ok_or(anyhow::Error::msg("Commit not found"))?;
+
+        // Statuses carry no timing of their own: a context's first pending
+        // status marks the start (kept by the upsert), a final one the end.
+        let build_status = BuildStatus::from_commit_state(&payload.state);
+        let reported_at = rfc3339_to_millis(Some(&payload.created_at));
+        let pending = build_status == BuildStatus::Pending;
+        let commit_build = GitCommitBuild {
+            repo_id: repo.id,
+            commit_id: commit.id,
+            check_name: GitCommitBuild::status_check_name(&payload.context),
+            status: build_status.into(),
+            url: payload
+                .target_url
+                .clone()
+                .filter(|u| !u.is_empty())
+                .unwrap_or_else(|| {
+                    format!(
+                        "https://github.com/{}/{}/commit/{}",
+                        repo.owner_name, repo.name, commit.sha
+                    )
+                }),
+            start_time: if pending { reported_at } else { None },
+            settle_time: if pending { None } else { reported_at },
+            app_id: None,
+        };
+        // Deliveries can arrive out of order; an older status for the same
+        // context must not replace a newer one.
+        if !GitCommitBuild::upsert_status(&commit_build, reported_at, &conn)
+            .context("Error upserting commit build")?
+        {
+            log::debug!(
+                "Ignoring stale status {} for {} at {}",
+                payload.context,
+                commit.sha,
+                payload.created_at
+            );
+            return Ok(());
+        }
+
+        crate::events::publish(crate::events::Event::Build {
+            repo_id: repo.id,
+            commit_sha: commit.sha,
+            check_name: commit_build.check_name,
+            status: commit_build.status,
+            url: commit_build.url,
+        });
+
         Ok(())
     }
 
@@ -194,8 +278,315 @@ impl WebhookHandler for DatabaseHandler {
         Ok(())
     }
 
+    async fn handle_pull_request(&self, payload: PullRequestEvent) -> Result<(), anyhow::Error> {
+        log::debug!(
+            "Received pull request event for {}/{}#{}: {}",
+            payload.repository.owner.login,
+            payload.repository.name,
+            payload.number,
+            payload.action
+        );
+
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get database connection")?;
+
+        let repo: GitRepo = payload.repository.clone().into();
+        repo.upsert(&conn).context("Error upserting repository")?;
+
+        let pr = &payload.pull_request;
+        let pull_request = GitPullRequest {
+            id: pr.id,
+            repo_id: repo.id,
+            number: pr.number,
+            title: pr.title.clone(),
+            author: pr.user.login.clone(),
+            state: pr.state.clone(),
+            draft: pr.draft,
+            merged: pr.merged.unwrap_or(pr.merged_at.is_some()),
+            head_branch: pr.head.r#ref.clone(),
+            head_sha: pr.head.sha.clone(),
+            head_repo_id: pr.head.repo.as_ref().map(|r| r.id),
+            base_branch: pr.base.r#ref.clone(),
+            html_url: pr.html_url.clone(),
+            updated_at: DateTime::parse_from_rfc3339(&pr.updated_at)
+                .context("Error parsing pull request update time")?
+                .timestamp_millis(),
+        };
+        pull_request
+            .upsert(&conn)
+            .context("Error upserting pull request")?;
+
+        Ok(())
+    }
+
+    async fn handle_workflow_run(&self, payload: WorkflowRunEvent) -> Result<(), anyhow::Error> {
+        log::debug!("Received workflow run event:\n{:#?}", payload);
+
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get database connection")?;
+
+        let repo: GitRepo = payload.repository.clone().into();
+        repo.upsert(&conn).context("Error upserting repository")?;
+
+        let run = &payload.workflow_run;
+        let workflow_run = WorkflowRun {
+            id: run.id,
+            repo_id: repo.id,
+            head_sha: run.head_sha.clone(),
+            head_branch: run.head_branch.clone(),
+            name: run.name.clone().unwrap_or_default(),
+            run_number: run.run_number,
+            run_attempt: run.run_attempt,
+            event: run.event.clone(),
+            status: run.status.clone(),
+            conclusion: run.conclusion.clone(),
+            html_url: run.html_url.clone(),
+            created_at: rfc3339_to_millis(Some(&run.created_at))
+                .context("Error parsing workflow run creation time")?,
+            run_started_at: rfc3339_to_millis(run.run_started_at.as_deref()),
+            updated_at: rfc3339_to_millis(Some(&run.updated_at))
+                .context("Error parsing workflow run update time")?,
+        };
+        workflow_run
+            .upsert(&conn)
+            .context("Error upserting workflow run")?;
+
+        Ok(())
+    }
+
+    async fn handle_workflow_job(&self, payload: WorkflowJobEvent) -> Result<(), anyhow::Error> {
+        log::debug!("Received workflow job event:\n{:#?}", payload);
+
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get database connection")?;
+
+        let repo: GitRepo = payload.repository.clone().into();
+        repo.upsert(&conn).context("Error upserting repository")?;
+
+        let job = &payload.workflow_job;
+        let workflow_job = WorkflowJob {
+            id: job.id,
+            run_id: job.run_id,
+            run_attempt: job.run_attempt,
+            repo_id: repo.id,
+            head_sha: job.head_sha.clone(),
+            name: job.name.clone(),
+            status: job.status.clone(),
+            conclusion: job.conclusion.clone(),
+            runner_name: job.runner_name.clone().filter(|r| !r.is_empty()),
+            html_url: job.html_url.clone(),
+            created_at: rfc3339_to_millis(Some(&job.created_at))
+                .context("Error parsing workflow job creation time")?,
+            started_at: rfc3339_to_millis(job.started_at.as_deref()),
+            completed_at: rfc3339_to_millis(job.completed_at.as_deref()),
+            steps: job
+                .steps
+                .iter()
+                .map(|step| WorkflowStep {
+                    number: step.number,
+                    name: step.name.clone(),
+                    status: step.status.clone(),
+                    conclusion: step.conclusion.clone(),
+                    started_at: rfc3339_to_millis(step.started_at.as_deref()),
+                    completed_at: rfc3339_to_millis(step.completed_at.as_deref()),
+                })
+                .collect(),
+        };
+        workflow_job
+            .upsert(&conn)
+            .context("Error upserting workflow job")?;
+
+        Ok(())
+    }
+
     async fn handle_unknown(&self, event_type: &str) -> Result<(), anyhow::Error> {
         log::debug!("Received unknown event: {}", event_type);
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn repository(id: u64, owner: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "name": "api",
+            "owner": { "login": owner },
+            "private": false,
+            "language": "Rust",
+            "default_branch": "main",
+        })
+    }
+
+    fn pull_request(id: u64, state: &str, head_repo_id: u64, updated_at: &str) -> PullRequestEvent {
+        serde_json::from_value(serde_json::json!({
+            "action": "synchronize",
+            "number": id,
+            "pull_request": {
+                "id": id * 100,
+                "number": id,
+                "title": "Add retries",
+                "user": { "login": "octocat" },
+                "state": state,
+                "draft": false,
+                "merged_at": null,
+                "html_url": format!("https://github.com/acme/api/pull/{}", id),
+                "head": {
+                    "ref": "retries",
+                    "sha": format!("{:040}", id),
+                    "repo": repository(head_repo_id, "acme"),
+                },
+                "base": { "ref": "main", "sha": "0".repeat(40), "repo": repository(1, "acme") },
+                "updated_at": updated_at,
+            },
+            "repository": repository(1, "acme"),
+        }))
+        .unwrap()
+    }
+
+    fn status(context: &str, state: &str, created_at: &str) -> StatusEvent {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "sha": "a".repeat(40),
+            "context": context,
+            "state": state,
+            "target_url": format!("https://ci.example.com/{}", context),
+            "description": null,
+            "created_at": created_at,
+            "updated_at": created_at,
+            "repository": repository(1, "acme"),
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn merges_statuses_per_context() {
+        let pool = crate::db::test_pool();
+        let handler = DatabaseHandler::new(pool.clone(), Octocrabs::default());
+        let conn = pool.get().unwrap();
+        GitRepo::from(
+            serde_json::from_value::<crate::webhooks::models::Repository>(repository(1, "acme"))
+                .unwrap(),
+        )
+        .upsert(&conn)
+        .unwrap();
+        let commit = GitCommit::upsert(
+            &GitCommitEgg {
+                sha: "a".repeat(40),
+                repo_id: 1,
+                message: "Add retries".to_string(),
+                timestamp: 0,
+                author: "octocat".to_string(),
+                committer: "octocat".to_string(),
+            },
+            &conn,
+        )
+        .unwrap();
+        drop(conn);
+
+        handler
+            .handle_status(status("ci/build", "pending", "2024-05-01T10:00:00Z"))
+            .await
+            .unwrap();
+        handler
+            .handle_status(status("ci/lint", "success", "2024-05-01T10:01:00Z"))
+            .await
+            .unwrap();
+        let conn = pool.get().unwrap();
+        let aggregate = commit.get_build_status(&conn).unwrap().unwrap();
+        assert_eq!(aggregate.status, "Pending");
+        drop(conn);
+
+        handler
+            .handle_status(status("ci/build", "error", "2024-05-01T10:05:00Z"))
+            .await
+            .unwrap();
+        let conn = pool.get().unwrap();
+        let builds = GitCommitBuild::get_all_by_commit_id(&commit.id, &1, &conn).unwrap();
+        assert_eq!(builds.len(), 2);
+        let build = builds
+            .iter()
+            .find(|b| b.check_name == "status-ci/build")
+            .unwrap();
+        assert_eq!(build.status, "Failure");
+        assert_eq!(build.url, "https://ci.example.com/ci/build");
+        assert_eq!(
+            build.settle_time.unwrap() - build.start_time.unwrap(),
+            5 * 60 * 1000
+        );
+        assert_eq!(
+            commit.get_build_status(&conn).unwrap().unwrap().status,
+            "Failure"
+        );
+        drop(conn);
+
+        // A late delivery of an earlier status doesn't replace the newer one.
+        handler
+            .handle_status(status("ci/build", "success", "2024-05-01T10:03:00Z"))
+            .await
+            .unwrap();
+        let conn = pool.get().unwrap();
+        assert_eq!(
+            commit.get_build_status(&conn).unwrap().unwrap().status,
+            "Failure"
+        );
+    }
+
+    #[tokio::test]
+    async fn links_pull_requests_to_branches() {
+        let pool = crate::db::test_pool();
+        let handler = DatabaseHandler::new(pool.clone(), Octocrabs::default());
+
+        // A fork's branch of the same name, then ours.
+        handler
+            .handle_pull_request(pull_request(6, "open", 2, "2024-05-01T10:00:00Z"))
+            .await
+            .unwrap();
+        handler
+            .handle_pull_request(pull_request(7, "open", 1, "2024-05-01T11:00:00Z"))
+            .await
+            .unwrap();
+        let branch = GitBranchEgg {
+            name: "retries".to_string(),
+            head_commit_sha: format!("{:040}", 7),
+            repo_id: 1,
+            active: true,
+        }
+        .upsert(&pool.get().unwrap())
+        .unwrap();
+
+        let linked = GitPullRequest::get_for_branch(&branch, &pool.get().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(linked.number, 7);
+        assert_eq!(linked.display_state(), "open");
+
+        // Closed, then a replay of the older event.
+        handler
+            .handle_pull_request(pull_request(7, "closed", 1, "2024-05-01T12:00:00Z"))
+            .await
+            .unwrap();
+        handler
+            .handle_pull_request(pull_request(7, "open", 1, "2024-05-01T11:00:00Z"))
+            .await
+            .unwrap();
+        let linked = GitPullRequest::get_for_branch(&branch, &pool.get().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(linked.display_state(), "closed");
+
+        // The batched lookup picks the same one, and still skips the fork's.
+        let batched = GitPullRequest::get_for_branches([&branch], &pool.get().unwrap()).unwrap();
+        assert_eq!(batched.len(), 1);
+        assert_eq!(batched.get(&(1, "retries".to_string())), Some(&linked));
+    }
+}
@@ -0,0 +1,132 @@
+//! `POST /api/github/webhook`: GitHub webhook deliveries sent straight to this
+//! server, as an alternative or in addition to the websocket proxy.
+
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, HttpResponse, Resource};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::format_anyhow_chain;
+use crate::prelude::*;
+use crate::webhooks::manager::{EventSource, WebhookManager};
+use crate::webhooks::models::WebhookEvent;
+
+pub const WEBHOOK_PATH: &str = "/api/github/webhook";
+
+/// GitHub sends payloads of up to 25 MB.
+const MAX_PAYLOAD_BYTES: usize = 25 * 1024 * 1024;
+
+/// The webhook route, with a payload limit large enough for GitHub.
+pub fn resource() -> Resource {
+    web::resource(WEBHOOK_PATH)
+        .app_data(web::PayloadConfig::new(MAX_PAYLOAD_BYTES))
+        .route(web::post().to(github_webhook))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Whether `signature` (`sha256=<hex>`) is the HMAC-SHA256 of `body` with
+/// `secret`, compared in constant time.
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature.strip_prefix("sha256=").and_then(decode_hex) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|h| h.to_str().ok())
+}
+
+/// Verify a delivery and hand it to the same pipeline as the websocket. The
+/// handlers run from the queue after responding, as GitHub gives up on slow
+/// endpoints.
+async fn github_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    manager: web::Data<Arc<WebhookManager>>,
+) -> AppResult<HttpResponse> {
+    let Some(secret) = manager.webhook_secret() else {
+        return Err(AppError::NotFound(
+            "GitHub webhook endpoint (set GITHUB_WEBHOOK_SECRET)".to_string(),
+        ));
+    };
+    let signature = header(&req, "X-Hub-Signature-256").unwrap_or_default();
+    if !verify_signature(secret, &body, signature) {
+        log::warn!(
+            "Rejected GitHub webhook delivery {} with an invalid signature",
+            header(&req, "X-GitHub-Delivery").unwrap_or("without id")
+        );
+        return Err(AppError::Unauthorized("Invalid signature".to_string()));
+    }
+
+    let event_type = header(&req, "X-GitHub-Event")
+        .ok_or_else(|| AppError::InvalidInput("Missing X-GitHub-Event header".to_string()))?
+        .to_string();
+    if event_type == "ping" {
+        return Ok(HttpResponse::Ok().body("pong"));
+    }
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid JSON payload: {}", e)))?;
+    let event = WebhookEvent {
+        event_type,
+        payload,
+        delivery_id: header(&req, "X-GitHub-Delivery").map(str::to_string),
+    };
+
+    // Stored before answering, so GitHub sees a failed delivery if that fails.
+    manager
+        .receive(event, EventSource::Http)
+        .await
+        .map_err(|e| {
+            log::error!("Error storing event: {}", format_anyhow_chain(&e));
+            AppError::Internal("Failed to store the event".to_string())
+        })?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_github_signatures() {
+        // The example from GitHub's webhook documentation.
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_signature(
+            "It's a Secret to Everybody",
+            b"Hello, World!",
+            signature
+        ));
+        assert!(!verify_signature(
+            "It's a Secret to Everybody",
+            b"Hello, World?",
+            signature
+        ));
+        assert!(!verify_signature("wrong", b"Hello, World!", signature));
+        assert!(!verify_signature(
+            "It's a Secret to Everybody",
+            b"Hello, World!",
+            "sha1=757107ea"
+        ));
+        assert!(!verify_signature(
+            "It's a Secret to Everybody",
+            b"",
+            "sha256=zz"
+        ));
+    }
+}
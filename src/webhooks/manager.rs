@@ -1,48 +1,179 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::RwLock;
 
+use crate::db::incoming_event::{HandlerOutcome, IncomingEvent, IncomingEventEgg};
 use crate::error::format_anyhow_chain;
 use crate::prelude::*;
-use crate::webhooks::models::CheckRunEvent;
-use crate::webhooks::models::CheckSuiteEvent;
-use crate::webhooks::models::DeleteEvent;
-use crate::webhooks::models::PushEvent;
+use crate::webhooks::catchup::{CatchUp, MAX_CATCHUP_WINDOW_MS};
 use crate::webhooks::models::WebhookEvent;
+use crate::webhooks::queue::{Dispatcher, Job, Processing, RetryPolicy, WorkQueue};
 use crate::webhooks::WebhookHandler;
 use futures_util::SinkExt;
 use futures_util::StreamExt;
+use opentelemetry::KeyValue;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use sha2::{Digest, Sha256};
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::{
     connect_async,
     tungstenite::{client::IntoClientRequest, http::HeaderValue},
 };
+/// Stored events are kept for this many days unless
+/// `WEBHOOK_EVENT_RETENTION_DAYS` says otherwise.
+const DEFAULT_RETENTION_DAYS: i64 = 14;
+
+/// At most this many stored events are kept, however recent.
+const MAX_STORED_EVENTS: u32 = 50_000;
+
+/// The transport an event arrived through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventSource {
+    Websocket,
+    Http,
+    /// Recovered after a websocket gap, see [`CatchUp`].
+    Catchup,
+}
+
+impl EventSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventSource::Websocket => "websocket",
+            EventSource::Http => "http",
+            EventSource::Catchup => "catchup",
+        }
+    }
+}
+
+/// Receives GitHub events through the websocket proxy at `WEBSOCKET_URL`,
+/// the `/api/github/webhook` endpoint, or both, stores each delivery and
+/// queues it for the handlers once.
 pub struct WebhookManager {
-    handlers: Vec<Arc<dyn WebhookHandler + Send + Sync>>,
-    websocket_url: String,
-    client_secret: String,
+    pool: Pool<SqliteConnectionManager>,
+    dispatcher: Dispatcher,
+    queue: WorkQueue,
+    /// The websocket proxy and its bearer token, if configured.
+    websocket: Option<(String, String)>,
+    /// Secret for `X-Hub-Signature-256` on the HTTP endpoint, if enabled.
+    webhook_secret: Option<String>,
+    retention_days: i64,
+    /// Recovers events missed while the websocket was down, if enabled.
+    catchup: Option<CatchUp>,
+    /// When the manager was created (ms). Events stored as pending before
+    /// then were left unprocessed by an earlier run.
+    started_at: i64,
 }
 
 impl WebhookManager {
-    pub fn new(websocket_url: String, client_secret: String) -> Self {
+    pub fn new(
+        pool: Pool<SqliteConnectionManager>,
+        websocket: Option<(String, String)>,
+        webhook_secret: Option<String>,
+    ) -> Self {
         Self {
-            handlers: Vec::new(),
-            websocket_url,
-            client_secret,
+            pool,
+            dispatcher: Dispatcher::default(),
+            queue: WorkQueue::default(),
+            websocket,
+            webhook_secret,
+            retention_days: DEFAULT_RETENTION_DAYS,
+            catchup: None,
+            started_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// From `WEBSOCKET_URL` and `CLIENT_SECRET` for the websocket proxy,
+    /// `GITHUB_WEBHOOK_SECRET` for the HTTP endpoint,
+    /// `WEBHOOK_EVENT_RETENTION_DAYS` for how long events are stored and
+    /// `WEBHOOK_HANDLER_TIMEOUT_SECS` and `WEBHOOK_HANDLER_ATTEMPTS` for the
+    /// handlers' [`RetryPolicy`].
+    pub fn from_env(pool: Pool<SqliteConnectionManager>) -> AppResult<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let websocket = match (var("WEBSOCKET_URL"), var("CLIENT_SECRET")) {
+            (Some(url), Some(secret)) => Some((url, secret)),
+            (Some(_), None) => {
+                return Err(AppError::Config(
+                    "CLIENT_SECRET must be set with WEBSOCKET_URL".to_string(),
+                ))
+            }
+            (None, _) => None,
+        };
+        let webhook_secret = var("GITHUB_WEBHOOK_SECRET");
+        if websocket.is_none() && webhook_secret.is_none() {
+            return Err(AppError::Config(
+                "Set WEBSOCKET_URL and CLIENT_SECRET, or GITHUB_WEBHOOK_SECRET, to receive GitHub events".to_string(),
+            ));
+        }
+        let mut manager = Self::new(pool, websocket, webhook_secret);
+        let number = |name: &str| -> AppResult<Option<u64>> {
+            var(name)
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| AppError::Config(format!("Invalid {}: {}", name, v)))
+                })
+                .transpose()
+        };
+        if let Some(days) = number("WEBHOOK_EVENT_RETENTION_DAYS")? {
+            manager.retention_days = days as i64;
         }
+        let mut retry = RetryPolicy::default();
+        if let Some(secs) = number("WEBHOOK_HANDLER_TIMEOUT_SECS")? {
+            retry.timeout = std::time::Duration::from_secs(secs);
+        }
+        if let Some(attempts) = number("WEBHOOK_HANDLER_ATTEMPTS")? {
+            retry.attempts = attempts.max(1) as u32;
+        }
+        manager.set_retry_policy(retry);
+        Ok(manager)
+    }
+
+    pub fn webhook_secret(&self) -> Option<&str> {
+        self.webhook_secret.as_deref()
+    }
+
+    pub fn set_catchup(&mut self, catchup: CatchUp) {
+        self.catchup = Some(catchup);
     }
 
     pub fn add_handler(&mut self, handler: impl WebhookHandler + Send + Sync + 'static) {
-        self.handlers.push(Arc::new(handler));
+        self.dispatcher.add_handler(handler);
     }
 
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.dispatcher.set_retry_policy(retry);
+    }
+
+    /// Receive events from the websocket proxy until the process exits. Without
+    /// a proxy configured this never returns, leaving the HTTP endpoint.
     pub async fn start(&self) -> Result<(), anyhow::Error> {
+        match self.requeue_pending() {
+            Ok(0) => {}
+            Ok(requeued) => log::info!("Requeued {} unprocessed webhook events", requeued),
+            Err(e) => log::error!("Failed to requeue unprocessed webhook events: {}", e),
+        }
+        let Some((websocket_url, client_secret)) = &self.websocket else {
+            log::info!("WEBSOCKET_URL not set, receiving GitHub events over HTTP only");
+            std::future::pending::<()>().await;
+            return Ok(());
+        };
+        // Events may have been missed since the last one stored before startup.
+        let mut disconnected_at = self
+            .pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|conn| IncomingEvent::latest_received_at(&conn))
+            .unwrap_or_else(|e| {
+                log::error!("Failed to get the latest stored event: {}", e);
+                None
+            });
         loop {
             log::info!(
                 "Attempting to connect to webhook WebSocket at {}",
-                self.websocket_url
+                websocket_url
             );
 
-            let mut request = match self.websocket_url.clone().into_client_request() {
+            let mut request = match websocket_url.clone().into_client_request() {
                 Ok(request) => request,
                 Err(e) => {
                     log::error!("Failed to create WebSocket request: {}", e);
@@ -54,7 +185,7 @@ impl WebhookManager {
 
             request.headers_mut().insert(
                 "Authorization",
-                match format!("Bearer {}", self.client_secret).parse::<HeaderValue>() {
+                match format!("Bearer {}", client_secret).parse::<HeaderValue>() {
                     Ok(header) => header,
                     Err(e) => {
                         log::error!("Failed to create Authorization header: {}", e);
@@ -70,6 +201,8 @@ impl WebhookManager {
             match connect_result {
                 Ok((ws_stream, _)) => {
                     log::info!("Connection to webhooks websocket established");
+                    let gap_since = disconnected_at.take();
+                    let caught_up = AtomicBool::new(false);
 
                     let (mut write, mut read) = ws_stream.split();
 
@@ -109,7 +242,8 @@ impl WebhookManager {
                                         if event.event_type == "conn_ping" {
                                             log::debug!("Got conn_ping reply");
                                         } else {
-                                            match self.process_event(event).await {
+                                            match self.receive(event, EventSource::Websocket).await
+                                            {
                                                 Ok(_) => {}
                                                 Err(e) => {
                                                     log::error!(
@@ -144,13 +278,28 @@ impl WebhookManager {
                         }
                     };
 
+                    // Runs alongside the new connection, so live events aren't held up.
+                    let catchup_closure = async || {
+                        if let (Some(catchup), Some(since)) = (&self.catchup, gap_since) {
+                            self.catch_up(catchup, since).await;
+                        }
+                        caught_up.store(true, Ordering::Relaxed);
+                        std::future::pending::<()>().await
+                    };
+
                     tokio::select! {
                         _ = ping_closure() => {}
                         _ = message_closure() => {}
                         _ = watchdog_closure() => {}
+                        _ = catchup_closure() => {}
                     }
 
                     log::error!("WebSocket connection closed, will attempt to reconnect...");
+                    // An interrupted catch-up is resumed from the same point.
+                    disconnected_at = match gap_since {
+                        Some(since) if !caught_up.load(Ordering::Relaxed) => Some(since),
+                        _ => Some(chrono::Utc::now().timestamp_millis()),
+                    };
                 }
                 Err(e) => {
                     log::error!("Failed to connect to WebSocket: {}", e);
@@ -163,80 +312,377 @@ impl WebhookManager {
         }
     }
 
-    async fn process_event(&self, event: WebhookEvent) -> Result<(), anyhow::Error> {
-        log::debug!("Received event: {}", event.event_type);
+    /// Queue the events an earlier run stored but didn't get to process.
+    /// Returns how many were queued.
+    pub fn requeue_pending(&self) -> AppResult<usize> {
+        let pending = IncomingEvent::get_pending(self.started_at, &self.pool.get()?)?;
+        let mut requeued = 0;
+        for event in pending {
+            let (job, _) = Job::new(event.id, event.event_type, event.payload, false);
+            if self.queue.enqueue_once(job, &self.dispatcher, &self.pool) {
+                requeued += 1;
+            }
+        }
+        Ok(requeued)
+    }
 
-        match event.event_type.as_str() {
-            "push" => match serde_json::from_value::<PushEvent>(event.payload.clone()) {
-                Ok(payload) => {
-                    for handler in &self.handlers {
-                        let handler_result = handler.handle_push(payload.clone()).await;
-                        if let Err(e) = handler_result {
-                            log::error!("Error handling push:\n{}", format_anyhow_chain(&e));
-                        }
-                    }
-                    Ok(())
-                }
-                Err(e) => Err(anyhow::Error::msg(format!(
-                    "Error parsing push event: {}",
-                    e
-                ))),
-            },
-            "check_run" => match serde_json::from_value::<CheckRunEvent>(event.payload) {
-                Ok(payload) => {
-                    for handler in &self.handlers {
-                        let handler_result = handler.handle_check_run(payload.clone()).await;
-                        if let Err(e) = handler_result {
-                            log::error!("Error handling check run:\n{}", format_anyhow_chain(&e));
-                        }
-                    }
-                    Ok(())
-                }
-                Err(e) => Err(anyhow::Error::msg(format!(
-                    "Error parsing check run event: {}",
-                    e
-                ))),
-            },
-            "check_suite" => match serde_json::from_value::<CheckSuiteEvent>(event.payload) {
-                Ok(payload) => {
-                    for handler in &self.handlers {
-                        let handler_result = handler.handle_check_suite(payload.clone()).await;
-                        if let Err(e) = handler_result {
-                            log::error!("Error handling check suite:\n{}", format_anyhow_chain(&e));
-                        }
-                    }
-                    Ok(())
-                }
-                Err(e) => Err(anyhow::Error::msg(format!(
-                    "Error parsing check suite event: {}",
-                    e
-                ))),
-            },
-            "delete" => match serde_json::from_value::<DeleteEvent>(event.payload) {
-                Ok(payload) => {
-                    for handler in &self.handlers {
-                        let handler_result = handler.handle_delete(payload.clone()).await;
-                        if let Err(e) = handler_result {
-                            log::error!("Error handling delete:\n{}", format_anyhow_chain(&e));
-                        }
-                    }
-                    Ok(())
+    /// Store an event and queue it for processing, unless the same delivery
+    /// or payload was already received through any transport. A duplicate
+    /// of an event an earlier run left unprocessed is queued in its place.
+    /// Returns `None` for any other duplicate.
+    pub async fn receive(
+        &self,
+        event: WebhookEvent,
+        source: EventSource,
+    ) -> Result<Option<Processing>, anyhow::Error> {
+        let egg = IncomingEventEgg {
+            payload_hash: payload_hash(&event),
+            delivery_id: event.delivery_id,
+            event_type: event.event_type,
+            payload: event.payload,
+            source: source.as_str().to_string(),
+        };
+        let conn = self.pool.get()?;
+        let Some(id) = IncomingEvent::insert(&egg, &conn)? else {
+            if let Some(stored) = IncomingEvent::find_duplicate(&egg, &conn)?
+                .filter(|e| e.status == "pending" && e.received_at < self.started_at)
+            {
+                drop(conn);
+                let (job, processing) =
+                    Job::new(stored.id, stored.event_type, stored.payload, false);
+                if self.queue.enqueue_once(job, &self.dispatcher, &self.pool) {
+                    return Ok(Some(processing));
                 }
-                Err(e) => Err(anyhow::Error::msg(format!(
-                    "Error parsing delete event: {}",
-                    e
-                ))),
-            },
-            _ => {
-                log::debug!("Received unknown event: {}", event.event_type);
-                for handler in &self.handlers {
-                    let handler_result = handler.handle_unknown(&event.event_type).await;
-                    if let Err(e) = handler_result {
-                        log::error!("Error handling unknown event:\n{}", format_anyhow_chain(&e));
-                    }
+            }
+            log::debug!(
+                "Skipping duplicate {} delivery {}",
+                egg.event_type,
+                egg.delivery_id.as_deref().unwrap_or("without id")
+            );
+            return Ok(None);
+        };
+
+        drop(conn);
+        let (job, processing) = Job::new(id, egg.event_type, egg.payload, false);
+        self.queue.enqueue(job, &self.dispatcher, &self.pool);
+        Ok(Some(processing))
+    }
+
+    /// Recover events missed since `since` (ms): the hooks' deliveries first,
+    /// then pushes, deletes and check runs found by comparing with GitHub.
+    async fn catch_up(&self, catchup: &CatchUp, since: i64) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let since = since.max(now - MAX_CATCHUP_WINDOW_MS);
+        crate::metrics::get()
+            .webhook_gap_seconds
+            .record((now - since) as f64 / 1000.0, &[]);
+        log::info!(
+            "Catching up on GitHub events missed in the last {}s",
+            (now - since) / 1000
+        );
+
+        let mut recovered = self
+            .receive_recovered(catchup.missed_deliveries(since).await, "deliveries")
+            .await;
+        let changes = catchup.missed_branch_changes().await;
+        recovered += self.receive_recovered(changes.events, "branches").await;
+        recovered += self
+            .receive_recovered(
+                catchup.missed_check_runs(&changes.heads).await,
+                "check_runs",
+            )
+            .await;
+        log::info!("Catch-up recovered {} missed events", recovered);
+    }
+
+    /// Receive recovered events and wait until they are processed, as the
+    /// next catch-up step compares against their results. Returns how many
+    /// weren't duplicates.
+    async fn receive_recovered(&self, events: Vec<WebhookEvent>, method: &'static str) -> u64 {
+        let mut processing = Vec::new();
+        for event in events {
+            let event_type = event.event_type.clone();
+            match self.receive(event, EventSource::Catchup).await {
+                Ok(Some(done)) => {
+                    crate::metrics::get().webhook_events_recovered.add(
+                        1,
+                        &[
+                            KeyValue::new("event_type", event_type),
+                            KeyValue::new("method", method),
+                        ],
+                    );
+                    processing.push(done);
                 }
-                Ok(())
+                Ok(None) => {}
+                Err(e) => log::error!(
+                    "Error storing recovered {} event: {}",
+                    event_type,
+                    format_anyhow_chain(&e)
+                ),
             }
         }
+        let recovered = processing.len() as u64;
+        join_all(processing).await;
+        recovered
+    }
+
+    /// Run a stored event through the handlers again, duplicate or not. It
+    /// queues behind its repo's pending events.
+    pub async fn replay(&self, id: i64) -> AppResult<Vec<HandlerOutcome>> {
+        let event = IncomingEvent::get_by_id(id, &self.pool.get()?)?
+            .ok_or_else(|| AppError::NotFound(format!("Webhook event {}", id)))?;
+        log::info!("Replaying {} event {}", event.event_type, id);
+
+        let (job, processing) = Job::new(id, event.event_type, event.payload, true);
+        self.queue.enqueue(job, &self.dispatcher, &self.pool);
+        processing
+            .await
+            .map_err(|_| AppError::Webhook(format!("Processing of event {} was aborted", id)))
+    }
+
+    /// Delete stored events past their retention, hourly, until the process
+    /// exits.
+    pub async fn prune_events(&self) {
+        loop {
+            let before = chrono::Utc::now().timestamp_millis() - self.retention_days * 86_400_000;
+            match self
+                .pool
+                .get()
+                .map_err(AppError::from)
+                .and_then(|conn| IncomingEvent::prune(before, MAX_STORED_EVENTS, &conn))
+            {
+                Ok(0) => {}
+                Ok(deleted) => log::info!("Pruned {} stored webhook events", deleted),
+                Err(e) => log::error!("Failed to prune webhook events: {}", e),
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(60 * 60)).await;
+        }
+    }
+}
+
+/// A hash of the event type and payload, which identifies a delivery on
+/// every transport, including those that don't pass the delivery id on.
+fn payload_hash(event: &WebhookEvent) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!("{}:{}", event.event_type, event.payload).as_bytes())
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::db::incoming_event::IncomingEventFilter;
+    use serenity::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn event(delivery_id: Option<&str>, starred_at: &str) -> WebhookEvent {
+        WebhookEvent {
+            event_type: "star".to_string(),
+            payload: serde_json::json!({ "starred_at": starred_at }),
+            delivery_id: delivery_id.map(str::to_string),
+        }
+    }
+
+    fn manager() -> WebhookManager {
+        // Recording metrics needs them initialized, once per process.
+        let _ = crate::metrics::init(&prometheus::Registry::new());
+        let pool = crate::db::test_pool();
+        let mut manager = WebhookManager::new(pool, None, Some("secret".to_string()));
+        manager.set_retry_policy(RetryPolicy {
+            timeout: Duration::from_secs(1),
+            attempts: 2,
+            backoff: Duration::from_millis(1),
+        });
+        manager
+    }
+
+    fn stored(manager: &WebhookManager) -> Vec<IncomingEvent> {
+        IncomingEvent::search(
+            &IncomingEventFilter::default(),
+            10,
+            &manager.pool.get().unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Receive an event and wait until it is processed.
+    async fn process(manager: &WebhookManager, event: WebhookEvent) {
+        let processing = manager.receive(event, EventSource::Http).await.unwrap();
+        processing.unwrap().await.unwrap();
+    }
+
+    struct FailingHandler;
+
+    #[async_trait]
+    impl WebhookHandler for FailingHandler {
+        async fn handle_unknown(&self, event_type: &str) -> Result<(), anyhow::Error> {
+            Err(anyhow::anyhow!("cannot handle {}", event_type))
+        }
+    }
+
+    /// Records the event types it finishes, taking a while for `slow` ones.
+    struct RecordingHandler(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl WebhookHandler for RecordingHandler {
+        async fn handle_unknown(&self, event_type: &str) -> Result<(), anyhow::Error> {
+            if event_type.ends_with("slow") {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            self.0.lock().unwrap().push(event_type.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn dedupes_deliveries_across_transports() {
+        let manager = manager();
+
+        // Over HTTP with an id, then the same payload over the websocket without one.
+        let deliveries = [
+            (event(Some("d1"), "abc"), EventSource::Http),
+            (event(None, "abc"), EventSource::Websocket),
+            // A redelivery of the same id.
+            (event(Some("d1"), "abc"), EventSource::Http),
+            // A different event.
+            (event(Some("d2"), "def"), EventSource::Websocket),
+        ];
+        let mut processing = Vec::new();
+        for (event, source) in deliveries {
+            processing.push(manager.receive(event, source).await.unwrap());
+        }
+        assert_eq!(
+            processing.iter().map(Option::is_some).collect::<Vec<_>>(),
+            vec![true, false, false, true]
+        );
+        join_all(processing.into_iter().flatten()).await;
+
+        let events = stored(&manager);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].delivery_id.as_deref(), Some("d2"));
+        assert_eq!(events[0].source, "websocket");
+        assert_eq!(events[1].source, "http");
+        assert!(events.iter().all(|e| e.status == "success"));
+    }
+
+    #[tokio::test]
+    async fn requeues_events_left_pending() {
+        let earlier = manager();
+        // Stored by an earlier run that stopped before processing them.
+        for delivery in ["d1", "d2"] {
+            let egg = IncomingEventEgg {
+                delivery_id: Some(delivery.to_string()),
+                payload_hash: payload_hash(&event(Some(delivery), delivery)),
+                event_type: "star".to_string(),
+                payload: serde_json::json!({ "starred_at": delivery }),
+                source: "http".to_string(),
+            };
+            IncomingEvent::insert(&egg, &earlier.pool.get().unwrap()).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        let manager = WebhookManager::new(earlier.pool.clone(), None, None);
+
+        // A redelivery processes the stored event, once.
+        let processing = manager
+            .receive(event(Some("d1"), "d1"), EventSource::Http)
+            .await
+            .unwrap();
+        assert!(manager
+            .receive(event(Some("d1"), "d1"), EventSource::Http)
+            .await
+            .unwrap()
+            .is_none());
+        processing.unwrap().await.unwrap();
+
+        // Startup picks up the rest.
+        assert_eq!(manager.requeue_pending().unwrap(), 1);
+        assert_eq!(manager.requeue_pending().unwrap(), 0);
+        // Done once an event queued behind it in the same lane is.
+        process(&manager, event(Some("d3"), "d3")).await;
+        assert!(stored(&manager)
+            .iter()
+            .all(|e| e.status == "success" && e.replays == 0));
+        assert!(manager
+            .receive(event(Some("d2"), "d2"), EventSource::Http)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn orders_events_within_a_repo_only() {
+        let mut manager = manager();
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        manager.add_handler(RecordingHandler(finished.clone()));
+
+        let mut processing = Vec::new();
+        for (event_type, repo) in [
+            ("a-slow", "acme/a"),
+            ("a-fast", "acme/a"),
+            ("b-fast", "acme/b"),
+        ] {
+            let event = WebhookEvent {
+                event_type: event_type.to_string(),
+                payload: serde_json::json!({ "repository": { "full_name": repo } }),
+                delivery_id: None,
+            };
+            processing.push(
+                manager
+                    .receive(event, EventSource::Websocket)
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+        join_all(processing).await;
+
+        assert_eq!(
+            *finished.lock().unwrap(),
+            vec!["b-fast", "a-slow", "a-fast"]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_outcomes_and_replays() {
+        let mut manager = manager();
+        manager.add_handler(FailingHandler);
+
+        let event = WebhookEvent {
+            event_type: "star".to_string(),
+            payload: serde_json::json!({}),
+            delivery_id: None,
+        };
+        process(&manager, event).await;
+        let broken = WebhookEvent {
+            event_type: "push".to_string(),
+            payload: serde_json::json!({ "ref": 1 }),
+            delivery_id: None,
+        };
+        process(&manager, broken).await;
+
+        let events = stored(&manager);
+        assert_eq!(events[0].status, "failed");
+        assert_eq!(events[0].outcomes[0].handler, "parse");
+        assert_eq!(events[1].status, "failed");
+        assert_eq!(
+            events[1].outcomes,
+            vec![HandlerOutcome {
+                handler: "FailingHandler".to_string(),
+                error: Some("cannot handle star".to_string()),
+                attempts: 2,
+            }]
+        );
+
+        let outcomes = manager.replay(events[1].id).await.unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(stored(&manager)[1].replays, 1);
+        assert!(manager.replay(1000).await.is_err());
+
+        let conn = manager.pool.get().unwrap();
+        assert_eq!(IncomingEvent::prune(0, 1, &conn).unwrap(), 1);
+        assert_eq!(IncomingEvent::prune(i64::MAX, 1, &conn).unwrap(), 1);
     }
 }
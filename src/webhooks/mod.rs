@@ -1,17 +1,29 @@
 use serenity::async_trait;
 
-use crate::webhooks::models::{CheckRunEvent, CheckSuiteEvent, DeleteEvent, PushEvent};
+use crate::webhooks::models::{
+    CheckRunEvent, CheckSuiteEvent, DeleteEvent, PullRequestEvent, PushEvent, StatusEvent,
+    WorkflowJobEvent, WorkflowRunEvent,
+};
 
+pub mod catchup;
 pub mod config_sync;
+pub mod config_validation;
 pub mod database;
+pub mod http;
 pub mod log;
 pub mod manager;
 pub mod metrics;
 pub mod models;
+pub mod queue;
 pub mod util;
 
 #[async_trait]
 pub trait WebhookHandler {
+    /// Shown with this handler's outcome of each stored event.
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
     async fn handle_push(&self, __event: PushEvent) -> Result<(), anyhow::Error> {
         Ok(())
     }
@@ -21,9 +33,21 @@ pub trait WebhookHandler {
     async fn handle_check_suite(&self, __event: CheckSuiteEvent) -> Result<(), anyhow::Error> {
         Ok(())
     }
+    async fn handle_status(&self, __event: StatusEvent) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
     async fn handle_delete(&self, __event: DeleteEvent) -> Result<(), anyhow::Error> {
         Ok(())
     }
+    async fn handle_pull_request(&self, __event: PullRequestEvent) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+    async fn handle_workflow_run(&self, __event: WorkflowRunEvent) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+    async fn handle_workflow_job(&self, __event: WorkflowJobEvent) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
     async fn handle_unknown(&self, __event_type: &str) -> Result<(), anyhow::Error> {
         Ok(())
     }
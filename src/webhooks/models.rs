@@ -102,12 +102,17 @@ pub struct PushEvent {
     pub head_commit: Option<PushCommit>,
     pub commits: Vec<PushCommit>,
     pub deleted: bool,
+    /// The user who pushed.
+    pub sender: Option<RepoOwner>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WebhookEvent {
     pub event_type: String,
     pub payload: serde_json::Value,
+    /// GitHub's `X-GitHub-Delivery`, when the transport passes it on.
+    #[serde(default)]
+    pub delivery_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -116,3 +121,117 @@ pub struct CheckSuiteEvent {
     pub check_suite: CheckSuite,
     pub repository: Repository,
 }
+
+/// A commit status from the older Statuses API, used by external CI.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusEvent {
+    pub id: u64,
+    pub sha: String,
+    /// Identifies the reporting CI job; the latest status per context counts.
+    pub context: String,
+    /// pending | success | failure | error
+    pub state: String,
+    pub target_url: Option<String>,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub repository: Repository,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PullRequestRef {
+    pub r#ref: String, // branch name, without "refs/heads/"
+    pub sha: String,
+    /// Missing when the head repo (a fork) was deleted.
+    pub repo: Option<Repository>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PullRequest {
+    pub id: u64,
+    pub number: u64,
+    pub title: String,
+    pub user: RepoOwner,
+    /// open | closed
+    pub state: String,
+    #[serde(default)]
+    pub draft: bool,
+    /// Not included in every payload; `merged_at` always is.
+    pub merged: Option<bool>,
+    pub merged_at: Option<String>,
+    pub html_url: String,
+    pub head: PullRequestRef,
+    pub base: PullRequestRef,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PullRequestEvent {
+    pub action: String,
+    pub number: u64,
+    pub pull_request: PullRequest,
+    pub repository: Repository,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowRun {
+    pub id: u64,
+    pub name: Option<String>,
+    pub head_branch: Option<String>,
+    pub head_sha: String,
+    pub run_number: u64,
+    pub run_attempt: u32,
+    /// What triggered the run, e.g. push or pull_request.
+    pub event: String,
+    /// queued | in_progress | completed (and occasionally waiting/requested/pending)
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub html_url: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub run_started_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowRunEvent {
+    pub action: String,
+    pub workflow_run: WorkflowRun,
+    pub repository: Repository,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowStep {
+    pub name: String,
+    pub number: u32,
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowJob {
+    pub id: u64,
+    pub run_id: u64,
+    pub run_attempt: u32,
+    pub head_sha: String,
+    pub name: String,
+    /// queued | in_progress | completed | waiting
+    pub status: String,
+    pub conclusion: Option<String>,
+    /// When the job was queued.
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+    pub runner_name: Option<String>,
+    pub html_url: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkflowJobEvent {
+    pub action: String,
+    pub workflow_job: WorkflowJob,
+    pub repository: Repository,
+}
@@ -0,0 +1,459 @@
+//! Processing of stored events: concurrent across repos, in order within a
+//! repo, with a timeout and retries for each handler.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use opentelemetry::KeyValue;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+
+use crate::db::incoming_event::{HandlerOutcome, IncomingEvent};
+use crate::error::format_anyhow_chain;
+use crate::webhooks::models::{
+    CheckRunEvent, CheckSuiteEvent, DeleteEvent, PullRequestEvent, PushEvent, StatusEvent,
+    WorkflowJobEvent, WorkflowRunEvent,
+};
+use crate::webhooks::WebhookHandler;
+
+/// Events processed at once, across all repos.
+const MAX_CONCURRENT_EVENTS: usize = 8;
+
+/// A repo's lane is shut down after this long without events.
+const LANE_IDLE: Duration = Duration::from_secs(60);
+
+/// How long each handler may take, and how often it is tried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub timeout: Duration,
+    pub attempts: u32,
+    /// Wait before the second attempt, doubling for each one after.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            attempts: 3,
+            backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Resolves to the handlers' outcomes once an event has been processed.
+pub type Processing = oneshot::Receiver<Vec<HandlerOutcome>>;
+
+/// Runs an event through every handler.
+#[derive(Clone, Default)]
+pub struct Dispatcher {
+    handlers: Vec<Arc<dyn WebhookHandler + Send + Sync>>,
+    retry: RetryPolicy,
+}
+
+impl Dispatcher {
+    pub fn add_handler(&mut self, handler: impl WebhookHandler + Send + Sync + 'static) {
+        self.handlers.push(Arc::new(handler));
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Dispatch an event to every handler, returning what each made of it.
+    pub async fn dispatch(
+        &self,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> Vec<HandlerOutcome> {
+        log::debug!("Received event: {}", event_type);
+
+        let mut outcomes = Vec::with_capacity(self.handlers.len());
+        match event_type {
+            "push" => match serde_json::from_value::<PushEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_push(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "push", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("push", e)),
+            },
+            "check_run" => match serde_json::from_value::<CheckRunEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_check_run(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "check run", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("check run", e)),
+            },
+            "check_suite" => match serde_json::from_value::<CheckSuiteEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_check_suite(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "check suite", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("check suite", e)),
+            },
+            "status" => match serde_json::from_value::<StatusEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_status(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "status", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("status", e)),
+            },
+            "delete" => match serde_json::from_value::<DeleteEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_delete(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "delete", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("delete", e)),
+            },
+            "pull_request" => match serde_json::from_value::<PullRequestEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_pull_request(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "pull request", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("pull request", e)),
+            },
+            "workflow_run" => match serde_json::from_value::<WorkflowRunEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_workflow_run(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "workflow run", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("workflow run", e)),
+            },
+            "workflow_job" => match serde_json::from_value::<WorkflowJobEvent>(payload.clone()) {
+                Ok(payload) => {
+                    for handler in &self.handlers {
+                        let call = || handler.handle_workflow_job(payload.clone());
+                        outcomes.push(self.run(handler.as_ref(), "workflow job", call).await);
+                    }
+                }
+                Err(e) => outcomes.push(parse_failure("workflow job", e)),
+            },
+            _ => {
+                log::debug!("Received unknown event: {}", event_type);
+                for handler in &self.handlers {
+                    let call = || handler.handle_unknown(event_type);
+                    outcomes.push(self.run(handler.as_ref(), "unknown event", call).await);
+                }
+            }
+        }
+        outcomes
+    }
+
+    /// Call a handler until it succeeds or runs out of attempts. A call that
+    /// times out is dropped where it was and counts as failed.
+    async fn run<F, Fut>(
+        &self,
+        handler: &(dyn WebhookHandler + Send + Sync),
+        what: &str,
+        call: F,
+    ) -> HandlerOutcome
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(), anyhow::Error>>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = tokio::time::timeout(self.retry.timeout, call())
+                .await
+                .unwrap_or_else(|_| {
+                    Err(anyhow::anyhow!(
+                        "Timed out after {}s",
+                        self.retry.timeout.as_secs_f64()
+                    ))
+                });
+            let Err(e) = result else {
+                return HandlerOutcome {
+                    handler: handler.name().to_string(),
+                    error: None,
+                    attempts,
+                };
+            };
+
+            let chain = format_anyhow_chain(&e);
+            if attempts >= self.retry.attempts {
+                log::error!(
+                    "Error handling {} in {} after {} attempts:\n{}",
+                    what,
+                    handler.name(),
+                    attempts,
+                    chain
+                );
+                return HandlerOutcome {
+                    handler: handler.name().to_string(),
+                    error: Some(chain),
+                    attempts,
+                };
+            }
+            let backoff = self.retry.backoff * 2u32.pow(attempts - 1);
+            log::warn!(
+                "Error handling {} in {}, retrying in {:?}:\n{}",
+                what,
+                handler.name(),
+                backoff,
+                chain
+            );
+            crate::metrics::get()
+                .webhook_handler_retries
+                .add(1, &[KeyValue::new("handler", handler.name())]);
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+fn parse_failure(what: &str, e: serde_json::Error) -> HandlerOutcome {
+    log::error!("Error parsing {} event: {}", what, e);
+    HandlerOutcome {
+        handler: "parse".to_string(),
+        error: Some(format!("Error parsing {} event: {}", what, e)),
+        attempts: 1,
+    }
+}
+
+/// A stored event waiting to be processed.
+pub struct Job {
+    pub id: i64,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    /// Counted as a replay when its outcomes are recorded.
+    pub replayed: bool,
+    enqueued_at: Instant,
+    done: oneshot::Sender<Vec<HandlerOutcome>>,
+}
+
+impl Job {
+    pub fn new(
+        id: i64,
+        event_type: String,
+        payload: serde_json::Value,
+        replayed: bool,
+    ) -> (Self, Processing) {
+        let (done, processing) = oneshot::channel();
+        let job = Self {
+            id,
+            event_type,
+            payload,
+            replayed,
+            enqueued_at: Instant::now(),
+            done,
+        };
+        (job, processing)
+    }
+
+    /// Events of the same repo are processed in order; the key is the repo's
+    /// full name, or empty for events without one.
+    fn lane(&self) -> String {
+        let repository = &self.payload["repository"];
+        repository["full_name"]
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| {
+                Some(format!(
+                    "{}/{}",
+                    repository["owner"]["login"].as_str()?,
+                    repository["name"].as_str()?
+                ))
+            })
+            .unwrap_or_default()
+    }
+}
+
+struct Lanes {
+    senders: Mutex<HashMap<String, mpsc::UnboundedSender<Job>>>,
+    /// Events received and waiting or being processed, by how often.
+    queued: Mutex<HashMap<i64, usize>>,
+    permits: Semaphore,
+}
+
+/// One lane per repo, each a task working through its events in order, with
+/// at most [`MAX_CONCURRENT_EVENTS`] events processed at once overall.
+pub struct WorkQueue {
+    lanes: Arc<Lanes>,
+    /// Lanes run on the runtime the queue was created on, whichever runtime
+    /// an event arrives on.
+    runtime: Option<tokio::runtime::Handle>,
+}
+
+impl Default for WorkQueue {
+    fn default() -> Self {
+        Self {
+            lanes: Arc::new(Lanes {
+                senders: Mutex::new(HashMap::new()),
+                queued: Mutex::new(HashMap::new()),
+                permits: Semaphore::new(MAX_CONCURRENT_EVENTS),
+            }),
+            runtime: tokio::runtime::Handle::try_current().ok(),
+        }
+    }
+}
+
+impl WorkQueue {
+    pub fn enqueue(&self, job: Job, dispatcher: &Dispatcher, pool: &Pool<SqliteConnectionManager>) {
+        if let Ok(mut queued) = self.lanes.queued.lock() {
+            *queued.entry(job.id).or_default() += 1;
+        }
+        self.send(job, dispatcher, pool);
+    }
+
+    /// Enqueue an event unless it is already waiting or being processed.
+    /// Returns whether it was enqueued.
+    pub fn enqueue_once(
+        &self,
+        job: Job,
+        dispatcher: &Dispatcher,
+        pool: &Pool<SqliteConnectionManager>,
+    ) -> bool {
+        let Ok(mut queued) = self.lanes.queued.lock() else {
+            return false;
+        };
+        if queued.contains_key(&job.id) {
+            return false;
+        }
+        queued.insert(job.id, 1);
+        drop(queued);
+        self.send(job, dispatcher, pool);
+        true
+    }
+
+    fn send(&self, job: Job, dispatcher: &Dispatcher, pool: &Pool<SqliteConnectionManager>) {
+        let key = job.lane();
+        crate::metrics::get().webhook_queue_depth.add(1, &[]);
+
+        let Ok(mut senders) = self.lanes.senders.lock() else {
+            log::error!("Webhook queue lock poisoned, dropping event {}", job.id);
+            return;
+        };
+        let job = match senders.get(&key) {
+            Some(sender) => match sender.send(job) {
+                Ok(()) => return,
+                // The lane's task died; start a new one.
+                Err(mpsc::error::SendError(job)) => job,
+            },
+            None => job,
+        };
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // Just created, so the receiver is alive.
+        let _ = sender.send(job);
+        senders.insert(key.clone(), sender);
+        let lane = run_lane(
+            key,
+            receiver,
+            self.lanes.clone(),
+            dispatcher.clone(),
+            pool.clone(),
+        );
+        match &self.runtime {
+            Some(runtime) => drop(runtime.spawn(lane)),
+            None => drop(tokio::spawn(lane)),
+        }
+    }
+}
+
+async fn run_lane(
+    key: String,
+    mut receiver: mpsc::UnboundedReceiver<Job>,
+    lanes: Arc<Lanes>,
+    dispatcher: Dispatcher,
+    pool: Pool<SqliteConnectionManager>,
+) {
+    loop {
+        let job = match tokio::time::timeout(LANE_IDLE, receiver.recv()).await {
+            Ok(Some(job)) => job,
+            Ok(None) => return,
+            Err(_) => {
+                // Enqueueing holds the lock, so nothing arrives between the
+                // check and the removal.
+                let Ok(mut senders) = lanes.senders.lock() else {
+                    return;
+                };
+                if receiver.is_empty() {
+                    senders.remove(&key);
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let Ok(_permit) = lanes.permits.acquire().await else {
+            return;
+        };
+        let metrics = crate::metrics::get();
+        let labels = [KeyValue::new("event_type", job.event_type.clone())];
+        metrics.webhook_queue_depth.add(-1, &[]);
+        metrics
+            .webhook_queue_latency_seconds
+            .record(job.enqueued_at.elapsed().as_secs_f64(), &labels);
+
+        let started = Instant::now();
+        let outcomes = dispatcher.dispatch(&job.event_type, &job.payload).await;
+        metrics
+            .webhook_processing_seconds
+            .record(started.elapsed().as_secs_f64(), &labels);
+
+        let recorded = pool
+            .get()
+            .map_err(crate::error::AppError::from)
+            .and_then(|conn| {
+                IncomingEvent::record_outcomes(job.id, &outcomes, job.replayed, &conn)
+            });
+        if let Err(e) = recorded {
+            log::error!("Failed to record outcomes of event {}: {}", job.id, e);
+        }
+        if let Ok(mut queued) = lanes.queued.lock() {
+            if let Some(count) = queued.get_mut(&job.id) {
+                *count -= 1;
+                if *count == 0 {
+                    queued.remove(&job.id);
+                }
+            }
+        }
+        // Nobody may be waiting.
+        let _ = job.done.send(outcomes);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lanes_by_repo() {
+        let (job, _) = Job::new(
+            1,
+            "push".to_string(),
+            serde_json::json!({ "repository": { "full_name": "acme/api" } }),
+            false,
+        );
+        assert_eq!(job.lane(), "acme/api");
+
+        let (job, _) = Job::new(
+            2,
+            "check_run".to_string(),
+            serde_json::json!({ "repository": { "name": "api", "owner": { "login": "acme" } } }),
+            false,
+        );
+        assert_eq!(job.lane(), "acme/api");
+
+        let (job, _) = Job::new(3, "star".to_string(), serde_json::json!({}), false);
+        assert_eq!(job.lane(), "");
+    }
+}